@@ -0,0 +1,450 @@
+//! TLS for the web server, on top of the ESP-TLS server API
+//!
+//! The certificate is either user-uploaded or self-signed and generated on first boot,
+//! and is persisted in NVS. The TLS handshake blocks for most of its duration on the crypto,
+//! so it is done on a short-lived worker thread, while the executor keeps serving the other
+//! connections. Plain HTTP requests are redirected to HTTPS.
+
+use core::ffi::c_uint;
+use core::{mem, ptr};
+
+use std::io::{self, ErrorKind};
+use std::net::{self, TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use async_io::Async;
+
+use futures::channel::oneshot;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+use log::{info, warn};
+
+use embassy_time::{with_timeout, Duration};
+
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_nal_async::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use embedded_nal_async_xtra::{TcpAccept, TcpSplittableConnection};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::{self, EspError};
+
+use ruwm::tls::{self, CERTIFICATE_MAX_LEN, KEY_MAX_LEN};
+
+use crate::errors::InitError;
+
+pub const HTTP_PORT: u16 = 80;
+pub const HTTPS_PORT: u16 = 443;
+
+const COMMON_NAME: &str = "ruwm.local";
+
+const HANDSHAKE_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(10);
+
+// mbedtls needs a bit more than the default pthread stack for the server handshake
+const HANDSHAKE_STACK_SIZE: usize = 10 * 1024;
+
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REDIRECT_REQUEST_MAX_LEN: usize = 1024;
+
+const NVS_NAMESPACE: &str = "TLS";
+const NVS_CERTIFICATE: &str = "cert";
+const NVS_KEY: &str = "key";
+
+/// Uploaded PEM bundles are rarely larger, even with an intermediate certificate
+pub const UPLOAD_MAX_LEN: usize = 8192;
+
+// The values of the corresponding mbedtls errors, which ESP-TLS passes through
+const ERR_WANT_READ: isize = -0x6900;
+const ERR_WANT_WRITE: isize = -0x6880;
+
+/// The server certificate and its private key, either DER encoded or NUL-terminated PEM
+pub struct TlsCertificate {
+    certificate: Vec<u8>,
+    key: Vec<u8>,
+}
+
+pub struct CertificateStore(Mutex<EspNvs<NvsDefault>>);
+
+impl CertificateStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self(Mutex::new(EspNvs::new(
+            partition,
+            NVS_NAMESPACE,
+            true,
+        )?)))
+    }
+
+    /// Loads the persisted certificate, generating a self-signed one if there is none yet
+    pub fn load_or_generate(&self) -> Result<TlsCertificate, InitError> {
+        let nvs = self.0.lock().unwrap();
+
+        if let (Some(certificate_len), Some(key_len)) =
+            (nvs.blob_len(NVS_CERTIFICATE)?, nvs.blob_len(NVS_KEY)?)
+        {
+            let mut certificate = vec![0; certificate_len];
+            let mut key = vec![0; key_len];
+
+            nvs.get_raw(NVS_CERTIFICATE, &mut certificate)?;
+            nvs.get_raw(NVS_KEY, &mut key)?;
+
+            return Ok(TlsCertificate { certificate, key });
+        }
+
+        info!("No TLS certificate found in NVS, generating a self-signed one");
+
+        let mut certificate = vec![0; CERTIFICATE_MAX_LEN];
+        let mut key = vec![0; KEY_MAX_LEN];
+
+        let (certificate_len, key_len) = loop {
+            let mut secret = [0; 32];
+            let mut serial = [0; 16];
+
+            unsafe {
+                sys::esp_fill_random(secret.as_mut_ptr() as *mut _, secret.len());
+                sys::esp_fill_random(serial.as_mut_ptr() as *mut _, serial.len());
+            }
+
+            match tls::generate_self_signed(
+                &secret,
+                &serial,
+                COMMON_NAME,
+                &mut certificate,
+                &mut key,
+            ) {
+                Ok(lens) => break lens,
+                Err(tls::TlsError::Key) => continue,
+                Err(err) => panic!("Cannot generate a TLS certificate: {err:?}"),
+            }
+        };
+
+        certificate.truncate(certificate_len);
+        key.truncate(key_len);
+
+        drop(nvs);
+
+        self.store(&certificate, &key)?;
+
+        Ok(TlsCertificate { certificate, key })
+    }
+
+    /// Replaces the certificate; it is used after the next restart
+    pub fn store(&self, certificate: &[u8], key: &[u8]) -> Result<(), EspError> {
+        let mut nvs = self.0.lock().unwrap();
+
+        nvs.set_raw(NVS_CERTIFICATE, certificate)?;
+        nvs.set_raw(NVS_KEY, key)?;
+
+        Ok(())
+    }
+
+    /// Replaces the certificate with an uploaded PEM bundle of the certificate (chain) and its key
+    pub fn store_pem_bundle(&self, bundle: &[u8]) -> Result<bool, EspError> {
+        let Some((certificate, key)) = core::str::from_utf8(bundle)
+            .ok()
+            .and_then(tls::split_pem_bundle)
+        else {
+            return Ok(false);
+        };
+
+        // mbedtls only recognizes PEM when it is NUL-terminated
+        let nul_terminated = |pem: &str| pem.bytes().chain(Some(0)).collect::<Vec<_>>();
+
+        self.store(&nul_terminated(certificate), &nul_terminated(key))?;
+
+        Ok(true)
+    }
+}
+
+pub struct TlsAcceptor {
+    listener: Async<TcpListener>,
+    certificate: Arc<TlsCertificate>,
+}
+
+impl TlsAcceptor {
+    pub fn bind(port: u16, certificate: TlsCertificate) -> io::Result<Self> {
+        Ok(Self {
+            listener: Async::<TcpListener>::bind(net::SocketAddr::from(([0, 0, 0, 0], port)))?,
+            certificate: Arc::new(certificate),
+        })
+    }
+
+    async fn handshake(&self, stream: TcpStream) -> io::Result<TlsConnection> {
+        let certificate = self.certificate.clone();
+        let (sender, receiver) = oneshot::channel();
+
+        thread::Builder::new()
+            .stack_size(HANDSHAKE_STACK_SIZE)
+            .spawn(move || {
+                let result = TlsSession::handshake(&stream, &certificate);
+
+                let _ = sender.send(result.map(|session| (stream, session)));
+            })?;
+
+        let (stream, session) = receiver
+            .await
+            .map_err(|_| io::Error::from(ErrorKind::Interrupted))??;
+
+        Ok(TlsConnection {
+            session,
+            socket: Async::new(stream)?,
+        })
+    }
+}
+
+impl TcpAccept for TlsAcceptor {
+    type Error = io::Error;
+
+    type Connection<'m>
+        = TlsConnection
+    where
+        Self: 'm;
+
+    async fn accept(&self) -> Result<(SocketAddr, Self::Connection<'_>), Self::Error> {
+        loop {
+            let (stream, addr) = self.listener.accept().await?;
+
+            match self.handshake(stream.into_inner()?).await {
+                Ok(connection) => break Ok((to_nal_addr(addr), connection)),
+                // A client rejecting the self-signed certificate ends up here as well
+                Err(err) => warn!("TLS handshake with {addr} failed: {err}"),
+            }
+        }
+    }
+}
+
+/// An ESP-TLS server session; deleting it does not close the socket, which is owned by `TcpStream`
+struct TlsSession(*mut sys::esp_tls_t);
+
+// The session is only ever used by one thread at a time: the worker one during the handshake,
+// and the executor one afterwards
+unsafe impl Send for TlsSession {}
+
+impl TlsSession {
+    fn handshake(stream: &TcpStream, certificate: &TlsCertificate) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        let mut cfg: sys::esp_tls_cfg_server_t = unsafe { mem::zeroed() };
+
+        cfg.__bindgen_anon_3.servercert_buf = certificate.certificate.as_ptr();
+        cfg.__bindgen_anon_4.servercert_bytes = certificate.certificate.len() as c_uint;
+        cfg.__bindgen_anon_5.serverkey_buf = certificate.key.as_ptr();
+        cfg.__bindgen_anon_6.serverkey_bytes = certificate.key.len() as c_uint;
+
+        let tls = unsafe { sys::esp_tls_init() };
+        if tls.is_null() {
+            return Err(io::Error::from(ErrorKind::OutOfMemory));
+        }
+
+        let session = Self(tls);
+
+        let result =
+            unsafe { sys::esp_tls_server_session_create(&mut cfg, stream.as_raw_fd(), tls) };
+        if result != 0 {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                format!("ESP-TLS error {result}"),
+            ));
+        }
+
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+
+        Ok(session)
+    }
+}
+
+impl Drop for TlsSession {
+    fn drop(&mut self) {
+        unsafe {
+            sys::esp_tls_server_session_delete(self.0);
+        }
+
+        self.0 = ptr::null_mut();
+    }
+}
+
+pub struct TlsConnection {
+    // Declared first, so that the session is deleted before the socket is closed
+    session: TlsSession,
+    socket: Async<TcpStream>,
+}
+
+impl TlsConnection {
+    async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let result = unsafe {
+                sys::esp_tls_conn_read(self.session.0, buf.as_mut_ptr() as *mut _, buf.len())
+            } as isize;
+
+            match result {
+                ERR_WANT_READ => self.socket.readable().await?,
+                ERR_WANT_WRITE => self.socket.writable().await?,
+                len if len >= 0 => break Ok(len as usize),
+                err => break Err(tls_error(err)),
+            }
+        }
+    }
+
+    async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let result = unsafe {
+                sys::esp_tls_conn_write(self.session.0, buf.as_ptr() as *const _, buf.len())
+            } as isize;
+
+            match result {
+                ERR_WANT_READ => self.socket.readable().await?,
+                ERR_WANT_WRITE => self.socket.writable().await?,
+                len if len >= 0 => break Ok(len as usize),
+                err => break Err(tls_error(err)),
+            }
+        }
+    }
+}
+
+impl ErrorType for TlsConnection {
+    type Error = io::Error;
+}
+
+impl Read for TlsConnection {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        TlsConnection::read(self, buf).await
+    }
+}
+
+impl Write for TlsConnection {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        TlsConnection::write(self, buf).await
+    }
+}
+
+impl TcpSplittableConnection for TlsConnection {
+    type Read<'a>
+        = TlsHalf<'a>
+    where
+        Self: 'a;
+
+    type Write<'a>
+        = TlsHalf<'a>
+    where
+        Self: 'a;
+
+    // Both halves are used from the same executor, and each ESP-TLS call completes
+    // before the other half gets the chance to run
+    fn split(&mut self) -> Result<(Self::Read<'_>, Self::Write<'_>), Self::Error> {
+        Ok((TlsHalf(self), TlsHalf(self)))
+    }
+}
+
+pub struct TlsHalf<'a>(&'a TlsConnection);
+
+impl<'a> ErrorType for TlsHalf<'a> {
+    type Error = io::Error;
+}
+
+impl<'a> Read for TlsHalf<'a> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+impl<'a> Write for TlsHalf<'a> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+}
+
+/// Redirects the plain HTTP requests to HTTPS, so that `http://` links and bookmarks keep working
+pub async fn redirect(port: u16) -> io::Result<()> {
+    let listener = Async::<TcpListener>::bind(net::SocketAddr::from(([0, 0, 0, 0], port)))?;
+
+    loop {
+        let (mut stream, addr) = listener.accept().await?;
+
+        // One client at a time is plenty, but a stalled one should not block the others
+        match with_timeout(REDIRECT_TIMEOUT, redirect_request(&mut stream)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => warn!("Redirecting {addr} failed: {err}"),
+            Err(_) => warn!("Redirecting {addr} timed out"),
+        }
+    }
+}
+
+async fn redirect_request(stream: &mut Async<TcpStream>) -> io::Result<()> {
+    let mut buf = [0; REDIRECT_REQUEST_MAX_LEN];
+    let mut len = 0;
+
+    // Only the request line and the `Host` header are of interest, so a truncated request is fine
+    while len < buf.len() && !buf[..len].windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf[len..]).await?;
+        if read == 0 {
+            break;
+        }
+
+        len += read;
+    }
+
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut lines = request.split("\r\n");
+
+    let path = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .filter(|path| path.starts_with('/'))
+        .unwrap_or("/");
+
+    let host = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Host"))
+        .map(|(_, host)| strip_port(host.trim()).to_string())
+        .filter(|host| !host.is_empty());
+
+    let host = match host {
+        Some(host) => host,
+        None => match stream.get_ref().local_addr()? {
+            net::SocketAddr::V4(addr) => addr.ip().to_string(),
+            net::SocketAddr::V6(addr) => format!("[{}]", addr.ip()),
+        },
+    };
+
+    let response = format!(
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: https://{host}{path}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await
+}
+
+/// Strips the port from a `Host` header value, i.e. `ruwm.local:80` or `[fe80::1]:80`
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if port.bytes().all(|c| c.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+fn tls_error(err: isize) -> io::Error {
+    io::Error::new(ErrorKind::Other, format!("ESP-TLS error {err}"))
+}
+
+fn to_nal_addr(addr: net::SocketAddr) -> SocketAddr {
+    match addr {
+        net::SocketAddr::V4(addr) => SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::from(addr.ip().octets()),
+            addr.port(),
+        )),
+        net::SocketAddr::V6(addr) => SocketAddr::V6(SocketAddrV6::new(
+            Ipv6Addr::from(addr.ip().octets()),
+            addr.port(),
+            addr.flowinfo(),
+            addr.scope_id(),
+        )),
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(async_fn_in_trait)]
 #![warn(clippy::large_futures)]
 
+#[cfg(feature = "nvs")]
+use core::fmt::Write;
 use core::pin::pin;
 use std::thread::Scope;
 
@@ -8,35 +10,48 @@ extern crate alloc;
 
 use edge_executor::LocalExecutor;
 
+use edge_frame::dto::Role;
+
 #[cfg(feature = "nvs")]
 use embassy_sync::blocking_mutex::Mutex;
-use embassy_time::Duration;
 
 #[cfg(feature = "nvs")]
 use embedded_svc::storage::Storage;
 
 use esp_idf_svc::hal::adc::attenuation;
 use esp_idf_svc::hal::gpio::*;
-use esp_idf_svc::hal::reset::WakeupReason;
 use esp_idf_svc::hal::task::block_on;
 use esp_idf_svc::hal::task::thread::ThreadSpawnConfiguration;
 
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::sys::esp;
 use esp_idf_svc::timer::EspTaskTimerService;
 use esp_idf_svc::wifi::AuthMethod;
 
+use ruwm::alert::{self, AlertConfig};
+use ruwm::away::{self, AwayConfig};
+use ruwm::battery::{self, BatteryConfig, BatteryHistory};
+use ruwm::button::{self, ButtonTimings, PressedLevel};
+use ruwm::moisture::{self, SensorConfigs};
+use ruwm::ota::{self, OtaStatus};
+use ruwm::power::{self, PowerPolicy, SleepController};
+use ruwm::pressure::{self, PressureConfig};
 use ruwm::quit;
 use ruwm::spawn;
-use ruwm::wifi;
-use ruwm::wm::WaterMeterState;
+use ruwm::temperature::{self, TemperatureConfig};
+use ruwm::time::{self, TimeZone};
+use ruwm::valve::{self, ValveConfigs, ValveProfiles};
+use ruwm::web;
+use ruwm::wifi::{self, WifiGiveUp, WifiPolicy};
+use ruwm::wm::{self, MeterConfigs, WaterMeterState, WaterMeterStates, MAX_METERS};
+use ruwm::wm_stats::CalendarStats;
 use ruwm::ws;
 
 use crate::errors::*;
-use crate::peripherals::{ButtonsPeripherals, PulseCounterPeripherals};
 
 mod errors;
+#[cfg(feature = "https")]
+mod https;
 mod peripherals;
 mod services;
 #[cfg(feature = "ulp")]
@@ -48,7 +63,27 @@ compile_error!("Feature `ulp` is supported only on esp32, esp32s2 and esp32s3");
 const SSID: &str = env!("RUWM_WIFI_SSID");
 const PASS: &str = env!("RUWM_WIFI_PASS");
 
-const SLEEP_TIME: Duration = Duration::from_secs(30);
+// Both are optional; without a manifest URL checking for updates fails,
+// and without a public key downloaded updates are refused
+const OTA_MANIFEST_URL: Option<&str> = option_env!("RUWM_OTA_MANIFEST_URL");
+const OTA_PUBLIC_KEY: Option<&str> = option_env!("RUWM_OTA_PUBLIC_KEY");
+
+// Grants admin access to the REST API; without it only HTTP basic auth is accepted
+const API_TOKEN: Option<&str> = option_env!("RUWM_API_TOKEN");
+
+// The passwords of the `admin` and `user` accounts, for the web UI and HTTP basic auth;
+// an account without a password cannot log in
+const ADMIN_PASSWORD: Option<&str> = option_env!("RUWM_ADMIN_PASSWORD");
+const USER_PASSWORD: Option<&str> = option_env!("RUWM_USER_PASSWORD");
+
+static CREDENTIALS: [web::Credentials; 2] = [
+    web::Credentials::new("admin", ADMIN_PASSWORD, Role::Admin),
+    web::Credentials::new("user", USER_PASSWORD, Role::User),
+];
+
+// A POSIX TZ string, i.e. `CET-1CEST,M3.5.0,M10.5.0/3`; can be changed later over MQTT
+const TZ: Option<&str> = option_env!("RUWM_TZ");
+
 const MQTT_MAX_TOPIC_LEN: usize = 64;
 
 // Make sure that the firmware will contain
@@ -59,11 +94,11 @@ fn main() -> Result<(), InitError> {
     esp_idf_svc::hal::task::critical_section::link();
     esp_idf_svc::timer::embassy_time_driver::link();
 
-    let wakeup_reason = WakeupReason::get();
+    let mut sleep_controller = services::sleep_controller();
 
     init()?;
 
-    log::info!("Wakeup reason: {:?}", wakeup_reason);
+    log::info!("Wakeup reason: {:?}", sleep_controller.wake_reason());
 
     // TODO: Persist the Wifi configuration in NVS, and start with an open AP,
     // or whatever configuration the user has set via the UI
@@ -80,11 +115,17 @@ fn main() -> Result<(), InitError> {
         }),
     ));
 
-    std::thread::scope(|scope| run(scope, wakeup_reason))?;
+    std::thread::scope(|scope| run(scope, &sleep_controller))?;
 
-    log::info!("Going to sleep now");
+    if matches!(ota::STATE.get().status, OtaStatus::Ready(_)) {
+        log::info!("Restarting into the updated firmware");
+
+        unsafe {
+            esp_idf_svc::sys::esp_restart();
+        }
+    }
 
-    sleep()?;
+    power::sleep(&mut sleep_controller)?;
 
     unreachable!()
 }
@@ -97,32 +138,25 @@ fn init() -> Result<(), InitError> {
     Ok(())
 }
 
-fn sleep() -> Result<(), InitError> {
-    unsafe {
-        #[cfg(feature = "ulp")]
-        esp!(esp_idf_svc::sys::esp_sleep_enable_ulp_wakeup())?;
-
-        esp!(esp_idf_svc::sys::esp_sleep_enable_timer_wakeup(
-            SLEEP_TIME.as_micros()
-        ))?;
-
-        log::info!("Going to sleep");
-
-        esp_idf_svc::sys::esp_deep_sleep_start();
-    }
-}
+fn run<'s>(
+    scope: &'s Scope<'s, '_>,
+    sleep_controller: &services::EspSleepController,
+) -> Result<(), InitError> {
+    let wake_reason = sleep_controller.wake_reason();
 
-fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(), InitError> {
     let peripherals = peripherals::SystemPeripherals::take();
 
-    // Valve pins
+    // Valve driver
 
-    let (valve_power_pin, valve_open_pin, valve_close_pin) =
-        services::valve_pins(peripherals.valve, wakeup_reason)?;
+    let valve_driver = services::valve_driver(peripherals.valve, wake_reason)?;
 
     // Deep sleep wakeup init
 
-    mark_wakeup_pins(&peripherals.pulse_counter, &peripherals.buttons)?;
+    sleep_controller.mark_wakeup_pins(
+        &peripherals.pulse_counter,
+        &peripherals.buttons,
+        peripherals.sensors.leak_probe.as_ref(),
+    )?;
 
     // ESP-IDF basics
 
@@ -133,39 +167,282 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
     // Storage
 
     #[cfg(feature = "nvs")]
-    let (wm_state, storage) = {
+    let (
+        wm_state,
+        wm_history,
+        battery_history,
+        wm_config,
+        moisture_config,
+        temperature_config,
+        pressure_config,
+        battery_config,
+        power_policy,
+        button_timings,
+        wifi_policy,
+        valve_profile,
+        valve_config,
+        alert_config,
+        away_config,
+        time_zone,
+        storage,
+    ) = {
         let storage = services::storage(nvs_default_partition.clone())?;
 
-        if let Some(wm_state) = storage
-            .lock(|storage| storage.borrow().get::<WaterMeterState>("wm-state"))
-            .unwrap()
-        {
-            (wm_state, storage)
-        } else {
-            log::warn!("No WM edge count found in NVS, assuming new device");
-
-            (Default::default(), storage)
+        let wm_history: [Option<CalendarStats>; MAX_METERS] = core::array::from_fn(|meter| {
+            storage
+                .lock(|storage| {
+                    storage
+                        .borrow()
+                        .get::<CalendarStats>(&wm_key("wm-history", meter))
+                })
+                .unwrap()
+        });
+
+        let battery_history = storage
+            .lock(|storage| storage.borrow().get::<BatteryHistory>("battery-history"))
+            .unwrap();
+
+        let wm_config = storage
+            .lock(|storage| storage.borrow().get::<MeterConfigs>("wm-config"))
+            .unwrap();
+
+        let moisture_config = storage
+            .lock(|storage| storage.borrow().get::<SensorConfigs>("moisture-config"))
+            .unwrap();
+
+        let temperature_config = storage
+            .lock(|storage| storage.borrow().get::<TemperatureConfig>("temp-config"))
+            .unwrap();
+
+        let pressure_config = storage
+            .lock(|storage| storage.borrow().get::<PressureConfig>("pressure-config"))
+            .unwrap();
+
+        let battery_config = storage
+            .lock(|storage| storage.borrow().get::<BatteryConfig>("battery-config"))
+            .unwrap();
+
+        let power_policy = storage
+            .lock(|storage| storage.borrow().get::<PowerPolicy>("power-policy"))
+            .unwrap();
+
+        let button_timings = storage
+            .lock(|storage| storage.borrow().get::<ButtonTimings>("button-timings"))
+            .unwrap();
+
+        let wifi_policy = storage
+            .lock(|storage| storage.borrow().get::<WifiPolicy>("wifi-policy"))
+            .unwrap();
+
+        // Not under the "valve-profile" key of the single valve profile, which does not deserialize
+        // as the profiles of all valves
+        let valve_profile = storage
+            .lock(|storage| storage.borrow().get::<ValveProfiles>("valve-profiles"))
+            .unwrap();
+
+        let valve_config = storage
+            .lock(|storage| storage.borrow().get::<ValveConfigs>("valve-config"))
+            .unwrap();
+
+        let alert_config = storage
+            .lock(|storage| storage.borrow().get::<AlertConfig>("alert-config"))
+            .unwrap();
+
+        let away_config = storage
+            .lock(|storage| storage.borrow().get::<AwayConfig>("away-config"))
+            .unwrap();
+
+        let time_zone = storage
+            .lock(|storage| storage.borrow().get::<TimeZone>("time-zone"))
+            .unwrap();
+
+        let mut wm_state: WaterMeterStates = Default::default();
+
+        for (meter, wm_state) in wm_state.iter_mut().enumerate() {
+            if let Some(state) = storage
+                .lock(|storage| {
+                    storage
+                        .borrow()
+                        .get::<WaterMeterState>(&wm_key("wm-state", meter))
+                })
+                .unwrap()
+            {
+                *wm_state = state;
+            } else if meter == 0 {
+                log::warn!("No WM edge count found in NVS, assuming new device");
+            }
         }
+
+        (
+            wm_state,
+            wm_history,
+            battery_history,
+            wm_config,
+            moisture_config,
+            temperature_config,
+            pressure_config,
+            battery_config,
+            power_policy,
+            button_timings,
+            wifi_policy,
+            valve_profile,
+            valve_config,
+            alert_config,
+            away_config,
+            time_zone,
+            storage,
+        )
     };
 
     #[cfg(not(feature = "nvs"))]
-    let wm_state: WaterMeterState = Default::default();
+    let (
+        wm_state,
+        wm_history,
+        battery_history,
+        wm_config,
+        moisture_config,
+        temperature_config,
+        pressure_config,
+        battery_config,
+        power_policy,
+        button_timings,
+        wifi_policy,
+        valve_profile,
+        valve_config,
+        alert_config,
+        away_config,
+        time_zone,
+    ): (
+        WaterMeterStates,
+        [Option<CalendarStats>; MAX_METERS],
+        Option<BatteryHistory>,
+        Option<MeterConfigs>,
+        Option<SensorConfigs>,
+        Option<TemperatureConfig>,
+        Option<PressureConfig>,
+        Option<BatteryConfig>,
+        Option<PowerPolicy>,
+        Option<ButtonTimings>,
+        Option<WifiPolicy>,
+        Option<ValveProfiles>,
+        Option<ValveConfigs>,
+        Option<AlertConfig>,
+        Option<AwayConfig>,
+        Option<TimeZone>,
+    ) = (
+        Default::default(),
+        Default::default(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
 
     unsafe {
         services::RTC_MEMORY.wm = wm_state;
 
+        // The RTC memory is fresh after a power loss, while the history in NVS survives it
+        for (wm_stats, wm_history) in services::RTC_MEMORY.wm_stats.iter_mut().zip(wm_history) {
+            if wm_stats.calendar.edges_count.is_none() {
+                if let Some(wm_history) = wm_history {
+                    wm_stats.calendar = wm_history;
+                }
+            }
+        }
+
+        if services::RTC_MEMORY.battery_history == BatteryHistory::new() {
+            if let Some(battery_history) = battery_history {
+                services::RTC_MEMORY.battery_history = battery_history;
+            }
+        }
+
+        if let Some(valve_profile) = valve_profile {
+            services::RTC_MEMORY.valve_profile = valve_profile;
+        }
+
         ruwm::valve::STATE.set(services::RTC_MEMORY.valve);
+        valve::PROFILE.set(services::RTC_MEMORY.valve_profile);
+
+        if let Some(valve_config) = valve_config {
+            valve::CONFIG.set(valve_config);
+        }
+        if let Some(wm_config) = wm_config {
+            wm::CONFIG.set(wm_config);
+        }
+        if let Some(moisture_config) = moisture_config {
+            moisture::CONFIG.set(moisture_config);
+        }
+        if let Some(temperature_config) = temperature_config {
+            temperature::CONFIG.set(temperature_config);
+        }
+        if let Some(pressure_config) = pressure_config {
+            pressure::CONFIG.set(pressure_config);
+        }
+        if let Some(battery_config) = battery_config {
+            battery::CONFIG.set(battery_config);
+        }
+        if let Some(power_policy) = power_policy {
+            power::POLICY.set(power_policy);
+        }
+        if let Some(button_timings) = button_timings {
+            button::TIMINGS.set(button_timings);
+        }
+        // On battery, do not keep the radio busy until the next wakeup if all known networks are
+        // unreachable, unless configured otherwise
+        wifi::POLICY.set(wifi_policy.unwrap_or(WifiPolicy {
+            give_up: Some(WifiGiveUp {
+                after_attempts: 3,
+                battery_only: true,
+                sleep_secs: None,
+            }),
+            ..WifiPolicy::new()
+        }));
         ruwm::wm::STATE.set(services::RTC_MEMORY.wm);
-        ruwm::wm_stats::STATE.set(services::RTC_MEMORY.wm_stats);
+        ruwm::wm_stats::STATE.set(services::RTC_MEMORY.wm_stats.clone());
+        time::STATE.set(services::RTC_MEMORY.time);
+        alert::STATE.set(services::RTC_MEMORY.alert);
+        battery::HISTORY.set(services::RTC_MEMORY.battery_history.clone());
+
+        if let Some(alert_config) = alert_config {
+            alert::CONFIG.set(alert_config);
+        }
+
+        away::STATE.set(services::RTC_MEMORY.away);
+
+        if let Some(away_config) = away_config {
+            away::CONFIG.set(away_config);
+        }
+
+        // The RTC memory is fresh after a power loss, while the time zone set via MQTT survives
+        // it in NVS
+        if let Some(time_zone) = services::RTC_MEMORY
+            .time_zone
+            .or(time_zone)
+            .or_else(|| TZ.and_then(|tz| tz.parse().ok()))
+        {
+            time::TIME_ZONE.set(time_zone);
+        }
     }
 
     // Pulse counter
 
+    // The channel of each meter has the index of the meter
     #[cfg(feature = "ulp")]
-    let (pulse_counter, pulse_wakeup) = services::pulse(peripherals.pulse_counter, wakeup_reason)?;
+    let ulp_pulse_counter = services::pulse(peripherals.pulse_counter, wake_reason)?;
 
     #[cfg(not(feature = "ulp"))]
-    let (pulse_counter, pulse_wakeup) = services::pulse(peripherals.pulse_counter)?;
+    let ((pulse_counter, pulse_wakeup), pulse_counter1) =
+        services::pulse(peripherals.pulse_counter)?;
 
     // High-prio tasks
 
@@ -182,13 +459,19 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
     let high_prio_execution = std::thread::Builder::new()
         .stack_size(10000)
         .spawn_scoped(scope, move || {
-            let executor = LocalExecutor::<16>::new();
+            // Shared by the battery and the analog sensors, which are all read on this thread
+            let adc = services::shared_adc(peripherals.battery.adc)?;
+
+            let executor = LocalExecutor::<24>::new();
+
+            #[cfg(feature = "ulp")]
+            let (pulse_counter, pulse_wakeup) =
+                (ulp_pulse_counter.channel(0), ulp_pulse_counter.channel(0));
 
             spawn::high_prio(
                 &executor,
-                valve_power_pin,
-                valve_open_pin,
-                valve_close_pin,
+                wake_reason,
+                valve_driver,
                 |state| unsafe {
                     services::RTC_MEMORY.valve = state;
                 },
@@ -200,10 +483,7 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
                 |state| unsafe {
                     services::RTC_MEMORY.wm_stats = state;
                 },
-                services::adc::<{ attenuation::NONE }, _, _>(
-                    peripherals.battery.adc,
-                    peripherals.battery.voltage,
-                )?,
+                services::adc::<{ attenuation::NONE }, _, _>(&adc, peripherals.battery.voltage)?,
                 PinDriver::input(peripherals.battery.power)?,
                 false,
                 services::button(peripherals.buttons.button1)?,
@@ -211,6 +491,75 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
                 services::button(peripherals.buttons.button3)?,
             );
 
+            // The main meter is spawned with the other high priority tasks
+
+            #[cfg(feature = "ulp")]
+            for meter in 1..ulp_pulse_counter.channels() {
+                spawn::wm(
+                    &executor,
+                    meter,
+                    ulp_pulse_counter.channel(meter),
+                    ulp_pulse_counter.channel(meter),
+                );
+            }
+
+            #[cfg(not(feature = "ulp"))]
+            if let Some((pulse_counter, pulse_wakeup)) = pulse_counter1 {
+                spawn::wm(&executor, 1, pulse_counter, pulse_wakeup);
+            }
+
+            // Moisture sensors
+
+            if let Some(leak_probe) = peripherals.sensors.leak_probe {
+                spawn::moisture_digital(
+                    &executor,
+                    0,
+                    PinDriver::input(leak_probe)?,
+                    PressedLevel::Low,
+                );
+            }
+
+            if let Some(moisture) = peripherals.sensors.moisture {
+                spawn::moisture_analog(
+                    &executor,
+                    1,
+                    services::adc::<{ attenuation::DB_11 }, _, _>(&adc, moisture)?,
+                );
+            }
+
+            // Temperature
+
+            if let Some(temperature) = peripherals.sensors.temperature {
+                spawn::temperature(
+                    &executor,
+                    services::thermistor(services::adc::<{ attenuation::DB_11 }, _, _>(
+                        &adc,
+                        temperature,
+                    )?),
+                );
+            }
+
+            // Pressure
+
+            if let Some(pressure) = peripherals.sensors.pressure {
+                spawn::pressure(
+                    &executor,
+                    services::adc::<{ attenuation::DB_11 }, _, _>(&adc, pressure)?,
+                );
+            }
+
+            // Only the main valve has a shunt; the valve ignores it until its profile has
+            // current limits
+            if let Some(current_sense) = peripherals.sensors.current_sense {
+                spawn::valve_current_sense(
+                    &executor,
+                    0,
+                    services::adc::<{ attenuation::NONE }, _, _>(&adc, current_sense)?,
+                );
+            }
+
+            ota::started();
+
             block_on(executor.run(quit::QUIT[0].wait()));
 
             Ok(())
@@ -233,7 +582,9 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
     let mid_prio_execution = std::thread::Builder::new()
         .stack_size(60000)
         .spawn_scoped(scope, move || {
-            let executor = LocalExecutor::<8>::new();
+            let ota_public_key = OTA_PUBLIC_KEY.and_then(ota::decode_hex::<32>);
+
+            let executor = LocalExecutor::<16>::new();
 
             // Wifi
 
@@ -246,6 +597,22 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
 
             spawn::wifi(&executor, &mut wifi);
 
+            // Time
+
+            spawn::time(
+                &executor,
+                services::rtc(),
+                services::sntp(),
+                |state, time_zone| unsafe {
+                    services::RTC_MEMORY.time = state;
+                    services::RTC_MEMORY.time_zone = Some(time_zone);
+                },
+                move |_time_zone| {
+                    #[cfg(feature = "nvs")]
+                    flash_time_zone(storage, _time_zone);
+                },
+            );
+
             // Mqtt
 
             let (mqtt_topic_prefix, mut mqtt_client, mut mqtt_conn) = services::mqtt()?;
@@ -260,17 +627,53 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
 
             // Httpd
 
+            #[cfg(feature = "https")]
+            let (certificate_store, certificate) = {
+                let certificate_store =
+                    https::CertificateStore::new(nvs_default_partition.clone())?;
+                let certificate = certificate_store.load_or_generate()?;
+
+                (certificate_store, certificate)
+            };
+
+            // Shared by the updates from the manifest URL and the ones uploaded from the web UI
+            let ota = ota::SharedOta::new(services::ota());
+
+            web::set_credentials(&CREDENTIALS);
+
             let mut httpd = services::httpd()?;
-            let handler = services::httpd_handler()?;
+            let handler = services::httpd_handler(API_TOKEN, wake_reason, &ota)?;
 
+            #[cfg(not(feature = "https"))]
             let httpd = pin!(services::run_httpd(&mut httpd, &handler));
 
+            #[cfg(feature = "https")]
+            let handler = handler.with_certificate_store(&certificate_store);
+
+            #[cfg(feature = "https")]
+            let httpd = pin!(services::run_httpd(&mut httpd, &handler, certificate));
+
             executor.spawn(httpd).detach();
 
+            #[cfg(feature = "https")]
+            executor.spawn(https::redirect(https::HTTP_PORT)).detach();
+
             // WS
 
             executor.spawn(ws::broadcast()).detach();
 
+            // OTA
+
+            spawn::ota(
+                &executor,
+                &ota,
+                services::http_client()?,
+                OTA_MANIFEST_URL,
+                ota_public_key.as_ref(),
+            );
+
+            ota::started();
+
             block_on(executor.run(quit::QUIT[1].wait()));
 
             Ok(())
@@ -292,15 +695,114 @@ fn run<'s>(scope: &'s Scope<'s, '_>, wakeup_reason: WakeupReason) -> Result<(),
     let low_prio_execution = std::thread::Builder::new()
         .stack_size(10000)
         .spawn_scoped(scope, move || {
-            let executor = LocalExecutor::<4>::new();
+            let executor = LocalExecutor::<16>::new();
 
             let mut display = services::display(display_peripherals)?;
 
-            spawn::low_prio(&executor, &mut display, move |_new_state| {
+            spawn::low_prio(
+                &executor,
+                &mut display,
+                move |_meter, _new_state| {
+                    #[cfg(feature = "nvs")]
+                    flash_wm_state(storage, _meter, _new_state);
+                },
+                move |_meter, _history| {
+                    #[cfg(feature = "nvs")]
+                    flash_wm_history(storage, _meter, _history);
+                },
+            );
+
+            spawn::valve_profile(
+                &executor,
+                |profile| unsafe {
+                    services::RTC_MEMORY.valve_profile = profile;
+                },
+                move |_profile| {
+                    #[cfg(feature = "nvs")]
+                    flash_valve_profile(storage, _profile);
+                },
+            );
+
+            spawn::valve_config(&executor, move |_config| {
+                #[cfg(feature = "nvs")]
+                flash_valve_config(storage, _config);
+            });
+
+            spawn::wm_config(&executor, move |_config| {
+                #[cfg(feature = "nvs")]
+                flash_wm_config(storage, _config);
+            });
+
+            spawn::moisture_config(&executor, move |_config| {
+                #[cfg(feature = "nvs")]
+                flash_moisture_config(storage, _config);
+            });
+
+            spawn::temperature_config(&executor, move |_config| {
                 #[cfg(feature = "nvs")]
-                flash_wm_state(storage, _new_state);
+                flash_temperature_config(storage, _config);
             });
 
+            spawn::pressure_config(&executor, move |_config| {
+                #[cfg(feature = "nvs")]
+                flash_pressure_config(storage, _config);
+            });
+
+            spawn::battery_config(&executor, move |_config| {
+                #[cfg(feature = "nvs")]
+                flash_battery_config(storage, _config);
+            });
+
+            spawn::power_policy(&executor, move |_policy| {
+                #[cfg(feature = "nvs")]
+                flash_power_policy(storage, _policy);
+            });
+
+            spawn::button_timings(&executor, move |_timings| {
+                #[cfg(feature = "nvs")]
+                flash_button_timings(storage, _timings);
+            });
+
+            spawn::wifi_policy(&executor, move |_policy| {
+                #[cfg(feature = "nvs")]
+                flash_wifi_policy(storage, _policy);
+            });
+
+            spawn::battery_history(
+                &executor,
+                |history| unsafe {
+                    services::RTC_MEMORY.battery_history = history;
+                },
+                move |_history| {
+                    #[cfg(feature = "nvs")]
+                    flash_battery_history(storage, _history);
+                },
+            );
+
+            spawn::alert(
+                &executor,
+                |state| unsafe {
+                    services::RTC_MEMORY.alert = state;
+                },
+                move |_config| {
+                    #[cfg(feature = "nvs")]
+                    flash_alert_config(storage, _config);
+                },
+            );
+
+            spawn::away(
+                &executor,
+                |state| unsafe {
+                    services::RTC_MEMORY.away = state;
+                },
+                move |_config| {
+                    #[cfg(feature = "nvs")]
+                    flash_away_config(storage, _config);
+                },
+            );
+
+            ota::started();
+
             block_on(executor.run(quit::QUIT[2].wait()));
 
             Ok(())
@@ -330,50 +832,232 @@ fn flash_wm_state<S>(
         impl embassy_sync::blocking_mutex::raw::RawMutex,
         core::cell::RefCell<S>,
     >,
+    meter: usize,
     new_state: WaterMeterState,
 ) where
     S: Storage,
 {
+    let key = wm_key("wm-state", meter);
+
     ruwm::log_err!(storage.lock(|storage| {
-        let old_state = storage.borrow().get("wm-state")?;
+        let old_state = storage.borrow().get(&key)?;
         if old_state != Some(new_state) {
-            storage.borrow_mut().set("wm-state", &new_state)?;
+            storage.borrow_mut().set(&key, &new_state)?;
         }
 
         Ok::<_, S::Error>(())
     }));
 }
 
-fn mark_wakeup_pins(
-    pulse_counter_peripherals: &PulseCounterPeripherals<impl RTCPin + InputPin>,
-    buttons_peripherals: &ButtonsPeripherals<
-        impl RTCPin + InputPin,
-        impl RTCPin + InputPin,
-        impl RTCPin + InputPin,
+#[cfg(feature = "nvs")]
+fn flash_wm_history<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
     >,
-) -> Result<(), InitError> {
-    unsafe {
-        let mut mask = (1 << buttons_peripherals.button1.pin())
-            | (1 << buttons_peripherals.button2.pin())
-            | (1 << buttons_peripherals.button3.pin());
+    meter: usize,
+    history: CalendarStats,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage
+        .borrow_mut()
+        .set(&wm_key("wm-history", meter), &history)));
+}
 
-        #[cfg(not(feature = "ulp"))]
-        {
-            mask |= 1 << pulse_counter_peripherals.pulse.pin();
-        }
+#[cfg(feature = "nvs")]
+fn flash_wm_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: MeterConfigs,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("wm-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_moisture_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: SensorConfigs,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("moisture-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_temperature_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: TemperatureConfig,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("temp-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_pressure_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: PressureConfig,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("pressure-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_battery_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: BatteryConfig,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("battery-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_power_policy<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    policy: PowerPolicy,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("power-policy", &policy)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_button_timings<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    timings: ButtonTimings,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("button-timings", &timings)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_wifi_policy<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    policy: WifiPolicy,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("wifi-policy", &policy)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_battery_history<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    history: BatteryHistory,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("battery-history", &history)));
+}
+
+/// The NVS key of the state of a meter; the main meter keeps the key it had before
+/// there were more meters
+#[cfg(feature = "nvs")]
+fn wm_key(prefix: &str, meter: usize) -> heapless::String<16> {
+    let mut key = heapless::String::new();
+
+    key.push_str(prefix).unwrap();
 
-        #[cfg(any(esp32, esp32s2, esp32s3))]
-        esp!(esp_idf_svc::sys::esp_sleep_enable_ext1_wakeup(
-            mask,
-            esp_idf_svc::sys::esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ALL_LOW,
-        ))?;
-
-        #[cfg(not(any(esp32, esp32s2, esp32s3)))]
-        esp!(esp_idf_svc::sys::esp_deep_sleep_enable_gpio_wakeup(
-            mask,
-            esp_idf_svc::sys::esp_deepsleep_gpio_wake_up_mode_t_ESP_GPIO_WAKEUP_GPIO_LOW,
-        ))?;
+    if meter > 0 {
+        write!(&mut key, "-{}", meter).unwrap();
     }
 
-    Ok(())
+    key
+}
+
+#[cfg(feature = "nvs")]
+fn flash_valve_profile<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    profile: ValveProfiles,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("valve-profiles", &profile)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_valve_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: ValveConfigs,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("valve-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_time_zone<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    time_zone: TimeZone,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("time-zone", &time_zone)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_alert_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: AlertConfig,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("alert-config", &config)));
+}
+
+#[cfg(feature = "nvs")]
+fn flash_away_config<S>(
+    storage: &'static Mutex<
+        impl embassy_sync::blocking_mutex::raw::RawMutex,
+        core::cell::RefCell<S>,
+    >,
+    config: AwayConfig,
+) where
+    S: Storage,
+{
+    ruwm::log_err!(storage.lock(|storage| storage.borrow_mut().set("away-config", &config)));
 }
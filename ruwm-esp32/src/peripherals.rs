@@ -4,23 +4,40 @@ use esp_idf_svc::hal::modem::Modem;
 use esp_idf_svc::hal::peripherals::Peripherals;
 use esp_idf_svc::hal::spi::*;
 
-pub struct SystemPeripherals<P, ADC, V, B1, B2, B3, SPI> {
-    pub pulse_counter: PulseCounterPeripherals<P>,
+pub struct SystemPeripherals<P, P1, ADC, V, CS, M, T, PR, B1, B2, B3, SPI> {
+    pub pulse_counter: PulseCounterPeripherals<P, P1>,
     pub valve: ValvePeripherals,
     pub battery: BatteryPeripherals<ADC, V>,
+    pub sensors: SensorPeripherals<CS, M, T, PR>,
     pub buttons: ButtonsPeripherals<B1, B2, B3>,
     pub display: DisplaySpiPeripherals<SPI>,
     pub modem: Modem,
 }
 
 #[cfg(esp32)]
-impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
+impl
+    SystemPeripherals<
+        Gpio33,
+        Gpio15,
+        ADC1,
+        Gpio36,
+        Gpio34,
+        Gpio38,
+        Gpio37,
+        Gpio39,
+        Gpio2,
+        Gpio4,
+        Gpio32,
+        SPI2,
+    >
+{
     pub fn take() -> Self {
         let peripherals = Peripherals::take().unwrap();
 
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio33,
+                pulse1: Some(peripherals.pins.gpio15),
                 #[cfg(feature = "ulp")]
                 ulp: peripherals.ulp,
             },
@@ -30,10 +47,18 @@ impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
                 close: peripherals.pins.gpio27.into(),
             },
             battery: BatteryPeripherals {
-                power: peripherals.pins.gpio35.into(),
+                // Not on GPIO35, which wakes up the device on a leak
+                power: peripherals.pins.gpio21.into(),
                 voltage: peripherals.pins.gpio36,
                 adc: peripherals.adc1,
             },
+            sensors: SensorPeripherals {
+                current_sense: Some(peripherals.pins.gpio34),
+                leak_probe: Some(peripherals.pins.gpio35.into()),
+                moisture: Some(peripherals.pins.gpio38),
+                temperature: Some(peripherals.pins.gpio37),
+                pressure: Some(peripherals.pins.gpio39),
+            },
             buttons: ButtonsPeripherals {
                 button1: peripherals.pins.gpio2,
                 button2: peripherals.pins.gpio4,
@@ -41,7 +66,8 @@ impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
             },
             display: DisplaySpiPeripherals {
                 control: DisplayControlPeripherals {
-                    backlight: Some(peripherals.pins.gpio15.into()),
+                    // Not on GPIO15, which the ULP needs for the pulses of the second meter
+                    backlight: Some(peripherals.pins.gpio22.into()),
                     dc: peripherals.pins.gpio18.into(),
                     rst: peripherals.pins.gpio19.into(),
                 },
@@ -56,26 +82,52 @@ impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
 }
 
 #[cfg(any(esp32s2, esp32s3))]
-impl SystemPeripherals<Gpio1, ADC1, Gpio9, Gpio2, Gpio4, Gpio12, SPI2> {
+impl
+    SystemPeripherals<
+        Gpio1,
+        Gpio11,
+        ADC1,
+        Gpio9,
+        Gpio10,
+        Gpio8,
+        Gpio3,
+        Gpio6,
+        Gpio2,
+        Gpio4,
+        Gpio12,
+        SPI2,
+    >
+{
     pub fn take() -> Self {
         let peripherals = Peripherals::take().unwrap();
 
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio1,
+                pulse1: Some(peripherals.pins.gpio11),
                 #[cfg(feature = "ulp")]
                 ulp: peripherals.ulp,
             },
             valve: ValvePeripherals {
-                power: peripherals.pins.gpio3.into(),
-                open: peripherals.pins.gpio6.into(),
+                // Not on GPIO3 and GPIO6, which are on the ADC and taken by the thermistor and
+                // the pressure transducer
+                power: peripherals.pins.gpio17.into(),
+                open: peripherals.pins.gpio20.into(),
                 close: peripherals.pins.gpio7.into(),
             },
             battery: BatteryPeripherals {
-                power: peripherals.pins.gpio8.into(),
+                // Not on GPIO8, which is the last pin of the ADC left for the moisture sensor
+                power: peripherals.pins.gpio21.into(),
                 voltage: peripherals.pins.gpio9,
                 adc: peripherals.adc1,
             },
+            sensors: SensorPeripherals {
+                current_sense: Some(peripherals.pins.gpio10),
+                leak_probe: Some(peripherals.pins.gpio16.into()),
+                moisture: Some(peripherals.pins.gpio8),
+                temperature: Some(peripherals.pins.gpio3),
+                pressure: Some(peripherals.pins.gpio6),
+            },
             buttons: ButtonsPeripherals {
                 button1: peripherals.pins.gpio2,
                 button2: peripherals.pins.gpio4,
@@ -98,13 +150,29 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio9, Gpio2, Gpio4, Gpio12, SPI2> {
 }
 
 #[cfg(not(any(esp32, esp32s2, esp32s3)))]
-impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
+impl
+    SystemPeripherals<
+        Gpio1,
+        Gpio1,
+        ADC1,
+        Gpio0,
+        Gpio0,
+        Gpio0,
+        Gpio0,
+        Gpio0,
+        Gpio2,
+        Gpio3,
+        Gpio4,
+        SPI2,
+    >
+{
     pub fn take() -> Self {
         let peripherals = Peripherals::take().unwrap();
 
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio1,
+                pulse1: None,
             },
             valve: ValvePeripherals {
                 power: peripherals.pins.gpio6.into(),
@@ -116,6 +184,14 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
                 voltage: peripherals.pins.gpio0,
                 adc: peripherals.adc1,
             },
+            // All pins of the ADC, and all pins which can wake up the device, are taken
+            sensors: SensorPeripherals {
+                current_sense: None,
+                leak_probe: None,
+                moisture: None,
+                temperature: None,
+                pressure: None,
+            },
             buttons: ButtonsPeripherals {
                 button1: peripherals.pins.gpio2,
                 button2: peripherals.pins.gpio3,
@@ -137,8 +213,11 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
     }
 }
 
-pub struct PulseCounterPeripherals<P> {
+pub struct PulseCounterPeripherals<P, P1> {
+    /// The pulses of the main meter
     pub pulse: P,
+    /// The pulses of the second meter, if the board has a pin left for them
+    pub pulse1: Option<P1>,
     #[cfg(feature = "ulp")]
     pub ulp: esp_idf_svc::hal::ulp::ULP,
 }
@@ -155,6 +234,21 @@ pub struct BatteryPeripherals<ADC, V> {
     pub adc: ADC,
 }
 
+/// The optional sensors; the analog ones share the ADC of the battery
+pub struct SensorPeripherals<CS, M, T, PR> {
+    /// The shunt of the valve motor
+    pub current_sense: Option<CS>,
+    /// A digital moisture probe, which pulls its pin low when wet against an external pull-up,
+    /// and wakes up the device
+    pub leak_probe: Option<AnyInputPin>,
+    /// An analog moisture sensor
+    pub moisture: Option<M>,
+    /// An NTC thermistor, as read by `services::thermistor`
+    pub temperature: Option<T>,
+    /// A pressure transducer, behind a divider bringing its output into the range of the ADC
+    pub pressure: Option<PR>,
+}
+
 pub struct ButtonsPeripherals<B1, B2, B3> {
     pub button1: B1,
     pub button2: B2,
@@ -1,6 +1,7 @@
+use core::ffi::{c_char, CStr};
 use core::fmt::Debug;
-use core::mem;
-use std::cell::UnsafeCell;
+use core::{mem, ptr};
+use std::cell::{RefCell, UnsafeCell};
 use std::mem::MaybeUninit;
 
 extern crate alloc;
@@ -9,6 +10,7 @@ use channel_bridge::asynch::ws::{WsError, DEFAULT_BUF_SIZE};
 
 use edge_frame::assets::serve::AssetMetadata;
 use edge_frame::assets::{self, serve::Asset};
+use edge_frame::dto::Role;
 use edge_http::io::{self, server::Server};
 use edge_http::ws::MAX_BASE64_KEY_RESPONSE_LEN;
 use edge_http::{Method, DEFAULT_MAX_HEADERS_COUNT};
@@ -20,9 +22,8 @@ use embassy_time::Duration;
 use embedded_nal_async::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use embedded_nal_async_xtra::{TcpListen, TcpSplittableConnection};
 
-use embedded_hal::digital::OutputPin as EHOutputPin;
-
 use embedded_io_async::{Read, Write};
+use embedded_svc::http::client::asynch::TrivialUnblockingConnection;
 use embedded_svc::http::server::asynch::Request;
 use embedded_svc::mqtt::client::asynch::{Client, Connection, Publish};
 use embedded_svc::wifi::asynch::Wifi;
@@ -37,43 +38,79 @@ use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::hal::prelude::*;
 use esp_idf_svc::hal::reset::WakeupReason;
 use esp_idf_svc::hal::spi::*;
+use esp_idf_svc::http::client::{Configuration as HttpClientConfiguration, EspHttpConnection};
 
 use esp_idf_svc::mqtt::client::{EspAsyncMqttClient, MqttClientConfiguration};
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sntp::{EspSntp, SyncStatus};
 use esp_idf_svc::timer::EspTaskTimerService;
 use esp_idf_svc::wifi::{AsyncWifi, EspWifi};
 
-use esp_idf_svc::sys::{adc_atten_t, EspError};
+use esp_idf_svc::sys::{self, adc_atten_t, esp, EspError};
 
 use gfx_xtra::draw_target::{Flushable, OwnedDrawTargetExt};
 
+use ruwm::alert::AlertState;
+use ruwm::away::AwayState;
+use ruwm::battery::BatteryHistory;
 use ruwm::button::PressedLevel;
+use ruwm::metrics::SystemMetrics;
+use ruwm::ota::{FirmwareVersion, Ota, OtaError, SharedOta};
+use ruwm::power::{SleepController, WakeReason};
 use ruwm::pulse_counter::PulseCounter;
 use ruwm::pulse_counter::PulseWakeup;
 use ruwm::screen::Color;
-use ruwm::valve::{self, ValveState};
-use ruwm::wm::WaterMeterState;
-use ruwm::wm_stats::WaterMeterStatsState;
+use ruwm::temperature::TemperatureSensor;
+use ruwm::time::{Rtc, Sntp, TimeState, TimeZone};
+use ruwm::valve::{
+    self, ValveCommand, ValveDriver, ValveProfile, ValveProfiles, ValveState, ValveStates,
+    MAX_VALVES,
+};
+use ruwm::wm::{WaterMeterState, WaterMeterStates, MAX_METERS};
+use ruwm::wm_stats::{WaterMeterStatsState, WaterMeterStatsStates};
 use ruwm::ws::{WS_MAX_CONNECTIONS, WS_MAX_FRAME_LEN};
 
 use crate::errors::*;
-use crate::peripherals::{DisplaySpiPeripherals, PulseCounterPeripherals, ValvePeripherals};
+#[cfg(feature = "https")]
+use crate::https;
+use crate::peripherals::{
+    ButtonsPeripherals, DisplaySpiPeripherals, PulseCounterPeripherals, ValvePeripherals,
+};
+#[cfg(feature = "ulp")]
+use crate::ulp_pulse_counter;
 
 const ASSETS: assets::serve::Assets = edge_frame::assets!("RUWM_WEB");
 
 #[derive(Default)]
 pub struct RtcMemory {
-    pub valve: Option<ValveState>,
-    pub wm: WaterMeterState,
-    pub wm_stats: WaterMeterStatsState,
+    pub valve: ValveStates,
+    pub valve_profile: ValveProfiles,
+    pub wm: WaterMeterStates,
+    pub wm_stats: WaterMeterStatsStates,
+    pub time: TimeState,
+    pub time_zone: Option<TimeZone>,
+    pub alert: AlertState,
+    pub away: AwayState,
+    pub battery_history: BatteryHistory,
+    /// The pins of the leak probes, which tell a leak from a button press on a GPIO wakeup
+    pub leak_probe_mask: u64,
 }
 
 impl RtcMemory {
+    const WM_STATS: WaterMeterStatsState = WaterMeterStatsState::new();
+
     pub const fn new() -> Self {
         Self {
-            valve: None,
-            wm: WaterMeterState::new(),
-            wm_stats: WaterMeterStatsState::new(),
+            valve: [None; MAX_VALVES],
+            valve_profile: [ValveProfile::DEFAULT; MAX_VALVES],
+            wm: [WaterMeterState::new(); MAX_METERS],
+            wm_stats: [Self::WM_STATS; MAX_METERS],
+            time: TimeState::new(),
+            time_zone: None,
+            alert: AlertState::new(),
+            away: AwayState::new(),
+            battery_history: BatteryHistory::new(),
+            leak_probe_mask: 0,
         }
     }
 }
@@ -81,24 +118,232 @@ impl RtcMemory {
 #[cfg_attr(feature = "rtc-mem", link_section = ".rtc.data.rtc_memory")]
 pub static mut RTC_MEMORY: RtcMemory = RtcMemory::new();
 
-pub fn valve_pins(
+/// Wakes up from the deep sleep on the timer, the ULP and the pins marked with
+/// `mark_wakeup_pins`
+pub struct EspSleepController {
+    wake_reason: WakeReason,
+}
+
+impl EspSleepController {
+    /// The buttons, the leak probe, and without the ULP the pulse pins, wake up the device by
+    /// pulling their pin low
+    ///
+    /// Where the other pins share ext1, the leak probe wakes up on its own with ext0, as ext1 on
+    /// the ESP32 only wakes up once all of its pins are low.
+    pub fn mark_wakeup_pins(
+        &self,
+        pulse_counter_peripherals: &PulseCounterPeripherals<
+            impl RTCPin + InputPin,
+            impl RTCPin + InputPin,
+        >,
+        buttons_peripherals: &ButtonsPeripherals<
+            impl RTCPin + InputPin,
+            impl RTCPin + InputPin,
+            impl RTCPin + InputPin,
+        >,
+        leak_probe: Option<&AnyInputPin>,
+    ) -> Result<(), EspError> {
+        unsafe {
+            let mut mask = (1 << buttons_peripherals.button1.pin())
+                | (1 << buttons_peripherals.button2.pin())
+                | (1 << buttons_peripherals.button3.pin());
+
+            #[cfg(any(esp32, esp32s2, esp32s3))]
+            if let Some(leak_probe) = leak_probe {
+                esp!(sys::esp_sleep_enable_ext0_wakeup(leak_probe.pin(), 0))?;
+            }
+
+            #[cfg(not(any(esp32, esp32s2, esp32s3)))]
+            {
+                let leak_probe_mask = leak_probe.map(|pin| 1 << pin.pin()).unwrap_or(0);
+
+                // The mask is read back by `sleep_controller` on the next wakeup
+                RTC_MEMORY.leak_probe_mask = leak_probe_mask;
+
+                mask |= leak_probe_mask;
+            }
+
+            #[cfg(not(feature = "ulp"))]
+            {
+                mask |= 1 << pulse_counter_peripherals.pulse.pin();
+
+                if let Some(pulse1) = &pulse_counter_peripherals.pulse1 {
+                    mask |= 1 << pulse1.pin();
+                }
+            }
+
+            #[cfg(any(esp32, esp32s2, esp32s3))]
+            esp!(sys::esp_sleep_enable_ext1_wakeup(
+                mask,
+                sys::esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ALL_LOW,
+            ))?;
+
+            #[cfg(not(any(esp32, esp32s2, esp32s3)))]
+            esp!(sys::esp_deep_sleep_enable_gpio_wakeup(
+                mask,
+                sys::esp_deepsleep_gpio_wake_up_mode_t_ESP_GPIO_WAKEUP_GPIO_LOW,
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SleepController for EspSleepController {
+    type Error = EspError;
+
+    fn wake_reason(&self) -> WakeReason {
+        self.wake_reason
+    }
+
+    fn sleep(&mut self, duration: Duration) -> Result<(), Self::Error> {
+        unsafe {
+            #[cfg(feature = "ulp")]
+            esp!(sys::esp_sleep_enable_ulp_wakeup())?;
+
+            esp!(sys::esp_sleep_enable_timer_wakeup(duration.as_micros()))?;
+
+            // Keeps the valve pins held by `HoldingValveDriver` through the deep sleep
+            #[cfg(any(esp32, esp32s2, esp32s3, esp32c3))]
+            sys::gpio_deep_sleep_hold_en();
+
+            sys::esp_deep_sleep_start();
+        }
+    }
+}
+
+/// Has to be called first thing, before anything else can clear the wakeup cause
+pub fn sleep_controller() -> EspSleepController {
+    let wake_reason = match WakeupReason::get() {
+        _ if leak_probe_woke() => WakeReason::Leak,
+        WakeupReason::Button | WakeupReason::GPIO => WakeReason::Button,
+        WakeupReason::ULP => WakeReason::Leak,
+        WakeupReason::Timer => WakeReason::Timer,
+        WakeupReason::Unknown => WakeReason::Reset,
+        _ => WakeReason::Other,
+    };
+
+    EspSleepController { wake_reason }
+}
+
+/// Whether the pin of a leak probe, rather than a button, woke up the device
+#[cfg(any(esp32, esp32s2, esp32s3))]
+fn leak_probe_woke() -> bool {
+    // The leak probe is the only ext0 wakeup source
+    unsafe { sys::esp_sleep_get_wakeup_cause() == sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0 }
+}
+
+/// Whether the pin of a leak probe, rather than a button, woke up the device
+#[cfg(not(any(esp32, esp32s2, esp32s3)))]
+fn leak_probe_woke() -> bool {
+    unsafe {
+        sys::esp_sleep_get_wakeup_cause() == sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO
+            && sys::esp_sleep_get_gpio_wakeup_status() & RTC_MEMORY.leak_probe_mask != 0
+    }
+}
+
+/// Holds the valve pins while the valve is at rest, so that they keep their level through the
+/// deep sleep and the restart after it, instead of floating and then starting low
+pub struct HoldingValveDriver<D> {
+    driver: D,
+    pins: [i32; 3],
+}
+
+impl<D> HoldingValveDriver<D> {
+    fn hold(&self, hold: bool) {
+        for pin in self.pins {
+            unsafe {
+                if hold {
+                    sys::gpio_hold_en(pin);
+                } else {
+                    sys::gpio_hold_dis(pin);
+                }
+            }
+        }
+    }
+}
+
+impl<D> ValveDriver for HoldingValveDriver<D>
+where
+    D: ValveDriver,
+{
+    type Error = D::Error;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        if command.is_some() {
+            self.hold(false);
+        }
+
+        self.driver.drive(command)?;
+
+        if command.is_none() {
+            self.hold(true);
+        }
+
+        Ok(())
+    }
+
+    fn pulse(&self) -> Option<Duration> {
+        self.driver.pulse()
+    }
+
+    fn restore(&mut self, state: Option<ValveState>) -> Result<(), Self::Error> {
+        self.driver.restore(state)?;
+
+        // The pins are still held from before the restart, so release them only once restored
+        self.hold(false);
+        self.hold(true);
+
+        Ok(())
+    }
+}
+
+pub fn valve_driver(
     peripherals: ValvePeripherals,
-    wakeup_reason: WakeupReason,
-) -> Result<(impl EHOutputPin, impl EHOutputPin, impl EHOutputPin), EspError> {
-    let mut power = PinDriver::output(peripherals.power)?;
-    let mut open = PinDriver::output(peripherals.open)?;
-    let mut close = PinDriver::output(peripherals.close)?;
+    wake_reason: WakeReason,
+) -> Result<impl ValveDriver, EspError> {
+    let pins = [
+        peripherals.power.pin(),
+        peripherals.open.pin(),
+        peripherals.close.pin(),
+    ];
+
+    #[cfg(not(any(
+        feature = "valve-solenoid",
+        feature = "valve-latching",
+        feature = "valve-single-line"
+    )))]
+    let mut driver = valve::HBridgeDriver::new(
+        PinDriver::output(peripherals.power)?,
+        PinDriver::output(peripherals.open)?,
+        PinDriver::output(peripherals.close)?,
+    );
 
-    // TODO: Do we need this?
-    // power.set_pull(Pull::Floating)?;
-    // open.set_pull(Pull::Floating)?;
-    // close.set_pull(Pull::Floating)?;
+    // The coil of a normally-open solenoid closes the valve, so it is on the close line
+    #[cfg(feature = "valve-solenoid")]
+    let mut driver = valve::SolenoidDriver::new(PinDriver::output(peripherals.close)?);
+
+    // The travel time of the profile is only for tracking the position, as the coils are pulsed
+    #[cfg(feature = "valve-latching")]
+    let mut driver = valve::LatchingSolenoidDriver::new(
+        PinDriver::output(peripherals.open)?,
+        PinDriver::output(peripherals.close)?,
+        Duration::from_millis(50),
+    );
 
-    if wakeup_reason == WakeupReason::ULP {
-        valve::emergency_close(&mut power, &mut open, &mut close, &mut FreeRtos);
+    #[cfg(feature = "valve-single-line")]
+    let mut driver = valve::SingleLineDriver::new(PinDriver::output(peripherals.open)?);
+
+    let mut driver = HoldingValveDriver { driver, pins };
+
+    if wake_reason == WakeReason::Leak {
+        // NVS is not available yet, so this is the profile copied to the RTC memory before sleeping
+        let profile = unsafe { RTC_MEMORY.valve_profile[0] };
+
+        valve::emergency_close(&mut driver, &profile, &mut FreeRtos);
     }
 
-    Ok((power, open, close))
+    Ok(driver)
 }
 
 #[cfg(feature = "nvs")]
@@ -111,7 +356,7 @@ pub fn storage(
     >,
     InitError,
 > {
-    const POSTCARD_BUF_SIZE: usize = 500;
+    const POSTCARD_BUF_SIZE: usize = 1024;
 
     struct PostcardSerDe;
 
@@ -156,33 +401,53 @@ pub fn storage(
     Ok(storage)
 }
 
+/// The pulse counters of the main meter and of the second one, if the board has a pin for it
 #[cfg(not(feature = "ulp"))]
+#[allow(clippy::type_complexity)]
 pub fn pulse(
-    peripherals: PulseCounterPeripherals<impl InputPin>,
-) -> Result<(impl PulseCounter, impl PulseWakeup), InitError> {
-    let pulse_counter = ruwm::pulse_counter::CpuPulseCounter::new(
-        PinDriver::input(peripherals.pulse)?,
-        PressedLevel::Low,
-        Some(Duration::from_millis(50)),
-    );
+    peripherals: PulseCounterPeripherals<impl InputPin, impl InputPin>,
+) -> Result<
+    (
+        (impl PulseCounter, impl PulseWakeup),
+        Option<(impl PulseCounter, impl PulseWakeup)>,
+    ),
+    InitError,
+> {
+    fn cpu_pulse_counter(
+        pin: impl InputPin,
+    ) -> Result<(impl PulseCounter, impl PulseWakeup), InitError> {
+        let pulse_counter = ruwm::pulse_counter::CpuPulseCounter::new(
+            PinDriver::input(pin)?,
+            PressedLevel::Low,
+            Some(Duration::from_millis(50)),
+        );
+
+        Ok((pulse_counter, ()))
+    }
 
-    Ok((pulse_counter, ()))
+    Ok((
+        cpu_pulse_counter(peripherals.pulse)?,
+        peripherals.pulse1.map(cpu_pulse_counter).transpose()?,
+    ))
 }
 
+/// The ULP pulse counter, with a channel per meter, starting with the main one
 #[cfg(feature = "ulp")]
 pub fn pulse(
-    peripherals: PulseCounterPeripherals<impl RTCPin>,
-    wakeup_reason: WakeupReason,
-) -> Result<(impl PulseCounter, impl PulseWakeup), InitError> {
+    peripherals: PulseCounterPeripherals<impl RTCPin, impl RTCPin>,
+    wake_reason: WakeReason,
+) -> Result<ulp_pulse_counter::UlpPulseCounter<'static>, InitError> {
     let mut pulse_counter = ulp_pulse_counter::UlpPulseCounter::new(
-        esp_idf_svc::hal::ulp::UlpDriver::new(ulp)?,
+        esp_idf_svc::hal::ulp::UlpDriver::new(peripherals.ulp)?,
         peripherals.pulse,
-        wakeup_reason == WakeupReason::Unknown,
+        wake_reason == WakeReason::Reset,
     )?;
 
-    //let (pulse_counter, pulse_wakeup) = pulse_counter.split();
+    if let Some(pulse1) = peripherals.pulse1 {
+        pulse_counter.add_channel(pulse1)?;
+    }
 
-    Ok((pulse_counter, ()))
+    Ok(pulse_counter)
 }
 
 pub fn button<'d, P: InputPin>(
@@ -192,20 +457,32 @@ pub fn button<'d, P: InputPin>(
     Ok(PinDriver::input(pin)?)
 }
 
-pub fn adc<'d, const A: adc_atten_t, ADC: Adc + 'd, P: ADCPin<Adc = ADC>>(
+/// The driver of an ADC, shared by the channels read on the same thread
+pub type SharedAdc<'d, ADC> = RefCell<AdcDriver<'d, ADC>>;
+
+pub fn shared_adc<'d, ADC: Adc + 'd>(
     adc: impl Peripheral<P = ADC> + 'd,
+) -> Result<SharedAdc<'d, ADC>, InitError> {
+    Ok(RefCell::new(AdcDriver::new(
+        adc,
+        &AdcConfig::new().calibration(true),
+    )?))
+}
+
+pub fn adc<'a, 'd, const A: adc_atten_t, ADC: Adc + 'd, P: ADCPin<Adc = ADC>>(
+    adc: &'a SharedAdc<'d, ADC>,
     pin: impl Peripheral<P = P> + 'd,
-) -> Result<impl ruwm::battery::Adc + 'd, InitError> {
-    struct AdcImpl<'d, const A: adc_atten_t, ADC, V>
+) -> Result<impl ruwm::battery::Adc + 'a, InitError> {
+    struct AdcImpl<'a, 'd, const A: adc_atten_t, ADC, V>
     where
         ADC: Adc,
         V: ADCPin<Adc = ADC>,
     {
-        driver: AdcDriver<'d, ADC>,
+        driver: &'a SharedAdc<'d, ADC>,
         channel_driver: AdcChannelDriver<'d, A, V>,
     }
 
-    impl<'d, const A: adc_atten_t, ADC, V> ruwm::battery::Adc for AdcImpl<'d, A, ADC, V>
+    impl<'a, 'd, const A: adc_atten_t, ADC, V> ruwm::battery::Adc for AdcImpl<'a, 'd, A, ADC, V>
     where
         ADC: Adc,
         V: ADCPin<Adc = ADC>,
@@ -213,16 +490,52 @@ pub fn adc<'d, const A: adc_atten_t, ADC: Adc + 'd, P: ADCPin<Adc = ADC>>(
         type Error = EspError;
 
         async fn read(&mut self) -> Result<u16, Self::Error> {
-            self.driver.read(&mut self.channel_driver)
+            self.driver.borrow_mut().read(&mut self.channel_driver)
         }
     }
 
     Ok(AdcImpl {
-        driver: AdcDriver::new(adc, &AdcConfig::new().calibration(true))?,
+        driver: adc,
         channel_driver: AdcChannelDriver::<{ A }, _>::new(pin)?,
     })
 }
 
+/// A 10 kOhm NTC thermistor (B = 3950) from the pin to ground, below a 10 kOhm resistor
+/// from 3.3 V, read in mV
+pub fn thermistor(adc: impl ruwm::battery::Adc<Error = EspError>) -> impl TemperatureSensor {
+    struct Thermistor<A>(A);
+
+    impl<A> TemperatureSensor for Thermistor<A>
+    where
+        A: ruwm::battery::Adc<Error = EspError>,
+    {
+        type Error = EspError;
+
+        async fn read(&mut self) -> Result<i16, Self::Error> {
+            const SUPPLY_MV: f32 = 3300.0;
+            const SERIES_OHMS: f32 = 10_000.0;
+            const NOMINAL_OHMS: f32 = 10_000.0;
+            const NOMINAL_KELVIN: f32 = 298.15;
+            const BETA: f32 = 3950.0;
+
+            let mv = self.0.read().await? as f32;
+
+            // A missing or shorted thermistor reads at the ends of the range of the ADC, which
+            // must not be taken for a frost closing the valves
+            if !(100.0..=3000.0).contains(&mv) {
+                return Err(EspError::from_infallible::<{ sys::ESP_ERR_INVALID_RESPONSE }>());
+            }
+
+            let ohms = SERIES_OHMS * mv / (SUPPLY_MV - mv);
+            let kelvin = 1.0 / (1.0 / NOMINAL_KELVIN + (ohms / NOMINAL_OHMS).ln() / BETA);
+
+            Ok(((kelvin - 273.15) * 10.0).round() as i16)
+        }
+    }
+
+    Thermistor(adc)
+}
+
 #[inline(always)]
 pub fn display(
     peripherals: DisplaySpiPeripherals<impl Peripheral<P = impl SpiAnyPins + 'static> + 'static>,
@@ -317,6 +630,234 @@ pub fn wifi<'d>(
     )?)
 }
 
+pub fn ota() -> EspOta {
+    EspOta { update: None }
+}
+
+/// The OTA partitions of ESP-IDF
+pub struct EspOta {
+    update: Option<(*const sys::esp_partition_t, sys::esp_ota_handle_t)>,
+}
+
+impl Ota for EspOta {
+    type Error = EspError;
+
+    fn running_version(&self) -> Option<FirmwareVersion> {
+        let desc = unsafe { sys::esp_app_get_description().as_ref() }?;
+
+        parse_version(&desc.version)
+    }
+
+    fn check_header(&self, header: &[u8]) -> Result<Option<FirmwareVersion>, OtaError> {
+        const IMAGE_HEADER_LEN: usize = mem::size_of::<sys::esp_image_header_t>();
+        const SEGMENT_HEADER_LEN: usize = mem::size_of::<sys::esp_image_segment_header_t>();
+        const APP_DESC_LEN: usize = mem::size_of::<sys::esp_app_desc_t>();
+
+        if header.len() < IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN + APP_DESC_LEN {
+            return Err(OtaError::Image);
+        }
+
+        // The application description always follows the header of the first segment
+        let (image_header, app_desc) = unsafe {
+            (
+                ptr::read_unaligned(header.as_ptr() as *const sys::esp_image_header_t),
+                ptr::read_unaligned(header[IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN..].as_ptr()
+                    as *const sys::esp_app_desc_t),
+            )
+        };
+
+        if image_header.magic as u32 != sys::ESP_IMAGE_HEADER_MAGIC
+            || image_header.chip_id as u32 != sys::CONFIG_IDF_FIRMWARE_CHIP_ID
+            || app_desc.magic_word != sys::ESP_APP_DESC_MAGIC_WORD
+        {
+            return Err(OtaError::Image);
+        }
+
+        Ok(parse_version(&app_desc.version))
+    }
+
+    fn is_pending_verification(&self) -> Result<bool, Self::Error> {
+        let mut state = sys::esp_ota_img_states_t_ESP_OTA_IMG_UNDEFINED;
+
+        let result = esp!(unsafe {
+            sys::esp_ota_get_state_partition(sys::esp_ota_get_running_partition(), &mut state)
+        });
+
+        match result {
+            Ok(()) => Ok(state == sys::esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY),
+            // Not running from an OTA partition
+            Err(err) if err.code() == sys::ESP_ERR_NOT_SUPPORTED => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn mark_valid(&mut self) -> Result<(), Self::Error> {
+        esp!(unsafe { sys::esp_ota_mark_app_valid_cancel_rollback() })
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        esp!(unsafe { sys::esp_ota_mark_app_invalid_rollback_and_reboot() })
+    }
+
+    async fn begin(&mut self, size: usize) -> Result<(), Self::Error> {
+        self.abort().await?;
+
+        let partition = unsafe { sys::esp_ota_get_next_update_partition(ptr::null()) };
+        if partition.is_null() {
+            return Err(EspError::from_infallible::<{ sys::ESP_ERR_NOT_FOUND }>());
+        }
+
+        let mut handle = 0;
+        esp!(unsafe { sys::esp_ota_begin(partition, size, &mut handle) })?;
+
+        self.update = Some((partition, handle));
+
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let (_, handle) = self
+            .update
+            .ok_or(EspError::from_infallible::<{ sys::ESP_ERR_INVALID_STATE }>())?;
+
+        // Also rejects an image with an invalid header as soon as the first chunk is written
+        esp!(unsafe { sys::esp_ota_write(handle, data.as_ptr() as *const _, data.len()) })
+    }
+
+    async fn complete(&mut self) -> Result<(), Self::Error> {
+        let (partition, handle) = self
+            .update
+            .take()
+            .ok_or(EspError::from_infallible::<{ sys::ESP_ERR_INVALID_STATE }>())?;
+
+        // Validates the whole image, including its checksum
+        esp!(unsafe { sys::esp_ota_end(handle) })?;
+        esp!(unsafe { sys::esp_ota_set_boot_partition(partition) })
+    }
+
+    async fn abort(&mut self) -> Result<(), Self::Error> {
+        if let Some((_, handle)) = self.update.take() {
+            esp!(unsafe { sys::esp_ota_abort(handle) })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_version(version: &[c_char]) -> Option<FirmwareVersion> {
+    let version =
+        unsafe { core::slice::from_raw_parts(version.as_ptr() as *const u8, version.len()) };
+
+    CStr::from_bytes_until_nul(version)
+        .ok()?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// The system time, which ESP-IDF keeps in the RTC across deep sleep
+pub fn rtc() -> impl Rtc {
+    struct RtcImpl;
+
+    impl Rtc for RtcImpl {
+        type Error = EspError;
+
+        fn get(&self) -> Result<u64, Self::Error> {
+            let mut tv: sys::timeval = Default::default();
+
+            if unsafe { sys::gettimeofday(&mut tv, ptr::null_mut()) } == 0 {
+                Ok(tv.tv_sec as u64 * 1_000_000 + tv.tv_usec as u64)
+            } else {
+                Err(EspError::from_infallible::<{ sys::ESP_FAIL }>())
+            }
+        }
+
+        fn set(&mut self, utc_micros: u64) -> Result<(), Self::Error> {
+            let tv = sys::timeval {
+                tv_sec: (utc_micros / 1_000_000) as _,
+                tv_usec: (utc_micros % 1_000_000) as _,
+            };
+
+            if unsafe { sys::settimeofday(&tv, ptr::null()) } == 0 {
+                Ok(())
+            } else {
+                Err(EspError::from_infallible::<{ sys::ESP_FAIL }>())
+            }
+        }
+    }
+
+    RtcImpl
+}
+
+pub fn sntp() -> impl Sntp {
+    struct SntpImpl;
+
+    impl Sntp for SntpImpl {
+        type Error = EspError;
+
+        async fn sync(&mut self) -> Result<u64, Self::Error> {
+            // The service sends its first request when started, so it is started for each sync
+            let sntp = EspSntp::new_default()?;
+
+            while sntp.get_sync_status() != SyncStatus::Completed {
+                embassy_time::Timer::after(Duration::from_millis(100)).await;
+            }
+
+            rtc().get()
+        }
+    }
+
+    SntpImpl
+}
+
+#[derive(Copy, Clone)]
+pub struct EspSystemMetrics {
+    wake_reason: WakeReason,
+}
+
+impl SystemMetrics for EspSystemMetrics {
+    type WakeupReason = WakeReason;
+
+    fn wakeup_reason(&self) -> Self::WakeupReason {
+        self.wake_reason
+    }
+
+    fn wifi_rssi(&self) -> Option<i8> {
+        let mut info: sys::wifi_ap_record_t = Default::default();
+
+        esp!(unsafe { sys::esp_wifi_sta_get_ap_info(&mut info) })
+            .ok()
+            .map(|_| info.rssi)
+    }
+
+    fn heap_free(&self) -> Option<u32> {
+        Some(unsafe { sys::esp_get_free_heap_size() })
+    }
+
+    fn heap_min_free(&self) -> Option<u32> {
+        Some(unsafe { sys::esp_get_minimum_free_heap_size() })
+    }
+
+    fn tasks(&self) -> Option<u32> {
+        Some(unsafe { sys::uxTaskGetNumberOfTasks() } as _)
+    }
+}
+
+pub fn system_metrics(wake_reason: WakeReason) -> EspSystemMetrics {
+    EspSystemMetrics { wake_reason }
+}
+
+pub fn http_client() -> Result<impl embedded_svc::http::client::asynch::Connection, InitError> {
+    let connection = EspHttpConnection::new(&HttpClientConfiguration {
+        buffer_size: Some(1024),
+        crt_bundle_attach: Some(sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+
+    Ok(TrivialUnblockingConnection::new(connection))
+}
+
 #[derive(Debug)]
 pub enum HttpdError<T> {
     Http(io::Error<T>),
@@ -337,20 +878,45 @@ impl<T> From<WsError<edge_ws::io::Error<T>>> for HttpdError<T> {
 
 pub struct HttpdHandler<'a> {
     assets: &'a [Asset],
+    api_token: Option<&'a str>,
+    system_metrics: EspSystemMetrics,
+    ota: &'a SharedOta<EspOta>,
+    #[cfg(feature = "https")]
+    certificate_store: Option<&'a https::CertificateStore>,
     send_bufs: UnsafeCell<MaybeUninit<[[u8; WS_MAX_FRAME_LEN]; WS_MAX_CONNECTIONS]>>,
     recv_bufs: UnsafeCell<MaybeUninit<[[u8; WS_MAX_FRAME_LEN]; WS_MAX_CONNECTIONS]>>,
 }
 
 impl<'a> HttpdHandler<'a> {
     #[inline(always)]
-    pub fn new(assets: &'a [Asset]) -> Self {
+    pub fn new(
+        assets: &'a [Asset],
+        api_token: Option<&'a str>,
+        system_metrics: EspSystemMetrics,
+        ota: &'a SharedOta<EspOta>,
+    ) -> Self {
         Self {
             assets,
+            api_token,
+            system_metrics,
+            ota,
+            #[cfg(feature = "https")]
+            certificate_store: None,
             send_bufs: UnsafeCell::new(MaybeUninit::uninit()),
             recv_bufs: UnsafeCell::new(MaybeUninit::uninit()),
         }
     }
 
+    /// Accepts uploads of the TLS certificate
+    #[cfg(feature = "https")]
+    pub fn with_certificate_store(
+        mut self,
+        certificate_store: &'a https::CertificateStore,
+    ) -> Self {
+        self.certificate_store = Some(certificate_store);
+        self
+    }
+
     async fn handle<'b, T, const N: usize>(
         &self,
         task_id: usize,
@@ -359,8 +925,29 @@ impl<'a> HttpdHandler<'a> {
     where
         T: Read + Write + TcpSplittableConnection,
     {
-        if matches!(con.headers().unwrap().method, Some(Method::Get)) {
-            if matches!(con.headers()?.path, Some("/ws")) {
+        let headers = con.headers()?;
+
+        #[cfg(feature = "https")]
+        if matches!(headers.method, Some(Method::Post))
+            && matches!(headers.path, Some("/api/tls/certificate"))
+        {
+            return Ok(self.handle_certificate_upload(con).await?);
+        }
+
+        if matches!(headers.method, Some(Method::Post))
+            && matches!(headers.path, Some("/api/firmware"))
+        {
+            self.handle_firmware_upload(con).await?;
+        } else if headers
+            .path
+            .map(|path| path.starts_with("/api/"))
+            .unwrap_or(false)
+        {
+            ruwm::api::handle(&mut *con, task_id, self.api_token).await?;
+        } else if matches!(headers.path, Some("/metrics")) {
+            ruwm::metrics::handle(&mut *con, self.system_metrics, self.api_token).await?;
+        } else if matches!(headers.method, Some(Method::Get)) {
+            if matches!(headers.path, Some("/ws")) {
                 let send_buf = &mut unsafe {
                     self.send_bufs.get().as_mut().unwrap().assume_init_mut()[task_id]
                 };
@@ -413,6 +1000,106 @@ impl<'a> HttpdHandler<'a> {
         Ok(())
     }
 
+    async fn handle_firmware_upload<'b, T, const N: usize>(
+        &self,
+        con: &mut io::server::Connection<'b, T, N>,
+    ) -> Result<(), io::Error<T::Error>>
+    where
+        T: Read + Write,
+    {
+        let headers = &con.headers()?.headers;
+
+        let role = ruwm::web::http_role(headers.get("Authorization"), self.api_token);
+        let size = headers.content_len();
+
+        if role < Role::Admin {
+            let (status, headers): (_, &[_]) = if role == Role::None {
+                (401, &[("WWW-Authenticate", "Basic realm=\"ruwm\"")])
+            } else {
+                (403, &[])
+            };
+
+            return con.initiate_response(status, None, headers).await;
+        }
+
+        let Some(size) = size else {
+            return con.initiate_response(411, None, &[]).await;
+        };
+
+        log::info!("Receiving a firmware upload of {size} bytes");
+
+        // Progress and outcome are reported to the web UI via the OTA state,
+        // and on success the device restarts shortly after the response is sent
+        let status = match ruwm::ota::upload(self.ota, &mut *con, size as _).await {
+            Ok(()) => 200,
+            Err(OtaError::Busy) => 409,
+            Err(OtaError::Size | OtaError::Image | OtaError::Checksum) => 400,
+            Err(_) => 500,
+        };
+
+        con.initiate_response(status, None, &[("Content-Length", "0")])
+            .await
+    }
+
+    #[cfg(feature = "https")]
+    async fn handle_certificate_upload<'b, T, const N: usize>(
+        &self,
+        con: &mut io::server::Connection<'b, T, N>,
+    ) -> Result<(), io::Error<T::Error>>
+    where
+        T: Read + Write,
+    {
+        let headers = &con.headers()?.headers;
+
+        let role = ruwm::web::http_role(headers.get("Authorization"), self.api_token);
+        let size = headers.content_len();
+
+        if role < Role::Admin {
+            let (status, headers): (_, &[_]) = if role == Role::None {
+                (401, &[("WWW-Authenticate", "Basic realm=\"ruwm\"")])
+            } else {
+                (403, &[])
+            };
+
+            return con.initiate_response(status, None, headers).await;
+        }
+
+        let (Some(size), Some(certificate_store)) = (size, self.certificate_store) else {
+            return con.initiate_response(411, None, &[]).await;
+        };
+
+        if size as usize > https::UPLOAD_MAX_LEN {
+            return con.initiate_response(413, None, &[]).await;
+        }
+
+        let mut bundle = vec![0; size as usize];
+        let mut len = 0;
+
+        while len < bundle.len() {
+            let read = con.read(&mut bundle[len..]).await?;
+            if read == 0 {
+                break;
+            }
+
+            len += read;
+        }
+
+        bundle.truncate(len);
+
+        // The new certificate is used after the next restart
+        let status = match certificate_store.store_pem_bundle(&bundle) {
+            Ok(true) => 200,
+            Ok(false) => 400,
+            Err(err) => {
+                log::warn!("Cannot store the TLS certificate: {err}");
+                500
+            }
+        };
+
+        con.initiate_response(status, None, &[("Content-Length", "0")])
+            .await
+    }
+
     async fn handle_assets<'b, T, const N: usize>(
         &self,
         con: &mut io::server::Connection<'b, T, N>,
@@ -459,10 +1146,20 @@ pub fn httpd() -> Result<HttpdServer, InitError> {
 }
 
 #[inline(always)]
-pub fn httpd_handler() -> Result<HttpdHandler<'static>, InitError> {
-    Ok(HttpdHandler::new(&ASSETS))
+pub fn httpd_handler<'a>(
+    api_token: Option<&'a str>,
+    wake_reason: WakeReason,
+    ota: &'a SharedOta<EspOta>,
+) -> Result<HttpdHandler<'a>, InitError> {
+    Ok(HttpdHandler::new(
+        &ASSETS,
+        api_token,
+        system_metrics(wake_reason),
+        ota,
+    ))
 }
 
+#[cfg(not(feature = "https"))]
 pub async fn run_httpd<H>(
     server: &mut HttpdServer,
     handler: H,
@@ -482,6 +1179,27 @@ where
     Ok(())
 }
 
+#[cfg(feature = "https")]
+pub async fn run_httpd<H>(
+    server: &mut HttpdServer,
+    handler: H,
+    certificate: https::TlsCertificate,
+) -> Result<(), io::Error<std::io::Error>>
+where
+    H: for<'b> io::server::TaskHandler<
+        'b,
+        &'b mut https::TlsConnection,
+        { DEFAULT_MAX_HEADERS_COUNT },
+    >,
+{
+    let acceptor =
+        https::TlsAcceptor::bind(https::HTTPS_PORT, certificate).map_err(io::Error::Io)?;
+
+    server.run_with_task_id(acceptor, handler, None).await?;
+
+    Ok(())
+}
+
 #[inline(always)]
 pub fn mqtt() -> Result<
     (
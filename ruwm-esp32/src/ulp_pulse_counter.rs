@@ -1,13 +1,8 @@
-use core::future::Future;
+use core::marker::PhantomData;
 
 use embassy_time::{Duration, Timer};
 
-use esp_idf_hal::{
-    gpio::RTCPin,
-    interrupt::CriticalSection,
-    peripheral::{Peripheral, PeripheralRef},
-    ulp,
-};
+use esp_idf_hal::{gpio::RTCPin, interrupt::CriticalSection, peripheral::Peripheral, ulp};
 use esp_idf_sys::EspError;
 
 use ruwm::pulse_counter;
@@ -19,60 +14,106 @@ mod ulp_code_vars {
     include!(env!("ULP_FSM_RS"));
 }
 
-pub struct UlpPulseCounter<'d, P> {
+/// As many as the per-channel arrays in `ulp_pulse_counter.S`
+pub const MAX_CHANNELS: usize = 3;
+
+/// Counts the pulses of up to `MAX_CHANNELS` pins, one per water meter, also in deep sleep
+pub struct UlpPulseCounter<'d> {
     driver: ulp::UlpDriver<'d>,
-    pin: PeripheralRef<'d, P>,
+    channels: usize,
+    _pins: PhantomData<&'d mut ()>,
 }
 
-impl<'d, P> UlpPulseCounter<'d, P>
-where
-    P: RTCPin,
-{
+impl<'d> UlpPulseCounter<'d> {
     const ULP_CODE: &'static [u8] = include_bytes!(env!("ULP_FSM_BIN"));
 
-    pub fn new(
+    /// Watches `pin` as the first channel
+    pub fn new<P: RTCPin>(
         driver: ulp::UlpDriver<'d>,
         pin: impl Peripheral<P = P> + 'd,
         cold_boot: bool,
     ) -> Result<Self, EspError> {
-        esp_idf_hal::into_ref!(pin);
-
-        let mut this = Self { driver, timer, pin };
+        let mut this = Self {
+            driver,
+            channels: 0,
+            _pins: PhantomData,
+        };
 
         if cold_boot {
             this.initialize()?;
+        }
+
+        this.add_channel(pin)?;
+
+        if cold_boot {
             this.start()?;
         }
 
         Ok(this)
     }
 
-    pub fn split(
+    /// Watches one more pin, and returns the index of its channel
+    ///
+    /// Channels have to be added in the same order after every boot, as the ULP keeps
+    /// counting in deep sleep
+    pub fn add_channel<P: RTCPin>(
         &mut self,
-    ) -> (
-        &mut (impl pulse_counter::PulseCounter + 'd),
-        &mut (impl pulse_counter::PulseWakeup + 'd),
-    ) {
-        let ptr: *mut Self = self;
-
-        // This is safe because the access to the Ulp driver is protected with critical sections
-        unsafe { (ptr.as_mut().unwrap(), ptr.as_mut().unwrap()) }
+        pin: impl Peripheral<P = P> + 'd,
+    ) -> Result<usize, EspError> {
+        assert!(self.channels < MAX_CHANNELS);
+
+        esp_idf_hal::into_ref!(pin);
+
+        let channel = self.channels;
+
+        {
+            let _cs = CriticalSection::new();
+
+            unsafe {
+                self.driver
+                    .write_word(ulp_code_vars::io_number.add(channel), pin.rtc_pin() as _)?;
+
+                self.driver
+                    .write_word(ulp_code_vars::channel_count, (channel + 1) as _)?;
+            }
+        }
+
+        self.channels += 1;
+
+        Ok(channel)
+    }
+
+    /// The number of channels, i.e. of watched pins
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The pulse counter and the wakeup of a channel
+    pub fn channel(&self, channel: usize) -> UlpChannel<'_, 'd> {
+        assert!(channel < self.channels);
+
+        UlpChannel {
+            driver: &self.driver,
+            channel,
+        }
     }
 
     fn initialize(&mut self) -> Result<(), EspError> {
         unsafe {
             self.driver.load(Self::ULP_CODE)?;
 
-            self.driver.write_word(ulp_code_vars::edge_count, 0)?;
+            for channel in 0..MAX_CHANNELS {
+                self.driver
+                    .write_word(ulp_code_vars::edge_count.add(channel), 0)?;
 
-            self.driver
-                .write_word(ulp_code_vars::edge_count_to_wake_up, 0)?;
+                self.driver
+                    .write_word(ulp_code_vars::edge_count_to_wake_up.add(channel), 0)?;
+            }
 
-            self.driver
-                .write_word(ulp_code_vars::debounce_max_count, 5)?;
+            self.driver.write_word(ulp_code_vars::channel_count, 0)?;
 
             self.driver
-                .write_word(ulp_code_vars::io_number, self.pin.rtc_pin() as _)?;
+                .write_word(ulp_code_vars::debounce_max_count, 5)?;
         }
 
         Ok(())
@@ -87,56 +128,53 @@ where
     // }
 }
 
-impl<'d, P> pulse_counter::PulseCounter for UlpPulseCounter<'d, P>
-where
-    P: 'd,
-{
+/// A single channel of the ULP pulse counter
+///
+/// The channels share the ULP driver, whose memory is only accessed within critical sections
+pub struct UlpChannel<'a, 'd> {
+    driver: &'a ulp::UlpDriver<'d>,
+    channel: usize,
+}
+
+impl<'a, 'd> pulse_counter::PulseCounter for UlpChannel<'a, 'd> {
     type Error = EspError;
 
-    type TakePulsesFuture<'a> = impl Future<Output = Result<u64, Self::Error>> where Self: 'a;
+    async fn take_pulses(&mut self) -> Result<u64, Self::Error> {
+        Timer::after(Duration::from_secs(2) /*TODO*/).await;
 
-    fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
-        async move {
-            Timer::after(Duration::from_secs(2) /*TODO*/).await;
+        let edges_count = {
+            let _cs = CriticalSection::new();
 
-            let edges_count = {
-                let _cs = CriticalSection::new();
+            unsafe {
+                let edge_count = ulp_code_vars::edge_count.add(self.channel);
 
-                unsafe {
-                    let edges_count = self.driver.read_word(ulp_code_vars::edge_count)?.value();
+                let edges_count = self.driver.read_word(edge_count)?.value();
 
-                    self.driver.write_word(ulp_code_vars::edge_count, 0)?;
+                self.driver.write_word(edge_count, 0)?;
 
-                    edges_count
-                }
-            };
+                edges_count
+            }
+        };
 
-            Ok(edges_count as _)
-        }
+        Ok(edges_count as _)
     }
 }
 
-impl<'d, P> pulse_counter::PulseWakeup for UlpPulseCounter<'d, P>
-where
-    P: RTCPin,
-{
+impl<'a, 'd> pulse_counter::PulseWakeup for UlpChannel<'a, 'd> {
     type Error = EspError;
 
     fn set_enabled(&mut self, enabled: bool) -> Result<(), Self::Error> {
         let _cs = CriticalSection::new();
 
-        let wakeup_edges = unsafe {
-            self.driver
-                .read_word(ulp_code_vars::edge_count_to_wake_up)?
-                .value()
-        };
+        let edge_count_to_wake_up =
+            unsafe { ulp_code_vars::edge_count_to_wake_up.add(self.channel) };
+
+        let wakeup_edges = unsafe { self.driver.read_word(edge_count_to_wake_up)?.value() };
 
         if enabled != (wakeup_edges > 0) {
             unsafe {
-                self.driver.write_word(
-                    ulp_code_vars::edge_count_to_wake_up,
-                    if enabled { 1 } else { 0 },
-                )?;
+                self.driver
+                    .write_word(edge_count_to_wake_up, if enabled { 1 } else { 0 })?;
             }
         }
 
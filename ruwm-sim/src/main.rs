@@ -10,7 +10,13 @@ use static_cell::StaticCell;
 
 use yew::prelude::*;
 
+use edge_frame::dto::Role;
+
+use ruwm::button::PressedLevel;
+use ruwm::power::{SimulatedSleepController, SleepController, WakeReason};
 use ruwm::spawn;
+use ruwm::temperature::MockTemperatureSensor;
+use ruwm::web::{self, Credentials};
 
 mod peripherals;
 mod services;
@@ -35,17 +41,19 @@ fn main() {
     start();
 }
 
-static EXECUTOR: StaticCell<LocalExecutor<'static, 32>> = StaticCell::new();
+static EXECUTOR: StaticCell<LocalExecutor<'static, 48>> = StaticCell::new();
+
+/// 20 °C, as the simulator has no temperature sensor
+static TEMPERATURE: MockTemperatureSensor = MockTemperatureSensor::new(200);
 
 fn start() {
     info!("Initializing services & peripherals");
 
     let peripherals = peripherals::SystemPeripherals::take();
 
-    // Valve pins
+    // Valve driver
 
-    let (valve_power_pin, valve_open_pin, valve_close_pin) =
-        services::valve_pins(peripherals.valve);
+    let valve_driver = services::valve_driver(peripherals.valve);
 
     // Storage
 
@@ -79,23 +87,30 @@ fn start() {
     // Pulse counter
 
     let (pulse_counter, pulse_wakeup) = services::pulse(peripherals.pulse);
+    let (pulse_counter1, pulse_wakeup1) = services::pulse(peripherals.pulse1);
 
     // TODO
     // Mqtt
 
     // let (mqtt_topic_prefix, mqtt_client, mqtt_conn) = services::mqtt()?;
 
+    // ADC
+
+    let adc = services::shared_adc(peripherals.battery.adc);
+
     // Executor
 
     let executor = &*EXECUTOR.init(Default::default());
 
     // High-prio tasks
 
+    // The simulator never quits, so it never sleeps either
+    let sleep_controller = SimulatedSleepController::new(WakeReason::Reset);
+
     spawn::high_prio(
         executor,
-        valve_power_pin,
-        valve_open_pin,
-        valve_close_pin,
+        sleep_controller.wake_reason(),
+        valve_driver,
         |state| unsafe {
             services::RTC_MEMORY.valve = state;
         },
@@ -107,7 +122,7 @@ fn start() {
         |state| unsafe {
             services::RTC_MEMORY.wm_stats = state;
         },
-        services::adc(peripherals.battery.adc, peripherals.battery.voltage),
+        services::adc(&adc, peripherals.battery.voltage),
         peripherals.battery.power,
         false,
         services::button(peripherals.buttons.button1),
@@ -115,8 +130,44 @@ fn start() {
         services::button(peripherals.buttons.button3),
     );
 
+    spawn::wm(executor, 1, pulse_counter1, pulse_wakeup1);
+
+    spawn::moisture_digital(
+        executor,
+        0,
+        services::button(peripherals.sensors.leak_probe),
+        PressedLevel::High,
+    );
+
+    spawn::moisture_analog(
+        executor,
+        1,
+        services::adc(&adc, peripherals.sensors.moisture),
+    );
+
+    spawn::valve_current_sense(
+        executor,
+        0,
+        services::adc(&adc, peripherals.sensors.current_sense),
+    );
+
     // Mid-prio tasks
 
+    spawn::time(
+        executor,
+        services::JsClock,
+        services::JsClock,
+        |state, time_zone| unsafe {
+            services::RTC_MEMORY.time = state;
+            services::RTC_MEMORY.time_zone = Some(time_zone);
+        },
+        |_time_zone| (),
+    );
+
+    spawn::temperature(executor, &TEMPERATURE);
+
+    spawn::pressure(executor, services::adc(&adc, peripherals.sensors.pressure));
+
     // TODO
     // MQTT
     // spawn::mqtt_send::<MQTT_MAX_TOPIC_LEN, 4, _, _>(
@@ -129,10 +180,64 @@ fn start() {
 
     let display = peripherals.display;
 
-    spawn::low_prio_owned(executor, services::display(display), move |_new_state| {
-        #[cfg(feature = "nvs")]
-        flash_wm_state(storage, _new_state);
-    });
+    spawn::low_prio_owned(
+        executor,
+        services::display(display),
+        move |_meter, _new_state| {
+            #[cfg(feature = "nvs")]
+            flash_wm_state(storage, _meter, _new_state);
+        },
+        |_meter, _history| (),
+    );
+
+    spawn::valve_profile(
+        executor,
+        |profile| unsafe {
+            services::RTC_MEMORY.valve_profile = profile;
+        },
+        |_profile| (),
+    );
+
+    spawn::valve_config(executor, |_config| ());
+
+    spawn::wm_config(executor, |_config| ());
+
+    spawn::moisture_config(executor, |_config| ());
+
+    spawn::temperature_config(executor, |_config| ());
+
+    spawn::pressure_config(executor, |_config| ());
+
+    spawn::battery_config(executor, |_config| ());
+    spawn::power_policy(executor, |_policy| ());
+    spawn::button_timings(executor, |_timings| ());
+    spawn::wifi_policy(executor, |_policy| ());
+
+    spawn::battery_history(executor, |_history| (), |_history| ());
+
+    spawn::alert(
+        executor,
+        |state| unsafe {
+            services::RTC_MEMORY.alert = state;
+        },
+        |_config| (),
+    );
+
+    spawn::away(
+        executor,
+        |state| unsafe {
+            services::RTC_MEMORY.away = state;
+        },
+        |_config| (),
+    );
+
+    // Well known, as the simulator only runs in the browser
+    static CREDENTIALS: [Credentials; 2] = [
+        Credentials::new("admin", Some("admin"), Role::Admin),
+        Credentials::new("user", Some("user"), Role::User),
+    ];
+
+    web::set_credentials(&CREDENTIALS);
 
     let (sender, receiver) = ruwm_web::local_queue();
 
@@ -157,14 +262,21 @@ fn flash_wm_state<S>(
         impl embassy_sync::blocking_mutex::raw::RawMutex,
         core::cell::RefCell<S>,
     >,
+    meter: usize,
     new_state: WaterMeterState,
 ) where
     S: Storage,
 {
+    let key = if meter > 0 {
+        format!("wm-state-{meter}")
+    } else {
+        "wm-state".into()
+    };
+
     ruwm::log_err!(storage.lock(|storage| {
-        let old_state = storage.borrow().get("wm-state")?;
+        let old_state = storage.borrow().get(&key)?;
         if old_state != Some(new_state) {
-            storage.borrow_mut().set("wm-state", &new_state)?;
+            storage.borrow_mut().set(&key, &new_state)?;
         }
 
         Ok::<_, S::Error>(())
@@ -7,15 +7,18 @@ use hal_sim::gpio::*;
 use hal_sim::io;
 use hal_sim::peripherals::*;
 
-use ruwm::battery::BatteryState;
+use ruwm::battery::BatteryConfig;
 
 //pub const DISPLAY_SIZE: Size = Size::new(320, 240);
 pub const DISPLAY_SIZE: Size = Size::new(128, 128);
 
 pub struct SystemPeripherals {
     pub pulse: Pin<Input>,
+    /// The pulses of the second meter
+    pub pulse1: Pin<Input>,
     pub valve: ValvePeripherals,
     pub battery: BatteryPeripherals,
+    pub sensors: SensorPeripherals,
     pub buttons: ButtonsPeripherals,
     pub display: Display<Rgb888>,
 }
@@ -24,10 +27,17 @@ impl SystemPeripherals {
     pub fn take() -> Self {
         let mut peripherals = Peripherals::take(io::peripherals_callback).unwrap();
 
+        // The whole discharge curve of the default chemistry
+        let curve = BatteryConfig::new().chemistry.curve();
+        let (empty, full) = (curve[0].0, curve[curve.len() - 1].0);
+
         SystemPeripherals {
             pulse: peripherals
                 .pins
                 .input_click("Pulse", "Pulse Counter", false),
+            pulse1: peripherals
+                .pins
+                .input_click("Pulse 2", "Pulse Counter", false),
             valve: ValvePeripherals {
                 power: peripherals.pins.output("Power", "Valve", false),
                 open: peripherals.pins.output("Open", "Valve", false),
@@ -38,12 +48,24 @@ impl SystemPeripherals {
                 voltage: peripherals.pins.adc_range(
                     "Voltage",
                     "Battery",
-                    BatteryState::LOW_VOLTAGE,
-                    BatteryState::MAX_VOLTAGE,
-                    (BatteryState::LOW_VOLTAGE + BatteryState::MAX_VOLTAGE) / 2,
+                    empty,
+                    full,
+                    (empty + full) / 2,
                 ),
                 adc: peripherals.adc0,
             },
+            sensors: SensorPeripherals {
+                // The shunt voltage in mV, from an idle to a jammed motor
+                current_sense: peripherals.pins.adc_range("Current", "Valve", 0, 1000, 0),
+                leak_probe: peripherals.pins.input("Wet", "Moisture", false),
+                moisture: peripherals
+                    .pins
+                    .adc_range("Moisture", "Moisture", 0, 3300, 0),
+                // The mV of a 0.5-4.5 V transducer, as per the default pressure configuration
+                pressure: peripherals
+                    .pins
+                    .adc_range("Pressure", "Pressure", 500, 4500, 2500),
+            },
             buttons: ButtonsPeripherals {
                 button1: peripherals.pins.input_click("Prev", "Display", false),
                 button2: peripherals.pins.input_click("Next", "Display", false),
@@ -71,6 +93,15 @@ pub struct BatteryPeripherals {
     pub adc: Adc<0>,
 }
 
+/// The sensors; the analog ones share the ADC of the battery
+pub struct SensorPeripherals {
+    pub current_sense: Pin<Adc<0>>,
+    /// A digital moisture probe, which is high when wet
+    pub leak_probe: Pin<Input>,
+    pub moisture: Pin<Adc<0>>,
+    pub pressure: Pin<Adc<0>>,
+}
+
 pub struct ButtonsPeripherals {
     pub button1: Pin<Input>,
     pub button2: Pin<Input>,
@@ -1,13 +1,16 @@
+use core::cell::RefCell;
 use core::convert::Infallible;
 use core::fmt::Debug;
 
+use std::rc::Rc;
+
 use embassy_time::Duration;
 
 use embedded_graphics_core::pixelcolor::Rgb888;
 
 use gfx_xtra::draw_target::{buffer_size, Flushable, OwnedDrawTargetExt};
 
-use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::digital::InputPin;
 use embedded_hal02::adc::OneShot;
 use embedded_hal_async::digital::Wait;
 
@@ -17,13 +20,16 @@ use hal_sim::adc::Adc;
 use hal_sim::display::Display;
 use hal_sim::gpio::{Input, Pin};
 
+use ruwm::alert::AlertState;
+use ruwm::away::AwayState;
 use ruwm::button::PressedLevel;
 use ruwm::pulse_counter::PulseCounter;
 use ruwm::pulse_counter::PulseWakeup;
 use ruwm::screen::Color;
-use ruwm::valve::ValveState;
-use ruwm::wm::WaterMeterState;
-use ruwm::wm_stats::WaterMeterStatsState;
+use ruwm::time::{Rtc, Sntp, TimeState, TimeZone};
+use ruwm::valve::{self, ValveDriver, ValveProfile, ValveProfiles, ValveStates, MAX_VALVES};
+use ruwm::wm::{WaterMeterState, WaterMeterStates, MAX_METERS};
+use ruwm::wm_stats::{WaterMeterStatsState, WaterMeterStatsStates};
 
 use crate::peripherals::ValvePeripherals;
 
@@ -31,33 +37,58 @@ pub static mut RTC_MEMORY: RtcMemory = RtcMemory::new();
 
 #[derive(Default)]
 pub struct RtcMemory {
-    pub valve: Option<ValveState>,
-    pub wm: WaterMeterState,
-    pub wm_stats: WaterMeterStatsState,
+    pub valve: ValveStates,
+    pub valve_profile: ValveProfiles,
+    pub wm: WaterMeterStates,
+    pub wm_stats: WaterMeterStatsStates,
+    pub time: TimeState,
+    pub time_zone: Option<TimeZone>,
+    pub alert: AlertState,
+    pub away: AwayState,
 }
 
 impl RtcMemory {
+    const WM_STATS: WaterMeterStatsState = WaterMeterStatsState::new();
+
     pub const fn new() -> Self {
         Self {
-            valve: None,
-            wm: WaterMeterState::new(),
-            wm_stats: WaterMeterStatsState::new(),
+            valve: [None; MAX_VALVES],
+            valve_profile: [ValveProfile::DEFAULT; MAX_VALVES],
+            wm: [WaterMeterState::new(); MAX_METERS],
+            wm_stats: [Self::WM_STATS; MAX_METERS],
+            time: TimeState::new(),
+            time_zone: None,
+            alert: AlertState::new(),
+            away: AwayState::new(),
         }
     }
 }
 
-pub fn valve_pins(
-    peripherals: ValvePeripherals,
-) -> (
-    impl OutputPin<Error = impl Debug>,
-    impl OutputPin<Error = impl Debug>,
-    impl OutputPin<Error = impl Debug>,
-) {
-    let power = peripherals.power;
-    let open = peripherals.open;
-    let close = peripherals.close;
-
-    (power, open, close)
+/// The browser clock, which is synced by the host already
+pub struct JsClock;
+
+impl Rtc for JsClock {
+    type Error = Infallible;
+
+    fn get(&self) -> Result<u64, Self::Error> {
+        Ok((js_sys::Date::now() * 1000.0) as u64)
+    }
+
+    fn set(&mut self, _utc_micros: u64) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Sntp for JsClock {
+    type Error = Infallible;
+
+    async fn sync(&mut self) -> Result<u64, Self::Error> {
+        self.get()
+    }
+}
+
+pub fn valve_driver(peripherals: ValvePeripherals) -> impl ValveDriver {
+    valve::HBridgeDriver::new(peripherals.power, peripherals.open, peripherals.close)
 }
 
 #[cfg(feature = "nvs")]
@@ -129,9 +160,16 @@ pub fn button(pin: Pin<Input>) -> impl InputPin<Error = impl Debug> + Wait {
     pin
 }
 
-pub fn adc<const ID: u8>(adc: Adc<ID>, pin: Pin<Adc<ID>>) -> impl ruwm::battery::Adc {
+/// An ADC shared by the channels of the battery and the sensors
+pub type SharedAdc<const ID: u8> = Rc<RefCell<Adc<ID>>>;
+
+pub fn shared_adc<const ID: u8>(adc: Adc<ID>) -> SharedAdc<ID> {
+    Rc::new(RefCell::new(adc))
+}
+
+pub fn adc<const ID: u8>(adc: &SharedAdc<ID>, pin: Pin<Adc<ID>>) -> impl ruwm::battery::Adc {
     struct AdcImpl<const ID: u8> {
-        adc: Adc<ID>,
+        adc: SharedAdc<ID>,
         pin: Pin<Adc<ID>>,
     }
 
@@ -139,11 +177,14 @@ pub fn adc<const ID: u8>(adc: Adc<ID>, pin: Pin<Adc<ID>>) -> impl ruwm::battery:
         type Error = nb01::Error<Infallible>;
 
         async fn read(&mut self) -> Result<u16, Self::Error> {
-            self.adc.read(&mut self.pin)
+            self.adc.borrow_mut().read(&mut self.pin)
         }
     }
 
-    AdcImpl { adc, pin }
+    AdcImpl {
+        adc: adc.clone(),
+        pin,
+    }
 }
 
 pub fn display(
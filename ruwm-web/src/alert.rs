@@ -0,0 +1,146 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::alert::{
+    AlertAction, AlertCommand, AlertConfig, AlertKind, AlertState, AlertStatus,
+};
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct AlertStore {
+    pub state: AlertState,
+    pub config: AlertConfig,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AlertMsg {
+    State(AlertState),
+    Config(AlertConfig),
+}
+
+impl Reducer<AlertStore> for AlertMsg {
+    fn apply(self, mut store: Rc<AlertStore>) -> Rc<AlertStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self {
+            Self::State(alert_state) => state.state = alert_state,
+            Self::Config(config) => state.config = config,
+        }
+
+        store
+    }
+}
+
+#[function_component(Alerts)]
+pub fn alerts() -> Html {
+    let alert_store = use_store_value::<AlertStore>();
+    let mcx = use_mcx();
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::AlertCommand(command)))
+    };
+
+    let threshold_refs = AlertKind::ALL.map(|_| NodeRef::default());
+    let close_valve_ref = use_node_ref();
+
+    let onsave = {
+        let mcx = mcx.clone();
+        let threshold_refs = threshold_refs.clone();
+        let close_valve_ref = close_valve_ref.clone();
+
+        Callback::from(move |_| {
+            // Empty or invalid thresholds disable the alert
+            let [daily_budget, weekly_budget, monthly_budget, draw, pressure_drop] =
+                threshold_refs.clone().map(|threshold_ref| {
+                    threshold_ref
+                        .cast::<HtmlInputElement>()
+                        .and_then(|input| input.value().trim().parse().ok())
+                });
+
+            let close_valve = close_valve_ref
+                .cast::<HtmlInputElement>()
+                .map(|input| input.checked())
+                .unwrap_or(false);
+
+            mcx.invoke(WebRequest::AlertCommand(AlertCommand::Configure(
+                AlertConfig {
+                    daily_budget,
+                    weekly_budget,
+                    monthly_budget,
+                    draw,
+                    pressure_drop,
+                    action: if close_valve {
+                        AlertAction::CloseValve
+                    } else {
+                        AlertAction::Notify
+                    },
+                },
+            )))
+        })
+    };
+
+    let state = alert_store.state;
+    let config = alert_store.config;
+
+    html! {
+        <>
+            {
+                AlertKind::ALL.into_iter().map(|kind| {
+                    let status = state.status(kind);
+
+                    html! {
+                        <div class="field is-grouped">
+                            <p class="control">{format!("{}: {:?}", kind.name(), status)}</p>
+                            <p class="control">
+                                <button class="button is-small" disabled={status != AlertStatus::Raised} onclick={command(AlertCommand::Acknowledge(kind))}>
+                                    {"Acknowledge"}
+                                </button>
+                            </p>
+                            <p class="control">
+                                <button class="button is-small" disabled={!matches!(status, AlertStatus::Raised | AlertStatus::Acknowledged)} onclick={command(AlertCommand::Clear(kind))}>
+                                    {"Clear"}
+                                </button>
+                            </p>
+                        </div>
+                    }
+                }).collect::<Html>()
+            }
+            <Role role={RoleDto::Admin}>
+                {
+                    AlertKind::ALL.into_iter().zip(threshold_refs.iter()).map(|(kind, threshold_ref)| html! {
+                        <div class="field">
+                            <label class="label">{format!("{} ({})", kind.name(), if kind == AlertKind::PressureDrop { "mbar" } else { "edges" })}</label>
+                            <div class="control">
+                                <input
+                                    class="input"
+                                    type="number"
+                                    min="1"
+                                    value={config.threshold(kind).map(|threshold| threshold.to_string()).unwrap_or_default()}
+                                    ref={threshold_ref.clone()}
+                                />
+                            </div>
+                        </div>
+                    }).collect::<Html>()
+                }
+                <div class="field">
+                    <label class="checkbox">
+                        <input type="checkbox" checked={config.action == AlertAction::CloseValve} ref={close_valve_ref}/>
+                        {" Close the valve when an alert is raised"}
+                    </label>
+                </div>
+                <button class="button is-primary" onclick={onsave}>
+                    {"Save"}
+                </button>
+            </Role>
+        </>
+    }
+}
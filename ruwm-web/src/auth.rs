@@ -0,0 +1,30 @@
+use std::rc::Rc;
+
+use yewdux::prelude::*;
+
+/// The `Authorization` header for plain HTTP requests to the backend,
+/// derived from the credentials used to authenticate on the websocket
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct AuthStore(pub Option<String>);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthMsg(pub Option<String>);
+
+impl AuthMsg {
+    pub fn basic(username: &str, password: &str) -> Self {
+        let credentials = web_sys::window()
+            .and_then(|window| window.btoa(&format!("{username}:{password}")).ok());
+
+        Self(credentials.map(|credentials| format!("Basic {credentials}")))
+    }
+}
+
+impl Reducer<AuthStore> for AuthMsg {
+    fn apply(self, mut store: Rc<AuthStore>) -> Rc<AuthStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
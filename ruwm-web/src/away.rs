@@ -0,0 +1,138 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use ruwm::dto::away::{AwayCommand, AwayConfig, AwaySchedule, AwayState};
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct AwayStore {
+    pub state: AwayState,
+    pub config: AwayConfig,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AwayMsg {
+    State(AwayState),
+    Config(AwayConfig),
+}
+
+impl Reducer<AwayStore> for AwayMsg {
+    fn apply(self, mut store: Rc<AwayStore>) -> Rc<AwayStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self {
+            Self::State(away_state) => state.state = away_state,
+            Self::Config(config) => state.config = config,
+        }
+
+        store
+    }
+}
+
+#[function_component(Away)]
+pub fn away() -> Html {
+    let away_store = use_store_value::<AwayStore>();
+    let mcx = use_mcx();
+
+    let schedule_ref = use_node_ref();
+    let threshold_ref = use_node_ref();
+    let schedule_error = use_state(|| false);
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::AwayCommand(command)))
+    };
+
+    let onsave = {
+        let mcx = mcx.clone();
+        let schedule_ref = schedule_ref.clone();
+        let threshold_ref = threshold_ref.clone();
+        let schedule_error = schedule_error.clone();
+        let enabled = away_store.config.enabled;
+
+        Callback::from(move |_| {
+            let schedule = schedule_ref
+                .cast::<HtmlInputElement>()
+                .map(|input| input.value().parse::<AwaySchedule>());
+
+            // An empty or invalid threshold tolerates no flow at all
+            let threshold = threshold_ref
+                .cast::<HtmlInputElement>()
+                .and_then(|input| input.value().trim().parse().ok());
+
+            match schedule {
+                Some(Ok(schedule)) => {
+                    schedule_error.set(false);
+
+                    mcx.invoke(WebRequest::AwayCommand(AwayCommand::Configure(
+                        AwayConfig {
+                            enabled,
+                            schedule,
+                            threshold,
+                        },
+                    )));
+                }
+                _ => schedule_error.set(true),
+            }
+        })
+    };
+
+    let state = away_store.state;
+    let config = away_store.config;
+
+    html! {
+        <>
+            <p>
+                {format!(
+                    "Away: {}{}",
+                    if state.active { "active" } else { "inactive" },
+                    if state.tripped { ", valve closed due to flow" } else { "" }
+                )}
+            </p>
+            <div class="buttons">
+                <button class="button" disabled={config.enabled} onclick={command(AwayCommand::Enable(true))}>
+                    {"Away"}
+                </button>
+                <button class="button" disabled={!config.enabled} onclick={command(AwayCommand::Enable(false))}>
+                    {"Home"}
+                </button>
+            </div>
+            <div class="field">
+                <label class="label">{"Schedule"}</label>
+                <div class="control">
+                    <input
+                        class="input"
+                        type="text"
+                        placeholder="2024-07-01/2024-07-14,01:00-05:00"
+                        value={config.schedule.to_string()}
+                        ref={schedule_ref}
+                    />
+                </div>
+                if *schedule_error {
+                    <p class="help is-danger">{"Invalid schedule"}</p>
+                }
+            </div>
+            <div class="field">
+                <label class="label">{"Tolerated flow (edges/h)"}</label>
+                <div class="control">
+                    <input
+                        class="input"
+                        type="number"
+                        min="1"
+                        value={config.threshold.map(|threshold| threshold.to_string()).unwrap_or_default()}
+                        ref={threshold_ref}
+                    />
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
@@ -1,21 +1,36 @@
 use std::rc::Rc;
 
+use web_sys::HtmlInputElement;
+
 use yew::prelude::*;
 use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
 
-use ruwm::dto::battery::BatteryState;
+use ruwm::dto::battery::{BatteryConfig, BatteryState, Chemistry, Days};
+use ruwm::dto::web::WebRequest;
 
 #[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
-pub struct BatteryStore(pub BatteryState);
+pub struct BatteryStore {
+    pub state: BatteryState,
+    pub config: BatteryConfig,
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct BatteryMsg(pub BatteryState);
+pub enum BatteryMsg {
+    State(BatteryState),
+    Config(BatteryConfig),
+}
 
 impl Reducer<BatteryStore> for BatteryMsg {
     fn apply(self, mut store: Rc<BatteryStore>) -> Rc<BatteryStore> {
         let state = Rc::make_mut(&mut store);
 
-        state.0 = self.0;
+        match self {
+            Self::State(battery_state) => state.state = battery_state,
+            Self::Config(config) => state.config = config,
+        }
 
         store
     }
@@ -25,7 +40,151 @@ impl Reducer<BatteryStore> for BatteryMsg {
 pub fn battery() -> Html {
     let battery_store = use_store_value::<BatteryStore>();
 
+    let state = &battery_store.state;
+
+    let charge = match (state.voltage, state.percentage) {
+        (Some(voltage), Some(percentage)) => format!("{}% ({} mV)", percentage, voltage),
+        _ => "Unknown".to_string(),
+    };
+
+    let flag = if state.low {
+        ", low"
+    } else if state.charged {
+        ", charged"
+    } else {
+        ""
+    };
+
+    let remaining = state
+        .remaining_days
+        .map(|remaining_days| format!("Estimated {} left", Days(remaining_days)))
+        .unwrap_or_else(|| "Not enough history for an estimate yet".to_string());
+
+    html! {
+        <>
+            <h2 class="subtitle">{"Battery"}</h2>
+            <p class={if state.low { "has-text-danger" } else { "" }}>
+                {format!("{}{}, powered: {}", charge, flag, state.powered.unwrap_or(false))}
+            </p>
+            <p>{remaining}</p>
+            <Role role={RoleDto::Admin}>
+                <BatteryConfigForm/>
+            </Role>
+        </>
+    }
+}
+
+#[function_component(BatteryConfigForm)]
+fn battery_config_form() -> Html {
+    let battery_store = use_store_value::<BatteryStore>();
+    let mcx = use_mcx();
+
+    let chemistry_refs = Chemistry::ALL.map(|_| NodeRef::default());
+    let divider_num_ref = use_node_ref();
+    let divider_den_ref = use_node_ref();
+    let offset_ref = use_node_ref();
+    let low_ref = use_node_ref();
+    let charged_ref = use_node_ref();
+    let hysteresis_ref = use_node_ref();
+
+    let onsave = {
+        let chemistry_refs = chemistry_refs.clone();
+        let divider_num_ref = divider_num_ref.clone();
+        let divider_den_ref = divider_den_ref.clone();
+        let offset_ref = offset_ref.clone();
+        let low_ref = low_ref.clone();
+        let charged_ref = charged_ref.clone();
+        let hysteresis_ref = hysteresis_ref.clone();
+        let config = battery_store.config;
+
+        Callback::from(move |_| {
+            // Invalid numbers keep the current ones
+            fn number<T: core::str::FromStr>(node_ref: &NodeRef, current: T) -> T {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse().ok())
+                    .unwrap_or(current)
+            }
+
+            let chemistry = Chemistry::ALL
+                .into_iter()
+                .zip(chemistry_refs.iter())
+                .find_map(|(chemistry, chemistry_ref)| {
+                    chemistry_ref
+                        .cast::<HtmlInputElement>()
+                        .filter(|input| input.checked())
+                        .map(|_| chemistry)
+                })
+                .unwrap_or(config.chemistry);
+
+            mcx.invoke(WebRequest::BatteryConfig(BatteryConfig {
+                chemistry,
+                divider_num: number(&divider_num_ref, config.divider_num),
+                divider_den: number(&divider_den_ref, config.divider_den),
+                offset: number(&offset_ref, config.offset),
+                low_percentage: number(&low_ref, config.low_percentage),
+                charged_percentage: number(&charged_ref, config.charged_percentage),
+                hysteresis: number(&hysteresis_ref, config.hysteresis),
+            }));
+        })
+    };
+
+    let config = &battery_store.config;
+
     html! {
-        {format!("Battery Powered: {}, mV: {:?}", battery_store.0.powered.unwrap_or(false), battery_store.0.voltage)}
+        <>
+            <div class="field">
+                {
+                    Chemistry::ALL.into_iter().zip(chemistry_refs.iter()).map(|(chemistry, chemistry_ref)| html! {
+                        <label class="radio">
+                            <input
+                                type="radio"
+                                name="battery-chemistry"
+                                checked={config.chemistry == chemistry}
+                                ref={chemistry_ref.clone()}
+                            />
+                            {format!(" {}", chemistry.name())}
+                        </label>
+                    }).collect::<Html>()
+                }
+            </div>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"Divider"}</label>
+                    <input class="input" type="number" min="1" value={config.divider_num.to_string()} ref={divider_num_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"/"}</label>
+                    <input class="input" type="number" min="1" value={config.divider_den.to_string()} ref={divider_den_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"ADC offset (mV)"}</label>
+                <div class="control">
+                    <input class="input" type="number" value={config.offset.to_string()} ref={offset_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Low below (%)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" max="100" value={config.low_percentage.to_string()} ref={low_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Charged from (%)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" max="100" value={config.charged_percentage.to_string()} ref={charged_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Hysteresis (%)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" max="100" value={config.hysteresis.to_string()} ref={hysteresis_ref}/>
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
     }
 }
@@ -0,0 +1,95 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::button::ButtonTimings;
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct ButtonTimingsStore(pub ButtonTimings);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ButtonTimingsMsg(pub ButtonTimings);
+
+impl Reducer<ButtonTimingsStore> for ButtonTimingsMsg {
+    fn apply(self, mut store: Rc<ButtonTimingsStore>) -> Rc<ButtonTimingsStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[function_component(Buttons)]
+pub fn buttons() -> Html {
+    html! {
+        <Role role={RoleDto::Admin}>
+            <h2 class="subtitle">{"Buttons"}</h2>
+            <ButtonTimingsForm/>
+        </Role>
+    }
+}
+
+#[function_component(ButtonTimingsForm)]
+fn button_timings_form() -> Html {
+    let button_timings_store = use_store_value::<ButtonTimingsStore>();
+    let mcx = use_mcx();
+
+    let long_press_ref = use_node_ref();
+    let double_press_ref = use_node_ref();
+    let chord_ref = use_node_ref();
+
+    let onsave = {
+        let long_press_ref = long_press_ref.clone();
+        let double_press_ref = double_press_ref.clone();
+        let chord_ref = chord_ref.clone();
+        let timings = button_timings_store.0;
+
+        Callback::from(move |_| {
+            // Invalid numbers keep the current ones
+            fn number(node_ref: &NodeRef, current: u16) -> u16 {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse().ok())
+                    .unwrap_or(current)
+            }
+
+            mcx.invoke(WebRequest::ButtonTimings(ButtonTimings {
+                long_press_ms: number(&long_press_ref, timings.long_press_ms),
+                double_press_ms: number(&double_press_ref, timings.double_press_ms),
+                chord_ms: number(&chord_ref, timings.chord_ms),
+            }));
+        })
+    };
+
+    let timings = &button_timings_store.0;
+
+    html! {
+        <>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"Long press (ms)"}</label>
+                    <input class="input" type="number" min="1" value={timings.long_press_ms.to_string()} ref={long_press_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"Double press, 0 to disable (ms)"}</label>
+                    <input class="input" type="number" min="0" value={timings.double_press_ms.to_string()} ref={double_press_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"Chord (ms)"}</label>
+                    <input class="input" type="number" min="0" value={timings.chord_ms.to_string()} ref={chord_ref}/>
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
@@ -0,0 +1,38 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use ruwm::dto::water_meter::{FlowState, MAX_METERS};
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct FlowStore(pub [FlowState; MAX_METERS]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlowMsg(pub [FlowState; MAX_METERS]);
+
+impl Reducer<FlowStore> for FlowMsg {
+    fn apply(self, mut store: Rc<FlowStore>) -> Rc<FlowStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct FlowProps {
+    pub meter: usize,
+}
+
+#[function_component(Flow)]
+pub fn flow(props: &FlowProps) -> Html {
+    let flow_store = use_store_value::<FlowStore>();
+
+    let flow = &flow_store.0[props.meter];
+
+    html! {
+        {format!("Flowing: {}, edges/h: {}", flow.flowing, flow.edges_per_hour)}
+    }
+}
@@ -17,11 +17,37 @@ use edge_frame::wifi_setup::*;
 
 use ruwm::dto::web::*;
 
+use crate::alert::*;
+use crate::auth::*;
+use crate::away::*;
 use crate::battery::*;
+use crate::button::*;
+use crate::flow::*;
+use crate::meter::*;
+use crate::moisture::*;
+use crate::ota::*;
+use crate::power::*;
+use crate::pressure::*;
+use crate::temperature::*;
+use crate::time::*;
 use crate::valve::*;
+use crate::wifi::*;
 
+mod alert;
+mod auth;
+mod away;
 mod battery;
+mod button;
+mod flow;
+mod meter;
+mod moisture;
+mod ota;
+mod power;
+mod pressure;
+mod temperature;
+mod time;
 mod valve;
+mod wifi;
 
 #[cfg(feature = "sim")]
 static REQUEST_QUEUE: embassy_sync::channel::Channel<
@@ -40,6 +66,8 @@ static EVENT_QUEUE: embassy_sync::channel::Channel<
 enum Routes {
     #[at("/wifi")]
     Wifi,
+    #[at("/update")]
+    Update,
     #[at("/authstate")]
     AuthState,
     #[at("/")]
@@ -78,6 +106,7 @@ fn render(route: Routes) -> Html {
                 <Role role={RoleDto::Admin}>
                     <RouteNavItem<Routes> text="Home" icon="fa-solid fa-droplet" route={Routes::Home}/>
                     <WifiNavItem<Routes> route={Routes::Wifi}/>
+                    <RouteNavItem<Routes> text="Update" icon="fa-solid fa-download" route={Routes::Update}/>
                 </Role>
             </Nav>
             <Status>
@@ -91,8 +120,17 @@ fn render(route: Routes) -> Html {
                     match route {
                         Routes::Home => html! {
                             <Role role={RoleDto::User} auth=true>
+                                <Time/>
                                 <Valve/>
+                                <Meters/>
+                                <Moisture/>
+                                <Temperature/>
+                                <Pressure/>
+                                <Alerts/>
+                                <Away/>
                                 <Battery/>
+                                <Power/>
+                                <Buttons/>
                             </Role>
                         },
                         Routes::AuthState => html! {
@@ -101,6 +139,12 @@ fn render(route: Routes) -> Html {
                         Routes::Wifi => html! {
                             <Role role={RoleDto::Admin} auth=true>
                                 <WifiSetup/>
+                                <WifiRetries/>
+                            </Role>
+                        },
+                        Routes::Update => html! {
+                            <Role role={RoleDto::Admin} auth=true>
+                                <Ota/>
                             </Role>
                         },
                     }
@@ -111,7 +155,7 @@ fn render(route: Routes) -> Html {
 }
 
 fn init_middleware(mcx: &MiddlewareContext) {
-    // Dispatch WebEvent messages => redispatch as BatteryMsg, ValveMsg, RoleState or WifiConf messages
+    // Dispatch WebEvent messages => redispatch as AlertMsg, AwayMsg, BatteryMsg, FlowMsg, WaterMeterMsg, MeterConfigMsg, MoistureMsg, TemperatureMsg, PressureMsg, PowerPolicyMsg, ButtonTimingsMsg, WifiPolicyMsg, ValveMsg, ValveProfileMsg, ValveFaultMsg, ValveConfigMsg, OtaMsg, RoleState or WifiConf messages
     mcx.register::<WebEvent, _>(|mcx: &MiddlewareContext, event| {
         match event {
             WebEvent::NoPermissions => unreachable!(),
@@ -123,8 +167,31 @@ fn init_middleware(mcx: &MiddlewareContext) {
             } // TODO
             WebEvent::RoleState(role) => mcx.invoke(RoleState::Role(role)),
             WebEvent::ValveState(valve) => mcx.invoke(ValveMsg(valve)),
-            WebEvent::BatteryState(battery) => mcx.invoke(BatteryMsg(battery)),
-            WebEvent::WaterMeterState(_) => (), // TODO
+            WebEvent::ValveProfile(profile) => mcx.invoke(ValveProfileMsg(profile)),
+            WebEvent::ValveFault(fault) => mcx.invoke(ValveFaultMsg(fault)),
+            WebEvent::ValveConfig(config) => mcx.invoke(ValveConfigMsg(config)),
+            WebEvent::BatteryState(battery) => mcx.invoke(BatteryMsg::State(battery)),
+            WebEvent::BatteryConfig(config) => mcx.invoke(BatteryMsg::Config(config)),
+            WebEvent::PowerPolicy(policy) => mcx.invoke(PowerPolicyMsg(policy)),
+            WebEvent::ButtonTimings(timings) => mcx.invoke(ButtonTimingsMsg(timings)),
+            WebEvent::WifiPolicy(policy) => mcx.invoke(WifiPolicyMsg(policy)),
+            WebEvent::WaterMeterState(wm) => mcx.invoke(WaterMeterMsg(wm)),
+            WebEvent::WaterMeterConfig(config) => mcx.invoke(MeterConfigMsg(config)),
+            WebEvent::FlowState(flow) => mcx.invoke(FlowMsg(flow)),
+            WebEvent::MoistureState(moisture) => mcx.invoke(MoistureMsg::State(moisture)),
+            WebEvent::MoistureConfig(config) => mcx.invoke(MoistureMsg::Config(config)),
+            WebEvent::TemperatureState(temperature) => {
+                mcx.invoke(TemperatureMsg::State(temperature))
+            }
+            WebEvent::TemperatureConfig(config) => mcx.invoke(TemperatureMsg::Config(config)),
+            WebEvent::PressureState(pressure) => mcx.invoke(PressureMsg::State(pressure)),
+            WebEvent::PressureConfig(config) => mcx.invoke(PressureMsg::Config(config)),
+            WebEvent::OtaState(ota) => mcx.invoke(OtaMsg(ota)),
+            WebEvent::AlertState(alert) => mcx.invoke(AlertMsg::State(alert)),
+            WebEvent::AlertConfig(config) => mcx.invoke(AlertMsg::Config(config)),
+            WebEvent::AwayState(away) => mcx.invoke(AwayMsg::State(away)),
+            WebEvent::AwayConfig(config) => mcx.invoke(AwayMsg::Config(config)),
+            WebEvent::TimeState(time) => mcx.invoke(TimeMsg(time)),
         }
     });
 
@@ -133,12 +200,47 @@ fn init_middleware(mcx: &MiddlewareContext) {
     ));
     mcx.register(log::<WifiConfStore, WifiConf>(MiddlewareContext::store));
     mcx.register(log::<BatteryStore, BatteryMsg>(MiddlewareContext::store));
+    mcx.register(log::<PowerPolicyStore, PowerPolicyMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<ButtonTimingsStore, ButtonTimingsMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<WifiPolicyStore, WifiPolicyMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<FlowStore, FlowMsg>(MiddlewareContext::store));
+    mcx.register(log::<WaterMeterStore, WaterMeterMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<MeterConfigStore, MeterConfigMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<MoistureStore, MoistureMsg>(MiddlewareContext::store));
+    mcx.register(log::<TemperatureStore, TemperatureMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<PressureStore, PressureMsg>(MiddlewareContext::store));
+    mcx.register(log::<AlertStore, AlertMsg>(MiddlewareContext::store));
+    mcx.register(log::<AwayStore, AwayMsg>(MiddlewareContext::store));
+    mcx.register(log::<TimeStore, TimeMsg>(MiddlewareContext::store));
     mcx.register(log::<ValveStore, ValveMsg>(MiddlewareContext::store));
+    mcx.register(log::<ValveProfileStore, ValveProfileMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<ValveFaultStore, ValveFaultMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<ValveConfigStore, ValveConfigMsg>(
+        MiddlewareContext::store,
+    ));
+    mcx.register(log::<OtaStore, OtaMsg>(MiddlewareContext::store));
+    mcx.register(log::<AuthStore, AuthMsg>(MiddlewareContext::store));
 
     #[cfg(not(feature = "sim"))]
     {
         let (sender, receiver) =
-            middleware::open("/ws").unwrap_or_else(|_| panic!("Failed to open websocket"));
+            middleware::open(&ws_url("/ws")).unwrap_or_else(|_| panic!("Failed to open websocket"));
 
         // Dispatch WebRequest messages => send to backend
         mcx.register(middleware::send::<WebRequest>(sender));
@@ -159,6 +261,21 @@ fn init_middleware(mcx: &MiddlewareContext) {
     }
 }
 
+/// The absolute URL of a websocket endpoint on the device serving the page,
+/// so that pages served over HTTPS use a secure websocket
+#[cfg(not(feature = "sim"))]
+fn ws_url(path: &str) -> String {
+    let location = web_sys::window().unwrap().location();
+
+    let scheme = if location.protocol().ok().as_deref() == Some("https:") {
+        "wss"
+    } else {
+        "ws"
+    };
+
+    format!("{scheme}://{}{path}", location.host().unwrap())
+}
+
 #[cfg(feature = "sim")]
 pub fn local_queue() -> (
     embassy_sync::channel::DynamicSender<'static, WebEvent>,
@@ -183,11 +300,19 @@ fn role_as_request(
     dispatch: impl MiddlewareDispatch<RoleState>,
 ) {
     let request = match &msg {
-        RoleState::Authenticating(credentials) => Some(WebRequest::Authenticate(
-            credentials.username.as_str().try_into().unwrap(),
-            credentials.password.as_str().try_into().unwrap(),
-        )),
-        RoleState::LoggingOut(_) => Some(WebRequest::Logout),
+        RoleState::Authenticating(credentials) => {
+            mcx.invoke(AuthMsg::basic(&credentials.username, &credentials.password));
+
+            Some(WebRequest::Authenticate(
+                credentials.username.as_str().try_into().unwrap(),
+                credentials.password.as_str().try_into().unwrap(),
+            ))
+        }
+        RoleState::LoggingOut(_) => {
+            mcx.invoke(AuthMsg(None));
+
+            Some(WebRequest::Logout)
+        }
         _ => None,
     };
 
@@ -0,0 +1,250 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::valve::MAX_VALVES;
+use ruwm::dto::water_meter::{
+    LeakPolicy, MeterConfig, WaterMeterCommand, WaterMeterState, MAX_METERS,
+};
+use ruwm::dto::web::WebRequest;
+
+use crate::flow::Flow;
+use crate::valve::ValveConfigStore;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct WaterMeterStore(pub [WaterMeterState; MAX_METERS]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WaterMeterMsg(pub [WaterMeterState; MAX_METERS]);
+
+impl Reducer<WaterMeterStore> for WaterMeterMsg {
+    fn apply(self, mut store: Rc<WaterMeterStore>) -> Rc<WaterMeterStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct MeterConfigStore(pub [MeterConfig; MAX_METERS]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MeterConfigMsg(pub [MeterConfig; MAX_METERS]);
+
+impl Reducer<MeterConfigStore> for MeterConfigMsg {
+    fn apply(self, mut store: Rc<MeterConfigStore>) -> Rc<MeterConfigStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[function_component(Meters)]
+pub fn meters() -> Html {
+    html! {
+        {
+            (0..MAX_METERS).map(|meter| html! {
+                <Meter {meter}/>
+            }).collect::<Html>()
+        }
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct MeterProps {
+    pub meter: usize,
+}
+
+/// A single water meter; only the admins see the disabled ones, so as to enable them
+#[function_component(Meter)]
+pub fn meter(props: &MeterProps) -> Html {
+    let meter = props.meter;
+
+    let config_store = use_store_value::<MeterConfigStore>();
+
+    let config = &config_store.0[meter];
+
+    html! {
+        <>
+            <h2 class="subtitle">{config.display_name(meter).to_string()}</h2>
+            if config.enabled {
+                <MeterControl {meter}/>
+                <Flow {meter}/>
+            }
+            <Role role={RoleDto::Admin}>
+                <MeterConfigForm {meter}/>
+            </Role>
+        </>
+    }
+}
+
+#[function_component(MeterControl)]
+fn meter_control(props: &MeterProps) -> Html {
+    let meter = props.meter;
+
+    let wm_store = use_store_value::<WaterMeterStore>();
+    let mcx = use_mcx();
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::WaterMeterCommand(meter, command)))
+    };
+
+    let state = wm_store.0[meter];
+
+    html! {
+        <>
+            <p>{format!("Edges: {}", state.edges_count)}</p>
+            if state.leaking {
+                <p class="has-text-danger">{"Leak detected"}</p>
+            }
+            <div class="field is-grouped">
+                <p class="control">
+                    <button class="button is-small" disabled={state.armed} onclick={command(WaterMeterCommand::Arm)}>
+                        {"Arm"}
+                    </button>
+                </p>
+                <p class="control">
+                    <button class="button is-small" disabled={!state.armed} onclick={command(WaterMeterCommand::Disarm)}>
+                        {"Disarm"}
+                    </button>
+                </p>
+            </div>
+        </>
+    }
+}
+
+#[function_component(MeterConfigForm)]
+fn meter_config_form(props: &MeterProps) -> Html {
+    let meter = props.meter;
+
+    let config_store = use_store_value::<MeterConfigStore>();
+    let valve_config_store = use_store_value::<ValveConfigStore>();
+    let mcx = use_mcx();
+
+    // Closing all valves, closing one of them, or only reporting the leak
+    let policies = [LeakPolicy::CloseAll]
+        .into_iter()
+        .chain((0..MAX_VALVES).map(LeakPolicy::CloseValve))
+        .chain([LeakPolicy::Report])
+        .collect::<Vec<_>>();
+
+    let enabled_ref = use_node_ref();
+    let name_ref = use_node_ref();
+    let edges_per_liter_ref = use_node_ref();
+    let policy_refs = policies
+        .iter()
+        .map(|_| NodeRef::default())
+        .collect::<Vec<_>>();
+
+    let onsave = {
+        let enabled_ref = enabled_ref.clone();
+        let name_ref = name_ref.clone();
+        let edges_per_liter_ref = edges_per_liter_ref.clone();
+        let policies = policies.clone();
+        let policy_refs = policy_refs.clone();
+        let config = config_store.0[meter].clone();
+
+        Callback::from(move |_| {
+            let checked = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.checked())
+                    .unwrap_or(false)
+            };
+
+            // A name which is too long keeps the current one, as does an invalid calibration
+            let name = name_ref
+                .cast::<HtmlInputElement>()
+                .and_then(|input| input.value().trim().try_into().ok())
+                .unwrap_or_else(|| config.name.clone());
+
+            let edges_per_liter = edges_per_liter_ref
+                .cast::<HtmlInputElement>()
+                .and_then(|input| input.value().trim().parse::<u32>().ok())
+                .filter(|edges_per_liter| *edges_per_liter > 0)
+                .unwrap_or(config.edges_per_liter);
+
+            let leak = policies
+                .iter()
+                .zip(policy_refs.iter())
+                .find_map(|(policy, policy_ref)| checked(policy_ref).then_some(*policy))
+                .unwrap_or(config.leak);
+
+            mcx.invoke(WebRequest::WaterMeterConfig(
+                meter,
+                MeterConfig {
+                    enabled: meter == 0 || checked(&enabled_ref),
+                    name,
+                    edges_per_liter,
+                    leak,
+                },
+            ));
+        })
+    };
+
+    let config = &config_store.0[meter];
+
+    let policy_text = |policy: &LeakPolicy| match policy {
+        LeakPolicy::CloseAll => "Close all valves on a leak".to_string(),
+        LeakPolicy::CloseValve(valve) => format!(
+            "Close {} on a leak",
+            valve_config_store.0[*valve].display_name(*valve)
+        ),
+        LeakPolicy::Report => "Report a leak only".to_string(),
+    };
+
+    html! {
+        <>
+            if meter > 0 {
+                <div class="field">
+                    <label class="checkbox">
+                        <input type="checkbox" checked={config.enabled} ref={enabled_ref}/>
+                        {" Installed"}
+                    </label>
+                </div>
+            }
+            <div class="field">
+                <label class="label">{"Meter name"}</label>
+                <div class="control">
+                    <input class="input" type="text" value={config.name.as_str().to_string()} ref={name_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Pulse edges per liter"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={config.edges_per_liter.to_string()} ref={edges_per_liter_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                {
+                    policies.iter().zip(policy_refs.iter()).map(|(policy, policy_ref)| html! {
+                        <label class="radio">
+                            <input
+                                type="radio"
+                                name={format!("meter-{meter}-leak")}
+                                checked={config.leak == *policy}
+                                ref={policy_ref.clone()}
+                            />
+                            {format!(" {}", policy_text(policy))}
+                        </label>
+                    }).collect::<Html>()
+                }
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
@@ -0,0 +1,158 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::moisture::{SensorConfig, SensorState, MAX_SENSORS};
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct MoistureStore {
+    pub state: [Option<SensorState>; MAX_SENSORS],
+    pub config: [SensorConfig; MAX_SENSORS],
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MoistureMsg {
+    State([Option<SensorState>; MAX_SENSORS]),
+    Config([SensorConfig; MAX_SENSORS]),
+}
+
+impl Reducer<MoistureStore> for MoistureMsg {
+    fn apply(self, mut store: Rc<MoistureStore>) -> Rc<MoistureStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self {
+            Self::State(moisture_state) => state.state = moisture_state,
+            Self::Config(config) => state.config = config,
+        }
+
+        store
+    }
+}
+
+/// The installed moisture sensors, i.e. the ones which report a state
+#[function_component(Moisture)]
+pub fn moisture() -> Html {
+    let moisture_store = use_store_value::<MoistureStore>();
+
+    let sensors = moisture_store
+        .state
+        .iter()
+        .enumerate()
+        .filter_map(|(sensor, state)| state.map(|state| (sensor, state)))
+        .collect::<Vec<_>>();
+
+    if sensors.is_empty() {
+        return html! {};
+    }
+
+    html! {
+        <>
+            <h2 class="subtitle">{"Moisture sensors"}</h2>
+            {
+                sensors.into_iter().map(|(sensor, state)| html! {
+                    <>
+                        <p class={if state.is_wet() { "has-text-danger" } else { "" }}>
+                            {format!(
+                                "{}: {}",
+                                moisture_store.config[sensor].display_name(sensor),
+                                if state.is_wet() { "Wet" } else { "Dry" },
+                            )}
+                        </p>
+                        <Role role={RoleDto::Admin}>
+                            <SensorConfigForm {sensor}/>
+                        </Role>
+                    </>
+                }).collect::<Html>()
+            }
+        </>
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+struct SensorProps {
+    sensor: usize,
+}
+
+#[function_component(SensorConfigForm)]
+fn sensor_config_form(props: &SensorProps) -> Html {
+    let sensor = props.sensor;
+
+    let moisture_store = use_store_value::<MoistureStore>();
+    let mcx = use_mcx();
+
+    let name_ref = use_node_ref();
+    let debounce_ref = use_node_ref();
+    let threshold_ref = use_node_ref();
+
+    let onsave = {
+        let name_ref = name_ref.clone();
+        let debounce_ref = debounce_ref.clone();
+        let threshold_ref = threshold_ref.clone();
+        let config = moisture_store.config[sensor].clone();
+
+        Callback::from(move |_| {
+            let value = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.value().trim().to_string())
+            };
+
+            // A name which is too long keeps the current one, as do invalid numbers
+            let name = value(&name_ref)
+                .and_then(|name| name.as_str().try_into().ok())
+                .unwrap_or_else(|| config.name.clone());
+
+            let debounce_ms = value(&debounce_ref)
+                .and_then(|debounce_ms| debounce_ms.parse().ok())
+                .unwrap_or(config.debounce_ms);
+
+            let threshold = value(&threshold_ref)
+                .and_then(|threshold| threshold.parse().ok())
+                .unwrap_or(config.threshold);
+
+            mcx.invoke(WebRequest::MoistureConfig(
+                sensor,
+                SensorConfig {
+                    name,
+                    debounce_ms,
+                    threshold,
+                },
+            ));
+        })
+    };
+
+    let config = &moisture_store.config[sensor];
+
+    html! {
+        <>
+            <div class="field">
+                <label class="label">{"Sensor name"}</label>
+                <div class="control">
+                    <input class="input" type="text" value={config.name.as_str().to_string()} ref={name_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Debounce (ms)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={config.debounce_ms.to_string()} ref={debounce_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Wet threshold (analog sensors)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={config.threshold.to_string()} ref={threshold_ref}/>
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
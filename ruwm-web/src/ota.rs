@@ -0,0 +1,202 @@
+use std::rc::Rc;
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+
+use web_sys::{File, HtmlInputElement, Storage, XmlHttpRequest};
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use ruwm::dto::ota::{FirmwareVersion, OtaCommand, OtaState, OtaStatus};
+use ruwm::dto::web::WebRequest;
+
+use crate::auth::AuthStore;
+
+const UPLOAD_URI: &str = "/api/firmware";
+
+// Survives the page being reloaded while the device restarts
+const EXPECTED_VERSION_KEY: &str = "ruwm-expected-version";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum UpdateOutcome {
+    Updated(FirmwareVersion),
+    RolledBack {
+        expected: FirmwareVersion,
+        running: FirmwareVersion,
+    },
+}
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct OtaStore {
+    pub state: OtaState,
+    pub outcome: Option<UpdateOutcome>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OtaMsg(pub OtaState);
+
+impl Reducer<OtaStore> for OtaMsg {
+    fn apply(self, mut store: Rc<OtaStore>) -> Rc<OtaStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self.0.status {
+            OtaStatus::Ready(version) => set_expected_version(version),
+            _ => {
+                // The first state after the restart tells whether the new firmware is the one running
+                if let (Some(expected), Some(running)) =
+                    (expected_version(), self.0.running_version)
+                {
+                    set_expected_version(None);
+
+                    state.outcome = Some(if expected == running {
+                        UpdateOutcome::Updated(running)
+                    } else {
+                        UpdateOutcome::RolledBack { expected, running }
+                    });
+                }
+            }
+        }
+
+        state.state = self.0;
+
+        store
+    }
+}
+
+#[function_component(Ota)]
+pub fn ota() -> Html {
+    let ota_store = use_store_value::<OtaStore>();
+    let auth_store = use_store_value::<AuthStore>();
+    let mcx = use_mcx();
+
+    let file_ref = use_node_ref();
+    let upload_error = use_state(|| None::<String>);
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::OtaCommand(command)))
+    };
+
+    let onupload = {
+        let file_ref = file_ref.clone();
+        let authorization = auth_store.0.clone();
+        let upload_error = upload_error.clone();
+
+        Callback::from(move |_| {
+            let file = file_ref
+                .cast::<HtmlInputElement>()
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0));
+
+            if let Some(file) = file {
+                upload_error.set(None);
+
+                let on_error = upload_error.clone();
+
+                let result = upload(&file, authorization.as_deref(), move |status| {
+                    if status != 200 {
+                        on_error.set(Some(format!("Upload failed with HTTP status {status}")));
+                    }
+                });
+
+                if let Err(err) = result {
+                    upload_error.set(Some(format!("Upload failed: {err:?}")));
+                }
+            }
+        })
+    };
+
+    let status = ota_store.state.status;
+
+    html! {
+        <>
+            <p>
+                {format!(
+                    "Firmware: {}",
+                    ota_store.state.running_version.map(|version| version.to_string()).unwrap_or_else(|| "unknown".into())
+                )}
+            </p>
+            <p>{format!("Update: {:?}", status)}</p>
+            if let Some(progress) = status.progress() {
+                <progress class="progress is-primary" value={progress.to_string()} max="100"/>
+            }
+            if let Some(outcome) = ota_store.outcome {
+                <p>
+                    {match outcome {
+                        UpdateOutcome::Updated(version) => format!("Update succeeded, now running {version}"),
+                        UpdateOutcome::RolledBack { expected, running } => format!("Update to {expected} did not stick, still running {running}"),
+                    }}
+                </p>
+            }
+            <div class="buttons">
+                <button class="button" disabled={status.is_busy()} onclick={command(OtaCommand::CheckForUpdate)}>
+                    {"Check for Update"}
+                </button>
+                <button class="button is-primary" disabled={!matches!(status, OtaStatus::UpdateAvailable(_))} onclick={command(OtaCommand::Update)}>
+                    {"Update"}
+                </button>
+            </div>
+            <div class="field has-addons">
+                <div class="control">
+                    <input class="input" type="file" accept=".bin" ref={file_ref}/>
+                </div>
+                <div class="control">
+                    <button class="button is-primary" disabled={status.is_busy()} onclick={onupload}>
+                        {"Upload"}
+                    </button>
+                </div>
+            </div>
+            if let Some(error) = upload_error.as_ref() {
+                <p class="help is-danger">{error}</p>
+            }
+        </>
+    }
+}
+
+// Progress is not tracked here, as the backend reports it over the websocket
+fn upload(
+    file: &File,
+    authorization: Option<&str>,
+    on_done: impl FnOnce(u16) + 'static,
+) -> Result<(), JsValue> {
+    let request = XmlHttpRequest::new()?;
+
+    request.open_with_async("POST", UPLOAD_URI, true)?;
+
+    if let Some(authorization) = authorization {
+        request.set_request_header("Authorization", authorization)?;
+    }
+
+    let onloadend = {
+        let request = request.clone();
+
+        Closure::once_into_js(move || on_done(request.status().unwrap_or(0)))
+    };
+
+    request.set_onloadend(Some(onloadend.unchecked_ref()));
+    request.send_with_opt_blob(Some(file.as_ref()))
+}
+
+fn session_storage() -> Option<Storage> {
+    web_sys::window()?.session_storage().ok()?
+}
+
+fn expected_version() -> Option<FirmwareVersion> {
+    session_storage()?
+        .get_item(EXPECTED_VERSION_KEY)
+        .ok()??
+        .parse()
+        .ok()
+}
+
+fn set_expected_version(version: Option<FirmwareVersion>) {
+    if let Some(storage) = session_storage() {
+        let _ = match version {
+            Some(version) => storage.set_item(EXPECTED_VERSION_KEY, &version.to_string()),
+            None => storage.remove_item(EXPECTED_VERSION_KEY),
+        };
+    }
+}
@@ -0,0 +1,133 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::power::PowerPolicy;
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct PowerPolicyStore(pub PowerPolicy);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PowerPolicyMsg(pub PowerPolicy);
+
+impl Reducer<PowerPolicyStore> for PowerPolicyMsg {
+    fn apply(self, mut store: Rc<PowerPolicyStore>) -> Rc<PowerPolicyStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[function_component(Power)]
+pub fn power() -> Html {
+    html! {
+        <Role role={RoleDto::Admin}>
+            <h2 class="subtitle">{"Power"}</h2>
+            <PowerPolicyForm/>
+        </Role>
+    }
+}
+
+#[function_component(PowerPolicyForm)]
+fn power_policy_form() -> Html {
+    let power_policy_store = use_store_value::<PowerPolicyStore>();
+    let mcx = use_mcx();
+
+    let button_awake_ref = use_node_ref();
+    let leak_awake_ref = use_node_ref();
+    let timer_awake_ref = use_node_ref();
+    let armed_sleep_ref = use_node_ref();
+    let disarmed_sleep_ref = use_node_ref();
+    let low_battery_sleep_ref = use_node_ref();
+    let web_keeps_awake_ref = use_node_ref();
+
+    let onsave = {
+        let button_awake_ref = button_awake_ref.clone();
+        let leak_awake_ref = leak_awake_ref.clone();
+        let timer_awake_ref = timer_awake_ref.clone();
+        let armed_sleep_ref = armed_sleep_ref.clone();
+        let disarmed_sleep_ref = disarmed_sleep_ref.clone();
+        let low_battery_sleep_ref = low_battery_sleep_ref.clone();
+        let web_keeps_awake_ref = web_keeps_awake_ref.clone();
+        let policy = power_policy_store.0;
+
+        Callback::from(move |_| {
+            // Invalid numbers keep the current ones
+            fn number<T: core::str::FromStr>(node_ref: &NodeRef, current: T) -> T {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse().ok())
+                    .unwrap_or(current)
+            }
+
+            mcx.invoke(WebRequest::PowerPolicy(PowerPolicy {
+                button_awake_secs: number(&button_awake_ref, policy.button_awake_secs),
+                leak_awake_secs: number(&leak_awake_ref, policy.leak_awake_secs),
+                timer_awake_secs: number(&timer_awake_ref, policy.timer_awake_secs),
+                armed_sleep_secs: number(&armed_sleep_ref, policy.armed_sleep_secs),
+                disarmed_sleep_secs: number(&disarmed_sleep_ref, policy.disarmed_sleep_secs),
+                low_battery_sleep_secs: number(
+                    &low_battery_sleep_ref,
+                    policy.low_battery_sleep_secs,
+                ),
+                web_keeps_awake: web_keeps_awake_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.checked())
+                    .unwrap_or(policy.web_keeps_awake),
+            }));
+        })
+    };
+
+    let policy = &power_policy_store.0;
+
+    html! {
+        <>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"Awake after a button (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.button_awake_secs.to_string()} ref={button_awake_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"After a leak (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.leak_awake_secs.to_string()} ref={leak_awake_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"After the timer (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.timer_awake_secs.to_string()} ref={timer_awake_ref}/>
+                </div>
+            </div>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"Sleep when armed (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.armed_sleep_secs.to_string()} ref={armed_sleep_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"When disarmed (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.disarmed_sleep_secs.to_string()} ref={disarmed_sleep_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"At least, on a low battery (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.low_battery_sleep_secs.to_string()} ref={low_battery_sleep_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="checkbox">
+                    <input type="checkbox" checked={policy.web_keeps_awake} ref={web_keeps_awake_ref}/>
+                    {" Stay awake while a web client is connected"}
+                </label>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
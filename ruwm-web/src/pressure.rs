@@ -0,0 +1,185 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::pressure::{PressureCommand, PressureConfig, PressureState};
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct PressureStore {
+    pub state: PressureState,
+    pub config: PressureConfig,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PressureMsg {
+    State(PressureState),
+    Config(PressureConfig),
+}
+
+impl Reducer<PressureStore> for PressureMsg {
+    fn apply(self, mut store: Rc<PressureStore>) -> Rc<PressureStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self {
+            Self::State(pressure_state) => state.state = pressure_state,
+            Self::Config(config) => state.config = config,
+        }
+
+        store
+    }
+}
+
+/// Only shown with a pressure transducer
+#[function_component(Pressure)]
+pub fn pressure() -> Html {
+    let pressure_store = use_store_value::<PressureStore>();
+    let mcx = use_mcx();
+
+    let Some(pressure) = pressure_store.state.pressure else {
+        return html! {};
+    };
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::PressureCommand(command)))
+    };
+
+    let state = pressure_store.state;
+
+    let test = match (state.test_start, state.test_drop) {
+        (Some(start), _) => format!("Testing, {} mbar lost", start.saturating_sub(pressure)),
+        (None, Some(drop)) => format!("Last test: {} mbar lost", drop),
+        (None, None) => "Not tested yet".to_string(),
+    };
+
+    html! {
+        <>
+            <h2 class="subtitle">{"Pressure"}</h2>
+            <p>{format!("{} mbar", pressure)}</p>
+            <div class="field is-grouped">
+                <p class="control">{test}</p>
+                <p class="control">
+                    <button class="button is-small" disabled={state.is_testing()} onclick={command(PressureCommand::StartTest)}>
+                        {"Test"}
+                    </button>
+                </p>
+                <p class="control">
+                    <button class="button is-small" disabled={!state.is_testing()} onclick={command(PressureCommand::AbortTest)}>
+                        {"Abort"}
+                    </button>
+                </p>
+            </div>
+            <Role role={RoleDto::Admin}>
+                <PressureConfigForm/>
+            </Role>
+        </>
+    }
+}
+
+#[function_component(PressureConfigForm)]
+fn pressure_config_form() -> Html {
+    let pressure_store = use_store_value::<PressureStore>();
+    let mcx = use_mcx();
+
+    let zero_ref = use_node_ref();
+    let span_ref = use_node_ref();
+    let full_scale_ref = use_node_ref();
+    let test_at_ref = use_node_ref();
+    let test_mins_ref = use_node_ref();
+
+    let onsave = {
+        let zero_ref = zero_ref.clone();
+        let span_ref = span_ref.clone();
+        let full_scale_ref = full_scale_ref.clone();
+        let test_at_ref = test_at_ref.clone();
+        let test_mins_ref = test_mins_ref.clone();
+        let config = pressure_store.config;
+
+        Callback::from(move |_| {
+            let value = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.value().trim().to_string())
+            };
+
+            // Invalid numbers keep the current ones
+            let number = |node_ref: &NodeRef, current: u16| {
+                value(node_ref)
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(current)
+            };
+
+            // I.e. `03:00`; an empty or invalid time only runs the test on request
+            let test_at_mins = value(&test_at_ref).and_then(|test_at| {
+                let (hours, mins) = test_at.split_once(':')?;
+                let hours = hours.parse::<u16>().ok().filter(|hours| *hours < 24)?;
+                let mins = mins.parse::<u16>().ok().filter(|mins| *mins < 60)?;
+
+                Some(hours * 60 + mins)
+            });
+
+            mcx.invoke(WebRequest::PressureCommand(PressureCommand::Configure(
+                PressureConfig {
+                    zero: number(&zero_ref, config.zero),
+                    span: number(&span_ref, config.span),
+                    full_scale: number(&full_scale_ref, config.full_scale),
+                    test_at_mins,
+                    test_mins: number(&test_mins_ref, config.test_mins),
+                },
+            )));
+        })
+    };
+
+    let config = &pressure_store.config;
+
+    let test_at = config
+        .test_at_mins
+        .map(|mins| format!("{:02}:{:02}", mins / 60, mins % 60))
+        .unwrap_or_default();
+
+    html! {
+        <>
+            <div class="field">
+                <label class="label">{"Reading at zero pressure"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={config.zero.to_string()} ref={zero_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Reading at full scale"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={config.span.to_string()} ref={span_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Full scale (mbar)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={config.full_scale.to_string()} ref={full_scale_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Daily test at"}</label>
+                <div class="control">
+                    <input class="input" type="time" value={test_at} ref={test_at_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Test duration (minutes)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={config.test_mins.to_string()} ref={test_mins_ref}/>
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
@@ -0,0 +1,172 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::temperature::{Celsius, FreezeStatus, TemperatureConfig, TemperatureState};
+use ruwm::dto::valve::MAX_VALVES;
+use ruwm::dto::web::WebRequest;
+
+use crate::valve::ValveConfigStore;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct TemperatureStore {
+    pub state: TemperatureState,
+    pub config: TemperatureConfig,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TemperatureMsg {
+    State(TemperatureState),
+    Config(TemperatureConfig),
+}
+
+impl Reducer<TemperatureStore> for TemperatureMsg {
+    fn apply(self, mut store: Rc<TemperatureStore>) -> Rc<TemperatureStore> {
+        let state = Rc::make_mut(&mut store);
+
+        match self {
+            Self::State(temperature_state) => state.state = temperature_state,
+            Self::Config(config) => state.config = config,
+        }
+
+        store
+    }
+}
+
+/// Only shown with a temperature sensor
+#[function_component(Temperature)]
+pub fn temperature() -> Html {
+    let temperature_store = use_store_value::<TemperatureStore>();
+
+    let Some(temperature) = temperature_store.state.temperature else {
+        return html! {};
+    };
+
+    let (class, status) = match temperature_store.state.status {
+        FreezeStatus::Normal => ("", ""),
+        FreezeStatus::Warning => ("has-text-warning", " - risk of frost"),
+        FreezeStatus::Freezing => ("has-text-danger", " - freezing, valves closed"),
+    };
+
+    html! {
+        <>
+            <h2 class="subtitle">{"Temperature"}</h2>
+            <p {class}>{format!("{} °C{}", Celsius(temperature), status)}</p>
+            <Role role={RoleDto::Admin}>
+                <TemperatureConfigForm/>
+            </Role>
+        </>
+    }
+}
+
+#[function_component(TemperatureConfigForm)]
+fn temperature_config_form() -> Html {
+    let temperature_store = use_store_value::<TemperatureStore>();
+    let valve_config_store = use_store_value::<ValveConfigStore>();
+    let mcx = use_mcx();
+
+    // No drain, or one of the valves
+    let drains = [None]
+        .into_iter()
+        .chain((0..MAX_VALVES).map(Some))
+        .collect::<Vec<_>>();
+
+    let warn_ref = use_node_ref();
+    let freeze_ref = use_node_ref();
+    let drain_refs = drains
+        .iter()
+        .map(|_| NodeRef::default())
+        .collect::<Vec<_>>();
+
+    let onsave = {
+        let warn_ref = warn_ref.clone();
+        let freeze_ref = freeze_ref.clone();
+        let drains = drains.clone();
+        let drain_refs = drain_refs.clone();
+        let config = temperature_store.config;
+
+        Callback::from(move |_| {
+            // In °C; an empty or invalid threshold disables its policy
+            let threshold = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse::<f32>().ok())
+                    .map(|celsius| (celsius * 10.0).round() as i16)
+            };
+
+            let drain = drains
+                .iter()
+                .zip(drain_refs.iter())
+                .find_map(|(drain, drain_ref)| {
+                    drain_ref
+                        .cast::<HtmlInputElement>()
+                        .filter(|input| input.checked())
+                        .map(|_| *drain)
+                })
+                .unwrap_or(config.drain);
+
+            mcx.invoke(WebRequest::TemperatureConfig(TemperatureConfig {
+                warn_below: threshold(&warn_ref),
+                freeze_below: threshold(&freeze_ref),
+                drain,
+            }));
+        })
+    };
+
+    let config = &temperature_store.config;
+
+    let value = |threshold: Option<i16>| {
+        threshold
+            .map(|threshold| Celsius(threshold).to_string())
+            .unwrap_or_default()
+    };
+
+    let drain_text = |drain: &Option<usize>| match drain {
+        None => "No drain".to_string(),
+        Some(valve) => format!(
+            "Open {} to drain",
+            valve_config_store.0[*valve].display_name(*valve)
+        ),
+    };
+
+    html! {
+        <>
+            <div class="field">
+                <label class="label">{"Warn below (°C)"}</label>
+                <div class="control">
+                    <input class="input" type="number" step="0.1" value={value(config.warn_below)} ref={warn_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Close the valves below (°C)"}</label>
+                <div class="control">
+                    <input class="input" type="number" step="0.1" value={value(config.freeze_below)} ref={freeze_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                {
+                    drains.iter().zip(drain_refs.iter()).map(|(drain, drain_ref)| html! {
+                        <label class="radio">
+                            <input
+                                type="radio"
+                                name="temperature-drain"
+                                checked={config.drain == *drain}
+                                ref={drain_ref.clone()}
+                            />
+                            {format!(" {}", drain_text(drain))}
+                        </label>
+                    }).collect::<Html>()
+                }
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
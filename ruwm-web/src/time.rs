@@ -0,0 +1,36 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+
+use ruwm::dto::time::TimeState;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct TimeStore(pub TimeState);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimeMsg(pub TimeState);
+
+impl Reducer<TimeStore> for TimeMsg {
+    fn apply(self, mut store: Rc<TimeStore>) -> Rc<TimeStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+/// Warns while the clock is not synced, as the consumption history and the budgets need it
+#[function_component(Time)]
+pub fn time() -> Html {
+    let time_store = use_store_value::<TimeStore>();
+
+    html! {
+        if !time_store.0.valid {
+            <p class="has-text-warning">
+                {"The clock is not synced yet; the consumption history and budgets are on hold"}
+            </p>
+        }
+    }
+}
@@ -1,15 +1,24 @@
 use std::rc::Rc;
 
+use web_sys::HtmlInputElement;
+
 use yew::prelude::*;
 use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
 
-use ruwm::dto::valve::ValveState;
+use ruwm::dto::valve::{
+    CurrentLimits, EmergencyPolicy, ValveCommand, ValveConfig, ValveFault, ValveProfile,
+    ValveState, MAX_VALVES,
+};
+use ruwm::dto::web::WebRequest;
 
 #[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
-pub struct ValveStore(pub Option<ValveState>);
+pub struct ValveStore(pub [Option<ValveState>; MAX_VALVES]);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct ValveMsg(pub Option<ValveState>);
+pub struct ValveMsg(pub [Option<ValveState>; MAX_VALVES]);
 
 impl Reducer<ValveStore> for ValveMsg {
     fn apply(self, mut store: Rc<ValveStore>) -> Rc<ValveStore> {
@@ -21,11 +30,350 @@ impl Reducer<ValveStore> for ValveMsg {
     }
 }
 
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct ValveProfileStore(pub [ValveProfile; MAX_VALVES]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValveProfileMsg(pub [ValveProfile; MAX_VALVES]);
+
+impl Reducer<ValveProfileStore> for ValveProfileMsg {
+    fn apply(self, mut store: Rc<ValveProfileStore>) -> Rc<ValveProfileStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct ValveFaultStore(pub [Option<ValveFault>; MAX_VALVES]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValveFaultMsg(pub [Option<ValveFault>; MAX_VALVES]);
+
+impl Reducer<ValveFaultStore> for ValveFaultMsg {
+    fn apply(self, mut store: Rc<ValveFaultStore>) -> Rc<ValveFaultStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct ValveConfigStore(pub [ValveConfig; MAX_VALVES]);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValveConfigMsg(pub [ValveConfig; MAX_VALVES]);
+
+impl Reducer<ValveConfigStore> for ValveConfigMsg {
+    fn apply(self, mut store: Rc<ValveConfigStore>) -> Rc<ValveConfigStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
 #[function_component(Valve)]
 pub fn valve() -> Html {
+    html! {
+        {
+            (0..MAX_VALVES).map(|valve| html! {
+                <ValveZone {valve}/>
+            }).collect::<Html>()
+        }
+    }
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct ValveZoneProps {
+    pub valve: usize,
+}
+
+/// A single valve; only the admins see the disabled ones, so as to enable them
+#[function_component(ValveZone)]
+pub fn valve_zone(props: &ValveZoneProps) -> Html {
+    let valve = props.valve;
+
+    let config_store = use_store_value::<ValveConfigStore>();
+
+    let config = &config_store.0[valve];
+
+    html! {
+        <>
+            <h2 class="subtitle">{config.display_name(valve).to_string()}</h2>
+            if config.enabled {
+                <ValveControl {valve}/>
+                <Role role={RoleDto::Admin}>
+                    <ValveProfileForm {valve}/>
+                </Role>
+            }
+            <Role role={RoleDto::Admin}>
+                <ValveConfigForm {valve}/>
+            </Role>
+        </>
+    }
+}
+
+#[function_component(ValveControl)]
+fn valve_control(props: &ValveZoneProps) -> Html {
+    let valve = props.valve;
+
     let valve_store = use_store_value::<ValveStore>();
+    let profile_store = use_store_value::<ValveProfileStore>();
+    let fault_store = use_store_value::<ValveFaultStore>();
+    let mcx = use_mcx();
+
+    let command = |command| {
+        let mcx = mcx.clone();
+
+        Callback::from(move |_| mcx.invoke(WebRequest::ValveCommand(valve, command)))
+    };
+
+    let state = valve_store.0[valve];
+
+    html! {
+        <>
+            <p>{format!("Valve State: {:?}", state.as_ref())}</p>
+            if let Some(fault) = fault_store.0[valve] {
+                <p class="has-text-danger">{format!("Valve fault: {}", fault.name())}</p>
+            }
+            <div class="field is-grouped">
+                <p class="control">
+                    <button class="button is-small" disabled={matches!(state, Some(ValveState::Open) | Some(ValveState::Opening(_)))} onclick={command(ValveCommand::Open)}>
+                        {"Open"}
+                    </button>
+                </p>
+                <p class="control">
+                    <button class="button is-small" disabled={matches!(state, Some(ValveState::Closed) | Some(ValveState::Closing(_)))} onclick={command(ValveCommand::Close)}>
+                        {"Close"}
+                    </button>
+                </p>
+                if profile_store.0[valve].partial.is_some() {
+                    <p class="control">
+                        <button class="button is-small" onclick={command(ValveCommand::OpenPartially)}>
+                            {"Partial Open"}
+                        </button>
+                    </p>
+                }
+            </div>
+        </>
+    }
+}
+
+#[function_component(ValveProfileForm)]
+fn valve_profile_form(props: &ValveZoneProps) -> Html {
+    let valve = props.valve;
+
+    let profile_store = use_store_value::<ValveProfileStore>();
+    let mcx = use_mcx();
+
+    let open_ref = use_node_ref();
+    let close_ref = use_node_ref();
+    let dead_time_ref = use_node_ref();
+    let partial_ref = use_node_ref();
+    let stall_ref = use_node_ref();
+    let overcurrent_ref = use_node_ref();
+    let inrush_ref = use_node_ref();
+
+    let onsave = {
+        let open_ref = open_ref.clone();
+        let close_ref = close_ref.clone();
+        let dead_time_ref = dead_time_ref.clone();
+        let partial_ref = partial_ref.clone();
+        let stall_ref = stall_ref.clone();
+        let overcurrent_ref = overcurrent_ref.clone();
+        let inrush_ref = inrush_ref.clone();
+        let profile = profile_store.0[valve];
+
+        Callback::from(move |_| {
+            let value = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse::<u32>().ok())
+            };
+
+            // Invalid travel and dead times keep the current ones, while an empty or invalid
+            // partial position or current limit disables it
+            let new_profile = ValveProfile {
+                open_ms: value(&open_ref)
+                    .map(|secs| secs * 1000)
+                    .unwrap_or(profile.open_ms),
+                close_ms: value(&close_ref)
+                    .map(|secs| secs * 1000)
+                    .unwrap_or(profile.close_ms),
+                dead_time_ms: value(&dead_time_ref).unwrap_or(profile.dead_time_ms),
+                partial: value(&partial_ref)
+                    .filter(|partial| (1..100).contains(partial))
+                    .map(|partial| partial as u8),
+                current_limits: value(&stall_ref).zip(value(&overcurrent_ref)).map(
+                    |(stall, overcurrent)| CurrentLimits {
+                        stall: stall.min(u16::MAX as _) as _,
+                        overcurrent: overcurrent.min(u16::MAX as _) as _,
+                        inrush_ms: value(&inrush_ref).unwrap_or(0),
+                    },
+                ),
+            };
+
+            mcx.invoke(WebRequest::ValveProfile(valve, new_profile));
+        })
+    };
+
+    let profile = profile_store.0[valve];
+    let limits = profile.current_limits;
+
+    html! {
+        <>
+            <div class="field">
+                <label class="label">{"Opening time (s)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={(profile.open_ms / 1000).to_string()} ref={open_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Closing time (s)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={(profile.close_ms / 1000).to_string()} ref={close_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Dead time before reversing (ms)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={profile.dead_time_ms.to_string()} ref={dead_time_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Partial position (%)"}</label>
+                <div class="control">
+                    <input
+                        class="input"
+                        type="number"
+                        min="1"
+                        max="99"
+                        value={profile.partial.map(|partial| partial.to_string()).unwrap_or_default()}
+                        ref={partial_ref}
+                    />
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Stall current (ADC units)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={limits.map(|limits| limits.stall.to_string()).unwrap_or_default()} ref={stall_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Overcurrent (ADC units)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="1" value={limits.map(|limits| limits.overcurrent.to_string()).unwrap_or_default()} ref={overcurrent_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="label">{"Inrush time (ms)"}</label>
+                <div class="control">
+                    <input class="input" type="number" min="0" value={limits.map(|limits| limits.inrush_ms.to_string()).unwrap_or_default()} ref={inrush_ref}/>
+                </div>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
+
+const POLICIES: [(EmergencyPolicy, &str); 3] = [
+    (EmergencyPolicy::Any, "Close on any emergency"),
+    (EmergencyPolicy::Leak, "Close on a leak only"),
+    (EmergencyPolicy::Ignore, "Manual only"),
+];
+
+#[function_component(ValveConfigForm)]
+fn valve_config_form(props: &ValveZoneProps) -> Html {
+    let valve = props.valve;
+
+    let config_store = use_store_value::<ValveConfigStore>();
+    let mcx = use_mcx();
+
+    let enabled_ref = use_node_ref();
+    let name_ref = use_node_ref();
+    let policy_refs = POLICIES.map(|_| NodeRef::default());
+
+    let onsave = {
+        let enabled_ref = enabled_ref.clone();
+        let name_ref = name_ref.clone();
+        let policy_refs = policy_refs.clone();
+        let config = config_store.0[valve].clone();
+
+        Callback::from(move |_| {
+            let checked = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.checked())
+                    .unwrap_or(false)
+            };
+
+            // A name which is too long keeps the current one
+            let name = name_ref
+                .cast::<HtmlInputElement>()
+                .and_then(|input| input.value().trim().try_into().ok())
+                .unwrap_or_else(|| config.name.clone());
+
+            let emergency = POLICIES
+                .iter()
+                .zip(policy_refs.iter())
+                .find_map(|((policy, _), policy_ref)| checked(policy_ref).then_some(*policy))
+                .unwrap_or(config.emergency);
+
+            mcx.invoke(WebRequest::ValveConfig(
+                valve,
+                ValveConfig {
+                    enabled: valve == 0 || checked(&enabled_ref),
+                    name,
+                    emergency,
+                },
+            ));
+        })
+    };
+
+    let config = &config_store.0[valve];
 
     html! {
-        {format!("Valve State: {:?}", valve_store.0.as_ref())}
+        <>
+            if valve > 0 {
+                <div class="field">
+                    <label class="checkbox">
+                        <input type="checkbox" checked={config.enabled} ref={enabled_ref}/>
+                        {" Installed"}
+                    </label>
+                </div>
+            }
+            <div class="field">
+                <label class="label">{"Zone name"}</label>
+                <div class="control">
+                    <input class="input" type="text" value={config.name.as_str().to_string()} ref={name_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                {
+                    POLICIES.iter().zip(policy_refs.iter()).map(|((policy, text), policy_ref)| html! {
+                        <label class="radio">
+                            <input
+                                type="radio"
+                                name={format!("valve-{valve}-emergency")}
+                                checked={config.emergency == *policy}
+                                ref={policy_ref.clone()}
+                            />
+                            {format!(" {text}")}
+                        </label>
+                    }).collect::<Html>()
+                }
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
     }
 }
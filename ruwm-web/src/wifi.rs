@@ -0,0 +1,143 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+
+use yew::prelude::*;
+use yewdux::prelude::*;
+use yewdux_middleware::*;
+
+use edge_frame::role::*;
+
+use ruwm::dto::web::WebRequest;
+use ruwm::dto::wifi::{WifiGiveUp, WifiPolicy};
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct WifiPolicyStore(pub WifiPolicy);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WifiPolicyMsg(pub WifiPolicy);
+
+impl Reducer<WifiPolicyStore> for WifiPolicyMsg {
+    fn apply(self, mut store: Rc<WifiPolicyStore>) -> Rc<WifiPolicyStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[function_component(WifiRetries)]
+pub fn wifi_retries() -> Html {
+    html! {
+        <Role role={RoleDto::Admin}>
+            <h2 class="subtitle">{"Reconnecting"}</h2>
+            <WifiPolicyForm/>
+        </Role>
+    }
+}
+
+#[function_component(WifiPolicyForm)]
+fn wifi_policy_form() -> Html {
+    let wifi_policy_store = use_store_value::<WifiPolicyStore>();
+    let mcx = use_mcx();
+
+    let backoff_initial_ref = use_node_ref();
+    let backoff_max_ref = use_node_ref();
+    let give_up_ref = use_node_ref();
+    let after_attempts_ref = use_node_ref();
+    let battery_only_ref = use_node_ref();
+    let sleep_ref = use_node_ref();
+
+    let onsave = {
+        let backoff_initial_ref = backoff_initial_ref.clone();
+        let backoff_max_ref = backoff_max_ref.clone();
+        let give_up_ref = give_up_ref.clone();
+        let after_attempts_ref = after_attempts_ref.clone();
+        let battery_only_ref = battery_only_ref.clone();
+        let sleep_ref = sleep_ref.clone();
+        let policy = wifi_policy_store.0;
+
+        Callback::from(move |_| {
+            // Invalid numbers keep the current ones
+            fn number<T: core::str::FromStr>(node_ref: &NodeRef, current: T) -> T {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.value().trim().parse().ok())
+                    .unwrap_or(current)
+            }
+
+            fn checked(node_ref: &NodeRef, current: bool) -> bool {
+                node_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.checked())
+                    .unwrap_or(current)
+            }
+
+            let current = policy.give_up.unwrap_or(WifiGiveUp {
+                after_attempts: 3,
+                battery_only: true,
+                sleep_secs: None,
+            });
+
+            let give_up = checked(&give_up_ref, policy.give_up.is_some()).then(|| WifiGiveUp {
+                after_attempts: number(&after_attempts_ref, current.after_attempts),
+                battery_only: checked(&battery_only_ref, current.battery_only),
+                // An empty field waits for a new configuration
+                sleep_secs: sleep_ref
+                    .cast::<HtmlInputElement>()
+                    .map(|input| input.value().trim().parse().ok())
+                    .unwrap_or(current.sleep_secs),
+            });
+
+            mcx.invoke(WebRequest::WifiPolicy(WifiPolicy {
+                backoff_initial_secs: number(&backoff_initial_ref, policy.backoff_initial_secs),
+                backoff_max_secs: number(&backoff_max_ref, policy.backoff_max_secs),
+                give_up,
+            }));
+        })
+    };
+
+    let policy = &wifi_policy_store.0;
+    let give_up = policy.give_up;
+
+    html! {
+        <>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"First retry after (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.backoff_initial_secs.to_string()} ref={backoff_initial_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"Retry at least every (s)"}</label>
+                    <input class="input" type="number" min="1" value={policy.backoff_max_secs.to_string()} ref={backoff_max_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="checkbox">
+                    <input type="checkbox" checked={give_up.is_some()} ref={give_up_ref}/>
+                    {" Give up when no known network can be reached"}
+                </label>
+            </div>
+            <div class="field is-grouped">
+                <div class="control">
+                    <label class="label">{"After attempts"}</label>
+                    <input class="input" type="number" min="1" value={give_up.map(|give_up| give_up.after_attempts).unwrap_or(3).to_string()} ref={after_attempts_ref}/>
+                </div>
+                <div class="control">
+                    <label class="label">{"Retry after, empty to wait for a new configuration (s)"}</label>
+                    <input class="input" type="number" min="1" value={give_up.and_then(|give_up| give_up.sleep_secs).map(|secs| secs.to_string()).unwrap_or_default()} ref={sleep_ref}/>
+                </div>
+            </div>
+            <div class="field">
+                <label class="checkbox">
+                    <input type="checkbox" checked={give_up.map(|give_up| give_up.battery_only).unwrap_or(true)} ref={battery_only_ref}/>
+                    {" Only when running on battery"}
+                </label>
+            </div>
+            <button class="button is-primary" onclick={onsave}>
+                {"Save"}
+            </button>
+        </>
+    }
+}
@@ -0,0 +1,148 @@
+//! Consumption budgets and threshold alerts
+//!
+//! Budgets are checked against the current day, ISO week and month of the consumption history,
+//! so they are only checked while the wall-clock time is valid, as the current periods of a
+//! history restored after a power loss may be long gone. The draw threshold is checked
+//! against the consumption since the water started flowing. Both apply to the main water meter.
+//! The pressure drop threshold is checked against the drop during the last pressure drop test.
+
+use embassy_futures::select::{select, select4, Either, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use channel_bridge::notification::Notification;
+
+use crate::button::{Button, ButtonGesture};
+use crate::state::State;
+use crate::wm_stats::{self, Period};
+use crate::{flow, pressure, time, wm};
+
+pub use crate::dto::alert::*;
+
+pub static STATE: State<AlertState> = State::new(
+    "ALERT",
+    AlertState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::ALERT_STATE_NOTIF,
+        &crate::screen::ALERT_STATE_NOTIF,
+        &crate::mqtt::ALERT_STATE_NOTIF,
+        &crate::web::ALERT_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+pub static CONFIG: State<AlertConfig> = State::new(
+    "ALERT CONFIG",
+    AlertConfig::new(),
+    &[&crate::web::ALERT_CONFIG_STATE_NOTIF, &CONFIG_FLASH_NOTIFY],
+);
+
+pub static COMMAND: Signal<CriticalSectionRawMutex, AlertCommand> = Signal::new();
+
+pub(crate) static BUTTON_GESTURE: Signal<CriticalSectionRawMutex, ButtonGesture> = Signal::new();
+
+pub(crate) static WM_STATS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static FLOW_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static PRESSURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TIME_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+pub async fn process() {
+    loop {
+        let command = match select4(
+            select(WM_STATS_STATE_NOTIF.wait(), TIME_STATE_NOTIF.wait()),
+            FLOW_STATE_NOTIF.wait(),
+            PRESSURE_STATE_NOTIF.wait(),
+            select(COMMAND.wait(), BUTTON_GESTURE.wait()),
+        )
+        .await
+        {
+            Either4::Fourth(Either::First(command)) => Some(command),
+            // Holding the action button acknowledges the raised alerts, which also lifts the
+            // emergency closing of the valves they caused
+            Either4::Fourth(Either::Second(ButtonGesture::LongPress(Button::Button3))) => {
+                Some(AlertCommand::AcknowledgeAll)
+            }
+            _ => None,
+        };
+
+        if let Some(AlertCommand::Configure(config)) = command {
+            CONFIG.update(config);
+        }
+
+        let config = CONFIG.get();
+        let calendar = time::STATE
+            .get()
+            .valid
+            .then(|| wm_stats::STATE.get()[0].calendar.clone());
+        let flowing = flow::STATE.get()[0].flowing;
+        let edges_count = wm::STATE.get()[0].edges_count;
+        let pressure_drop = pressure::STATE.get().test_drop;
+
+        let budget = |period| {
+            calendar
+                .as_ref()
+                .and_then(|calendar| calendar.history(period, 0))
+                .map(u64::from)
+        };
+
+        STATE.update_with(|mut state| {
+            state.draw_start = flowing.then(|| state.draw_start.unwrap_or(edges_count));
+
+            for kind in AlertKind::ALL {
+                let consumption = match kind {
+                    AlertKind::DailyBudget => budget(Period::Day),
+                    AlertKind::WeeklyBudget => budget(Period::Week),
+                    AlertKind::MonthlyBudget => budget(Period::Month),
+                    AlertKind::Draw => state
+                        .draw_start
+                        .map(|draw_start| edges_count.saturating_sub(draw_start)),
+                    AlertKind::PressureDrop => pressure_drop.map(u64::from),
+                };
+
+                let exceeded = config
+                    .threshold(kind)
+                    .zip(consumption)
+                    .map(|(threshold, consumption)| consumption > threshold as u64)
+                    .unwrap_or(false);
+
+                let status = state.status_mut(kind);
+
+                *status = match (*status, command) {
+                    _ if !exceeded => AlertStatus::Idle,
+                    (AlertStatus::Idle, _) => AlertStatus::Raised,
+                    (AlertStatus::Raised, Some(command)) if command.acknowledges(kind) => {
+                        AlertStatus::Acknowledged
+                    }
+                    (AlertStatus::Raised | AlertStatus::Acknowledged, Some(command))
+                        if command.clears(kind) =>
+                    {
+                        AlertStatus::Cleared
+                    }
+                    (status, _) => status,
+                };
+            }
+
+            state
+        });
+    }
+}
+
+pub async fn persist(mut persister: impl FnMut(AlertState)) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        persister(STATE.get());
+    }
+}
+
+pub async fn flash(mut flasher: impl FnMut(AlertConfig)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
@@ -0,0 +1,377 @@
+//! A JSON REST API for integrations which would rather not speak the websocket protocol
+//!
+//! - `GET /api/state` - a snapshot of the valves, water meters, flows, statistics, alerts,
+//!   away mode, moisture sensors, temperature, pipe pressure, battery, wifi and time state
+//! - `POST /api/valve` - `{"open": true|false, "valve": <index>}`, where the index of the
+//!   valve is optional and defaults to the main valve
+//! - `POST /api/meter/arm` - `{"armed": true|false, "meter": <index>}`, where the index of the
+//!   water meter is optional and defaults to the main meter
+//! - `GET /api/events` - a stream of the web events as Server-Sent Events
+//!
+//! Requests are authorized with HTTP basic auth or with a bearer token, and are subject
+//! to the same role checks as the websocket requests.
+//!
+//! Note that an events stream occupies one of the `ws::WS_MAX_CONNECTIONS` connection
+//! handlers for as long as the client stays connected.
+
+use core::fmt::Debug;
+use core::future::pending;
+use core::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use log::{info, warn};
+
+use embedded_svc::http::server::asynch::Connection;
+use embedded_svc::http::Method;
+use embedded_svc::io::asynch::{ErrorType, Write};
+
+use channel_bridge::asynch::{self, Receiver, Sender};
+
+use edge_frame::dto::Role;
+
+use crate::alert::{self, AlertState};
+use crate::away::{self, AwayState};
+use crate::battery::{self, BatteryState};
+use crate::flow::{self, FlowStates};
+use crate::moisture::{self, SensorStates};
+use crate::pressure::{self, PressureState};
+use crate::temperature::{self, TemperatureState};
+use crate::time::{self, TimeState};
+use crate::valve::{self, ValveCommand, ValveStates, MAX_VALVES};
+use crate::web::{self, WebEvent, WebRequest};
+use crate::wifi::{self, WifiState};
+use crate::wm::{self, WaterMeterCommand, WaterMeterStates, MAX_METERS};
+use crate::wm_stats::{self, WaterMeterStatsStates};
+use crate::ws;
+
+const PATHS: [&str; 4] = ["/api/state", "/api/events", "/api/valve", "/api/meter/arm"];
+
+const STATE_MAX_LEN: usize = 8192;
+const BODY_MAX_LEN: usize = 128;
+
+// Events are larger as JSON than in the websocket frames: with all names at their maximum length
+// and fully escaped, the largest ones (the moisture and valve configurations) still take less than 1 KiB
+const EVENT_MAX_LEN: usize = 2048;
+
+#[derive(Serialize)]
+struct ApiState {
+    valves: ValveStates,
+    water_meters: WaterMeterStates,
+    flows: FlowStates,
+    water_meter_stats: WaterMeterStatsStates,
+    alert: AlertState,
+    away: AwayState,
+    moisture: SensorStates,
+    temperature: TemperatureState,
+    pressure: PressureState,
+    battery: BatteryState,
+    wifi: WifiState,
+    time: TimeState,
+}
+
+#[derive(Deserialize)]
+struct ValveRequest {
+    open: bool,
+    #[serde(default)]
+    valve: usize,
+}
+
+#[derive(Deserialize)]
+struct ArmRequest {
+    armed: bool,
+    #[serde(default)]
+    meter: usize,
+}
+
+/// Serves a request to the `/api` paths
+///
+/// `index` is the index of the connection handler serving the request, in the range
+/// `0..ws::WS_MAX_CONNECTIONS`, and `api_token` is the optional bearer token granting
+/// the admin role
+pub async fn handle<C>(
+    mut connection: C,
+    index: usize,
+    api_token: Option<&str>,
+) -> Result<(), C::Error>
+where
+    C: Connection,
+{
+    let role = web::http_role(connection.header("Authorization"), api_token);
+
+    let method = connection.method();
+
+    let path = connection.uri().split('?').next().unwrap_or("");
+    let path = PATHS.iter().find(|known| **known == path).copied();
+
+    match (method, path.unwrap_or("")) {
+        (Method::Get, "/api/state") => {
+            if authorize(&mut connection, Role::User, role).await? {
+                state(connection).await?;
+            }
+        }
+        (Method::Get, "/api/events") => {
+            if authorize(&mut connection, Role::User, role).await? {
+                events(connection, index, role).await?;
+            }
+        }
+        (Method::Post, "/api/valve") => {
+            let request = read_body::<ValveRequest, _>(&mut connection)
+                .await?
+                .filter(|request| request.valve < MAX_VALVES)
+                .map(|request| {
+                    WebRequest::ValveCommand(
+                        request.valve,
+                        if request.open {
+                            ValveCommand::Open
+                        } else {
+                            ValveCommand::Close
+                        },
+                    )
+                });
+
+            command(connection, request, role).await?;
+        }
+        (Method::Post, "/api/meter/arm") => {
+            let request = read_body::<ArmRequest, _>(&mut connection)
+                .await?
+                .filter(|request| request.meter < MAX_METERS)
+                .map(|request| {
+                    WebRequest::WaterMeterCommand(
+                        request.meter,
+                        if request.armed {
+                            WaterMeterCommand::Arm
+                        } else {
+                            WaterMeterCommand::Disarm
+                        },
+                    )
+                });
+
+            command(connection, request, role).await?;
+        }
+        _ => {
+            let status = if path.is_some() { 405 } else { 404 };
+
+            connection.initiate_response(status, None, &[]).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn state<C>(mut connection: C) -> Result<(), C::Error>
+where
+    C: Connection,
+{
+    let state = ApiState {
+        valves: valve::STATE.get(),
+        water_meters: wm::STATE.get(),
+        flows: flow::STATE.get(),
+        water_meter_stats: wm_stats::STATE.get(),
+        alert: alert::STATE.get(),
+        away: away::STATE.get(),
+        moisture: moisture::STATE.get(),
+        temperature: temperature::STATE.get(),
+        pressure: pressure::STATE.get(),
+        battery: battery::STATE.get(),
+        wifi: wifi::STATE.get(),
+        time: time::STATE.get(),
+    };
+
+    let mut buf = [0; STATE_MAX_LEN];
+
+    let Ok(len) = serde_json_core::to_slice(&state, &mut buf) else {
+        return connection.initiate_response(500, None, &[]).await;
+    };
+
+    connection
+        .initiate_response(200, None, &[("Content-Type", "application/json")])
+        .await?;
+
+    connection.write_all(&buf[..len]).await
+}
+
+async fn command<C>(
+    mut connection: C,
+    request: Option<WebRequest>,
+    role: Role,
+) -> Result<(), C::Error>
+where
+    C: Connection,
+{
+    let Some(request) = request else {
+        return connection.initiate_response(400, None, &[]).await;
+    };
+
+    if !authorize(&mut connection, request.role(), role).await? {
+        return Ok(());
+    }
+
+    info!("[API] {:?}", request);
+
+    match request {
+        WebRequest::ValveCommand(valve, command) => valve::COMMAND[valve].signal(command),
+        WebRequest::WaterMeterCommand(meter, command) => wm::COMMAND[meter].signal(command),
+        _ => unreachable!(),
+    }
+
+    connection.initiate_response(204, None, &[]).await
+}
+
+async fn events<C>(mut connection: C, index: usize, role: Role) -> Result<(), C::Error>
+where
+    C: Connection,
+{
+    connection
+        .initiate_response(
+            200,
+            None,
+            &[
+                ("Content-Type", "text/event-stream"),
+                ("Cache-Control", "no-cache"),
+            ],
+        )
+        .await?;
+
+    let result = web::handle(
+        SseSender::new(&mut connection),
+        NoReceiver(PhantomData),
+        role,
+        ws::state_notifs(index),
+    )
+    .await;
+
+    match result {
+        Err(SseError::Io(err)) => Err(err),
+        Err(SseError::TooLarge) => {
+            // Ends the stream, as the client would otherwise silently miss a state
+            warn!("[API] Event too large for the events stream");
+
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+pub(crate) async fn authorize<C>(
+    connection: &mut C,
+    required: Role,
+    role: Role,
+) -> Result<bool, C::Error>
+where
+    C: Connection,
+{
+    if required <= role {
+        Ok(true)
+    } else {
+        let (status, headers): (_, &[_]) = if role == Role::None {
+            (401, &[("WWW-Authenticate", "Basic realm=\"ruwm\"")])
+        } else {
+            (403, &[])
+        };
+
+        connection.initiate_response(status, None, headers).await?;
+
+        Ok(false)
+    }
+}
+
+async fn read_body<T, C>(connection: &mut C) -> Result<Option<T>, C::Error>
+where
+    T: for<'a> Deserialize<'a>,
+    C: Connection,
+{
+    let mut buf = [0; BODY_MAX_LEN];
+    let mut len = 0;
+
+    loop {
+        if len == buf.len() {
+            // Too large for any of the requests
+            return Ok(None);
+        }
+
+        let read = connection.read(&mut buf[len..]).await?;
+        if read == 0 {
+            break;
+        }
+
+        len += read;
+    }
+
+    Ok(serde_json_core::from_slice::<T>(&buf[..len])
+        .ok()
+        .map(|(body, _)| body))
+}
+
+#[derive(Debug)]
+enum SseError<E> {
+    Io(E),
+    TooLarge,
+}
+
+impl<E> From<E> for SseError<E> {
+    fn from(err: E) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Sends the web events as Server-Sent Events
+struct SseSender<W> {
+    write: W,
+    buf: [u8; EVENT_MAX_LEN],
+}
+
+impl<W> SseSender<W> {
+    const fn new(write: W) -> Self {
+        Self {
+            write,
+            buf: [0; EVENT_MAX_LEN],
+        }
+    }
+}
+
+impl<W> asynch::ErrorType for SseSender<W>
+where
+    W: ErrorType,
+{
+    type Error = SseError<W::Error>;
+}
+
+impl<W> Sender for SseSender<W>
+where
+    W: Write,
+{
+    type Data = WebEvent;
+
+    async fn send(&mut self, data: Self::Data) -> Result<(), Self::Error> {
+        let len =
+            serde_json_core::to_slice(&data, &mut self.buf).map_err(|_| SseError::TooLarge)?;
+
+        self.write.write_all(b"data: ").await?;
+        self.write.write_all(&self.buf[..len]).await?;
+        self.write.write_all(b"\n\n").await?;
+
+        Ok(self.write.flush().await?)
+    }
+}
+
+/// SSE clients cannot send requests, so the stream lasts until sending fails
+struct NoReceiver<E>(PhantomData<E>);
+
+impl<E> asynch::ErrorType for NoReceiver<E>
+where
+    E: Debug,
+{
+    type Error = E;
+}
+
+impl<E> Receiver for NoReceiver<E>
+where
+    E: Debug,
+{
+    type Data = Option<WebRequest>;
+
+    async fn recv(&mut self) -> Result<Self::Data, Self::Error> {
+        pending().await
+    }
+}
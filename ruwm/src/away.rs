@@ -0,0 +1,134 @@
+//! Away mode, enabled manually or on a schedule
+//!
+//! Without a threshold the away mode arms the main water meter, so that any flow closes the valve,
+//! also when it wakes the device from deep sleep. With a threshold the meter is left alone and
+//! the valve is closed once the flow exceeds the threshold, which is only checked while awake.
+
+use embassy_futures::select::{select4, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::wm::{self, WaterMeterCommand};
+use crate::{flow, time};
+
+pub use crate::dto::away::*;
+
+pub static STATE: State<AwayState> = State::new(
+    "AWAY",
+    AwayState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::AWAY_STATE_NOTIF,
+        &crate::mqtt::AWAY_STATE_NOTIF,
+        &crate::web::AWAY_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+pub static CONFIG: State<AwayConfig> = State::new(
+    "AWAY CONFIG",
+    AwayConfig::new(),
+    &[&crate::web::AWAY_CONFIG_STATE_NOTIF, &CONFIG_FLASH_NOTIFY],
+);
+
+pub static COMMAND: Signal<CriticalSectionRawMutex, AwayCommand> = Signal::new();
+
+pub(crate) static FLOW_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TIME_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+pub async fn process() {
+    loop {
+        update();
+
+        // Schedule windows start and end on a full minute
+        let next_minute = time::local_now()
+            .map(|secs| 60 - secs.rem_euclid(60) as u64)
+            .unwrap_or(60);
+
+        let command = match select4(
+            COMMAND.wait(),
+            FLOW_STATE_NOTIF.wait(),
+            TIME_STATE_NOTIF.wait(),
+            Timer::after(Duration::from_secs(next_minute)),
+        )
+        .await
+        {
+            Either4::First(command) => Some(command),
+            _ => None,
+        };
+
+        if let Some(command) = command {
+            CONFIG.update_with(|mut config| {
+                match command {
+                    AwayCommand::Enable(enabled) => config.enabled = enabled,
+                    AwayCommand::Schedule(schedule) => config.schedule = schedule,
+                    AwayCommand::Threshold(threshold) => config.threshold = threshold,
+                    AwayCommand::Configure(new_config) => config = new_config,
+                }
+
+                config
+            });
+        }
+    }
+}
+
+pub async fn persist(mut persister: impl FnMut(AwayState)) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        persister(STATE.get());
+    }
+}
+
+pub async fn flash(mut flasher: impl FnMut(AwayConfig)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
+
+fn update() {
+    let config = CONFIG.get();
+
+    let active = config.enabled
+        || time::local_now()
+            .map(|secs| config.schedule.is_active(secs))
+            .unwrap_or(false);
+
+    let flow = flow::STATE.get()[0];
+    let wm_armed = wm::STATE.get()[0].armed;
+
+    STATE.update_with(|state| {
+        let mut armed_meter = state.armed_meter;
+
+        // Leave the meter alone if the user armed it, or disarmed it while away
+        if active && config.threshold.is_none() {
+            if !armed_meter && !wm_armed {
+                wm::COMMAND[0].signal(WaterMeterCommand::Arm);
+                armed_meter = true;
+            }
+        } else if armed_meter {
+            wm::COMMAND[0].signal(WaterMeterCommand::Disarm);
+            armed_meter = false;
+        }
+
+        let exceeded = config
+            .threshold
+            .map(|threshold| flow.flowing && flow.edges_per_hour > threshold)
+            .unwrap_or(false);
+
+        AwayState {
+            active,
+            armed_meter,
+            tripped: active && (state.tripped || exceeded),
+        }
+    });
+}
@@ -1,13 +1,23 @@
 use core::fmt::Debug;
 
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 
 use embedded_hal::digital::InputPin;
 
+use channel_bridge::notification::Notification;
+
+use crate::power::{self, SleepBlocker};
 use crate::state::State;
+use crate::time::{self, SECS_PER_DAY};
 
 pub use crate::dto::battery::*;
 
+/// How many readings the voltage is averaged over
+const AVERAGE_LEN: usize = 8;
+
+/// The averaged voltage is still rounded to that many mV, so that it does not flood the clients
+const ROUND: u16 = 10;
+
 pub trait Adc {
     type Error: Debug;
 
@@ -26,20 +36,124 @@ pub static STATE: State<BatteryState> = State::new(
     ],
 );
 
+pub static CONFIG: State<BatteryConfig> = State::new(
+    "BATTERY CONFIG",
+    BatteryConfig::new(),
+    &[
+        &crate::web::BATTERY_CONFIG_STATE_NOTIF,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
+/// The daily minimum voltages and times awake on battery, for the runtime estimate
+pub static HISTORY: State<BatteryHistory> = State::new(
+    "BATTERY HISTORY",
+    BatteryHistory::new(),
+    &[&HISTORY_PERSIST_NOTIFY],
+);
+
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+static HISTORY_PERSIST_NOTIFY: Notification = Notification::new();
+static HISTORY_FLASH_NOTIFY: Notification = Notification::new();
+
 pub async fn process(mut battery_adc: impl Adc, mut power_pin: impl InputPin) {
-    const ROUND_UP: u16 = 50; // TODO: Make it smaller once ADC is connected
+    // There is no point in saving the battery while powered
+    power::register_blocker(SleepBlocker::Powered, || {
+        STATE.get().powered.unwrap_or(false)
+    });
+
+    let mut readings = [0; AVERAGE_LEN];
+    let mut next = 0;
+    let mut len = 0;
+
+    let mut recorded = Instant::now();
 
     loop {
         Timer::after(Duration::from_secs(2)).await;
 
-        let voltage = battery_adc
-            .read()
-            .await
-            .ok()
-            .map(|voltage| voltage / ROUND_UP * ROUND_UP);
+        let config = CONFIG.get();
+
+        let reading = battery_adc.read().await.ok();
+
+        let voltage = reading.map(|reading| {
+            readings[next] = config.voltage(reading);
+            next = (next + 1) % AVERAGE_LEN;
+            len = (len + 1).min(AVERAGE_LEN);
+
+            let readings = &readings[..len];
+            let average =
+                readings.iter().map(|voltage| *voltage as u32).sum::<u32>() / readings.len() as u32;
+
+            average as u16 / ROUND * ROUND
+        });
 
         let powered = Some(power_pin.is_high().unwrap_or(false));
 
-        STATE.update(BatteryState { voltage, powered });
+        let now = Instant::now();
+        let awake_secs = ((now - recorded).as_millis() + 500) / 1000;
+        recorded = now;
+
+        // The voltage only tells about the drain while on battery
+        if let (Some(voltage), Some(false)) = (voltage, powered) {
+            let mut new_day = false;
+
+            HISTORY.update_with(|mut history| {
+                new_day = history.record(
+                    time::local_now().map(|secs| secs.div_euclid(SECS_PER_DAY)),
+                    voltage,
+                    awake_secs as u32,
+                );
+
+                history
+            });
+
+            if new_day {
+                HISTORY_FLASH_NOTIFY.notify();
+            }
+        }
+
+        let remaining_days = HISTORY.get().remaining_days(config.low_voltage());
+
+        STATE.update_with(|state| {
+            let percentage = voltage.map(|voltage| config.chemistry.percentage(voltage));
+
+            BatteryState {
+                voltage,
+                percentage,
+                low: percentage
+                    .map(|percentage| config.low(percentage, state.low))
+                    .unwrap_or(state.low),
+                charged: percentage
+                    .map(|percentage| config.charged(percentage, state.charged))
+                    .unwrap_or(false),
+                powered,
+                remaining_days,
+            }
+        });
+    }
+}
+
+pub async fn flash(mut flasher: impl FnMut(BatteryConfig)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
+
+pub async fn persist_history(mut persister: impl FnMut(BatteryHistory)) {
+    loop {
+        HISTORY_PERSIST_NOTIFY.wait().await;
+
+        persister(HISTORY.get());
+    }
+}
+
+/// Flashes the history once per day, as it outlives the RTC memory only in flash
+pub async fn flash_history(mut flasher: impl FnMut(BatteryHistory)) {
+    loop {
+        HISTORY_FLASH_NOTIFY.wait().await;
+
+        flasher(HISTORY.get());
     }
 }
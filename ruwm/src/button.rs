@@ -1,16 +1,32 @@
+//! Button gestures
+//!
+//! The three buttons are watched together, so that presses, double presses, long presses and
+//! chords of two buttons can be told apart. Only one gesture is recognized at a time: once a
+//! gesture is reported, or spoilt by a button pressed too late for a chord, all buttons have to be
+//! released before the next one starts.
+
 use core::fmt::Debug;
 
-use embassy_time::{Duration, Timer};
+use log::info;
+
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal_async::digital::Wait;
 use serde::{Deserialize, Serialize};
 
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
 
 use embedded_hal::digital::InputPin;
 
 use channel_bridge::notification::Notification;
 
-use crate::log_err;
+use crate::state::State;
+use crate::{check, log_err};
+
+pub use crate::dto::button::*;
+
+const DEBOUNCE: Duration = Duration::from_millis(50);
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum PressedLevel {
@@ -18,56 +34,261 @@ pub enum PressedLevel {
     High,
 }
 
-static BUTTON1_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON1_PRESSED_NOTIF];
-static BUTTON2_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON2_PRESSED_NOTIF];
-static BUTTON3_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON3_PRESSED_NOTIF];
-
-pub async fn button1_process(pin: impl InputPin + Wait, pressed_level: PressedLevel) {
-    button_process(pin, pressed_level, "BUTTON1 STATE", BUTTON1_NOTIFY).await;
+pub static TIMINGS: State<ButtonTimings> = State::new(
+    "BUTTON TIMINGS",
+    ButtonTimings::new(),
+    &[
+        &crate::web::BUTTON_TIMINGS_STATE_NOTIF,
+        &TIMINGS_FLASH_NOTIFY,
+    ],
+);
+
+static GESTURE_NOTIFY: &[&Signal<CriticalSectionRawMutex, ButtonGesture>] = &[
+    &crate::screen::BUTTON_GESTURE,
+    &crate::alert::BUTTON_GESTURE,
+];
+
+static TIMINGS_FLASH_NOTIFY: Notification = Notification::new();
+
+/// The pressed states of the buttons, in the order of `Button::ALL`
+type Pressed = [bool; Button::ALL.len()];
+
+struct Buttons<P1, P2, P3> {
+    pin1: P1,
+    pin2: P2,
+    pin3: P3,
+    pressed_level: PressedLevel,
 }
 
-pub async fn button2_process(pin: impl InputPin + Wait, pressed_level: PressedLevel) {
-    button_process(pin, pressed_level, "BUTTON2 STATE", BUTTON2_NOTIFY).await;
-}
+impl<P1, P2, P3> Buttons<P1, P2, P3>
+where
+    P1: InputPin + Wait,
+    P2: InputPin + Wait,
+    P3: InputPin + Wait,
+{
+    fn pressed(&mut self) -> Pressed {
+        [
+            is_pressed(&mut self.pin1, self.pressed_level),
+            is_pressed(&mut self.pin2, self.pressed_level),
+            is_pressed(&mut self.pin3, self.pressed_level),
+        ]
+    }
 
-pub async fn button3_process(pin: impl InputPin + Wait, pressed_level: PressedLevel) {
-    button_process(pin, pressed_level, "BUTTON3 STATE", BUTTON3_NOTIFY).await;
+    async fn wait_edge(&mut self) {
+        match select3(
+            self.pin1.wait_for_any_edge(),
+            self.pin2.wait_for_any_edge(),
+            self.pin3.wait_for_any_edge(),
+        )
+        .await
+        {
+            Either3::First(result) => {
+                log_err!(result);
+            }
+            Either3::Second(result) => {
+                log_err!(result);
+            }
+            Either3::Third(result) => {
+                log_err!(result);
+            }
+        }
+    }
+
+    /// Waits until the debounced pressed states differ from `pressed`
+    async fn wait_change(&mut self, pressed: Pressed) -> Pressed {
+        loop {
+            self.wait_edge().await;
+
+            while let Either::First(_) = select(self.wait_edge(), Timer::after(DEBOUNCE)).await {}
+
+            let new_pressed = self.pressed();
+
+            if new_pressed != pressed {
+                break new_pressed;
+            }
+        }
+    }
+
+    /// Like `wait_change`, but gives up at `deadline`
+    async fn wait_change_until(&mut self, pressed: Pressed, deadline: Instant) -> Option<Pressed> {
+        match select(self.wait_change(pressed), Timer::at(deadline)).await {
+            Either::First(new_pressed) => Some(new_pressed),
+            Either::Second(_) => None,
+        }
+    }
+
+    async fn wait_released(&mut self, mut pressed: Pressed) -> Pressed {
+        while pressed.iter().any(|pressed| *pressed) {
+            pressed = self.wait_change(pressed).await;
+        }
+
+        pressed
+    }
 }
 
-async fn button_process<'a>(
-    pin: impl InputPin + Wait,
+pub async fn process(
+    pin1: impl InputPin + Wait,
+    pin2: impl InputPin + Wait,
+    pin3: impl InputPin + Wait,
     pressed_level: PressedLevel,
-    pressed_sink_msg: &'a str,
-    pressed_sink: &'a [&'a Notification],
 ) {
-    process(
-        pin,
+    let mut buttons = Buttons {
+        pin1,
+        pin2,
+        pin3,
         pressed_level,
-        Some(Duration::from_millis(50)),
-        pressed_sink_msg,
-        pressed_sink,
-    )
-    .await;
+    };
+
+    // Whatever is held on startup (i.e. the button which woke the device up) is no gesture
+    let mut pressed = buttons.pressed();
+    pressed = buttons.wait_released(pressed).await;
+
+    let mut next = None;
+
+    loop {
+        let first = if let Some(first) = next.take() {
+            first
+        } else {
+            let new_pressed = buttons.wait_change(pressed).await;
+            let first = newly_pressed(pressed, new_pressed);
+
+            pressed = new_pressed;
+
+            if let Some(first) = first {
+                first
+            } else {
+                continue;
+            }
+        };
+
+        let (gesture, new_pressed, new_next) = recognize(&mut buttons, pressed, first).await;
+
+        pressed = new_pressed;
+        next = new_next;
+
+        if let Some(gesture) = gesture {
+            info!("[BUTTON GESTURE] {:?}", gesture);
+
+            for signal in GESTURE_NOTIFY {
+                signal.signal(gesture);
+            }
+
+            // Keeps the device awake like any other activity
+            crate::keepalive::NOTIF.notify();
+        }
+
+        // A gesture is reported as soon as it is recognized, e.g. a long press while still held,
+        // but the next one only starts once all buttons are released
+        if next.is_none() {
+            pressed = buttons.wait_released(pressed).await;
+        }
+    }
 }
 
-pub async fn process(
-    mut pin: impl InputPin + Wait,
-    pressed_level: PressedLevel,
-    debounce_duration: Option<Duration>,
-    pressed_sink_msg: &str,
-    pressed_sink: &[&Notification],
-) {
+pub async fn flash(mut flasher: impl FnMut(ButtonTimings)) {
     loop {
-        log_err!(wait_press(&mut pin, pressed_level, debounce_duration).await);
+        TIMINGS_FLASH_NOTIFY.wait().await;
+
+        flasher(TIMINGS.get());
+    }
+}
+
+/// Recognizes the gesture started by pressing `first`
+///
+/// Returns the gesture, if any, the pressed states once it is recognized, with buttons possibly
+/// still held, and the button which starts the next gesture, if that one was already pressed while
+/// waiting for a double press.
+async fn recognize<P1, P2, P3>(
+    buttons: &mut Buttons<P1, P2, P3>,
+    mut pressed: Pressed,
+    first: Button,
+) -> (Option<ButtonGesture>, Pressed, Option<Button>)
+where
+    P1: InputPin + Wait,
+    P2: InputPin + Wait,
+    P3: InputPin + Wait,
+{
+    let timings = TIMINGS.get();
+
+    let start = Instant::now();
+    let long_press_deadline = start + Duration::from_millis(timings.long_press_ms as _);
+    let chord_deadline = start + Duration::from_millis(timings.chord_ms as _);
+
+    // Both buttons of a chord may go down within the same debounce period
+    if let Some(second) = Button::ALL
+        .into_iter()
+        .find(|button| *button != first && pressed[button.index()])
+    {
+        return (Some(ButtonGesture::chord(first, second)), pressed, None);
+    }
 
-        log::info!("[{}]", pressed_sink_msg);
+    // Held
+    while pressed[first.index()] {
+        let Some(new_pressed) = buttons
+            .wait_change_until(pressed, long_press_deadline)
+            .await
+        else {
+            return (Some(ButtonGesture::LongPress(first)), pressed, None);
+        };
 
-        for notification in pressed_sink {
-            notification.notify();
+        let second = newly_pressed(pressed, new_pressed);
+
+        pressed = new_pressed;
+
+        if let Some(second) = second {
+            let gesture =
+                (Instant::now() <= chord_deadline).then(|| ButtonGesture::chord(first, second));
+
+            return (gesture, pressed, None);
+        }
+    }
+
+    if timings.double_press_ms == 0 {
+        return (Some(ButtonGesture::Press(first)), pressed, None);
+    }
+
+    // Released, waiting for a second press
+    let double_press_deadline =
+        Instant::now() + Duration::from_millis(timings.double_press_ms as _);
+
+    loop {
+        let Some(new_pressed) = buttons
+            .wait_change_until(pressed, double_press_deadline)
+            .await
+        else {
+            return (Some(ButtonGesture::Press(first)), pressed, None);
+        };
+
+        let second = newly_pressed(pressed, new_pressed);
+
+        pressed = new_pressed;
+
+        match second {
+            Some(second) if second == first => {
+                return (Some(ButtonGesture::DoublePress(first)), pressed, None);
+            }
+            Some(second) => return (Some(ButtonGesture::Press(first)), pressed, Some(second)),
+            None => (),
         }
     }
 }
 
+/// The first button which is pressed in `new_pressed`, but was not in `pressed`
+fn newly_pressed(pressed: Pressed, new_pressed: Pressed) -> Option<Button> {
+    Button::ALL
+        .into_iter()
+        .find(|button| new_pressed[button.index()] && !pressed[button.index()])
+}
+
+fn is_pressed<P: InputPin>(pin: &mut P, pressed_level: PressedLevel) -> bool {
+    let result = match pressed_level {
+        PressedLevel::Low => pin.is_low(),
+        PressedLevel::High => pin.is_high(),
+    };
+
+    check!(result).unwrap_or(false)
+}
+
 async fn wait_level<P>(
     pin: &mut P,
     pressed_level: PressedLevel,
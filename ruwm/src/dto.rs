@@ -1,5 +1,15 @@
+pub mod alert;
+pub mod away;
 pub mod battery;
+pub mod button;
+pub mod moisture;
+pub mod ota;
+pub mod power;
+pub mod pressure;
+pub mod temperature;
+pub mod time;
 pub mod valve;
 pub mod water_meter;
 pub mod water_meter_stats;
 pub mod web;
+pub mod wifi;
@@ -0,0 +1,175 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertKind {
+    DailyBudget,
+    WeeklyBudget,
+    MonthlyBudget,
+    /// A single continuous draw, i.e. without the flow stopping in between
+    Draw,
+    /// The pressure drop during the last pressure drop test
+    PressureDrop,
+}
+
+impl AlertKind {
+    pub const ALL: [Self; 5] = [
+        Self::DailyBudget,
+        Self::WeeklyBudget,
+        Self::MonthlyBudget,
+        Self::Draw,
+        Self::PressureDrop,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DailyBudget => "daily_budget",
+            Self::WeeklyBudget => "weekly_budget",
+            Self::MonthlyBudget => "monthly_budget",
+            Self::Draw => "draw",
+            Self::PressureDrop => "pressure_drop",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// What to do when an alert is raised, besides notifying
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AlertAction {
+    #[default]
+    Notify,
+    CloseValve,
+}
+
+/// The thresholds in pulse counter edges, or in mbar for the pressure drop; `None` disables the alert
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AlertConfig {
+    pub daily_budget: Option<u32>,
+    pub weekly_budget: Option<u32>,
+    pub monthly_budget: Option<u32>,
+    pub draw: Option<u32>,
+    #[serde(default)]
+    pub pressure_drop: Option<u32>,
+    pub action: AlertAction,
+}
+
+impl AlertConfig {
+    pub const fn new() -> Self {
+        Self {
+            daily_budget: None,
+            weekly_budget: None,
+            monthly_budget: None,
+            draw: None,
+            pressure_drop: None,
+            action: AlertAction::Notify,
+        }
+    }
+
+    pub fn threshold(&self, kind: AlertKind) -> Option<u32> {
+        match kind {
+            AlertKind::DailyBudget => self.daily_budget,
+            AlertKind::WeeklyBudget => self.weekly_budget,
+            AlertKind::MonthlyBudget => self.monthly_budget,
+            AlertKind::Draw => self.draw,
+            AlertKind::PressureDrop => self.pressure_drop,
+        }
+    }
+}
+
+/// An alert is raised once its threshold is exceeded, and stays raised until the consumption
+/// is accounted in a new period, or until the draw stops. Acknowledging it keeps it displayed
+/// without further action, while clearing it dismisses it altogether.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AlertStatus {
+    #[default]
+    Idle,
+    Raised,
+    Acknowledged,
+    Cleared,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AlertState {
+    pub daily_budget: AlertStatus,
+    pub weekly_budget: AlertStatus,
+    pub monthly_budget: AlertStatus,
+    pub draw: AlertStatus,
+    pub pressure_drop: AlertStatus,
+    /// The edges count at the start of the current draw
+    pub draw_start: Option<u64>,
+}
+
+impl AlertState {
+    pub const fn new() -> Self {
+        Self {
+            daily_budget: AlertStatus::Idle,
+            weekly_budget: AlertStatus::Idle,
+            monthly_budget: AlertStatus::Idle,
+            draw: AlertStatus::Idle,
+            pressure_drop: AlertStatus::Idle,
+            draw_start: None,
+        }
+    }
+
+    pub fn status(&self, kind: AlertKind) -> AlertStatus {
+        match kind {
+            AlertKind::DailyBudget => self.daily_budget,
+            AlertKind::WeeklyBudget => self.weekly_budget,
+            AlertKind::MonthlyBudget => self.monthly_budget,
+            AlertKind::Draw => self.draw,
+            AlertKind::PressureDrop => self.pressure_drop,
+        }
+    }
+
+    pub fn status_mut(&mut self, kind: AlertKind) -> &mut AlertStatus {
+        match kind {
+            AlertKind::DailyBudget => &mut self.daily_budget,
+            AlertKind::WeeklyBudget => &mut self.weekly_budget,
+            AlertKind::MonthlyBudget => &mut self.monthly_budget,
+            AlertKind::Draw => &mut self.draw,
+            AlertKind::PressureDrop => &mut self.pressure_drop,
+        }
+    }
+
+    /// The alerts with the given status
+    pub fn with_status(&self, status: AlertStatus) -> impl Iterator<Item = AlertKind> + '_ {
+        AlertKind::ALL
+            .into_iter()
+            .filter(move |kind| self.status(*kind) == status)
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.with_status(AlertStatus::Raised).next().is_some()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertCommand {
+    Acknowledge(AlertKind),
+    AcknowledgeAll,
+    Clear(AlertKind),
+    ClearAll,
+    Configure(AlertConfig),
+}
+
+impl AlertCommand {
+    pub fn acknowledges(&self, kind: AlertKind) -> bool {
+        match self {
+            Self::Acknowledge(acked) => *acked == kind,
+            Self::AcknowledgeAll => true,
+            _ => false,
+        }
+    }
+
+    pub fn clears(&self, kind: AlertKind) -> bool {
+        match self {
+            Self::Clear(cleared) => *cleared == kind,
+            Self::ClearAll => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use core::fmt::{self, Debug, Display};
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::time::{Date, SECS_PER_DAY};
+
+pub const MAX_WINDOWS: usize = 4;
+
+/// A daily time window in local time; wraps over midnight when it ends before it starts
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyWindow {
+    /// Minutes after midnight
+    pub start_mins: u16,
+    /// Minutes after midnight
+    pub end_mins: u16,
+}
+
+impl DailyWindow {
+    pub fn contains(&self, mins: u16) -> bool {
+        if self.start_mins <= self.end_mins {
+            (self.start_mins..self.end_mins).contains(&mins)
+        } else {
+            mins >= self.start_mins || mins < self.end_mins
+        }
+    }
+}
+
+/// When the away mode is active on its own
+///
+/// The schedule is active on the days of the date range (all days without one), and within
+/// these, during the daily windows (the whole day without any). An empty schedule is never active.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AwaySchedule {
+    /// The first and the last day, inclusive
+    pub dates: Option<(Date, Date)>,
+    pub windows: [Option<DailyWindow>; MAX_WINDOWS],
+}
+
+impl AwaySchedule {
+    pub const fn new() -> Self {
+        Self {
+            dates: None,
+            windows: [None; MAX_WINDOWS],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_none() && self.windows().next().is_none()
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = DailyWindow> + '_ {
+        self.windows.iter().flatten().copied()
+    }
+
+    /// Whether the schedule is active at that many seconds since the epoch in local time
+    pub fn is_active(&self, local_secs: i64) -> bool {
+        let date = Date::from_days(local_secs.div_euclid(SECS_PER_DAY));
+        let mins = (local_secs.rem_euclid(SECS_PER_DAY) / 60) as u16;
+
+        let in_dates = self
+            .dates
+            .map(|(first, last)| (first..=last).contains(&date))
+            .unwrap_or(true);
+
+        let mut windows = self.windows().peekable();
+        let in_windows = windows.peek().is_none() || windows.any(|window| window.contains(mins));
+
+        !self.is_empty() && in_dates && in_windows
+    }
+}
+
+/// Parses a comma-separated list of an optional date range and up to `MAX_WINDOWS` daily windows,
+/// i.e. `2024-07-01/2024-07-14,01:00-05:00`; an empty string is the empty schedule
+impl FromStr for AwaySchedule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut schedule = Self::new();
+        let mut windows = 0;
+
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            if let Some((first, last)) = item.split_once('/') {
+                if schedule.dates.is_some() {
+                    return Err(());
+                }
+
+                schedule.dates = Some((parse_date(first)?, parse_date(last)?));
+            } else {
+                let (start, end) = item.split_once('-').ok_or(())?;
+
+                *schedule.windows.get_mut(windows).ok_or(())? = Some(DailyWindow {
+                    start_mins: parse_time(start)?,
+                    end_mins: parse_time(end)?,
+                });
+
+                windows += 1;
+            }
+        }
+
+        Ok(schedule)
+    }
+}
+
+impl Display for AwaySchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+
+        if let Some((first, last)) = self.dates {
+            write!(
+                f,
+                "{:04}-{:02}-{:02}/{:04}-{:02}-{:02}",
+                first.year, first.month, first.day, last.year, last.month, last.day
+            )?;
+
+            separator = ",";
+        }
+
+        for window in self.windows() {
+            write!(
+                f,
+                "{}{:02}:{:02}-{:02}:{:02}",
+                separator,
+                window.start_mins / 60,
+                window.start_mins % 60,
+                window.end_mins / 60,
+                window.end_mins % 60
+            )?;
+
+            separator = ",";
+        }
+
+        Ok(())
+    }
+}
+
+/// `YYYY-MM-DD`
+fn parse_date(s: &str) -> Result<Date, ()> {
+    let mut parts = s.trim().splitn(3, '-');
+
+    let year = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    let month = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    let day = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+
+    let date = Date::new(year, month, day);
+
+    if (1..=12).contains(&month) && day >= 1 && day <= date.days_in_month() {
+        Ok(date)
+    } else {
+        Err(())
+    }
+}
+
+/// `HH:MM` in minutes after midnight
+fn parse_time(s: &str) -> Result<u16, ()> {
+    let (hours, mins) = s.trim().split_once(':').ok_or(())?;
+
+    let hours: u16 = hours.parse().map_err(|_| ())?;
+    let mins: u16 = mins.parse().map_err(|_| ())?;
+
+    if hours < 24 && mins < 60 {
+        Ok(hours * 60 + mins)
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AwayConfig {
+    /// Away regardless of the schedule
+    pub enabled: bool,
+    pub schedule: AwaySchedule,
+    /// The flow in edges per hour tolerated while away; `None` tolerates no flow at all
+    pub threshold: Option<u32>,
+}
+
+impl AwayConfig {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            schedule: AwaySchedule::new(),
+            threshold: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AwayState {
+    pub active: bool,
+    /// Whether the water meter was armed by the away mode, rather than by the user
+    pub armed_meter: bool,
+    /// Whether the flow exceeded the threshold while away
+    pub tripped: bool,
+}
+
+impl AwayState {
+    pub const fn new() -> Self {
+        Self {
+            active: false,
+            armed_meter: false,
+            tripped: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AwayCommand {
+    Enable(bool),
+    Schedule(AwaySchedule),
+    Threshold(Option<u32>),
+    Configure(AwayConfig),
+}
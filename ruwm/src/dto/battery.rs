@@ -1,36 +1,351 @@
-use core::{
-    cmp::{max, min},
-    fmt::Debug,
-};
+use core::cmp::min;
+use core::fmt::{self, Debug, Display};
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct BatteryState {
+    /// In mV at the battery terminals, i.e. after the divider and the calibration
     pub voltage: Option<u16>,
+    /// The charge according to the discharge curve of the chemistry
+    pub percentage: Option<u8>,
+    pub low: bool,
+    pub charged: bool,
     pub powered: Option<bool>,
+    /// The estimated runtime left on battery, in days
+    pub remaining_days: Option<u32>,
 }
 
 impl BatteryState {
-    pub const LOW_VOLTAGE: u16 = 2700;
-    pub const MAX_VOLTAGE: u16 = 3100;
-
     pub const fn new() -> Self {
         Self {
             voltage: None,
+            percentage: None,
+            low: false,
+            charged: false,
             powered: None,
+            remaining_days: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Chemistry {
+    LiIon1S,
+    LiFePO4,
+    #[default]
+    Alkaline2AA,
+    NiMH3,
+}
+
+impl Chemistry {
+    pub const ALL: [Self; 4] = [Self::LiIon1S, Self::LiFePO4, Self::Alkaline2AA, Self::NiMH3];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::LiIon1S => "Li-ion 1S",
+            Self::LiFePO4 => "LiFePO4",
+            Self::Alkaline2AA => "2xAA alkaline",
+            Self::NiMH3 => "3xNiMH",
+        }
+    }
+
+    /// The discharge curve at a light load, as (mV, %) points with rising voltages
+    pub const fn curve(&self) -> &'static [(u16, u8)] {
+        match self {
+            Self::LiIon1S => &[
+                (3000, 0),
+                (3300, 5),
+                (3500, 10),
+                (3600, 20),
+                (3700, 40),
+                (3750, 50),
+                (3800, 60),
+                (3900, 75),
+                (4000, 85),
+                (4100, 95),
+                (4200, 100),
+            ],
+            Self::LiFePO4 => &[
+                (2500, 0),
+                (2900, 5),
+                (3000, 10),
+                (3200, 20),
+                (3250, 40),
+                (3280, 60),
+                (3300, 70),
+                (3320, 80),
+                (3350, 90),
+                (3400, 100),
+            ],
+            Self::Alkaline2AA => &[
+                (1800, 0),
+                (2000, 5),
+                (2200, 15),
+                (2400, 40),
+                (2500, 55),
+                (2600, 70),
+                (2800, 90),
+                (3000, 98),
+                (3200, 100),
+            ],
+            Self::NiMH3 => &[
+                (3000, 0),
+                (3300, 5),
+                (3450, 10),
+                (3600, 30),
+                (3690, 50),
+                (3750, 70),
+                (3840, 85),
+                (3960, 95),
+                (4200, 100),
+            ],
+        }
+    }
+
+    /// The voltage at that charge, interpolated between the points of the curve
+    pub fn voltage(&self, percentage: u8) -> u16 {
+        let curve = self.curve();
+
+        for window in curve.windows(2) {
+            let (low_voltage, low_percentage) = window[0];
+            let (high_voltage, high_percentage) = window[1];
+
+            if percentage <= high_percentage {
+                let percentage = percentage.max(low_percentage);
+
+                return low_voltage
+                    + ((percentage - low_percentage) as u32 * (high_voltage - low_voltage) as u32
+                        / (high_percentage - low_percentage).max(1) as u32)
+                        as u16;
+            }
+        }
+
+        curve[curve.len() - 1].0
+    }
+
+    /// The charge at that voltage, interpolated between the points of the curve
+    pub fn percentage(&self, voltage: u16) -> u8 {
+        let curve = self.curve();
+
+        let (first_voltage, first_percentage) = curve[0];
+        if voltage <= first_voltage {
+            return first_percentage;
+        }
+
+        for window in curve.windows(2) {
+            let (low_voltage, low_percentage) = window[0];
+            let (high_voltage, high_percentage) = window[1];
+
+            if voltage <= high_voltage {
+                return low_percentage
+                    + ((voltage - low_voltage) as u32 * (high_percentage - low_percentage) as u32
+                        / (high_voltage - low_voltage) as u32) as u8;
+            }
         }
+
+        curve[curve.len() - 1].1
     }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryConfig {
+    pub chemistry: Chemistry,
+    /// The ratio of the divider between the battery and the ADC pin, i.e. 2/1 for two equal
+    /// resistors
+    pub divider_num: u16,
+    pub divider_den: u16,
+    /// Added to the ADC reading in mV, before the divider ratio is applied
+    pub offset: i16,
+    /// The battery is low below that percentage
+    pub low_percentage: u8,
+    /// The battery is charged from that percentage on
+    pub charged_percentage: u8,
+    /// How far the percentage has to move back past a threshold before its flag changes again
+    pub hysteresis: u8,
+}
+
+impl BatteryConfig {
+    pub const fn new() -> Self {
+        Self {
+            chemistry: Chemistry::Alkaline2AA,
+            divider_num: 1,
+            divider_den: 1,
+            offset: 0,
+            low_percentage: 10,
+            charged_percentage: 95,
+            hysteresis: 3,
+        }
+    }
+
+    /// The battery voltage in mV of an ADC reading
+    pub fn voltage(&self, reading: u16) -> u16 {
+        let reading = (reading as i32 + self.offset as i32).max(0) as u32;
+
+        (reading * self.divider_num as u32 / self.divider_den.max(1) as u32).min(u16::MAX as u32)
+            as u16
+    }
+
+    /// The voltage below which the battery is low
+    pub fn low_voltage(&self) -> u16 {
+        self.chemistry.voltage(self.low_percentage)
+    }
+
+    pub fn low(&self, percentage: u8, low: bool) -> bool {
+        if low {
+            percentage < self.low_percentage.saturating_add(self.hysteresis)
+        } else {
+            percentage < self.low_percentage
+        }
+    }
+
+    pub fn charged(&self, percentage: u8, charged: bool) -> bool {
+        if charged {
+            percentage >= self.charged_percentage.saturating_sub(self.hysteresis)
+        } else {
+            percentage >= self.charged_percentage
+        }
+    }
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many days of battery history the runtime estimate looks back
+pub const HISTORY_DAYS: usize = 30;
+
+/// How many of the last complete days the recent duty cycle is averaged over
+const DUTY_DAYS: usize = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryDay {
+    /// The lowest voltage of the day, in mV
+    pub min_voltage: u16,
+    /// How long the device was awake on battery during the day
+    pub awake_secs: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BatteryHistory {
+    /// The local day of `days[0]`, in days since the epoch
+    pub day: Option<i64>,
+    /// The most recent day first; `None` for the days spent on external power
+    pub days: [Option<BatteryDay>; HISTORY_DAYS],
+}
+
+impl BatteryHistory {
+    pub const fn new() -> Self {
+        Self {
+            day: None,
+            days: [None; HISTORY_DAYS],
+        }
+    }
+
+    /// Records a voltage and the time awake since the last record, returning whether a new day
+    /// started
+    ///
+    /// Without a wall-clock time everything goes to the most recent day.
+    pub fn record(&mut self, day: Option<i64>, voltage: u16, awake_secs: u32) -> bool {
+        let mut new_day = false;
+
+        if let Some(day) = day {
+            match self.day {
+                Some(current) if day > current => {
+                    let shift = min((day - current) as usize, HISTORY_DAYS);
+
+                    self.days.rotate_right(shift);
+                    self.days[..shift].fill(None);
+                    self.day = Some(day);
+
+                    new_day = true;
+                }
+                Some(_) => (),
+                None => self.day = Some(day),
+            }
+        }
+
+        let today = self.days[0].get_or_insert(BatteryDay {
+            min_voltage: voltage,
+            awake_secs: 0,
+        });
+
+        today.min_voltage = min(today.min_voltage, voltage);
+        today.awake_secs = today.awake_secs.saturating_add(awake_secs);
+
+        new_day
+    }
+
+    /// The days left until the battery drops to `empty_voltage`
+    ///
+    /// The drop of the daily minimum voltage over the history is put down to the time spent
+    /// awake, and extrapolated at the duty cycle of the last days. `None` until there are two
+    /// days on battery with a voltage drop in between.
+    pub fn remaining_days(&self, empty_voltage: u16) -> Option<u32> {
+        let (newest, newest_day) = self
+            .days
+            .iter()
+            .enumerate()
+            .find_map(|(index, day)| day.map(|day| (index, day)))?;
+        let (oldest, oldest_day) = self
+            .days
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, day)| day.map(|day| (index, day)))?;
+
+        if oldest <= newest {
+            return None;
+        }
+
+        let drop = oldest_day
+            .min_voltage
+            .checked_sub(newest_day.min_voltage)
+            .filter(|drop| *drop > 0)?;
+
+        // The days after the oldest one drained the battery by that much
+        let awake_secs = self.days[newest..oldest]
+            .iter()
+            .flatten()
+            .map(|day| day.awake_secs as u64)
+            .sum::<u64>();
+
+        let recent = self.days[1..=DUTY_DAYS]
+            .iter()
+            .flatten()
+            .map(|day| day.awake_secs as u64);
+        let recent_days = recent.clone().count() as u64;
+
+        // Without any of the last days on battery, the duty cycle of the whole history
+        let awake_secs_per_day = recent
+            .sum::<u64>()
+            .checked_div(recent_days)
+            .unwrap_or(awake_secs / (oldest - newest) as u64);
+
+        let remaining = newest_day.min_voltage.saturating_sub(empty_voltage) as u64;
+
+        // Never awake, no drain to extrapolate
+        let remaining_days =
+            (remaining * awake_secs).checked_div(drop as u64 * awake_secs_per_day)?;
+
+        Some(min(remaining_days, u32::MAX as u64) as u32)
+    }
+}
+
+/// Displays a number of days in a unit which suits it, i.e. `5 months`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Days(pub u32);
 
-    pub fn percentage(&self) -> Option<u8> {
-        self.voltage.map(|voltage| {
-            let voltage = max(
-                BatteryState::LOW_VOLTAGE,
-                min(BatteryState::MAX_VOLTAGE, voltage),
-            );
+impl Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (count, unit) = match self.0 {
+            days if days < 60 => (days, "day"),
+            days if days < 730 => (days / 30, "month"),
+            days => (days / 365, "year"),
+        };
 
-            ((voltage - BatteryState::LOW_VOLTAGE) * 100
-                / (BatteryState::MAX_VOLTAGE - BatteryState::LOW_VOLTAGE)) as u8
-        })
+        write!(f, "{} {}{}", count, unit, if count == 1 { "" } else { "s" })
     }
 }
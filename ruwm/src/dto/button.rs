@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Button {
+    /// Previous
+    Button1,
+    /// Next
+    Button2,
+    /// Action
+    Button3,
+}
+
+impl Button {
+    pub const ALL: [Self; 3] = [Self::Button1, Self::Button2, Self::Button3];
+
+    pub const fn index(&self) -> usize {
+        match self {
+            Self::Button1 => 0,
+            Self::Button2 => 1,
+            Self::Button3 => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ButtonGesture {
+    Press(Button),
+    /// A second press of the same button shortly after the first one
+    DoublePress(Button),
+    /// Reported as soon as the button is held long enough, not on its release
+    LongPress(Button),
+    /// Two buttons pressed together, in the order of `Button::ALL`
+    Chord(Button, Button),
+}
+
+impl ButtonGesture {
+    pub fn chord(button: Button, other: Button) -> Self {
+        if button.index() <= other.index() {
+            Self::Chord(button, other)
+        } else {
+            Self::Chord(other, button)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonTimings {
+    /// How long a button has to be held for a long press
+    pub long_press_ms: u16,
+    /// How soon the second press of a double press has to follow the release of the first one;
+    /// 0 disables the double presses, which also reports the single presses without a delay
+    pub double_press_ms: u16,
+    /// How soon the second button of a chord has to follow the first one
+    pub chord_ms: u16,
+}
+
+impl ButtonTimings {
+    pub const fn new() -> Self {
+        Self {
+            long_press_ms: 5000,
+            double_press_ms: 300,
+            chord_ms: 200,
+        }
+    }
+}
+
+impl Default for ButtonTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,60 @@
+use core::fmt::{Debug, Write};
+
+use serde::{Deserialize, Serialize};
+
+use heapless::String;
+
+pub const MAX_SENSORS: usize = 4;
+
+pub const SENSOR_NAME_MAX_LEN: usize = 16;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SensorState {
+    Dry,
+    Wet,
+}
+
+impl SensorState {
+    pub fn is_wet(&self) -> bool {
+        matches!(self, Self::Wet)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SensorConfig {
+    /// The place of the sensor, i.e. "Kitchen" or "Boiler"; empty for the default name
+    pub name: String<SENSOR_NAME_MAX_LEN>,
+    /// How long a reading has to stay the same before the sensor changes its state
+    pub debounce_ms: u32,
+    /// The ADC reading at and above which an analog sensor is wet; digital sensors ignore it
+    pub threshold: u16,
+}
+
+impl SensorConfig {
+    pub const fn new() -> Self {
+        Self {
+            name: String::new(),
+            debounce_ms: 2000,
+            threshold: 1000,
+        }
+    }
+
+    /// The configured name, or "Sensor <number>"
+    pub fn display_name(&self, sensor: usize) -> String<SENSOR_NAME_MAX_LEN> {
+        if self.name.is_empty() {
+            let mut name = String::new();
+
+            write!(&mut name, "Sensor {}", sensor + 1).unwrap();
+
+            name
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,119 @@
+use core::fmt::{self, Debug, Display};
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('v');
+
+        // Ignore any pre-release or build metadata suffix
+        let s = s.split(['-', '+']).next().unwrap_or(s);
+
+        let mut parts = s.split('.').map(|part| part.parse::<u16>().map_err(|_| ()));
+
+        let major = parts.next().ok_or(())??;
+        let minor = parts.next().unwrap_or(Ok(0))?;
+        let patch = parts.next().unwrap_or(Ok(0))?;
+
+        if parts.next().is_some() {
+            Err(())
+        } else {
+            Ok(Self::new(major, minor, patch))
+        }
+    }
+}
+
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OtaError {
+    Busy,
+    Network,
+    Http(u16),
+    Manifest,
+    Size,
+    Image,
+    Storage,
+    Checksum,
+    Signature,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OtaStatus {
+    #[default]
+    Idle,
+    /// Running a freshly updated firmware which is not yet confirmed as working
+    PendingVerification,
+    Checking,
+    UpToDate,
+    UpdateAvailable(FirmwareVersion),
+    Downloading(u8),
+    Verifying,
+    /// The new firmware is written and will be booted on the next restart
+    Ready(Option<FirmwareVersion>),
+    Failed(OtaError),
+}
+
+impl OtaStatus {
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            Self::PendingVerification | Self::Checking | Self::Downloading(_) | Self::Verifying
+        )
+    }
+
+    pub fn progress(&self) -> Option<u8> {
+        match self {
+            Self::Downloading(progress) => Some(*progress),
+            Self::Verifying | Self::Ready(_) => Some(100),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct OtaState {
+    pub running_version: Option<FirmwareVersion>,
+    pub status: OtaStatus,
+}
+
+impl OtaState {
+    pub const fn new() -> Self {
+        Self {
+            running_version: None,
+            status: OtaStatus::Idle,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum OtaCommand {
+    CheckForUpdate,
+    Update,
+}
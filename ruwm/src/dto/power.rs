@@ -0,0 +1,107 @@
+use core::cmp::max;
+
+use serde::{Deserialize, Serialize};
+
+/// Why the device woke up from the deep sleep
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WakeReason {
+    /// A power-on or a reset rather than a wakeup
+    #[default]
+    Reset,
+    Button,
+    /// The ULP coprocessor detected a leak
+    Leak,
+    Timer,
+    /// Anything else the platform reports
+    Other,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerPolicy {
+    /// How long to stay awake after the last activity, when woken up (or reset) by a button or
+    /// anything else which is not covered below
+    pub button_awake_secs: u16,
+    /// How long to stay awake after the last activity, when woken up by a leak
+    pub leak_awake_secs: u16,
+    /// How long to stay awake after the last activity, when woken up by the timer
+    pub timer_awake_secs: u16,
+    /// How long to sleep with a water meter armed
+    pub armed_sleep_secs: u32,
+    /// How long to sleep with all water meters disarmed
+    pub disarmed_sleep_secs: u32,
+    /// The shortest sleep with a low battery, armed or not
+    pub low_battery_sleep_secs: u32,
+    /// Do not sleep while a web client is connected
+    pub web_keeps_awake: bool,
+}
+
+impl PowerPolicy {
+    pub const fn new() -> Self {
+        Self {
+            button_awake_secs: 20,
+            leak_awake_secs: 60,
+            timer_awake_secs: 20,
+            armed_sleep_secs: 30,
+            disarmed_sleep_secs: 120,
+            low_battery_sleep_secs: 600,
+            web_keeps_awake: true,
+        }
+    }
+
+    pub fn awake_secs(&self, wake_reason: WakeReason) -> u16 {
+        match wake_reason {
+            WakeReason::Reset | WakeReason::Button | WakeReason::Other => self.button_awake_secs,
+            WakeReason::Leak => self.leak_awake_secs,
+            WakeReason::Timer => self.timer_awake_secs,
+        }
+    }
+
+    pub fn sleep_secs(&self, low_battery: bool, armed: bool) -> u32 {
+        let sleep_secs = if armed {
+            self.armed_sleep_secs
+        } else {
+            self.disarmed_sleep_secs
+        };
+
+        if low_battery {
+            max(sleep_secs, self.low_battery_sleep_secs)
+        } else {
+            sleep_secs
+        }
+    }
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What keeps the device from sleeping
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SleepBlocker {
+    /// There is no point in saving the battery
+    Powered,
+    /// A firmware update is downloaded or verified
+    Update,
+    PressureTest,
+    WebClient,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PowerState {
+    pub wake_reason: WakeReason,
+    pub blocker: Option<SleepBlocker>,
+    /// Set once the device has decided to sleep, right before the pre-sleep hook runs
+    pub sleeping: bool,
+}
+
+impl PowerState {
+    pub const fn new() -> Self {
+        Self {
+            wake_reason: WakeReason::Reset,
+            blocker: None,
+            sleeping: false,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PressureState {
+    /// In mbar; `None` without a transducer or a reading yet
+    pub pressure: Option<u16>,
+    /// The pressure when the valve closed, while a pressure drop test is running
+    pub test_start: Option<u16>,
+    /// The pressure drop in mbar during the last completed test
+    pub test_drop: Option<u16>,
+}
+
+impl PressureState {
+    pub const fn new() -> Self {
+        Self {
+            pressure: None,
+            test_start: None,
+            test_drop: None,
+        }
+    }
+
+    pub fn is_testing(&self) -> bool {
+        self.test_start.is_some()
+    }
+}
+
+/// The calibration of the transducer and the schedule of the pressure drop test
+///
+/// The threshold of the pressure drop which indicates a leak is the one of the pressure
+/// drop alert.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PressureConfig {
+    /// The ADC reading at zero pressure, i.e. 500 for a 0.5-4.5 V transducer read in mV
+    pub zero: u16,
+    /// The ADC reading at the full scale pressure
+    pub span: u16,
+    /// The full scale pressure of the transducer in mbar
+    pub full_scale: u16,
+    /// When to run the daily test, in minutes after local midnight; `None` to only run it on request
+    pub test_at_mins: Option<u16>,
+    /// How long the test watches the pressure with the valve closed
+    pub test_mins: u16,
+}
+
+impl PressureConfig {
+    pub const fn new() -> Self {
+        Self {
+            zero: 500,
+            span: 4500,
+            full_scale: 10000,
+            test_at_mins: None,
+            test_mins: 10,
+        }
+    }
+
+    /// The pressure in mbar of an ADC reading
+    pub fn pressure(&self, reading: u16) -> u16 {
+        if self.span <= self.zero {
+            return 0;
+        }
+
+        let reading = reading.clamp(self.zero, self.span);
+
+        ((reading - self.zero) as u32 * self.full_scale as u32 / (self.span - self.zero) as u32)
+            as u16
+    }
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PressureCommand {
+    StartTest,
+    /// Stops a running test and opens the valve again
+    AbortTest,
+    Configure(PressureConfig),
+}
@@ -0,0 +1,99 @@
+use core::fmt::{self, Debug, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// How much the temperature has to rise above a threshold before its policy no longer applies,
+/// in tenths of °C
+pub const HYSTERESIS: i16 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FreezeStatus {
+    #[default]
+    Normal,
+    /// Below the warning threshold
+    Warning,
+    /// Below the freezing threshold; the valves are closed
+    Freezing,
+}
+
+impl FreezeStatus {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Warning => "warning",
+            Self::Freezing => "freezing",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TemperatureState {
+    /// In tenths of °C; `None` without a sensor or a reading yet
+    pub temperature: Option<i16>,
+    pub status: FreezeStatus,
+}
+
+impl TemperatureState {
+    pub const fn new() -> Self {
+        Self {
+            temperature: None,
+            status: FreezeStatus::Normal,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemperatureConfig {
+    /// Warn below that temperature, in tenths of °C
+    pub warn_below: Option<i16>,
+    /// Close the valves below that temperature, in tenths of °C
+    pub freeze_below: Option<i16>,
+    /// The valve to open below the freezing threshold, so as to drain the pipes
+    pub drain: Option<usize>,
+}
+
+impl TemperatureConfig {
+    pub const fn new() -> Self {
+        Self {
+            warn_below: Some(30),
+            freeze_below: Some(10),
+            drain: None,
+        }
+    }
+
+    /// The status at that temperature, given the current status
+    pub fn status(&self, temperature: i16, current: FreezeStatus) -> FreezeStatus {
+        let below = |threshold: Option<i16>, active: bool| {
+            threshold
+                .map(|threshold| temperature < threshold + if active { HYSTERESIS } else { 0 })
+                .unwrap_or(false)
+        };
+
+        if below(self.freeze_below, current == FreezeStatus::Freezing) {
+            FreezeStatus::Freezing
+        } else if below(self.warn_below, current != FreezeStatus::Normal) {
+            FreezeStatus::Warning
+        } else {
+            FreezeStatus::Normal
+        }
+    }
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Displays a temperature in tenths of °C as degrees, i.e. `-0.5`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Celsius(pub i16);
+
+impl Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+
+        write!(f, "{}{}.{}", sign, abs / 10, abs % 10)
+    }
+}
@@ -0,0 +1,333 @@
+use core::fmt::Debug;
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+pub const SECS_PER_HOUR: i64 = 60 * 60;
+pub const SECS_PER_DAY: i64 = SECS_PER_HOUR * 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TimeState {
+    /// Whether the wall-clock time is known, i.e. it was synced since the last power loss
+    pub valid: bool,
+    /// Seconds since the epoch of the last sync
+    pub synced_secs: Option<u64>,
+    /// The measured RTC drift in parts per million; positive when the RTC runs fast
+    pub drift_ppm: Option<i32>,
+}
+
+impl TimeState {
+    pub const fn new() -> Self {
+        Self {
+            valid: false,
+            synced_secs: None,
+            drift_ppm: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TimeZone {
+    /// The offset of the standard time, positive east of Greenwich
+    pub utc_offset_mins: i16,
+    pub dst: Option<DaylightSaving>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaylightSaving {
+    /// The offset of the daylight saving time, positive east of Greenwich
+    pub utc_offset_mins: i16,
+    /// In standard time
+    pub start: Transition,
+    /// In daylight saving time
+    pub end: Transition,
+}
+
+/// A yearly transition on a weekday of a month, i.e. the POSIX `Mm.w.d/time` rule
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 5, where 5 is the last one in the month
+    pub week: u8,
+    /// 0 (Sunday) - 6
+    pub weekday: u8,
+    /// The local time of the transition in seconds after midnight
+    pub time_secs: i32,
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self::new(0);
+
+    pub const fn new(utc_offset_mins: i16) -> Self {
+        Self {
+            utc_offset_mins,
+            dst: None,
+        }
+    }
+
+    /// The offset from UTC in effect at `utc_secs`
+    pub fn utc_offset_secs(&self, utc_secs: u64) -> i64 {
+        let std_offset = self.utc_offset_mins as i64 * 60;
+
+        let Some(dst) = self.dst else {
+            return std_offset;
+        };
+
+        let dst_offset = dst.utc_offset_mins as i64 * 60;
+
+        let utc_secs = utc_secs as i64;
+        let year = Date::from_days((utc_secs + std_offset).div_euclid(SECS_PER_DAY)).year;
+
+        let start = dst.start.utc_secs(year, std_offset);
+        let end = dst.end.utc_secs(year, dst_offset);
+
+        let in_dst = if start < end {
+            (start..end).contains(&utc_secs)
+        } else {
+            // Southern hemisphere, where the daylight saving time spans the new year
+            utc_secs >= start || utc_secs < end
+        };
+
+        if in_dst {
+            dst_offset
+        } else {
+            std_offset
+        }
+    }
+
+    /// Seconds since the epoch in local time
+    pub fn local_secs(&self, utc_secs: u64) -> i64 {
+        utc_secs as i64 + self.utc_offset_secs(utc_secs)
+    }
+}
+
+/// Parses a POSIX `TZ` string, i.e. `CET-1CEST,M3.5.0,M10.5.0/3`
+///
+/// Only the `Mm.w.d` transition rules are supported, which is what all current time zones use
+impl FromStr for TimeZone {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = TzParser(s.trim());
+
+        parser.name()?;
+
+        // POSIX offsets are positive west of Greenwich
+        let std_offset = -parser.time()?;
+
+        let dst = if parser.0.is_empty() {
+            None
+        } else {
+            parser.name()?;
+
+            let dst_offset = if parser.0.starts_with(',') {
+                std_offset + SECS_PER_HOUR as i32
+            } else {
+                -parser.time()?
+            };
+
+            parser.expect(',')?;
+            let start = parser.transition()?;
+
+            parser.expect(',')?;
+            let end = parser.transition()?;
+
+            Some(DaylightSaving {
+                utc_offset_mins: (dst_offset / 60) as i16,
+                start,
+                end,
+            })
+        };
+
+        if parser.0.is_empty() {
+            Ok(Self {
+                utc_offset_mins: (std_offset / 60) as i16,
+                dst,
+            })
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl Transition {
+    /// Seconds since the epoch of the transition in `year`
+    fn utc_secs(&self, year: i32, utc_offset_secs: i64) -> i64 {
+        let first = Date::new(year, self.month, 1);
+        let first_weekday = first.iso_weekday() % 7;
+
+        let mut day = 1 + (self.weekday + 7 - first_weekday) % 7 + (self.week - 1) * 7;
+        while day > first.days_in_month() {
+            day -= 7;
+        }
+
+        Date::new(year, self.month, day).to_days() * SECS_PER_DAY + self.time_secs as i64
+            - utc_offset_secs
+    }
+}
+
+struct TzParser<'a>(&'a str);
+
+impl<'a> TzParser<'a> {
+    fn name(&mut self) -> Result<&'a str, ()> {
+        let (name, rest) = if let Some(quoted) = self.0.strip_prefix('<') {
+            let end = quoted.find('>').ok_or(())?;
+
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            let end = self
+                .0
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(self.0.len());
+
+            self.0.split_at(end)
+        };
+
+        self.0 = rest;
+
+        if name.len() >= 3 {
+            Ok(name)
+        } else {
+            Err(())
+        }
+    }
+
+    /// `[+|-]hh[:mm[:ss]]` in seconds
+    fn time(&mut self) -> Result<i32, ()> {
+        let sign = if let Some(rest) = self.0.strip_prefix('-') {
+            self.0 = rest;
+            -1
+        } else {
+            self.0 = self.0.strip_prefix('+').unwrap_or(self.0);
+            1
+        };
+
+        let mut secs = self.number()? * 60 * 60;
+
+        if self.0.starts_with(':') {
+            self.expect(':')?;
+            secs += self.number()? * 60;
+
+            if self.0.starts_with(':') {
+                self.expect(':')?;
+                secs += self.number()?;
+            }
+        }
+
+        Ok(sign * secs)
+    }
+
+    /// `Mm.w.d[/time]`
+    fn transition(&mut self) -> Result<Transition, ()> {
+        self.expect('M')?;
+        let month = self.number()?;
+        self.expect('.')?;
+        let week = self.number()?;
+        self.expect('.')?;
+        let weekday = self.number()?;
+
+        let time_secs = if self.0.starts_with('/') {
+            self.expect('/')?;
+            self.time()?
+        } else {
+            2 * 60 * 60
+        };
+
+        if (1..=12).contains(&month) && (1..=5).contains(&week) && (0..=6).contains(&weekday) {
+            Ok(Transition {
+                month: month as u8,
+                week: week as u8,
+                weekday: weekday as u8,
+                time_secs,
+            })
+        } else {
+            Err(())
+        }
+    }
+
+    fn number(&mut self) -> Result<i32, ()> {
+        let end = self
+            .0
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.0.len());
+
+        let (number, rest) = self.0.split_at(end);
+        self.0 = rest;
+
+        number.parse().map_err(|_| ())
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ()> {
+        self.0 = self.0.strip_prefix(c).ok_or(())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Date {
+    pub year: i32,
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 31
+    pub day: u8,
+}
+
+impl Date {
+    pub const fn new(year: i32, month: u8, day: u8) -> Self {
+        Self { year, month, day }
+    }
+
+    /// The date of the day with that number of days since 1970-01-01
+    pub fn from_days(days: i64) -> Self {
+        // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u8;
+        let year = (year_of_era + era * 400) as i32 + (month <= 2) as i32;
+
+        Self::new(year, month, day)
+    }
+
+    /// The number of days since 1970-01-01
+    pub fn to_days(&self) -> i64 {
+        // See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    }
+
+    pub fn days_in_month(&self) -> u8 {
+        let next = if self.month == 12 {
+            Self::new(self.year + 1, 1, 1)
+        } else {
+            Self::new(self.year, self.month + 1, 1)
+        };
+
+        (next.to_days() - Self::new(self.year, self.month, 1).to_days()) as u8
+    }
+
+    /// 1 (Monday) - 7 (Sunday)
+    pub fn iso_weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((self.to_days() + 3).rem_euclid(7) + 1) as u8
+    }
+}
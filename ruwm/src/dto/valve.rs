@@ -1,11 +1,20 @@
-use core::fmt::Debug;
+use core::fmt::{Debug, Write};
 
 use serde::{Deserialize, Serialize};
 
+use heapless::String;
+
+/// The number of valves supported; the first one is the main shutoff valve
+pub const MAX_VALVES: usize = 3;
+
+pub const VALVE_NAME_MAX_LEN: usize = 16;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ValveState {
     Open,
     Closed,
+    /// Open to the percentage of the partial position of the valve profile
+    Partial(u8),
     Opening(u8),
     Closing(u8),
 }
@@ -15,11 +24,20 @@ impl ValveState {
         match self {
             Self::Open => 100,
             Self::Closed => 0,
+            Self::Partial(percentage) => *percentage,
             Self::Opening(percentage) => *percentage,
             Self::Closing(percentage) => 100 - *percentage,
         }
     }
 
+    /// The open percentage, if the valve is at rest
+    pub fn position(&self) -> Option<u8> {
+        match self {
+            Self::Opening(_) | Self::Closing(_) => None,
+            _ => Some(self.open_percentage()),
+        }
+    }
+
     pub fn simplify(&self) -> Self {
         match self {
             Self::Opening(_) => Self::Opening(0),
@@ -33,4 +51,127 @@ impl ValveState {
 pub enum ValveCommand {
     Open,
     Close,
+    /// Open to the partial position of the valve profile
+    OpenPartially,
+}
+
+/// The timing of the valve motor
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ValveProfile {
+    /// The travel time from closed to open
+    pub open_ms: u32,
+    /// The travel time from open to closed
+    pub close_ms: u32,
+    /// How long the motor is stopped before reversing its direction
+    pub dead_time_ms: u32,
+    /// The open percentage of the partial position, if the valve supports one
+    pub partial: Option<u8>,
+    /// The limits of the motor current, if it is sensed
+    pub current_limits: Option<CurrentLimits>,
+}
+
+impl ValveProfile {
+    /// The timing the firmware used before profiles were configurable
+    pub const DEFAULT: Self = Self::symmetric(20_000);
+
+    /// A valve with the same travel time in both directions
+    pub const fn symmetric(travel_ms: u32) -> Self {
+        Self {
+            open_ms: travel_ms,
+            close_ms: travel_ms,
+            dead_time_ms: 500,
+            partial: None,
+            current_limits: None,
+        }
+    }
+
+    pub fn travel_ms(&self, command: ValveCommand) -> u32 {
+        match command {
+            ValveCommand::Close => self.close_ms,
+            _ => self.open_ms,
+        }
+    }
+}
+
+impl Default for ValveProfile {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The limits of the motor current, in the units of the current sense ADC
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CurrentLimits {
+    /// The current of a stalled motor; it ends a turn to an end stop early, while it is
+    /// a jam on the way to a partial position
+    pub stall: u16,
+    /// The current of a jammed motor
+    pub overcurrent: u16,
+    /// How long the current is not checked after the motor starts, to ignore its inrush current
+    pub inrush_ms: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ValveFault {
+    /// The motor stalled before it reached a partial position
+    Stalled,
+    Overcurrent,
+}
+
+impl ValveFault {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stalled => "stalled",
+            Self::Overcurrent => "overcurrent",
+        }
+    }
+}
+
+/// Which emergencies close a valve
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EmergencyPolicy {
+    /// A leak, a low battery, an alert which closes the valve or the tripped away mode
+    Any,
+    /// A leak only
+    Leak,
+    /// None; the valve is only operated manually
+    Ignore,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ValveConfig {
+    /// Whether the valve is installed; the main valve always is
+    pub enabled: bool,
+    /// The name of the zone behind the valve; empty for the default name
+    pub name: String<VALVE_NAME_MAX_LEN>,
+    pub emergency: EmergencyPolicy,
+}
+
+impl ValveConfig {
+    pub const fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            name: String::new(),
+            emergency: EmergencyPolicy::Any,
+        }
+    }
+
+    /// The configured name, or "Valve <number>"
+    pub fn display_name(&self, valve: usize) -> String<VALVE_NAME_MAX_LEN> {
+        if self.name.is_empty() {
+            let mut name = String::new();
+
+            write!(&mut name, "Valve {}", valve + 1).unwrap();
+
+            name
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+impl Default for ValveConfig {
+    fn default() -> Self {
+        Self::new(false)
+    }
 }
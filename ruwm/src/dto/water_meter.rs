@@ -1,7 +1,14 @@
-use core::fmt::Debug;
+use core::fmt::{Debug, Write};
 
 use serde::{Deserialize, Serialize};
 
+use heapless::String;
+
+/// The number of water meter channels supported; the first one is the main meter
+pub const MAX_METERS: usize = 3;
+
+pub const METER_NAME_MAX_LEN: usize = 16;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct WaterMeterState {
     pub edges_count: u64,
@@ -19,8 +26,77 @@ impl WaterMeterState {
     }
 }
 
+/// The instantaneous flow rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct FlowState {
+    /// Smoothed and decaying towards zero as long as no further edges arrive
+    pub edges_per_hour: u32,
+    pub flowing: bool,
+}
+
+impl FlowState {
+    pub const fn new() -> Self {
+        Self {
+            edges_per_hour: 0,
+            flowing: false,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum WaterMeterCommand {
     Arm,
     Disarm,
 }
+
+/// What a leak detected by a water meter closes
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LeakPolicy {
+    /// All valves which close on a leak
+    CloseAll,
+    /// The valve with that index only, i.e. the one of the zone the meter measures
+    CloseValve(usize),
+    /// None; the leak is only reported
+    Report,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MeterConfig {
+    /// Whether the meter is installed; the main meter always is
+    pub enabled: bool,
+    /// The name of the meter, i.e. "Cold" or "Garden"; empty for the default name
+    pub name: String<METER_NAME_MAX_LEN>,
+    /// The calibration of the meter, in pulse counter edges per liter
+    pub edges_per_liter: u32,
+    pub leak: LeakPolicy,
+}
+
+impl MeterConfig {
+    pub const fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            name: String::new(),
+            edges_per_liter: 1,
+            leak: LeakPolicy::CloseAll,
+        }
+    }
+
+    /// The configured name, or "Meter <number>"
+    pub fn display_name(&self, meter: usize) -> String<METER_NAME_MAX_LEN> {
+        if self.name.is_empty() {
+            let mut name = String::new();
+
+            write!(&mut name, "Meter {}", meter + 1).unwrap();
+
+            name
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+impl Default for MeterConfig {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
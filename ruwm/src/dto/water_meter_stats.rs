@@ -1,10 +1,16 @@
+use core::cmp::min;
 use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
-const FLOW_STATS_INSTANCES: usize = 8;
+use heapless::Vec;
 
-const DURATIONS: [u64; FLOW_STATS_INSTANCES] = [
+use super::time::{Date, TimeZone, SECS_PER_DAY, SECS_PER_HOUR};
+
+pub const FLOW_STATS_INSTANCES: usize = 8;
+
+/// The durations in seconds of the measurement windows
+pub const DURATIONS: [u64; FLOW_STATS_INSTANCES] = [
     60 * 5,
     60 * 30,
     60 * 60,
@@ -107,7 +113,142 @@ impl FlowMeasurement {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub const HOURS_HISTORY: usize = 48;
+pub const DAYS_HISTORY: usize = 31;
+pub const WEEKS_HISTORY: usize = 12;
+pub const MONTHS_HISTORY: usize = 12;
+pub const YEARS_HISTORY: usize = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Period {
+    Hour,
+    Day,
+    /// ISO week, i.e. starting on Monday
+    Week,
+    Month,
+    Year,
+}
+
+/// The consumption in the most recent calendar periods of the same kind
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PeriodHistory<const N: usize> {
+    /// The current period, i.e. hours, days, weeks or months since the epoch, or the year
+    pub period: i32,
+    /// Edges per period, oldest first and ending with the current period
+    pub edges: Vec<u32, N>,
+}
+
+impl<const N: usize> PeriodHistory<N> {
+    pub const fn new() -> Self {
+        Self {
+            period: 0,
+            edges: Vec::new(),
+        }
+    }
+
+    /// The edges counted `ago` periods before the current one
+    pub fn get(&self, ago: usize) -> Option<u32> {
+        self.edges
+            .len()
+            .checked_sub(ago + 1)
+            .map(|index| self.edges[index])
+    }
+
+    fn add(&mut self, period: i32, edges: u32) {
+        if self.edges.is_empty() {
+            self.period = period;
+            self.edges.push(0).unwrap();
+        } else if period > self.period {
+            for _ in 0..min(period - self.period, N as i32) {
+                if self.edges.is_full() {
+                    self.edges.remove(0);
+                }
+
+                self.edges.push(0).unwrap();
+            }
+
+            self.period = period;
+        }
+
+        // With the clock going back (i.e. on a time zone change), count in the current period
+        let current = self.edges.last_mut().unwrap();
+        *current = current.saturating_add(edges);
+    }
+}
+
+/// Consumption history in calendar periods of the local time
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CalendarStats {
+    /// The edges count up to which the consumption is accounted for
+    pub edges_count: Option<u64>,
+
+    pub hours: PeriodHistory<HOURS_HISTORY>,
+    pub days: PeriodHistory<DAYS_HISTORY>,
+    pub weeks: PeriodHistory<WEEKS_HISTORY>,
+    pub months: PeriodHistory<MONTHS_HISTORY>,
+    pub years: PeriodHistory<YEARS_HISTORY>,
+}
+
+impl CalendarStats {
+    pub const fn new() -> Self {
+        Self {
+            edges_count: None,
+            hours: PeriodHistory::new(),
+            days: PeriodHistory::new(),
+            weeks: PeriodHistory::new(),
+            months: PeriodHistory::new(),
+            years: PeriodHistory::new(),
+        }
+    }
+
+    /// The edges counted in the period `ago` periods before the current one,
+    /// i.e. `history(Period::Day, 1)` is the consumption yesterday
+    pub fn history(&self, period: Period, ago: usize) -> Option<u32> {
+        match period {
+            Period::Hour => self.hours.get(ago),
+            Period::Day => self.days.get(ago),
+            Period::Week => self.weeks.get(ago),
+            Period::Month => self.months.get(ago),
+            Period::Year => self.years.get(ago),
+        }
+    }
+
+    /// Accounts the edges since the previous update in the periods of `utc_secs`
+    ///
+    /// Returns the previous current hour when a new hour has started
+    pub fn update(&mut self, edges_count: u64, utc_secs: u64, time_zone: &TimeZone) -> Option<i32> {
+        let edges = self
+            .edges_count
+            .map(|prev| edges_count.saturating_sub(prev))
+            .unwrap_or(0);
+
+        self.edges_count = Some(edges_count);
+
+        let local_secs = time_zone.local_secs(utc_secs);
+
+        let hour = local_secs.div_euclid(SECS_PER_HOUR) as i32;
+        let day = local_secs.div_euclid(SECS_PER_DAY);
+        // 1970-01-01 was a Thursday and ISO weeks start on Monday
+        let week = (day + 3).div_euclid(7) as i32;
+        let date = Date::from_days(day);
+        let month = date.year * 12 + date.month as i32 - 1;
+
+        let prev_hour = self.hours.period;
+        let new_hour = self.hours.edges.is_empty() || hour > prev_hour;
+
+        let edges = min(edges, u32::MAX as u64) as u32;
+
+        self.hours.add(hour, edges);
+        self.days.add(day as i32, edges);
+        self.weeks.add(week, edges);
+        self.months.add(month, edges);
+        self.years.add(date.year, edges);
+
+        new_hour.then_some(prev_hour)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct WaterMeterStatsState {
     pub installation: FlowSnapshot,
 
@@ -115,6 +256,8 @@ pub struct WaterMeterStatsState {
 
     pub snapshots: [FlowSnapshot; FLOW_STATS_INSTANCES],
     pub measurements: [Option<FlowMeasurement>; FLOW_STATS_INSTANCES],
+
+    pub calendar: CalendarStats,
 }
 
 impl WaterMeterStatsState {
@@ -126,10 +269,21 @@ impl WaterMeterStatsState {
             most_recent: FlowSnapshot::new_default(),
             snapshots: [DEFAULT_SNAPSHOT; FLOW_STATS_INSTANCES],
             measurements: [None; FLOW_STATS_INSTANCES],
+            calendar: CalendarStats::new(),
         }
     }
 
-    pub fn update(&mut self, edges_count: u64, now_secs: u64) -> bool {
+    /// `now_secs` is the monotonic time and `utc_secs` the wall-clock time, if known
+    ///
+    /// Consumption while the wall-clock time is not known is accounted for in the calendar periods
+    /// once it becomes known
+    pub fn update(
+        &mut self,
+        edges_count: u64,
+        now_secs: u64,
+        utc_secs: Option<u64>,
+        time_zone: &TimeZone,
+    ) -> bool {
         let most_recent = FlowSnapshot::new(now_secs, edges_count);
 
         let mut updated = self.most_recent != most_recent;
@@ -146,6 +300,14 @@ impl WaterMeterStatsState {
             }
         }
 
+        if let Some(utc_secs) = utc_secs {
+            let calendar = self.calendar.clone();
+
+            self.calendar.update(edges_count, utc_secs, time_zone);
+
+            updated |= self.calendar != calendar;
+        }
+
         updated
     }
 }
@@ -6,9 +6,19 @@ use heapless::String;
 
 use edge_frame::dto::Role;
 
-use super::battery::BatteryState;
-use super::valve::{ValveCommand, ValveState};
-use super::water_meter::{WaterMeterCommand, WaterMeterState};
+use super::alert::{AlertCommand, AlertConfig, AlertState};
+use super::away::{AwayCommand, AwayConfig, AwayState};
+use super::battery::{BatteryConfig, BatteryState};
+use super::button::ButtonTimings;
+use super::moisture::{SensorConfig, SensorState, MAX_SENSORS};
+use super::ota::{OtaCommand, OtaState};
+use super::power::PowerPolicy;
+use super::pressure::{PressureCommand, PressureConfig, PressureState};
+use super::temperature::{TemperatureConfig, TemperatureState};
+use super::time::TimeState;
+use super::valve::{ValveCommand, ValveConfig, ValveFault, ValveProfile, ValveState, MAX_VALVES};
+use super::water_meter::{FlowState, MeterConfig, WaterMeterCommand, WaterMeterState, MAX_METERS};
+use super::wifi::WifiPolicy;
 
 pub const USERNAME_MAX_LEN: usize = 32;
 pub const PASSWORD_MAX_LEN: usize = 32;
@@ -18,8 +28,24 @@ pub enum WebRequest {
     Authenticate(String<USERNAME_MAX_LEN>, String<PASSWORD_MAX_LEN>),
     Logout,
 
-    ValveCommand(ValveCommand),
-    WaterMeterCommand(WaterMeterCommand),
+    /// The command and the index of the valve
+    ValveCommand(usize, ValveCommand),
+    ValveProfile(usize, ValveProfile),
+    ValveConfig(usize, ValveConfig),
+    /// The command and the index of the water meter
+    WaterMeterCommand(usize, WaterMeterCommand),
+    WaterMeterConfig(usize, MeterConfig),
+    /// The configuration and the index of the moisture sensor
+    MoistureConfig(usize, SensorConfig),
+    TemperatureConfig(TemperatureConfig),
+    PressureCommand(PressureCommand),
+    BatteryConfig(BatteryConfig),
+    PowerPolicy(PowerPolicy),
+    ButtonTimings(ButtonTimings),
+    WifiPolicy(WifiPolicy),
+    OtaCommand(OtaCommand),
+    AlertCommand(AlertCommand),
+    AwayCommand(AwayCommand),
     // TODO
     //WifiSettingsUpdate(...),
 }
@@ -29,22 +55,58 @@ impl WebRequest {
         match self {
             Self::Authenticate(_, _) => Role::None,
             Self::Logout => Role::None,
-            Self::ValveCommand(_) => Role::User,
-            Self::WaterMeterCommand(_) => Role::User,
+            Self::ValveCommand(_, _) => Role::User,
+            Self::ValveProfile(_, _) => Role::Admin,
+            Self::ValveConfig(_, _) => Role::Admin,
+            Self::WaterMeterCommand(_, _) => Role::User,
+            Self::WaterMeterConfig(_, _) => Role::Admin,
+            Self::MoistureConfig(_, _) => Role::Admin,
+            Self::TemperatureConfig(_) => Role::Admin,
+            Self::PressureCommand(PressureCommand::Configure(_)) => Role::Admin,
+            Self::PressureCommand(_) => Role::User,
+            Self::BatteryConfig(_) => Role::Admin,
+            Self::PowerPolicy(_) => Role::Admin,
+            Self::ButtonTimings(_) => Role::Admin,
+            Self::WifiPolicy(_) => Role::Admin,
+            Self::OtaCommand(_) => Role::Admin,
+            Self::AlertCommand(AlertCommand::Configure(_)) => Role::Admin,
+            Self::AlertCommand(_) => Role::User,
+            Self::AwayCommand(_) => Role::User,
         }
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum WebEvent {
     NoPermissions,
 
     AuthenticationFailed,
 
     RoleState(Role),
-    ValveState(Option<ValveState>),
-    WaterMeterState(WaterMeterState),
+    ValveState([Option<ValveState>; MAX_VALVES]),
+    ValveProfile([ValveProfile; MAX_VALVES]),
+    ValveFault([Option<ValveFault>; MAX_VALVES]),
+    ValveConfig([ValveConfig; MAX_VALVES]),
+    WaterMeterState([WaterMeterState; MAX_METERS]),
+    WaterMeterConfig([MeterConfig; MAX_METERS]),
+    FlowState([FlowState; MAX_METERS]),
+    MoistureState([Option<SensorState>; MAX_SENSORS]),
+    MoistureConfig([SensorConfig; MAX_SENSORS]),
+    TemperatureState(TemperatureState),
+    TemperatureConfig(TemperatureConfig),
+    PressureState(PressureState),
+    PressureConfig(PressureConfig),
     BatteryState(BatteryState),
+    BatteryConfig(BatteryConfig),
+    PowerPolicy(PowerPolicy),
+    ButtonTimings(ButtonTimings),
+    WifiPolicy(WifiPolicy),
+    OtaState(OtaState),
+    AlertState(AlertState),
+    AlertConfig(AlertConfig),
+    AwayState(AwayState),
+    AwayConfig(AwayConfig),
+    TimeState(TimeState),
     //WifiState(Status),
 
     // MqttPublishNotification(MessageId),
@@ -58,8 +120,29 @@ impl WebEvent {
             Self::AuthenticationFailed => Role::None,
             Self::RoleState(_) => Role::None,
             Self::ValveState(_) => Role::User,
+            Self::ValveProfile(_) => Role::User,
+            Self::ValveFault(_) => Role::User,
+            Self::ValveConfig(_) => Role::User,
             Self::WaterMeterState(_) => Role::User,
+            Self::WaterMeterConfig(_) => Role::User,
+            Self::FlowState(_) => Role::User,
+            Self::MoistureState(_) => Role::User,
+            Self::MoistureConfig(_) => Role::User,
+            Self::TemperatureState(_) => Role::User,
+            Self::TemperatureConfig(_) => Role::User,
+            Self::PressureState(_) => Role::User,
+            Self::PressureConfig(_) => Role::User,
             Self::BatteryState(_) => Role::User,
+            Self::BatteryConfig(_) => Role::User,
+            Self::PowerPolicy(_) => Role::User,
+            Self::ButtonTimings(_) => Role::User,
+            Self::WifiPolicy(_) => Role::User,
+            Self::OtaState(_) => Role::User,
+            Self::AlertState(_) => Role::User,
+            Self::AlertConfig(_) => Role::User,
+            Self::AwayState(_) => Role::User,
+            Self::AwayConfig(_) => Role::User,
+            Self::TimeState(_) => Role::User,
             //Self::WifiState(_) => Role::User,
         }
     }
@@ -0,0 +1,107 @@
+use core::cmp::min;
+use core::fmt::Debug;
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WifiState {
+    #[default]
+    Idle,
+    Connecting {
+        network: u8,
+        attempt: u16,
+    },
+    Connected {
+        network: u8,
+    },
+    Backoff {
+        attempt: u16,
+        retry_secs: u32,
+        error: bool,
+    },
+    GaveUp,
+}
+
+impl WifiState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Self::Connected { .. })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WifiGiveUp {
+    /// Number of failed connection attempts (across all known networks) after which the radio is stopped
+    pub after_attempts: u16,
+    /// Only give up when running on battery
+    pub battery_only: bool,
+    /// Retry again after that many seconds; if `None`, wait for a new configuration
+    pub sleep_secs: Option<u32>,
+}
+
+/// `<after_attempts>[,battery][,<sleep_secs>]`, i.e. `3,battery,3600`
+impl FromStr for WifiGiveUp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut items = s.split(',').map(str::trim);
+
+        let mut give_up = Self {
+            after_attempts: items.next().ok_or(())?.parse().map_err(|_| ())?,
+            battery_only: false,
+            sleep_secs: None,
+        };
+
+        for item in items {
+            if item == "battery" && !give_up.battery_only && give_up.sleep_secs.is_none() {
+                give_up.battery_only = true;
+            } else if give_up.sleep_secs.is_none() {
+                give_up.sleep_secs = Some(item.parse().map_err(|_| ())?);
+            } else {
+                return Err(());
+            }
+        }
+
+        Ok(give_up)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WifiPolicy {
+    pub backoff_initial_secs: u32,
+    pub backoff_max_secs: u32,
+    pub give_up: Option<WifiGiveUp>,
+}
+
+impl WifiPolicy {
+    pub const fn new() -> Self {
+        Self {
+            backoff_initial_secs: 5,
+            backoff_max_secs: 60 * 10,
+            give_up: None,
+        }
+    }
+
+    pub fn backoff_secs(&self, round: u32) -> u32 {
+        let factor = 1_u32
+            .checked_shl(round.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+
+        min(
+            self.backoff_initial_secs.saturating_mul(factor),
+            self.backoff_max_secs,
+        )
+    }
+
+    pub fn should_give_up(&self, attempt: u16, powered: bool) -> bool {
+        self.give_up
+            .map(|give_up| attempt >= give_up.after_attempts && !(give_up.battery_only && powered))
+            .unwrap_or(false)
+    }
+}
+
+impl Default for WifiPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
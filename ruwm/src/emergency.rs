@@ -1,53 +1,138 @@
-use embassy_futures::select::{select3, Either3};
+use embassy_futures::select::select_slice;
 
 use channel_bridge::notification::Notification;
 
-use crate::battery::{self, BatteryState};
-use crate::valve::{self, ValveCommand, ValveState};
-use crate::wm;
+use crate::alert::{self, AlertAction};
+use crate::away;
+use crate::battery;
+use crate::moisture;
+use crate::pressure;
+use crate::temperature::{self, FreezeStatus};
+use crate::valve::{self, EmergencyPolicy, ValveCommand, ValveState, MAX_VALVES};
+use crate::wm::{self, LeakPolicy};
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERT_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static AWAY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static MOISTURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TEMPERATURE_STATE_NOTIF: Notification = Notification::new();
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Emergency {
+    Leak,
+    /// A leak which only concerns the valve with that index
+    ValveLeak(usize),
+    /// Freezing pipes; the drain valve, if any, is opened rather than closed
+    Freeze(Option<usize>),
+    Other,
+}
 
 pub async fn process() {
-    let mut valve_state = None;
+    let mut valve_states = [None; MAX_VALVES];
+
+    let mut notifs = [
+        VALVE_STATE_NOTIF.wait(),
+        WM_STATE_NOTIF.wait(),
+        BATTERY_STATE_NOTIF.wait(),
+        ALERT_STATE_NOTIF.wait(),
+        AWAY_STATE_NOTIF.wait(),
+        MOISTURE_STATE_NOTIF.wait(),
+        TEMPERATURE_STATE_NOTIF.wait(),
+    ];
 
     loop {
-        let emergency_close = match select3(
-            VALVE_STATE_NOTIF.wait(),
-            WM_STATE_NOTIF.wait(),
-            BATTERY_STATE_NOTIF.wait(),
-        )
-        .await
-        {
-            Either3::First(_) => {
-                valve_state = valve::STATE.get();
+        let emergency = match select_slice(&mut notifs).await.1 {
+            0 => {
+                valve_states = valve::STATE.get();
 
-                false
+                None
             }
-            Either3::Second(_) => wm::STATE.get().leaking,
-            Either3::Third(_) => {
-                let battery = battery::STATE.get();
+            1 => {
+                let wm_states = wm::STATE.get();
 
-                let battery_low = battery
-                    .voltage
-                    .map(|voltage| voltage <= BatteryState::LOW_VOLTAGE)
-                    .unwrap_or(false);
+                for (meter, config) in wm::enabled() {
+                    if !wm_states[meter].leaking {
+                        continue;
+                    }
+
+                    match config.leak {
+                        LeakPolicy::CloseAll => close(Emergency::Leak, &valve_states),
+                        LeakPolicy::CloseValve(valve) => {
+                            close(Emergency::ValveLeak(valve), &valve_states)
+                        }
+                        LeakPolicy::Report => (),
+                    }
+                }
+
+                None
+            }
+            2 => {
+                let battery = battery::STATE.get();
 
                 let powered = battery.powered.unwrap_or(false);
 
-                battery_low && !powered
+                (battery.low && !powered).then_some(Emergency::Other)
             }
+            // Acknowledging the alert allows to open the valve again
+            3 => (alert::STATE.get().is_raised()
+                && alert::CONFIG.get().action == AlertAction::CloseValve)
+                .then_some(Emergency::Other),
+            4 => away::STATE.get().tripped.then_some(Emergency::Other),
+            // Water on the floor is a leak whether the meters are armed or not
+            5 => moisture::any_wet().then_some(Emergency::Leak),
+            6 => (temperature::STATE.get().status == FreezeStatus::Freezing).then(|| {
+                let drain = temperature::CONFIG.get().drain;
+
+                if let Some(drain) = drain {
+                    open_drain(drain, &valve_states);
+                }
+
+                Emergency::Freeze(drain)
+            }),
+            _ => unreachable!(),
         };
 
-        if emergency_close
+        if let Some(emergency) = emergency {
+            close(emergency, &valve_states);
+        }
+    }
+}
+
+fn close(emergency: Emergency, valve_states: &[Option<ValveState>; MAX_VALVES]) {
+    for (valve, config) in valve::enabled() {
+        let close = match (config.emergency, emergency) {
+            (EmergencyPolicy::Ignore, _) => false,
+            (_, Emergency::Freeze(Some(drain))) if drain == valve => false,
+            (_, Emergency::ValveLeak(leaking_valve)) => leaking_valve == valve,
+            (EmergencyPolicy::Any, _) => true,
+            (EmergencyPolicy::Leak, _) => emergency == Emergency::Leak,
+        };
+
+        if close
             && !matches!(
-                valve_state,
+                valve_states[valve],
                 Some(ValveState::Closing(_)) | Some(ValveState::Closed)
             )
         {
-            valve::COMMAND.signal(ValveCommand::Close);
+            valve::COMMAND[valve].signal(ValveCommand::Close);
         }
+
+        // The valve may be closed already by a pressure drop test, which should not open it again
+        if close && valve == 0 {
+            pressure::EMERGENCY.signal(());
+        }
+    }
+}
+
+fn open_drain(drain: usize, valve_states: &[Option<ValveState>; MAX_VALVES]) {
+    if valve::enabled().any(|(valve, _)| valve == drain)
+        && !matches!(
+            valve_states[drain],
+            Some(ValveState::Opening(_)) | Some(ValveState::Open)
+        )
+    {
+        valve::COMMAND[drain].signal(ValveCommand::Open);
     }
 }
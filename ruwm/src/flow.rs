@@ -0,0 +1,112 @@
+//! Instantaneous flow rate of each water meter, estimated from the intervals between the pulse
+//! counter edges
+//!
+//! The rate is smoothed with an exponential moving average. Once the edges stop, it decays
+//! with the time since the last edge, as the flow cannot be higher than one edge per that time.
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::wm::{self, MAX_METERS};
+
+pub use crate::dto::water_meter::FlowState;
+
+const MICROS_PER_HOUR: u64 = 60 * 60 * 1_000_000;
+
+/// The flow is considered stopped without edges for that long,
+/// so this is also the lowest flow rate which can be measured
+const STOP_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const DECAY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The weight of the previous rate in the moving average is `(SMOOTHING - 1) / SMOOTHING`
+const SMOOTHING: u64 = 4;
+
+pub type FlowStates = [FlowState; MAX_METERS];
+
+pub static STATE: State<FlowStates> = State::new(
+    "FLOW",
+    [FlowState::new(); MAX_METERS],
+    &[
+        &crate::screen::FLOW_STATE_NOTIF,
+        &crate::mqtt::FLOW_STATE_NOTIF,
+        &crate::web::FLOW_STATE_NOTIF,
+        &crate::alert::FLOW_STATE_NOTIF,
+        &crate::away::FLOW_STATE_NOTIF,
+    ],
+);
+
+pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+
+pub async fn process() {
+    let mut edges_counts = wm::STATE.get().map(|state| state.edges_count);
+    let mut last_edges: [Option<Instant>; MAX_METERS] = [None; MAX_METERS];
+
+    loop {
+        let flowing = STATE.get().iter().any(|state| state.flowing);
+
+        let edges = if flowing {
+            match select(WM_STATE_NOTIF.wait(), Timer::after(DECAY_INTERVAL)).await {
+                Either::First(_) => true,
+                Either::Second(_) => false,
+            }
+        } else {
+            WM_STATE_NOTIF.wait().await;
+
+            true
+        };
+
+        let now = Instant::now();
+
+        let wm_states = wm::STATE.get();
+
+        let mut states = STATE.get();
+
+        for (meter, state) in states.iter_mut().enumerate() {
+            let edges_count = &mut edges_counts[meter];
+            let last_edge = &mut last_edges[meter];
+
+            let new_edges_count = wm_states[meter].edges_count;
+            let new_edges = new_edges_count.saturating_sub(*edges_count);
+
+            *edges_count = new_edges_count;
+
+            if edges && new_edges > 0 {
+                // Without a recent edge the interval is unknown, so the rate is known only after the next one
+                let rate = last_edge
+                    .filter(|last_edge| now - *last_edge < STOP_TIMEOUT)
+                    .map(|last_edge| {
+                        new_edges * MICROS_PER_HOUR / (now - last_edge).as_micros().max(1)
+                    });
+
+                *last_edge = Some(now);
+
+                *state = FlowState {
+                    edges_per_hour: match rate {
+                        Some(rate) if state.edges_per_hour > 0 => {
+                            ((state.edges_per_hour as u64 * (SMOOTHING - 1) + rate) / SMOOTHING)
+                                as u32
+                        }
+                        Some(rate) => rate as u32,
+                        None => state.edges_per_hour,
+                    },
+                    flowing: true,
+                };
+            } else if let Some(since) = last_edge.map(|last_edge| now - last_edge) {
+                if since >= STOP_TIMEOUT {
+                    *last_edge = None;
+
+                    *state = FlowState::new();
+                } else {
+                    let max_rate = (MICROS_PER_HOUR / since.as_micros().max(1)) as u32;
+
+                    state.edges_per_hour = state.edges_per_hour.min(max_rate);
+                }
+            }
+        }
+
+        STATE.update(states);
+    }
+}
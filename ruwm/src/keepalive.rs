@@ -5,10 +5,9 @@ use embassy_time::{Duration, Instant, Timer};
 
 use channel_bridge::notification::Notification;
 
+use crate::power::{self, PowerState, WakeReason};
+use crate::quit;
 use crate::state::State;
-use crate::{battery, quit};
-
-const TIMEOUT: Duration = Duration::from_secs(20);
 
 pub static STATE: State<RemainingTime> = State::new(
     "REMAINING TIME",
@@ -27,8 +26,13 @@ pub enum RemainingTime {
     Duration(Duration),
 }
 
-pub async fn process() {
-    let mut quit_time = None;
+pub async fn process(wake_reason: WakeReason) {
+    power::STATE.update_with(|state| PowerState {
+        wake_reason,
+        ..state
+    });
+
+    let mut quit_time = Some(Instant::now() + timeout(wake_reason));
     let mut remaining_time_sent = None;
 
     loop {
@@ -40,10 +44,14 @@ pub async fn process() {
 
         let now = Instant::now();
 
-        if battery::STATE.get().powered.unwrap_or(false) {
+        let blocker = power::blocker();
+
+        power::STATE.update_with(|state| PowerState { blocker, ..state });
+
+        if blocker.is_some() {
             quit_time = None;
-        } else if matches!(result, Either::First(_)) {
-            quit_time = Some(now + TIMEOUT);
+        } else if matches!(result, Either::First(_)) || quit_time.is_none() {
+            quit_time = Some(now + timeout(wake_reason));
         }
 
         let remaining_time = if let Some(quit_time) = quit_time {
@@ -63,9 +71,15 @@ pub async fn process() {
         }
 
         if quit_time.map(|quit_time| now >= quit_time).unwrap_or(false) {
-            for notification in &quit::QUIT {
-                notification.notify();
-            }
+            power::prepare_sleep();
+
+            quit::shutdown().await;
+
+            break;
         }
     }
 }
+
+fn timeout(wake_reason: WakeReason) -> Duration {
+    Duration::from_secs(power::POLICY.get().awake_secs(wake_reason) as u64)
+}
@@ -2,6 +2,15 @@
 #![allow(async_fn_in_trait)]
 #![warn(clippy::large_futures)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "system")]
+pub mod alert;
+#[cfg(feature = "system")]
+pub mod api;
+#[cfg(feature = "system")]
+pub mod away;
 #[cfg(feature = "system")]
 pub mod battery;
 #[cfg(feature = "system")]
@@ -12,10 +21,22 @@ pub mod emergency;
 #[cfg(feature = "system")]
 pub mod error;
 #[cfg(feature = "system")]
+pub mod flow;
+#[cfg(feature = "system")]
 pub mod keepalive;
 #[cfg(feature = "system")]
+pub mod metrics;
+#[cfg(feature = "system")]
+pub mod moisture;
+#[cfg(feature = "system")]
 pub mod mqtt;
 #[cfg(feature = "system")]
+pub mod ota;
+#[cfg(feature = "system")]
+pub mod power;
+#[cfg(feature = "system")]
+pub mod pressure;
+#[cfg(feature = "system")]
 pub mod pulse_counter;
 #[cfg(feature = "system")]
 pub mod quit;
@@ -26,6 +47,12 @@ pub mod spawn;
 #[cfg(feature = "system")]
 pub mod state;
 #[cfg(feature = "system")]
+pub mod temperature;
+#[cfg(feature = "system")]
+pub mod time;
+#[cfg(feature = "system")]
+pub mod tls;
+#[cfg(feature = "system")]
 pub mod utils;
 #[cfg(feature = "system")]
 pub mod valve;
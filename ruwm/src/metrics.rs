@@ -0,0 +1,567 @@
+//! Prometheus metrics in the text exposition format
+//!
+//! Counters live in RAM and restart from zero after each deep sleep, which Prometheus
+//! handles as a regular counter reset.
+
+use core::fmt::{Debug, Display, Write as _};
+
+use serde::{Deserialize, Serialize};
+
+use heapless::String;
+
+use embassy_time::Instant;
+
+use embedded_svc::http::server::asynch::Connection;
+use embedded_svc::http::Method;
+use embedded_svc::io::asynch::Write;
+
+use channel_bridge::notification::Notification;
+
+use edge_frame::dto::Role;
+
+use crate::state::State;
+use crate::valve::{ValveState, MAX_VALVES};
+use crate::wm_stats::DURATIONS;
+use crate::{api, battery, flow, moisture, mqtt, pressure, temperature, valve, web, wm, wm_stats};
+
+const LINE_MAX_LEN: usize = 192;
+
+/// Platform-specific metrics
+pub trait SystemMetrics {
+    type WakeupReason: Debug;
+
+    fn wakeup_reason(&self) -> Self::WakeupReason;
+
+    fn wifi_rssi(&self) -> Option<i8> {
+        None
+    }
+
+    fn heap_free(&self) -> Option<u32> {
+        None
+    }
+
+    fn heap_min_free(&self) -> Option<u32> {
+        None
+    }
+
+    fn tasks(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl<T> SystemMetrics for &T
+where
+    T: SystemMetrics,
+{
+    type WakeupReason = T::WakeupReason;
+
+    fn wakeup_reason(&self) -> Self::WakeupReason {
+        (**self).wakeup_reason()
+    }
+
+    fn wifi_rssi(&self) -> Option<i8> {
+        (**self).wifi_rssi()
+    }
+
+    fn heap_free(&self) -> Option<u32> {
+        (**self).heap_free()
+    }
+
+    fn heap_min_free(&self) -> Option<u32> {
+        (**self).heap_min_free()
+    }
+
+    fn tasks(&self) -> Option<u32> {
+        (**self).tasks()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ValveTransitions {
+    pub opening: u32,
+    pub opened: u32,
+    pub closing: u32,
+    pub closed: u32,
+    pub partial: u32,
+}
+
+impl ValveTransitions {
+    pub const fn new() -> Self {
+        Self {
+            opening: 0,
+            opened: 0,
+            closing: 0,
+            closed: 0,
+            partial: 0,
+        }
+    }
+}
+
+pub static VALVE_TRANSITIONS: State<[ValveTransitions; MAX_VALVES]> = State::new(
+    "VALVE TRANSITIONS",
+    [ValveTransitions::new(); MAX_VALVES],
+    &[],
+);
+
+pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+
+pub async fn process() {
+    let mut valve_states = valve::STATE.get();
+
+    loop {
+        VALVE_STATE_NOTIF.wait().await;
+
+        let new_valve_states = valve::STATE.get();
+
+        for valve in 0..MAX_VALVES {
+            // Progress updates while the valve turns are not transitions
+            let transition = match (valve_states[valve], new_valve_states[valve]) {
+                (Some(ValveState::Opening(_)), Some(ValveState::Opening(_)))
+                | (Some(ValveState::Closing(_)), Some(ValveState::Closing(_))) => None,
+                (old, new) if old != new => new,
+                _ => None,
+            };
+
+            if let Some(transition) = transition {
+                VALVE_TRANSITIONS.update_with(|mut transitions| {
+                    let transitions_of_valve = &mut transitions[valve];
+
+                    let counter = match transition {
+                        ValveState::Opening(_) => &mut transitions_of_valve.opening,
+                        ValveState::Open => &mut transitions_of_valve.opened,
+                        ValveState::Closing(_) => &mut transitions_of_valve.closing,
+                        ValveState::Closed => &mut transitions_of_valve.closed,
+                        ValveState::Partial(_) => &mut transitions_of_valve.partial,
+                    };
+
+                    *counter = counter.wrapping_add(1);
+
+                    transitions
+                });
+            }
+        }
+
+        valve_states = new_valve_states;
+    }
+}
+
+/// Serves `GET /metrics`
+///
+/// Scrapers authenticate like the REST API clients, i.e. with HTTP basic auth or with the API
+/// bearer token.
+pub async fn handle<C>(
+    mut connection: C,
+    system: impl SystemMetrics,
+    api_token: Option<&str>,
+) -> Result<(), C::Error>
+where
+    C: Connection,
+{
+    if !matches!(connection.method(), Method::Get) {
+        return connection.initiate_response(405, None, &[]).await;
+    }
+
+    let role = web::http_role(connection.header("Authorization"), api_token);
+
+    if !api::authorize(&mut connection, Role::User, role).await? {
+        return Ok(());
+    }
+
+    connection
+        .initiate_response(
+            200,
+            None,
+            &[("Content-Type", "text/plain; version=0.0.4; charset=utf-8")],
+        )
+        .await?;
+
+    write(&mut connection, system).await
+}
+
+/// Writes all metrics in the text exposition format
+///
+/// The water volumes are converted to liters with the calibration of each water meter
+pub async fn write<W>(write: W, system: impl SystemMetrics) -> Result<(), W::Error>
+where
+    W: Write,
+{
+    let mut out = Exposition::new(write);
+
+    let wm_states = wm::STATE.get();
+
+    out.family(
+        "ruwm_water_meter_edges_total",
+        "counter",
+        "Pulse counter edges",
+    )
+    .await?;
+
+    for (meter, _) in wm::enabled() {
+        out.sample(
+            "ruwm_water_meter_edges_total",
+            Some(("meter", &meter)),
+            wm_states[meter].edges_count,
+        )
+        .await?;
+    }
+
+    out.family("ruwm_water_meter_liters_total", "counter", "Water consumed")
+        .await?;
+
+    for (meter, config) in wm::enabled() {
+        out.sample(
+            "ruwm_water_meter_liters_total",
+            Some(("meter", &meter)),
+            liters(wm_states[meter].edges_count, config.edges_per_liter),
+        )
+        .await?;
+    }
+
+    out.family("ruwm_water_meter_armed", "gauge", "Leak detection armed")
+        .await?;
+
+    for (meter, _) in wm::enabled() {
+        out.sample(
+            "ruwm_water_meter_armed",
+            Some(("meter", &meter)),
+            wm_states[meter].armed as u8,
+        )
+        .await?;
+    }
+
+    out.family("ruwm_water_meter_leaking", "gauge", "Leak detected")
+        .await?;
+
+    for (meter, _) in wm::enabled() {
+        out.sample(
+            "ruwm_water_meter_leaking",
+            Some(("meter", &meter)),
+            wm_states[meter].leaking as u8,
+        )
+        .await?;
+    }
+
+    let flow_states = flow::STATE.get();
+
+    out.family(
+        "ruwm_water_meter_flow_liters_per_minute",
+        "gauge",
+        "Instantaneous flow rate",
+    )
+    .await?;
+
+    for (meter, config) in wm::enabled() {
+        out.sample(
+            "ruwm_water_meter_flow_liters_per_minute",
+            Some(("meter", &meter)),
+            liters(
+                flow_states[meter].edges_per_hour as u64,
+                config.edges_per_liter,
+            ) / 60.0,
+        )
+        .await?;
+    }
+
+    let wm_stats_states = wm_stats::STATE.get();
+
+    out.family(
+        "ruwm_water_meter_window_liters",
+        "gauge",
+        "Water consumed in the most recent complete window, labeled by its duration",
+    )
+    .await?;
+
+    for (meter, config) in wm::enabled() {
+        for (measurement, duration) in wm_stats_states[meter]
+            .measurements
+            .iter()
+            .zip(DURATIONS.iter())
+        {
+            if let Some(measurement) = measurement {
+                let edges = measurement
+                    .end()
+                    .edges_count()
+                    .saturating_sub(measurement.start().edges_count());
+
+                out.sample_labeled(
+                    "ruwm_water_meter_window_liters",
+                    &[("meter", &meter), ("window_secs", duration)],
+                    liters(edges, config.edges_per_liter),
+                )
+                .await?;
+            }
+        }
+    }
+
+    let valve_states = valve::STATE.get();
+
+    out.family(
+        "ruwm_valve_state",
+        "gauge",
+        "Valve state; 1 for the current one",
+    )
+    .await?;
+
+    for (valve, _) in valve::enabled() {
+        let current = match valve_states[valve] {
+            Some(ValveState::Open) => "open",
+            Some(ValveState::Closed) => "closed",
+            Some(ValveState::Opening(_)) => "opening",
+            Some(ValveState::Closing(_)) => "closing",
+            Some(ValveState::Partial(_)) => "partial",
+            None => "unknown",
+        };
+
+        for state in ["open", "closed", "opening", "closing", "partial", "unknown"] {
+            out.sample_labeled(
+                "ruwm_valve_state",
+                &[("valve", &valve), ("state", &state)],
+                (state == current) as u8,
+            )
+            .await?;
+        }
+    }
+
+    out.family(
+        "ruwm_valve_open_percentage",
+        "gauge",
+        "How much the valve is open",
+    )
+    .await?;
+
+    for (valve, _) in valve::enabled() {
+        if let Some(valve_state) = valve_states[valve] {
+            out.sample(
+                "ruwm_valve_open_percentage",
+                Some(("valve", &valve)),
+                valve_state.open_percentage(),
+            )
+            .await?;
+        }
+    }
+
+    let transitions = VALVE_TRANSITIONS.get();
+
+    out.family(
+        "ruwm_valve_transitions_total",
+        "counter",
+        "Valve state transitions, labeled by the new state",
+    )
+    .await?;
+
+    for (valve, _) in valve::enabled() {
+        let transitions = &transitions[valve];
+
+        for (state, count) in [
+            ("opening", transitions.opening),
+            ("open", transitions.opened),
+            ("closing", transitions.closing),
+            ("closed", transitions.closed),
+            ("partial", transitions.partial),
+        ] {
+            out.sample_labeled(
+                "ruwm_valve_transitions_total",
+                &[("valve", &valve), ("state", &state)],
+                count,
+            )
+            .await?;
+        }
+    }
+
+    let moisture_states = moisture::STATE.get();
+
+    out.family(
+        "ruwm_moisture_wet",
+        "gauge",
+        "Water detected by a moisture sensor",
+    )
+    .await?;
+
+    for (sensor, moisture_state) in moisture_states.iter().enumerate() {
+        if let Some(moisture_state) = moisture_state {
+            out.sample(
+                "ruwm_moisture_wet",
+                Some(("sensor", &sensor)),
+                moisture_state.is_wet() as u8,
+            )
+            .await?;
+        }
+    }
+
+    let temperature_state = temperature::STATE.get();
+
+    out.family("ruwm_temperature_celsius", "gauge", "Temperature")
+        .await?;
+    if let Some(temperature) = temperature_state.temperature {
+        out.sample("ruwm_temperature_celsius", None, temperature as f32 / 10.0)
+            .await?;
+    }
+
+    let pressure_state = pressure::STATE.get();
+
+    out.family("ruwm_pressure_mbar", "gauge", "Pipe pressure")
+        .await?;
+    if let Some(pressure) = pressure_state.pressure {
+        out.sample("ruwm_pressure_mbar", None, pressure).await?;
+    }
+
+    let battery_state = battery::STATE.get();
+
+    out.family(
+        "ruwm_battery_voltage_millivolts",
+        "gauge",
+        "Battery voltage",
+    )
+    .await?;
+    if let Some(voltage) = battery_state.voltage {
+        out.sample("ruwm_battery_voltage_millivolts", None, voltage)
+            .await?;
+    }
+
+    out.family("ruwm_battery_charge_percent", "gauge", "Battery charge")
+        .await?;
+    if let Some(percentage) = battery_state.percentage {
+        out.sample("ruwm_battery_charge_percent", None, percentage)
+            .await?;
+    }
+
+    out.family(
+        "ruwm_battery_remaining_days",
+        "gauge",
+        "Estimated runtime left on battery",
+    )
+    .await?;
+    if let Some(remaining_days) = battery_state.remaining_days {
+        out.sample("ruwm_battery_remaining_days", None, remaining_days)
+            .await?;
+    }
+
+    out.family("ruwm_powered", "gauge", "External power present")
+        .await?;
+    if let Some(powered) = battery_state.powered {
+        out.sample("ruwm_powered", None, powered as u8).await?;
+    }
+
+    out.family("ruwm_wifi_rssi_dbm", "gauge", "Wifi signal strength")
+        .await?;
+    if let Some(rssi) = system.wifi_rssi() {
+        out.sample("ruwm_wifi_rssi_dbm", None, rssi).await?;
+    }
+
+    out.family("ruwm_mqtt_connected", "gauge", "MQTT broker connected")
+        .await?;
+    out.sample("ruwm_mqtt_connected", None, mqtt::CONNECTED.get() as u8)
+        .await?;
+
+    out.family(
+        "ruwm_uptime_seconds",
+        "counter",
+        "Time since the last wakeup",
+    )
+    .await?;
+    out.sample("ruwm_uptime_seconds", None, Instant::now().as_secs())
+        .await?;
+
+    let mut wakeup_reason = String::<32>::new();
+    write!(&mut wakeup_reason, "{:?}", system.wakeup_reason()).ok();
+
+    out.family("ruwm_wakeup_reason", "gauge", "Reason of the last wakeup")
+        .await?;
+    out.sample("ruwm_wakeup_reason", Some(("reason", &wakeup_reason)), 1)
+        .await?;
+
+    out.family("ruwm_heap_free_bytes", "gauge", "Free heap")
+        .await?;
+    if let Some(heap_free) = system.heap_free() {
+        out.sample("ruwm_heap_free_bytes", None, heap_free).await?;
+    }
+
+    out.family(
+        "ruwm_heap_min_free_bytes",
+        "gauge",
+        "Lowest free heap since the last wakeup",
+    )
+    .await?;
+    if let Some(heap_min_free) = system.heap_min_free() {
+        out.sample("ruwm_heap_min_free_bytes", None, heap_min_free)
+            .await?;
+    }
+
+    out.family("ruwm_tasks", "gauge", "Running tasks").await?;
+    if let Some(tasks) = system.tasks() {
+        out.sample("ruwm_tasks", None, tasks).await?;
+    }
+
+    out.flush().await
+}
+
+fn liters(edges_count: u64, edges_per_liter: u32) -> f32 {
+    edges_count as f32 / edges_per_liter.max(1) as f32
+}
+
+struct Exposition<W> {
+    write: W,
+    line: String<LINE_MAX_LEN>,
+}
+
+impl<W> Exposition<W>
+where
+    W: Write,
+{
+    const fn new(write: W) -> Self {
+        Self {
+            write,
+            line: String::new(),
+        }
+    }
+
+    async fn family(&mut self, name: &str, kind: &str, help: &str) -> Result<(), W::Error> {
+        self.line.clear();
+
+        writeln!(&mut self.line, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+
+        self.write.write_all(self.line.as_bytes()).await
+    }
+
+    async fn sample(
+        &mut self,
+        name: &str,
+        label: Option<(&str, &dyn Display)>,
+        value: impl Display,
+    ) -> Result<(), W::Error> {
+        if let Some(label) = label {
+            self.sample_labeled(name, &[label], value).await
+        } else {
+            self.sample_labeled(name, &[], value).await
+        }
+    }
+
+    async fn sample_labeled(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &dyn Display)],
+        value: impl Display,
+    ) -> Result<(), W::Error> {
+        self.line.clear();
+
+        write!(&mut self.line, "{name}").unwrap();
+
+        for (index, (label, label_value)) in labels.iter().enumerate() {
+            let separator = if index == 0 { '{' } else { ',' };
+
+            write!(&mut self.line, "{separator}{label}=\"{label_value}\"").unwrap();
+        }
+
+        if !labels.is_empty() {
+            self.line.push('}').unwrap();
+        }
+
+        writeln!(&mut self.line, " {value}").unwrap();
+
+        self.write.write_all(self.line.as_bytes()).await
+    }
+
+    async fn flush(&mut self) -> Result<(), W::Error> {
+        self.write.flush().await
+    }
+}
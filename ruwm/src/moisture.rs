@@ -0,0 +1,140 @@
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
+
+use channel_bridge::notification::Notification;
+
+use crate::battery::Adc;
+use crate::button::PressedLevel;
+use crate::state::State;
+
+pub use crate::dto::moisture::*;
+
+const ANALOG_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type SensorStates = [Option<SensorState>; MAX_SENSORS];
+pub type SensorConfigs = [SensorConfig; MAX_SENSORS];
+
+/// The state of each sensor; `None` for the sensors which are not installed
+pub static STATE: State<SensorStates> = State::new(
+    "MOISTURE",
+    [None; MAX_SENSORS],
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::MOISTURE_STATE_NOTIF,
+        &crate::screen::MOISTURE_STATE_NOTIF,
+        &crate::mqtt::MOISTURE_STATE_NOTIF,
+        &crate::web::MOISTURE_STATE_NOTIF,
+    ],
+);
+
+const SENSOR_CONFIG: SensorConfig = SensorConfig::new();
+
+pub static CONFIG: State<SensorConfigs> = State::new(
+    "MOISTURE CONFIG",
+    [SENSOR_CONFIG; MAX_SENSORS],
+    &[
+        &crate::web::MOISTURE_CONFIG_STATE_NOTIF,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+pub fn configure(sensor: usize, config: SensorConfig) {
+    CONFIG.update_with(|mut configs| {
+        configs[sensor] = config;
+        configs
+    });
+}
+
+/// Whether any of the sensors is wet
+pub fn any_wet() -> bool {
+    STATE
+        .get()
+        .iter()
+        .any(|state| matches!(state, Some(SensorState::Wet)))
+}
+
+/// Watches a probe which drives `pin` to `wet_level` when wet
+pub async fn process_digital(
+    sensor: usize,
+    mut pin: impl InputPin + Wait,
+    wet_level: PressedLevel,
+) {
+    loop {
+        let wet = match wet_level {
+            PressedLevel::Low => pin.is_low(),
+            PressedLevel::High => pin.is_high(),
+        }
+        .unwrap();
+
+        update(
+            sensor,
+            if wet {
+                SensorState::Wet
+            } else {
+                SensorState::Dry
+            },
+        );
+
+        // A drop of water bridging the probe makes it bounce for a while, so the
+        // level is read again only once it did not change for the debounce time
+        pin.wait_for_any_edge().await.unwrap();
+
+        while let Either::First(edge) =
+            select(pin.wait_for_any_edge(), Timer::after(debounce(sensor))).await
+        {
+            edge.unwrap();
+        }
+    }
+}
+
+/// Samples a sensor whose reading rises with the moisture, i.e. a resistive one
+pub async fn process_analog(sensor: usize, mut adc: impl Adc) {
+    let mut pending: Option<(SensorState, Instant)> = None;
+
+    loop {
+        if let Ok(reading) = adc.read().await {
+            let state = if reading >= CONFIG.get()[sensor].threshold {
+                SensorState::Wet
+            } else {
+                SensorState::Dry
+            };
+
+            let since = match pending {
+                Some((pending_state, since)) if pending_state == state => since,
+                _ => Instant::now(),
+            };
+
+            pending = Some((state, since));
+
+            if since.elapsed() >= debounce(sensor) {
+                update(sensor, state);
+            }
+        }
+
+        Timer::after(ANALOG_SAMPLE_INTERVAL).await;
+    }
+}
+
+pub async fn flash_config(mut flasher: impl FnMut(SensorConfigs)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
+
+fn debounce(sensor: usize) -> Duration {
+    Duration::from_millis(CONFIG.get()[sensor].debounce_ms as _)
+}
+
+fn update(sensor: usize, state: SensorState) {
+    STATE.update_with(|mut states| {
+        states[sensor] = Some(state);
+        states
+    });
+}
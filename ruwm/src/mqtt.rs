@@ -1,3 +1,5 @@
+use core::array;
+use core::fmt::Write;
 use core::str::{self, FromStr};
 use core::time::Duration;
 
@@ -7,7 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use heapless::String;
 
-use embassy_futures::select::{select4, Either4};
+use embassy_futures::select::{select, select3, select_slice, Either, Either3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 
@@ -17,10 +19,22 @@ use embedded_svc::mqtt::client::Details;
 use channel_bridge::notification::Notification;
 use wm::WaterMeterState;
 
+use crate::alert::{self, AlertCommand, AlertKind, AlertState, AlertStatus};
+use crate::away::{self, AwayCommand, AwaySchedule};
 use crate::battery::{self, BatteryState};
-use crate::valve::{ValveCommand, ValveState};
-use crate::wm::WaterMeterCommand;
-use crate::{error, valve, wm};
+use crate::flow::{self, FlowState};
+use crate::moisture::{self, MAX_SENSORS};
+use crate::ota::{OtaCommand, OtaState, OtaStatus};
+use crate::power;
+use crate::pressure::{self, PressureCommand};
+use crate::quit::{self, Participant};
+use crate::state::State;
+use crate::temperature;
+use crate::time::TimeZone;
+use crate::valve::{ValveCommand, ValveState, MAX_VALVES};
+use crate::wifi::{self, WifiGiveUp, WifiPolicy};
+use crate::wm::{WaterMeterCommand, MAX_METERS};
+use crate::{error, ota, time, valve, wm};
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct MqttConfiguration {
@@ -34,9 +48,19 @@ pub struct MqttConfiguration {
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MqttCommand {
     KeepAlive(Duration),
-    Valve(bool),
-    FlowWatch(bool),
+    /// The index of the valve and whether to open it
+    Valve(usize, bool),
+    ValvePartial(usize),
+    /// The index of the water meter and whether to arm it
+    FlowWatch(usize, bool),
     SystemUpdate,
+    TimeZone(TimeZone),
+    Alert(AlertCommand),
+    Away(AwayCommand),
+    Pressure(PressureCommand),
+    /// The initial and the longest backoff between the Wifi connection rounds, in seconds
+    WifiBackoff(u32, u32),
+    WifiGiveUp(Option<WifiGiveUp>),
 }
 
 // TODO: Web: connected info at least
@@ -46,12 +70,32 @@ static RECEIVE_NOTIFY: &[&Notification] =
     &[&crate::keepalive::NOTIF, &crate::screen::MQTT_STATE_NOTIF];
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static VALVE_FAULT_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static FLOW_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static OTA_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERT_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static AWAY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static MOISTURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TEMPERATURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static PRESSURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static POWER_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TIME_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static SHUTDOWN_NOTIF: Notification = Notification::new();
 
 static CONN_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
 
+pub static CONNECTED: State<bool> = State::new(
+    "MQTT CONNECTED",
+    false,
+    &[
+        &crate::screen::MQTT_STATE_NOTIF,
+        &crate::web::MQTT_STATE_NOTIF,
+    ],
+);
+
 pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Publish) {
     let mut connected = false;
 
@@ -63,48 +107,153 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
 
     let topic_commands = topic("/commands/#");
 
-    let topic_valve = topic("/valve");
+    // The main valve keeps the topics it had before there were more valves
+    let topic_valve = |valve: usize, topic_suffix: &str| {
+        let mut topic = topic("/valve");
+        if valve > 0 {
+            write!(&mut topic, "/{}", valve).unwrap();
+        }
+        topic.push_str(topic_suffix).unwrap();
+
+        topic
+    };
+
+    let topic_valves: [String<L>; MAX_VALVES] = array::from_fn(|valve| topic_valve(valve, ""));
+    let topic_valve_faults: [String<L>; MAX_VALVES] =
+        array::from_fn(|valve| topic_valve(valve, "/fault"));
 
-    let topic_meter_edges = topic("/meter/edges");
-    let topic_meter_armed = topic("/meter/armed");
-    let topic_meter_leak = topic("/meter/leak");
+    // Likewise for the main water meter
+    let topic_meter = |meter: usize, topic_suffix: &str| {
+        let mut topic = topic("/meter");
+        if meter > 0 {
+            write!(&mut topic, "/{}", meter).unwrap();
+        }
+        topic.push_str(topic_suffix).unwrap();
+
+        topic
+    };
+
+    let topic_meter_edges: [String<L>; MAX_METERS] =
+        array::from_fn(|meter| topic_meter(meter, "/edges"));
+    let topic_meter_armed: [String<L>; MAX_METERS] =
+        array::from_fn(|meter| topic_meter(meter, "/armed"));
+    let topic_meter_leak: [String<L>; MAX_METERS] =
+        array::from_fn(|meter| topic_meter(meter, "/leak"));
+    let topic_meter_flow: [String<L>; MAX_METERS] =
+        array::from_fn(|meter| topic_meter(meter, "/flow"));
 
     let topic_battery_voltage = topic("/battery/voltage");
     let topic_battery_low = topic("/battery/low");
     let topic_battery_charged = topic("/battery/charged");
+    let topic_battery_remaining = topic("/battery/remaining");
 
     let topic_powered = topic("/powered");
 
-    let mut published_valve_state = None;
-    let mut published_wm_state: Option<WaterMeterState> = None;
+    let topic_ota_status = topic("/ota/status");
+    let topic_ota_progress = topic("/ota/progress");
+    let topic_ota_version = topic("/ota/version");
+
+    let topic_away = topic("/away");
+
+    let topic_temperature = topic("/temperature");
+    let topic_temperature_status = topic("/temperature/status");
+
+    let topic_pressure = topic("/pressure");
+    let topic_pressure_test = topic("/pressure/test");
+    let topic_pressure_drop = topic("/pressure/drop");
+
+    let topic_sleeping = topic("/sleeping");
+
+    let topic_time_valid = topic("/time/valid");
+
+    let topic_moisture: [String<L>; MAX_SENSORS] = array::from_fn(|sensor| {
+        let mut topic = topic("/moisture/");
+        write!(&mut topic, "{}", sensor).unwrap();
+
+        topic
+    });
+
+    let topic_alerts = AlertKind::ALL.map(|kind| {
+        let mut topic = topic("/alert/");
+        topic.push_str(kind.name()).unwrap();
+
+        topic
+    });
+
+    let mut published_valve_states = [None; MAX_VALVES];
+    let mut published_wm_states: [Option<WaterMeterState>; MAX_METERS] = [None; MAX_METERS];
     let mut published_battery_state: Option<BatteryState> = None;
+    let mut published_ota_state: Option<OtaState> = None;
+    let mut published_flow_states: [Option<FlowState>; MAX_METERS] = [None; MAX_METERS];
+    let mut published_alert_state: Option<AlertState> = None;
+    let mut published_away_active = None;
+    let mut published_valve_faults = [None; MAX_VALVES];
+    let mut published_moisture_states = [None; MAX_SENSORS];
+    let mut published_temperature = None;
+    let mut published_freeze_status = None;
+    let mut published_pressure = None;
+    let mut published_pressure_testing = None;
+    let mut published_pressure_drop = None;
+    let mut published_sleeping = None;
+    let mut published_time_valid = None;
+
+    let mut notifs = [
+        VALVE_STATE_NOTIF.wait(),
+        WM_STATE_NOTIF.wait(),
+        BATTERY_STATE_NOTIF.wait(),
+        OTA_STATE_NOTIF.wait(),
+        FLOW_STATE_NOTIF.wait(),
+        ALERT_STATE_NOTIF.wait(),
+        AWAY_STATE_NOTIF.wait(),
+        VALVE_FAULT_STATE_NOTIF.wait(),
+        MOISTURE_STATE_NOTIF.wait(),
+        TEMPERATURE_STATE_NOTIF.wait(),
+        PRESSURE_STATE_NOTIF.wait(),
+        POWER_STATE_NOTIF.wait(),
+        TIME_STATE_NOTIF.wait(),
+    ];
+
+    quit::register(Participant::Mqtt);
 
     loop {
-        let (conn_state, valve_state, wm_state, battery_state) = if connected {
-            match select4(
+        // The pending state changes go before a shutdown, so that they are still published
+        let (conn_state, changed, shutdown) = if connected {
+            match select3(
                 CONN_SIGNAL.wait(),
-                VALVE_STATE_NOTIF.wait(),
-                WM_STATE_NOTIF.wait(),
-                BATTERY_STATE_NOTIF.wait(),
+                select_slice(&mut notifs),
+                SHUTDOWN_NOTIF.wait(),
             )
             .await
             {
-                Either4::First(conn_state) => (Some(conn_state), None, None, None),
-                Either4::Second(_) => (
-                    None,
-                    Some(valve::STATE.get().map(|state| state.simplify())),
-                    None,
-                    None,
-                ),
-                Either4::Third(_) => (None, None, Some(wm::STATE.get()), None),
-                Either4::Fourth(_) => (None, None, None, Some(battery::STATE.get())),
+                Either3::First(conn_state) => (Some(conn_state), None, false),
+                Either3::Second((_, index)) => (None, Some(index), false),
+                Either3::Third(_) => (None, None, true),
             }
         } else {
-            let conn_state = CONN_SIGNAL.wait().await;
-
-            (Some(conn_state), None, None, None)
+            match select(CONN_SIGNAL.wait(), SHUTDOWN_NOTIF.wait()).await {
+                Either::First(conn_state) => (Some(conn_state), None, false),
+                Either::Second(_) => (None, None, true),
+            }
         };
 
+        let valve_states = (changed == Some(0)).then(|| {
+            valve::STATE
+                .get()
+                .map(|state| state.map(|state| state.simplify()))
+        });
+        let wm_states = (changed == Some(1)).then(|| wm::STATE.get());
+        let battery_state = (changed == Some(2)).then(|| battery::STATE.get());
+        let ota_state = (changed == Some(3)).then(|| ota::STATE.get());
+        let flow_states = (changed == Some(4)).then(|| flow::STATE.get());
+        let alert_state = (changed == Some(5)).then(|| alert::STATE.get());
+        let away_active = (changed == Some(6)).then(|| away::STATE.get().active);
+        let valve_faults = (changed == Some(7)).then(|| valve::FAULT.get());
+        let moisture_states = (changed == Some(8)).then(|| moisture::STATE.get());
+        let temperature_state = (changed == Some(9)).then(|| temperature::STATE.get());
+        let pressure_state = (changed == Some(10)).then(|| pressure::STATE.get());
+        let sleeping = (changed == Some(11)).then(|| power::STATE.get().sleeping);
+        let time_valid = (changed == Some(12)).then(|| time::STATE.get().valid);
+
         if let Some(conn_state) = conn_state {
             if conn_state {
                 info!("MQTT is now connected, subscribing");
@@ -123,76 +272,323 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
             }
         }
 
-        if let Some(valve_state) = valve_state {
-            if published_valve_state != valve_state {
-                published_valve_state = valve_state;
+        if let Some(valve_states) = valve_states {
+            for (valve, _) in valve::enabled() {
+                let valve_state = valve_states[valve];
 
-                let status = match valve_state {
-                    Some(ValveState::Open) => "open",
-                    Some(ValveState::Opening(_)) => "opening",
-                    Some(ValveState::Closed) => "closed",
-                    Some(ValveState::Closing(_)) => "closing",
-                    None => "unknown",
-                };
+                if published_valve_states[valve] != valve_state {
+                    published_valve_states[valve] = valve_state;
+
+                    let status = match valve_state {
+                        Some(ValveState::Open) => "open",
+                        Some(ValveState::Opening(_)) => "opening",
+                        Some(ValveState::Closed) => "closed",
+                        Some(ValveState::Closing(_)) => "closing",
+                        Some(ValveState::Partial(_)) => "partial",
+                        None => "unknown",
+                    };
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_valves[valve],
+                        QoS::AtLeastOnce,
+                        status.as_bytes(),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        if let Some(valve_faults) = valve_faults {
+            for (valve, _) in valve::enabled() {
+                let valve_fault = valve_faults[valve];
+
+                if published_valve_faults[valve] != Some(valve_fault) {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_valve_faults[valve],
+                        QoS::AtLeastOnce,
+                        valve_fault
+                            .map(|fault| fault.name())
+                            .unwrap_or("none")
+                            .as_bytes(),
+                    )
+                    .await;
+
+                    published_valve_faults[valve] = Some(valve_fault);
+                }
+            }
+        }
+
+        if let Some(wm_states) = wm_states {
+            for (meter, _) in wm::enabled() {
+                let wm_state = wm_states[meter];
+
+                if published_wm_states[meter]
+                    .map(|p| p.edges_count != wm_state.edges_count)
+                    .unwrap_or(true)
+                {
+                    let num = wm_state.edges_count.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_meter_edges[meter],
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+                }
+
+                if published_wm_states[meter]
+                    .map(|p| p.armed != wm_state.armed)
+                    .unwrap_or(true)
+                {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_meter_armed[meter],
+                        QoS::AtLeastOnce,
+                        (if wm_state.armed { "true" } else { "false" }).as_bytes(),
+                    )
+                    .await;
+                }
+
+                if published_wm_states[meter]
+                    .map(|p| p.leaking != wm_state.leaking)
+                    .unwrap_or(true)
+                {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_meter_leak[meter],
+                        QoS::AtLeastOnce,
+                        (if wm_state.armed { "true" } else { "false" }).as_bytes(),
+                    )
+                    .await;
+                }
+
+                published_wm_states[meter] = Some(wm_state);
+            }
+        }
+
+        if let Some(flow_states) = flow_states {
+            for (meter, _) in wm::enabled() {
+                let flow_state = flow_states[meter];
+
+                // The rate changes with every edge, so only publish significant changes
+                let significant = published_flow_states[meter]
+                    .map(|p| {
+                        p.flowing != flow_state.flowing
+                            || p.edges_per_hour.abs_diff(flow_state.edges_per_hour)
+                                > p.edges_per_hour / 10
+                    })
+                    .unwrap_or(true);
+
+                if significant {
+                    let num = flow_state.edges_per_hour.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_meter_flow[meter],
+                        QoS::AtMostOnce,
+                        num_slice,
+                    )
+                    .await;
+
+                    published_flow_states[meter] = Some(flow_state);
+                }
+            }
+        }
+
+        if let Some(alert_state) = alert_state {
+            for (kind, topic) in AlertKind::ALL.iter().zip(topic_alerts.iter()) {
+                let status = alert_state.status(*kind);
+
+                if published_alert_state
+                    .map(|p| p.status(*kind) != status)
+                    .unwrap_or(true)
+                {
+                    let status = match status {
+                        AlertStatus::Idle => "idle",
+                        AlertStatus::Raised => "raised",
+                        AlertStatus::Acknowledged => "acknowledged",
+                        AlertStatus::Cleared => "cleared",
+                    };
 
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        topic,
+                        QoS::AtLeastOnce,
+                        status.as_bytes(),
+                    )
+                    .await;
+                }
+            }
+
+            published_alert_state = Some(alert_state);
+        }
+
+        if let Some(away_active) = away_active {
+            if published_away_active != Some(away_active) {
                 publish(
                     connected,
                     &mut mqtt,
-                    &topic_valve,
+                    &topic_away,
                     QoS::AtLeastOnce,
-                    status.as_bytes(),
+                    (if away_active { "true" } else { "false" }).as_bytes(),
                 )
                 .await;
+
+                published_away_active = Some(away_active);
             }
         }
 
-        if let Some(wm_state) = wm_state {
-            if published_wm_state
-                .map(|p| p.edges_count != wm_state.edges_count)
-                .unwrap_or(true)
-            {
-                let num = wm_state.edges_count.to_le_bytes();
-                let num_slice: &[u8] = &num;
-
+        if let Some(time_valid) = time_valid {
+            if published_time_valid != Some(time_valid) {
                 publish(
                     connected,
                     &mut mqtt,
-                    &topic_meter_edges,
+                    &topic_time_valid,
                     QoS::AtLeastOnce,
-                    num_slice,
+                    (if time_valid { "true" } else { "false" }).as_bytes(),
                 )
                 .await;
+
+                published_time_valid = Some(time_valid);
             }
+        }
 
-            if published_wm_state
-                .map(|p| p.armed != wm_state.armed)
-                .unwrap_or(true)
-            {
+        if let Some(moisture_states) = moisture_states {
+            for (sensor, moisture_state) in moisture_states.iter().enumerate() {
+                // Only the installed sensors report a state
+                let Some(moisture_state) = moisture_state else {
+                    continue;
+                };
+
+                if published_moisture_states[sensor] != Some(*moisture_state) {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_moisture[sensor],
+                        QoS::AtLeastOnce,
+                        (if moisture_state.is_wet() {
+                            "wet"
+                        } else {
+                            "dry"
+                        })
+                        .as_bytes(),
+                    )
+                    .await;
+
+                    published_moisture_states[sensor] = Some(*moisture_state);
+                }
+            }
+        }
+
+        if let Some(temperature_state) = temperature_state {
+            if let Some(temperature) = temperature_state.temperature {
+                // Readings jitter, so only publish changes of at least half a degree
+                let significant = published_temperature
+                    .map(|p: i16| p.abs_diff(temperature) >= 5)
+                    .unwrap_or(true);
+
+                if significant {
+                    let num = temperature.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_temperature,
+                        QoS::AtMostOnce,
+                        num_slice,
+                    )
+                    .await;
+
+                    published_temperature = Some(temperature);
+                }
+            }
+
+            if published_freeze_status != Some(temperature_state.status) {
                 publish(
                     connected,
                     &mut mqtt,
-                    &topic_meter_armed,
+                    &topic_temperature_status,
                     QoS::AtLeastOnce,
-                    (if wm_state.armed { "true" } else { "false" }).as_bytes(),
+                    temperature_state.status.name().as_bytes(),
                 )
                 .await;
+
+                published_freeze_status = Some(temperature_state.status);
             }
+        }
 
-            if published_wm_state
-                .map(|p| p.leaking != wm_state.leaking)
-                .unwrap_or(true)
-            {
+        if let Some(pressure_state) = pressure_state {
+            if let Some(pressure) = pressure_state.pressure {
+                // Only publish changes of at least 50 mbar, as the pressure follows every draw
+                let significant = published_pressure
+                    .map(|p: u16| p.abs_diff(pressure) >= 50)
+                    .unwrap_or(true);
+
+                if significant {
+                    let num = pressure.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_pressure,
+                        QoS::AtMostOnce,
+                        num_slice,
+                    )
+                    .await;
+
+                    published_pressure = Some(pressure);
+                }
+            }
+
+            if published_pressure_testing != Some(pressure_state.is_testing()) {
                 publish(
                     connected,
                     &mut mqtt,
-                    &topic_meter_leak,
+                    &topic_pressure_test,
                     QoS::AtLeastOnce,
-                    (if wm_state.armed { "true" } else { "false" }).as_bytes(),
+                    (if pressure_state.is_testing() {
+                        "true"
+                    } else {
+                        "false"
+                    })
+                    .as_bytes(),
                 )
                 .await;
+
+                published_pressure_testing = Some(pressure_state.is_testing());
             }
 
-            published_wm_state = Some(wm_state);
+            if let Some(drop) = pressure_state.test_drop {
+                if published_pressure_drop != Some(drop) {
+                    let num = drop.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_pressure_drop,
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+
+                    published_pressure_drop = Some(drop);
+                }
+            }
         }
 
         if let Some(battery_state) = battery_state {
@@ -212,46 +608,59 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
                         num_slice,
                     )
                     .await;
+                }
+            }
 
-                    if let Some(prev_voltage) = published_battery_state.and_then(|p| p.voltage) {
-                        if (prev_voltage > BatteryState::LOW_VOLTAGE)
-                            != (voltage > BatteryState::LOW_VOLTAGE)
-                        {
-                            let status = if voltage > BatteryState::LOW_VOLTAGE {
-                                "false"
-                            } else {
-                                "true"
-                            };
-
-                            publish(
-                                connected,
-                                &mut mqtt,
-                                &topic_battery_low,
-                                QoS::AtLeastOnce,
-                                status.as_bytes(),
-                            )
-                            .await;
-                        }
-
-                        if (prev_voltage >= BatteryState::MAX_VOLTAGE)
-                            != (voltage >= BatteryState::MAX_VOLTAGE)
-                        {
-                            let status = if voltage >= BatteryState::MAX_VOLTAGE {
-                                "true"
-                            } else {
-                                "false"
-                            };
-
-                            publish(
-                                connected,
-                                &mut mqtt,
-                                &topic_battery_charged,
-                                QoS::AtMostOnce,
-                                status.as_bytes(),
-                            )
-                            .await;
-                        }
-                    }
+            if published_battery_state
+                .map(|p| p.low != battery_state.low)
+                .unwrap_or(true)
+            {
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_battery_low,
+                    QoS::AtLeastOnce,
+                    (if battery_state.low { "true" } else { "false" }).as_bytes(),
+                )
+                .await;
+            }
+
+            if published_battery_state
+                .map(|p| p.charged != battery_state.charged)
+                .unwrap_or(true)
+            {
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_battery_charged,
+                    QoS::AtMostOnce,
+                    (if battery_state.charged {
+                        "true"
+                    } else {
+                        "false"
+                    })
+                    .as_bytes(),
+                )
+                .await;
+            }
+
+            if published_battery_state
+                .map(|p| p.remaining_days != battery_state.remaining_days)
+                .unwrap_or(true)
+            {
+                // In days
+                if let Some(remaining_days) = battery_state.remaining_days {
+                    let num = remaining_days.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_battery_remaining,
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
                 }
             }
 
@@ -273,6 +682,85 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
 
             published_battery_state = Some(battery_state);
         };
+
+        if let Some(ota_state) = ota_state {
+            if published_ota_state
+                .map(|p| p.status != ota_state.status)
+                .unwrap_or(true)
+            {
+                let status = match ota_state.status {
+                    OtaStatus::Idle => "idle",
+                    OtaStatus::PendingVerification => "pending_verification",
+                    OtaStatus::Checking => "checking",
+                    OtaStatus::UpToDate => "up_to_date",
+                    OtaStatus::UpdateAvailable(_) => "update_available",
+                    OtaStatus::Downloading(_) => "downloading",
+                    OtaStatus::Verifying => "verifying",
+                    OtaStatus::Ready(_) => "ready",
+                    OtaStatus::Failed(_) => "failed",
+                };
+
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_ota_status,
+                    QoS::AtLeastOnce,
+                    status.as_bytes(),
+                )
+                .await;
+
+                if let Some(progress) = ota_state.status.progress() {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_ota_progress,
+                        QoS::AtMostOnce,
+                        &[progress],
+                    )
+                    .await;
+                }
+            }
+
+            if published_ota_state
+                .map(|p| p.running_version != ota_state.running_version)
+                .unwrap_or(true)
+            {
+                if let Some(version) = ota_state.running_version {
+                    let mut text_buf = String::<20>::new();
+                    write!(&mut text_buf, "{}", version).unwrap();
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_ota_version,
+                        QoS::AtLeastOnce,
+                        text_buf.as_bytes(),
+                    )
+                    .await;
+                }
+            }
+
+            published_ota_state = Some(ota_state);
+        }
+
+        if let Some(sleeping) = sleeping {
+            if published_sleeping != Some(sleeping) {
+                published_sleeping = Some(sleeping);
+
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_sleeping,
+                    QoS::AtLeastOnce,
+                    (if sleeping { "true" } else { "false" }).as_bytes(),
+                )
+                .await;
+            }
+        }
+
+        if shutdown {
+            quit::flushed(Participant::Mqtt);
+        }
     }
 }
 
@@ -310,26 +798,56 @@ pub async fn receive(mut connection: impl Connection) {
         {
             if let Some(cmd) = parser.process(topic, data, &details) {
                 match cmd {
-                    MqttCommand::Valve(open) => {
-                        valve::COMMAND.signal(if open {
+                    MqttCommand::Valve(valve, open) => {
+                        valve::COMMAND[valve].signal(if open {
                             ValveCommand::Open
                         } else {
                             ValveCommand::Close
                         });
                     }
-                    MqttCommand::FlowWatch(enable) => {
-                        wm::COMMAND.signal(if enable {
+                    MqttCommand::ValvePartial(valve) => {
+                        valve::COMMAND[valve].signal(ValveCommand::OpenPartially);
+                    }
+                    MqttCommand::FlowWatch(meter, enable) => {
+                        wm::COMMAND[meter].signal(if enable {
                             WaterMeterCommand::Arm
                         } else {
                             WaterMeterCommand::Disarm
                         });
                     }
+                    MqttCommand::SystemUpdate => {
+                        ota::COMMAND.signal(OtaCommand::Update);
+                    }
+                    MqttCommand::TimeZone(time_zone) => {
+                        time::TIME_ZONE.update(time_zone);
+                    }
+                    MqttCommand::Alert(command) => {
+                        alert::COMMAND.signal(command);
+                    }
+                    MqttCommand::Away(command) => {
+                        away::COMMAND.signal(command);
+                    }
+                    MqttCommand::Pressure(command) => {
+                        pressure::COMMAND.signal(command);
+                    }
+                    MqttCommand::WifiBackoff(backoff_initial_secs, backoff_max_secs) => {
+                        wifi::POLICY.update_with(|policy| WifiPolicy {
+                            backoff_initial_secs,
+                            backoff_max_secs,
+                            ..policy
+                        });
+                    }
+                    MqttCommand::WifiGiveUp(give_up) => {
+                        wifi::POLICY.update_with(|policy| WifiPolicy { give_up, ..policy });
+                    }
                     _ => (),
                 }
             }
         } else if matches!(payload, EventPayload::Connected(_)) {
+            CONNECTED.update(true);
             CONN_SIGNAL.signal(true);
         } else if matches!(payload, EventPayload::Disconnected) {
+            CONNECTED.update(false);
             CONN_SIGNAL.signal(false);
         }
 
@@ -343,7 +861,9 @@ pub async fn receive(mut connection: impl Connection) {
 struct MessageParser {
     #[allow(clippy::type_complexity)]
     command_parser: Option<fn(&[u8]) -> Option<MqttCommand>>,
-    payload_buf: [u8; 16],
+    /// The valve or meter of the command, as only the initial chunk comes with the topic
+    index: usize,
+    payload_buf: [u8; 32],
 }
 
 impl MessageParser {
@@ -359,13 +879,20 @@ impl MessageParser {
     ) -> Option<MqttCommand> {
         match details {
             Details::Complete => {
-                Self::parse_command(topic.unwrap()).and_then(|parser| parser(payload))
+                let (topic, index) = Self::parse_index(topic.unwrap());
+
+                Self::parse_command(topic)
+                    .and_then(|parser| parser(payload))
+                    .map(|command| Self::with_index(command, index))
             }
             Details::InitialChunk(initial_chunk_data) => {
                 if initial_chunk_data.total_data_size > self.payload_buf.len() {
                     self.command_parser = None;
                 } else {
-                    self.command_parser = Self::parse_command(topic.unwrap());
+                    let (topic, index) = Self::parse_index(topic.unwrap());
+
+                    self.command_parser = Self::parse_command(topic);
+                    self.index = index;
                     self.payload_buf[..payload.len()].copy_from_slice(payload);
                 }
 
@@ -380,6 +907,7 @@ impl MessageParser {
                         == subsequent_chunk_data.current_data_offset + payload.len()
                     {
                         command_parser(&self.payload_buf[0..subsequent_chunk_data.total_data_size])
+                            .map(|command| Self::with_index(command, self.index))
                     } else {
                         None
                     }
@@ -390,27 +918,85 @@ impl MessageParser {
         }
     }
 
+    /// Splits the index of the valve or meter off the valve and flow watch command topics,
+    /// i.e. `/commands/valve/1`; the topics without an index are for the main valve or meter
+    fn parse_index(topic: &str) -> (&str, usize) {
+        topic
+            .rsplit_once('/')
+            .and_then(|(topic, index)| {
+                let max = if topic.ends_with("/commands/valve")
+                    || topic.ends_with("/commands/valve_partial")
+                {
+                    MAX_VALVES
+                } else if topic.ends_with("/commands/flow_watch") {
+                    MAX_METERS
+                } else {
+                    0
+                };
+
+                index
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|index| *index < max)
+                    .map(|index| (topic, index))
+            })
+            .unwrap_or((topic, 0))
+    }
+
+    fn with_index(command: MqttCommand, index: usize) -> MqttCommand {
+        match command {
+            MqttCommand::Valve(_, open) => MqttCommand::Valve(index, open),
+            MqttCommand::ValvePartial(_) => MqttCommand::ValvePartial(index),
+            MqttCommand::FlowWatch(_, enable) => MqttCommand::FlowWatch(index, enable),
+            command => command,
+        }
+    }
+
     #[allow(clippy::type_complexity)]
     fn parse_command(topic: &str) -> Option<fn(&[u8]) -> Option<MqttCommand>> {
         if topic.ends_with("/commands/valve") {
             Some(Self::parse_valve_command)
+        } else if topic.ends_with("/commands/valve_partial") {
+            Some(Self::parse_valve_partial_command)
         } else if topic.ends_with("/commands/flow_watch") {
             Some(Self::parse_flow_watch_command)
         } else if topic.ends_with("/commands/keep_alive") {
             Some(Self::parse_keep_alive_command)
         } else if topic.ends_with("/commands/system_update") {
             Some(Self::parse_system_update_command)
+        } else if topic.ends_with("/commands/time_zone") {
+            Some(Self::parse_time_zone_command)
+        } else if topic.ends_with("/commands/alert_ack") {
+            Some(Self::parse_alert_ack_command)
+        } else if topic.ends_with("/commands/alert_clear") {
+            Some(Self::parse_alert_clear_command)
+        } else if topic.ends_with("/commands/away") {
+            Some(Self::parse_away_command)
+        } else if topic.ends_with("/commands/away_schedule") {
+            Some(Self::parse_away_schedule_command)
+        } else if topic.ends_with("/commands/away_threshold") {
+            Some(Self::parse_away_threshold_command)
+        } else if topic.ends_with("/commands/pressure_test") {
+            Some(Self::parse_pressure_test_command)
+        } else if topic.ends_with("/commands/wifi_backoff") {
+            Some(Self::parse_wifi_backoff_command)
+        } else if topic.ends_with("/commands/wifi_give_up") {
+            Some(Self::parse_wifi_give_up_command)
         } else {
             None
         }
     }
 
     fn parse_valve_command(data: &[u8]) -> Option<MqttCommand> {
-        Self::parse::<bool>(data).map(MqttCommand::Valve)
+        Self::parse::<bool>(data).map(|open| MqttCommand::Valve(0, open))
+    }
+
+    fn parse_valve_partial_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse_empty(data).map(|_| MqttCommand::ValvePartial(0))
     }
 
     fn parse_flow_watch_command(data: &[u8]) -> Option<MqttCommand> {
-        Self::parse::<bool>(data).map(MqttCommand::FlowWatch)
+        Self::parse::<bool>(data).map(|enable| MqttCommand::FlowWatch(0, enable))
     }
 
     fn parse_keep_alive_command(data: &[u8]) -> Option<MqttCommand> {
@@ -421,6 +1007,87 @@ impl MessageParser {
         Self::parse_empty(data).map(|_| MqttCommand::SystemUpdate)
     }
 
+    /// A POSIX `TZ` string, i.e. `CET-1CEST,M3.5.0,M10.5.0/3`
+    fn parse_time_zone_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<TimeZone>(data).map(MqttCommand::TimeZone)
+    }
+
+    /// The name of the alert, i.e. `daily_budget`, or an empty payload for all alerts
+    fn parse_alert_ack_command(data: &[u8]) -> Option<MqttCommand> {
+        if data.is_empty() {
+            Some(MqttCommand::Alert(AlertCommand::AcknowledgeAll))
+        } else {
+            Self::parse_alert_kind(data)
+                .map(|kind| MqttCommand::Alert(AlertCommand::Acknowledge(kind)))
+        }
+    }
+
+    /// The name of the alert, i.e. `daily_budget`, or an empty payload for all alerts
+    fn parse_alert_clear_command(data: &[u8]) -> Option<MqttCommand> {
+        if data.is_empty() {
+            Some(MqttCommand::Alert(AlertCommand::ClearAll))
+        } else {
+            Self::parse_alert_kind(data).map(|kind| MqttCommand::Alert(AlertCommand::Clear(kind)))
+        }
+    }
+
+    fn parse_away_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<bool>(data).map(|enable| MqttCommand::Away(AwayCommand::Enable(enable)))
+    }
+
+    /// I.e. `2024-07-01/2024-07-14,01:00-05:00`, or an empty payload to clear the schedule
+    fn parse_away_schedule_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<AwaySchedule>(data)
+            .map(|schedule| MqttCommand::Away(AwayCommand::Schedule(schedule)))
+    }
+
+    /// The flow tolerated while away in edges per hour, or an empty payload to tolerate no flow
+    fn parse_away_threshold_command(data: &[u8]) -> Option<MqttCommand> {
+        if data.is_empty() {
+            Some(MqttCommand::Away(AwayCommand::Threshold(None)))
+        } else {
+            Self::parse::<u32>(data)
+                .map(|threshold| MqttCommand::Away(AwayCommand::Threshold(Some(threshold))))
+        }
+    }
+
+    /// Whether to start or to abort the pressure drop test
+    fn parse_pressure_test_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<bool>(data).map(|start| {
+            MqttCommand::Pressure(if start {
+                PressureCommand::StartTest
+            } else {
+                PressureCommand::AbortTest
+            })
+        })
+    }
+
+    /// I.e. `5,600`
+    fn parse_wifi_backoff_command(data: &[u8]) -> Option<MqttCommand> {
+        str::from_utf8(data)
+            .ok()
+            .and_then(|s| s.split_once(','))
+            .and_then(|(initial, max)| {
+                Some(MqttCommand::WifiBackoff(
+                    initial.trim().parse().ok()?,
+                    max.trim().parse().ok()?,
+                ))
+            })
+    }
+
+    /// I.e. `3,battery,3600`, or an empty payload to never give up
+    fn parse_wifi_give_up_command(data: &[u8]) -> Option<MqttCommand> {
+        if data.is_empty() {
+            Some(MqttCommand::WifiGiveUp(None))
+        } else {
+            Self::parse::<WifiGiveUp>(data).map(|give_up| MqttCommand::WifiGiveUp(Some(give_up)))
+        }
+    }
+
+    fn parse_alert_kind(data: &[u8]) -> Option<AlertKind> {
+        str::from_utf8(data).ok().and_then(AlertKind::from_name)
+    }
+
     fn parse<T>(data: &[u8]) -> Option<T>
     where
         T: str::FromStr,
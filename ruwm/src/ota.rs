@@ -0,0 +1,697 @@
+use core::cell::Cell;
+use core::cmp::min;
+use core::fmt::Debug;
+
+use log::{info, warn};
+
+use serde::Deserialize;
+
+use heapless::String;
+
+use sha2::{Digest, Sha256};
+
+use ed25519_compact::{PublicKey, Signature};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use embedded_svc::http::client::asynch::{Client, Connection};
+use embedded_svc::http::{Headers, Status};
+use embedded_svc::io::asynch::Read;
+
+use channel_bridge::notification::Notification;
+
+use crate::power::{self, SleepBlocker};
+use crate::quit;
+use crate::state::State;
+
+pub use crate::dto::ota::*;
+
+pub const MAX_URL_LEN: usize = 256;
+
+const MAX_MANIFEST_LEN: usize = 512;
+const CHUNK_LEN: usize = 1024;
+
+// A freshly updated firmware which does not start all of its executors in that time is rolled back
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// ... and it is marked as valid only once it kept running for that long after starting them;
+// a panic before that restarts the device with the firmware still pending, so that the
+// bootloader rolls it back
+const VERIFY_UPTIME: Duration = Duration::from_secs(60);
+
+// Give screen, web and MQTT a chance to report the new state before restarting
+const RESTART_DELAY: Duration = Duration::from_secs(3);
+
+/// The update slot, shared by the updates from the manifest URL (`process`) and the pushed ones (`upload`),
+/// so that only one of them writes to it at a time
+pub type SharedOta<O> = AsyncMutex<NoopRawMutex, O>;
+
+/// The inactive update slot of the device (an OTA partition on ESP-IDF, a file on the host)
+pub trait Ota {
+    type Error: Debug;
+
+    fn running_version(&self) -> Option<FirmwareVersion>;
+
+    /// Checks the header of an image (its first 1024 bytes or less) before writing it,
+    /// returning the image version, if the platform can tell it
+    fn check_header(&self, _header: &[u8]) -> Result<Option<FirmwareVersion>, OtaError> {
+        Ok(None)
+    }
+
+    fn is_pending_verification(&self) -> Result<bool, Self::Error>;
+
+    fn mark_valid(&mut self) -> Result<(), Self::Error>;
+
+    /// Marks the running firmware as invalid and switches back to the previous one
+    /// Might not return, as on the device this also restarts
+    fn rollback(&mut self) -> Result<(), Self::Error>;
+
+    async fn begin(&mut self, size: usize) -> Result<(), Self::Error>;
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Validates the written image and selects it for the next boot
+    async fn complete(&mut self) -> Result<(), Self::Error>;
+
+    async fn abort(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<T> Ota for &mut T
+where
+    T: Ota,
+{
+    type Error = T::Error;
+
+    fn running_version(&self) -> Option<FirmwareVersion> {
+        (**self).running_version()
+    }
+
+    fn check_header(&self, header: &[u8]) -> Result<Option<FirmwareVersion>, OtaError> {
+        (**self).check_header(header)
+    }
+
+    fn is_pending_verification(&self) -> Result<bool, Self::Error> {
+        (**self).is_pending_verification()
+    }
+
+    fn mark_valid(&mut self) -> Result<(), Self::Error> {
+        (**self).mark_valid()
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        (**self).rollback()
+    }
+
+    async fn begin(&mut self, size: usize) -> Result<(), Self::Error> {
+        (**self).begin(size).await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        (**self).write(data).await
+    }
+
+    async fn complete(&mut self) -> Result<(), Self::Error> {
+        (**self).complete().await
+    }
+
+    async fn abort(&mut self) -> Result<(), Self::Error> {
+        (**self).abort().await
+    }
+}
+
+pub static STATE: State<OtaState> = State::new(
+    "OTA",
+    OtaState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::screen::OTA_STATE_NOTIF,
+        &crate::mqtt::OTA_STATE_NOTIF,
+        &crate::web::OTA_STATE_NOTIF,
+    ],
+);
+
+static STARTED: Mutex<CriticalSectionRawMutex, Cell<usize>> = Mutex::new(Cell::new(0));
+static STARTED_NOTIF: Notification = Notification::new();
+
+static RESTART_NOTIF: Notification = Notification::new();
+
+pub static COMMAND: Signal<CriticalSectionRawMutex, OtaCommand> = Signal::new();
+
+/// The update manifest, as served on the manifest URL:
+/// `{"version": "0.6.0", "url": "...", "size": 1234, "sha256": "<hex>", "signature": "<hex>"}`
+///
+/// The signature is an Ed25519 one over the SHA-256 digest of the image
+#[derive(Deserialize)]
+struct Manifest<'a> {
+    version: &'a str,
+    url: &'a str,
+    size: usize,
+    sha256: &'a str,
+    signature: &'a str,
+}
+
+#[derive(Clone, Debug)]
+struct Update {
+    version: FirmwareVersion,
+    url: String<MAX_URL_LEN>,
+    size: usize,
+    sha256: [u8; 32],
+    signature: [u8; 64],
+}
+
+impl Update {
+    fn parse(data: &[u8]) -> Result<Self, OtaError> {
+        let (manifest, _) =
+            serde_json_core::from_slice::<Manifest>(data).map_err(|_| OtaError::Manifest)?;
+
+        Ok(Self {
+            version: manifest.version.parse().map_err(|_| OtaError::Manifest)?,
+            url: manifest.url.try_into().map_err(|_| OtaError::Manifest)?,
+            size: manifest.size,
+            sha256: decode_hex(manifest.sha256).ok_or(OtaError::Manifest)?,
+            signature: decode_hex(manifest.signature).ok_or(OtaError::Manifest)?,
+        })
+    }
+
+    fn verify_signature(&self, public_key: &[u8; 32]) -> Result<(), OtaError> {
+        let public_key = PublicKey::from_slice(public_key).map_err(|_| OtaError::Signature)?;
+        let signature = Signature::from_slice(&self.signature).map_err(|_| OtaError::Signature)?;
+
+        public_key
+            .verify(self.sha256, &signature)
+            .map_err(|_| OtaError::Signature)
+    }
+}
+
+pub async fn process(
+    ota: &SharedOta<impl Ota>,
+    mut connection: impl Connection,
+    manifest_url: Option<&str>,
+    public_key: Option<&[u8; 32]>,
+) {
+    power::register_blocker(SleepBlocker::Update, || STATE.get().status.is_busy());
+
+    let running_version = ota.lock().await.running_version();
+
+    STATE.update(OtaState {
+        running_version,
+        status: OtaStatus::Idle,
+    });
+
+    let pending = ota.lock().await.is_pending_verification();
+
+    match pending {
+        Ok(true) => verify(ota).await,
+        Ok(false) => (),
+        Err(err) => warn!("Cannot get the firmware state: {:?}", err),
+    }
+
+    let mut available = None;
+
+    loop {
+        let command = match select(COMMAND.wait(), RESTART_NOTIF.wait()).await {
+            Either::First(command) => command,
+            Either::Second(_) => {
+                restart().await;
+                continue;
+            }
+        };
+
+        info!("Got command: {:?}", command);
+
+        if STATE.get().status.is_busy() {
+            warn!("Another update is in progress, ignoring command");
+            continue;
+        }
+
+        let Some(manifest_url) = manifest_url else {
+            warn!("No update manifest URL configured");
+
+            set_status(OtaStatus::Failed(OtaError::Manifest));
+            continue;
+        };
+
+        if command == OtaCommand::CheckForUpdate || available.is_none() {
+            available = check(&mut connection, manifest_url, running_version).await;
+        }
+
+        if command == OtaCommand::Update {
+            let Ok(mut ota) = ota.try_lock() else {
+                warn!("A firmware is being uploaded, ignoring command");
+                continue;
+            };
+
+            if let Some(update) = available.take() {
+                match download(&mut *ota, &mut connection, &update, public_key).await {
+                    Ok(()) => {
+                        info!("Firmware {} is ready, restarting", update.version);
+
+                        set_status(OtaStatus::Ready(Some(update.version)));
+
+                        restart().await;
+                    }
+                    Err(err) => set_status(OtaStatus::Failed(err)),
+                }
+            }
+        }
+    }
+}
+
+/// Reports that an executor has spawned all of its tasks and is about to run them
+///
+/// A freshly updated firmware is verified once all executors (one per `quit::QUIT` notification)
+/// have reported
+pub fn started() {
+    STARTED.lock(|started| started.set(started.get() + 1));
+
+    STARTED_NOTIF.notify();
+}
+
+/// Only locks `ota` once done waiting, so that an upload in the meantime is refused right away
+/// as busy rather than blocking for the whole verification
+async fn verify(ota: &SharedOta<impl Ota>) {
+    info!("Running a new firmware, waiting for it to start");
+
+    set_status(OtaStatus::PendingVerification);
+
+    let result = select(
+        async {
+            while STARTED.lock(Cell::get) < quit::QUIT.len() {
+                STARTED_NOTIF.wait().await;
+            }
+        },
+        Timer::after(VERIFY_TIMEOUT),
+    )
+    .await;
+
+    if let Either::First(_) = result {
+        Timer::after(VERIFY_UPTIME).await;
+
+        info!("New firmware is running fine, marking it as valid");
+
+        if let Err(err) = ota.lock().await.mark_valid() {
+            warn!("Cannot mark the firmware as valid: {:?}", err);
+        }
+
+        set_status(OtaStatus::Idle);
+    } else {
+        warn!("New firmware did not start, rolling back");
+
+        if let Err(err) = ota.lock().await.rollback() {
+            warn!("Cannot roll back the firmware: {:?}", err);
+        }
+    }
+}
+
+async fn check(
+    connection: impl Connection,
+    manifest_url: &str,
+    running_version: Option<FirmwareVersion>,
+) -> Option<Update> {
+    set_status(OtaStatus::Checking);
+
+    match fetch_manifest(connection, manifest_url).await {
+        Ok(update) if running_version.map(|v| update.version > v).unwrap_or(true) => {
+            info!("Firmware {} is available", update.version);
+
+            set_status(OtaStatus::UpdateAvailable(update.version));
+
+            Some(update)
+        }
+        Ok(_) => {
+            set_status(OtaStatus::UpToDate);
+
+            None
+        }
+        Err(err) => {
+            warn!("Checking for update failed: {:?}", err);
+
+            set_status(OtaStatus::Failed(err));
+
+            None
+        }
+    }
+}
+
+async fn fetch_manifest(
+    connection: impl Connection,
+    manifest_url: &str,
+) -> Result<Update, OtaError> {
+    let mut client = Client::wrap(connection);
+
+    let mut response = client
+        .get(manifest_url)
+        .await
+        .map_err(network)?
+        .submit()
+        .await
+        .map_err(network)?;
+
+    check_status(&response)?;
+
+    let mut buf = [0; MAX_MANIFEST_LEN];
+
+    let len = read_full(&mut response, &mut buf).await?;
+    if len == buf.len() {
+        return Err(OtaError::Manifest);
+    }
+
+    Update::parse(&buf[..len])
+}
+
+async fn download(
+    ota: &mut impl Ota,
+    connection: impl Connection,
+    update: &Update,
+    public_key: Option<&[u8; 32]>,
+) -> Result<(), OtaError> {
+    // Refuse unsigned updates upfront, before downloading anything
+    update.verify_signature(public_key.ok_or(OtaError::Signature)?)?;
+
+    set_status(OtaStatus::Downloading(0));
+
+    let mut client = Client::wrap(connection);
+
+    let mut response = client
+        .get(&update.url)
+        .await
+        .map_err(network)?
+        .submit()
+        .await
+        .map_err(network)?;
+
+    check_status(&response)?;
+
+    if response
+        .content_len()
+        .map(|len| len != update.size as u64)
+        .unwrap_or(false)
+    {
+        return Err(OtaError::Size);
+    }
+
+    write(ota, &mut response, update.size, Some(&update.sha256))
+        .await
+        .map(|_| ())
+}
+
+/// Writes a firmware image pushed to the device (i.e. uploaded from the web UI) into the update slot
+///
+/// There is no manifest to check a pushed image against, so it is only validated
+/// by the platform (header and checksum). On success, the device restarts shortly
+/// after, which leaves the caller enough time to report the outcome.
+pub async fn upload(
+    ota: &SharedOta<impl Ota>,
+    read: impl Read,
+    size: usize,
+) -> Result<(), OtaError> {
+    let Ok(mut ota) = ota.try_lock() else {
+        return Err(OtaError::Busy);
+    };
+
+    if STATE.get().status.is_busy() {
+        return Err(OtaError::Busy);
+    }
+
+    match write(&mut *ota, read, size, None).await {
+        Ok(version) => {
+            info!("Uploaded firmware {:?} is ready", version);
+
+            set_status(OtaStatus::Ready(version));
+
+            RESTART_NOTIF.notify();
+
+            Ok(())
+        }
+        Err(err) => {
+            warn!("Firmware upload failed: {:?}", err);
+
+            set_status(OtaStatus::Failed(err));
+
+            Err(err)
+        }
+    }
+}
+
+async fn restart() {
+    Timer::after(RESTART_DELAY).await;
+
+    quit::shutdown().await;
+}
+
+async fn write(
+    ota: &mut impl Ota,
+    mut read: impl Read,
+    size: usize,
+    sha256: Option<&[u8; 32]>,
+) -> Result<Option<FirmwareVersion>, OtaError> {
+    if size == 0 {
+        return Err(OtaError::Size);
+    }
+
+    set_status(OtaStatus::Downloading(0));
+
+    let mut buf = [0; CHUNK_LEN];
+
+    // Read the first chunk in full, so that the image header is checked before touching the slot
+    let len = read_full(&mut read, &mut buf[..min(size, CHUNK_LEN)]).await?;
+    let version = ota.check_header(&buf[..len])?;
+
+    ota.begin(size).await.map_err(storage)?;
+
+    match write_chunks(ota, read, &mut buf, len, size, sha256).await {
+        Ok(()) => {
+            // The platform validates the whole image once it is complete
+            ota.complete().await.map_err(|err| {
+                warn!("OTA image rejected: {:?}", err);
+
+                OtaError::Image
+            })?;
+
+            Ok(version)
+        }
+        Err(err) => {
+            if let Err(err) = ota.abort().await {
+                warn!("Cannot abort the update: {:?}", err);
+            }
+
+            Err(err)
+        }
+    }
+}
+
+async fn write_chunks(
+    ota: &mut impl Ota,
+    mut read: impl Read,
+    buf: &mut [u8],
+    mut len: usize,
+    size: usize,
+    sha256: Option<&[u8; 32]>,
+) -> Result<(), OtaError> {
+    let mut hasher = Sha256::new();
+
+    let mut written = 0;
+    let mut progress = 0;
+
+    while len > 0 {
+        written += len;
+        if written > size {
+            return Err(OtaError::Size);
+        }
+
+        hasher.update(&buf[..len]);
+        ota.write(&buf[..len]).await.map_err(storage)?;
+
+        let new_progress = (written * 100 / size) as u8;
+        if new_progress != progress {
+            progress = new_progress;
+            set_status(OtaStatus::Downloading(progress));
+        }
+
+        len = read.read(buf).await.map_err(network)?;
+    }
+
+    if written != size {
+        return Err(OtaError::Size);
+    }
+
+    set_status(OtaStatus::Verifying);
+
+    if let Some(sha256) = sha256 {
+        if hasher.finalize()[..] != sha256[..] {
+            return Err(OtaError::Checksum);
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_full(mut read: impl Read, buf: &mut [u8]) -> Result<usize, OtaError> {
+    let mut len = 0;
+
+    while len < buf.len() {
+        let read = read.read(&mut buf[len..]).await.map_err(network)?;
+        if read == 0 {
+            break;
+        }
+
+        len += read;
+    }
+
+    Ok(len)
+}
+
+fn check_status(response: &impl Status) -> Result<(), OtaError> {
+    match response.status() {
+        200..=299 => Ok(()),
+        status => Err(OtaError::Http(status)),
+    }
+}
+
+fn set_status(status: OtaStatus) {
+    STATE.update_with(|state| OtaState { status, ..state });
+}
+
+fn network(err: impl Debug) -> OtaError {
+    warn!("OTA network error: {:?}", err);
+
+    OtaError::Network
+}
+
+fn storage(err: impl Debug) -> OtaError {
+    warn!("OTA storage error: {:?}", err);
+
+    OtaError::Storage
+}
+
+/// Decodes a fixed-size byte array, like a digest or a key, from its hex representation
+pub fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.as_bytes();
+
+    if hex.len() != N * 2 {
+        return None;
+    }
+
+    let mut data = [0; N];
+
+    for (byte, digits) in data.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    Some(data)
+}
+
+#[cfg(feature = "std")]
+pub use file::FileOta;
+
+#[cfg(feature = "std")]
+mod file {
+    use std::ffi::OsString;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+
+    use super::{FirmwareVersion, Ota};
+
+    /// An update slot backed by a file on the host
+    ///
+    /// The new image is written next to the firmware file and swapped with it
+    /// on completion; the previous image is kept until the new one is marked as valid
+    pub struct FileOta {
+        path: PathBuf,
+        running_version: Option<FirmwareVersion>,
+        update: Option<File>,
+    }
+
+    impl FileOta {
+        pub fn new(path: impl Into<PathBuf>, running_version: Option<FirmwareVersion>) -> Self {
+            Self {
+                path: path.into(),
+                running_version,
+                update: None,
+            }
+        }
+
+        fn sibling(&self, suffix: &str) -> PathBuf {
+            let mut path = OsString::from(self.path.as_os_str());
+            path.push(suffix);
+
+            path.into()
+        }
+    }
+
+    impl Ota for FileOta {
+        type Error = io::Error;
+
+        fn running_version(&self) -> Option<FirmwareVersion> {
+            self.running_version
+        }
+
+        fn is_pending_verification(&self) -> Result<bool, Self::Error> {
+            Ok(self.sibling(".pending").exists())
+        }
+
+        fn mark_valid(&mut self) -> Result<(), Self::Error> {
+            remove_if_exists(self.sibling(".pending"))?;
+            remove_if_exists(self.sibling(".prev"))
+        }
+
+        fn rollback(&mut self) -> Result<(), Self::Error> {
+            let prev = self.sibling(".prev");
+
+            if prev.exists() {
+                fs::rename(prev, &self.path)?;
+            }
+
+            remove_if_exists(self.sibling(".pending"))
+        }
+
+        async fn begin(&mut self, _size: usize) -> Result<(), Self::Error> {
+            self.update = Some(File::create(self.sibling(".new"))?);
+
+            Ok(())
+        }
+
+        async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+            self.update
+                .as_mut()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?
+                .write_all(data)
+        }
+
+        async fn complete(&mut self) -> Result<(), Self::Error> {
+            let update = self
+                .update
+                .take()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+
+            update.sync_all()?;
+
+            if self.path.exists() {
+                fs::rename(&self.path, self.sibling(".prev"))?;
+            }
+
+            fs::rename(self.sibling(".new"), &self.path)?;
+
+            File::create(self.sibling(".pending")).map(|_| ())
+        }
+
+        async fn abort(&mut self) -> Result<(), Self::Error> {
+            self.update = None;
+
+            remove_if_exists(self.sibling(".new"))
+        }
+    }
+
+    fn remove_if_exists(path: PathBuf) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
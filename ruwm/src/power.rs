@@ -0,0 +1,223 @@
+//! Wakeups and sleep
+//!
+//! The platform only reports why the device woke up and puts it to sleep, through a
+//! `SleepController`; deciding when and for how long to sleep is done here. While any subsystem
+//! registered with `register_blocker` blocks the sleep, the device stays awake. Once it decides
+//! to sleep, the pre-sleep hook announces it over MQTT, right before the graceful shutdown in
+//! `quit`.
+
+use core::cell::Cell;
+use core::fmt::Debug;
+
+use log::info;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Duration;
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::{battery, wm};
+
+pub use crate::dto::power::*;
+
+pub trait SleepController {
+    type Error: Debug;
+
+    fn wake_reason(&self) -> WakeReason;
+
+    /// Arms the wakeup sources and sleeps for `duration` at most
+    ///
+    /// On a real device this only returns on an error, as it wakes up with a reset.
+    fn sleep(&mut self, duration: Duration) -> Result<(), Self::Error>;
+}
+
+impl<T> SleepController for &mut T
+where
+    T: SleepController,
+{
+    type Error = T::Error;
+
+    fn wake_reason(&self) -> WakeReason {
+        (**self).wake_reason()
+    }
+
+    fn sleep(&mut self, duration: Duration) -> Result<(), Self::Error> {
+        (*self).sleep(duration)
+    }
+}
+
+/// A controller which records the sleeps rather than sleeping, for the simulator and for tests
+///
+/// Every sleep ends with a timer wakeup.
+pub struct SimulatedSleepController {
+    wake_reason: WakeReason,
+    slept: Option<Duration>,
+}
+
+impl SimulatedSleepController {
+    pub const fn new(wake_reason: WakeReason) -> Self {
+        Self {
+            wake_reason,
+            slept: None,
+        }
+    }
+
+    /// How long the last sleep would have lasted
+    pub fn slept(&self) -> Option<Duration> {
+        self.slept
+    }
+
+    /// Wakes up for another reason than the timer
+    pub fn wake(&mut self, wake_reason: WakeReason) {
+        self.wake_reason = wake_reason;
+    }
+}
+
+impl SleepController for SimulatedSleepController {
+    type Error = ();
+
+    fn wake_reason(&self) -> WakeReason {
+        self.wake_reason
+    }
+
+    fn sleep(&mut self, duration: Duration) -> Result<(), Self::Error> {
+        self.slept = Some(duration);
+        self.wake_reason = WakeReason::Timer;
+
+        Ok(())
+    }
+}
+
+pub static STATE: State<PowerState> = State::new(
+    "POWER",
+    PowerState::new(),
+    &[&crate::mqtt::POWER_STATE_NOTIF],
+);
+
+pub static POLICY: State<PowerPolicy> = State::new(
+    "POWER POLICY",
+    PowerPolicy::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::web::POWER_POLICY_STATE_NOTIF,
+        &POLICY_FLASH_NOTIFY,
+    ],
+);
+
+static POLICY_FLASH_NOTIFY: Notification = Notification::new();
+
+/// In the order in which they are reported
+const BLOCKERS: [SleepBlocker; 4] = [
+    SleepBlocker::Powered,
+    SleepBlocker::Update,
+    SleepBlocker::PressureTest,
+    SleepBlocker::WebClient,
+];
+
+type Blocks = fn() -> bool;
+
+static REGISTERED_BLOCKERS: Mutex<CriticalSectionRawMutex, Cell<[Option<Blocks>; BLOCKERS.len()]>> =
+    Mutex::new(Cell::new([None; BLOCKERS.len()]));
+
+/// Registers a subsystem, which keeps the device awake as long as `blocks` returns true
+///
+/// A subsystem registers itself when it starts; registering it again replaces `blocks`.
+pub fn register_blocker(blocker: SleepBlocker, blocks: Blocks) {
+    REGISTERED_BLOCKERS.lock(|registered| {
+        let mut blockers = registered.get();
+        blockers[blocker as usize] = Some(blocks);
+        registered.set(blockers);
+    });
+}
+
+/// The first registered subsystem which keeps the device awake, if any
+pub fn blocker() -> Option<SleepBlocker> {
+    let registered = REGISTERED_BLOCKERS.lock(Cell::get);
+
+    BLOCKERS
+        .into_iter()
+        .zip(registered)
+        .find(|(_, blocks)| blocks.map(|blocks| blocks()).unwrap_or(false))
+        .map(|(blocker, _)| blocker)
+}
+
+/// The pre-sleep hook, which runs before the shutdown
+pub fn prepare_sleep() {
+    STATE.update_with(|state| PowerState {
+        sleeping: true,
+        ..state
+    });
+}
+
+/// How long to sleep before the next timer wakeup
+pub fn sleep_duration() -> Duration {
+    let armed = wm::STATE.get().iter().any(|state| state.armed);
+
+    Duration::from_secs(POLICY.get().sleep_secs(battery::STATE.get().low, armed) as u64)
+}
+
+/// Puts the device to sleep, once the executors have quit
+pub fn sleep<S: SleepController>(mut controller: S) -> Result<(), S::Error> {
+    let duration = sleep_duration();
+
+    info!("Going to sleep for {}s", duration.as_secs());
+
+    controller.sleep(duration)
+}
+
+pub async fn flash(mut flasher: impl FnMut(PowerPolicy)) {
+    loop {
+        POLICY_FLASH_NOTIFY.wait().await;
+
+        flasher(POLICY.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    static TESTING: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn sleeps_once_nothing_blocks() {
+        let mut controller = SimulatedSleepController::new(WakeReason::Button);
+
+        register_blocker(SleepBlocker::PressureTest, || {
+            TESTING.load(Ordering::SeqCst)
+        });
+
+        TESTING.store(true, Ordering::SeqCst);
+        assert_eq!(blocker(), Some(SleepBlocker::PressureTest));
+
+        TESTING.store(false, Ordering::SeqCst);
+        assert_eq!(blocker(), None);
+
+        sleep(&mut controller).unwrap();
+
+        let policy = POLICY.get();
+
+        assert_eq!(controller.wake_reason(), WakeReason::Timer);
+        assert_eq!(
+            controller.slept(),
+            Some(Duration::from_secs(policy.sleep_secs(false, false) as u64))
+        );
+
+        // An armed meter wakes up more often, to report the flow
+        wm::STATE.update_with(|mut states| {
+            states[0].armed = true;
+            states
+        });
+
+        sleep(&mut controller).unwrap();
+
+        assert_eq!(
+            controller.slept(),
+            Some(Duration::from_secs(policy.sleep_secs(false, true) as u64))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Pipe pressure monitoring and the pressure drop test
+//!
+//! The test closes the main valve, watches the pressure for a while and opens the valve again.
+//! A pipe which does not hold the pressure leaks downstream of the valve, so the drop during the
+//! test is checked against the pressure drop alert. The test keeps the device awake; an emergency
+//! or a valve command during the test aborts it and leaves the valve alone.
+
+use core::cmp::max;
+
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::alert::{self, AlertAction};
+use crate::battery::Adc;
+use crate::power::{self, SleepBlocker};
+use crate::state::State;
+use crate::time::{self, SECS_PER_DAY};
+use crate::valve::{self, ValveCommand, ValveState};
+
+pub use crate::dto::pressure::*;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+const VALVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Readings are rounded to that many mbar, so that noise does not flood the clients
+const ROUND: u16 = 10;
+
+/// The valve closed during the test
+const VALVE: usize = 0;
+
+pub static STATE: State<PressureState> = State::new(
+    "PRESSURE",
+    PressureState::new(),
+    &[
+        &crate::alert::PRESSURE_STATE_NOTIF,
+        &crate::mqtt::PRESSURE_STATE_NOTIF,
+        &crate::web::PRESSURE_STATE_NOTIF,
+    ],
+);
+
+pub static CONFIG: State<PressureConfig> = State::new(
+    "PRESSURE CONFIG",
+    PressureConfig::new(),
+    &[
+        &crate::web::PRESSURE_CONFIG_STATE_NOTIF,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
+pub static COMMAND: Signal<CriticalSectionRawMutex, PressureCommand> = Signal::new();
+
+pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+
+/// Signalled when an emergency closes the valve of the test
+pub(crate) static EMERGENCY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+enum Outcome {
+    Completed(u16),
+    Aborted,
+    Interrupted,
+}
+
+pub async fn process_sensor(mut adc: impl Adc) {
+    loop {
+        let pressure = adc
+            .read()
+            .await
+            .ok()
+            .map(|reading| CONFIG.get().pressure(reading) / ROUND * ROUND);
+
+        STATE.update_with(|state| PressureState { pressure, ..state });
+
+        Timer::after(SAMPLE_INTERVAL).await;
+    }
+}
+
+pub async fn process() {
+    power::register_blocker(SleepBlocker::PressureTest, || STATE.get().is_testing());
+
+    loop {
+        // The daily test starts on a full minute
+        let next_minute = time::local_now()
+            .map(|secs| 60 - secs.rem_euclid(60) as u64)
+            .unwrap_or(60);
+
+        let start = match select(
+            COMMAND.wait(),
+            Timer::after(Duration::from_secs(next_minute)),
+        )
+        .await
+        {
+            Either::First(PressureCommand::StartTest) => true,
+            Either::First(PressureCommand::AbortTest) => false,
+            Either::First(PressureCommand::Configure(config)) => {
+                CONFIG.update(config);
+                false
+            }
+            Either::Second(_) => time::local_now()
+                .zip(CONFIG.get().test_at_mins)
+                .map(|(secs, at)| secs.rem_euclid(SECS_PER_DAY) / 60 == at as i64)
+                .unwrap_or(false),
+        };
+
+        if start {
+            test().await;
+        }
+    }
+}
+
+async fn test() {
+    // Only a valve which is open can tell anything about the pipes downstream
+    if valve::STATE.get()[VALVE] != Some(ValveState::Open) || STATE.get().pressure.is_none() {
+        return;
+    }
+
+    EMERGENCY.reset();
+
+    valve::COMMAND[VALVE].signal(ValveCommand::Close);
+
+    match select3(wait_closed(), EMERGENCY.wait(), Timer::after(VALVE_TIMEOUT)).await {
+        Either3::First(_) => (),
+        // The valve closes or faults anyway
+        Either3::Second(_) | Either3::Third(_) => return,
+    }
+
+    let Some(start) = STATE.get().pressure else {
+        valve::COMMAND[VALVE].signal(ValveCommand::Open);
+        return;
+    };
+
+    STATE.update_with(|state| PressureState {
+        test_start: Some(start),
+        ..state
+    });
+
+    let end = Instant::now() + Duration::from_secs(CONFIG.get().test_mins as u64 * 60);
+    let mut drop = 0;
+
+    let outcome = loop {
+        match select4(
+            COMMAND.wait(),
+            VALVE_STATE_NOTIF.wait(),
+            EMERGENCY.wait(),
+            Timer::after(SAMPLE_INTERVAL),
+        )
+        .await
+        {
+            Either4::First(PressureCommand::AbortTest) => break Outcome::Aborted,
+            Either4::First(PressureCommand::Configure(config)) => {
+                CONFIG.update(config);
+            }
+            Either4::First(PressureCommand::StartTest) => (),
+            Either4::Second(_) => {
+                if valve::STATE.get()[VALVE] != Some(ValveState::Closed) {
+                    break Outcome::Interrupted;
+                }
+            }
+            Either4::Third(_) => break Outcome::Interrupted,
+            Either4::Fourth(_) => {
+                if let Some(pressure) = STATE.get().pressure {
+                    drop = max(drop, start.saturating_sub(pressure));
+                }
+
+                if Instant::now() >= end {
+                    break Outcome::Completed(drop);
+                }
+            }
+        }
+    };
+
+    let reopen = match outcome {
+        Outcome::Completed(drop) => {
+            let config = alert::CONFIG.get();
+
+            // Leave the valve closed rather than have the alert close it again
+            config.action != AlertAction::CloseValve
+                || config
+                    .pressure_drop
+                    .map(|threshold| drop as u32 <= threshold)
+                    .unwrap_or(true)
+        }
+        Outcome::Aborted => true,
+        Outcome::Interrupted => false,
+    };
+
+    STATE.update_with(|state| PressureState {
+        test_start: None,
+        test_drop: match outcome {
+            Outcome::Completed(drop) => Some(drop),
+            _ => state.test_drop,
+        },
+        ..state
+    });
+
+    if reopen {
+        valve::COMMAND[VALVE].signal(ValveCommand::Open);
+    }
+}
+
+async fn wait_closed() {
+    while valve::STATE.get()[VALVE] != Some(ValveState::Closed) {
+        VALVE_STATE_NOTIF.wait().await;
+    }
+}
+
+pub async fn flash(mut flasher: impl FnMut(PressureConfig)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
@@ -1,7 +1,138 @@
+//! Graceful shutdown
+//!
+//! Before the executors quit, i.e. to sleep or to restart into an updated firmware, every
+//! registered shutdown participant is asked to flush, and the executors only quit once all of them
+//! are done, or after `FLUSH_TIMEOUT` at the latest.
+
+use core::cell::RefCell;
+
+use log::{info, warn};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Timer};
+
 use channel_bridge::notification::Notification;
 
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub static QUIT: [Notification; 3] = [
     Notification::new(),
     Notification::new(),
     Notification::new(),
 ];
+
+/// Asks the participants to flush
+static FLUSH_NOTIFY: &[&Notification] = &[
+    &crate::wm::SHUTDOWN_NOTIF,
+    &crate::mqtt::SHUTDOWN_NOTIF,
+    &crate::web::SHUTDOWN_NOTIF,
+];
+
+static FLUSHED_NOTIF: Notification = Notification::new();
+
+static PARTICIPANTS: Mutex<CriticalSectionRawMutex, RefCell<Participants>> =
+    Mutex::new(RefCell::new(Participants::new()));
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Participant {
+    /// Flashes the water meter states skipped by the write cycle
+    WaterMeter,
+    /// Publishes whatever is still pending
+    Mqtt,
+    /// Sends the last state to a web client; one per connected client
+    Web,
+}
+
+impl Participant {
+    const ALL: [Self; 3] = [Self::WaterMeter, Self::Mqtt, Self::Web];
+
+    const fn index(&self) -> usize {
+        match self {
+            Self::WaterMeter => 0,
+            Self::Mqtt => 1,
+            Self::Web => 2,
+        }
+    }
+}
+
+struct Participants {
+    registered: [usize; Participant::ALL.len()],
+    flushed: [usize; Participant::ALL.len()],
+}
+
+impl Participants {
+    const fn new() -> Self {
+        Self {
+            registered: [0; Participant::ALL.len()],
+            flushed: [0; Participant::ALL.len()],
+        }
+    }
+
+    fn pending(&self) -> Option<Participant> {
+        Participant::ALL.into_iter().find(|participant| {
+            self.flushed[participant.index()] < self.registered[participant.index()]
+        })
+    }
+}
+
+/// Registers a participant, which then has to call `flushed` whenever it is asked to flush
+pub fn register(participant: Participant) {
+    PARTICIPANTS.lock(|participants| {
+        participants.borrow_mut().registered[participant.index()] += 1;
+    });
+}
+
+/// Unregisters a participant which is gone, i.e. a disconnected web client
+pub fn unregister(participant: Participant) {
+    PARTICIPANTS.lock(|participants| {
+        let registered = &mut participants.borrow_mut().registered[participant.index()];
+
+        *registered = registered.saturating_sub(1);
+    });
+
+    // A shutdown does not have to wait for it anymore
+    FLUSHED_NOTIF.notify();
+}
+
+/// Acknowledges a flush request
+pub fn flushed(participant: Participant) {
+    PARTICIPANTS.lock(|participants| {
+        participants.borrow_mut().flushed[participant.index()] += 1;
+    });
+
+    FLUSHED_NOTIF.notify();
+}
+
+/// Asks the participants to flush, waits for them and then quits the executors
+pub async fn shutdown() {
+    info!("Shutting down");
+
+    PARTICIPANTS.lock(|participants| {
+        participants.borrow_mut().flushed = [0; Participant::ALL.len()];
+    });
+
+    for notification in FLUSH_NOTIFY {
+        notification.notify();
+    }
+
+    let all_flushed = async {
+        while PARTICIPANTS
+            .lock(|participants| participants.borrow().pending())
+            .is_some()
+        {
+            FLUSHED_NOTIF.wait().await;
+        }
+    };
+
+    if let Either::Second(_) = select(all_flushed, Timer::after(FLUSH_TIMEOUT)).await {
+        let pending = PARTICIPANTS.lock(|participants| participants.borrow().pending());
+
+        warn!("Shutting down without {:?} having flushed", pending);
+    }
+
+    for notification in &QUIT {
+        notification.notify();
+    }
+}
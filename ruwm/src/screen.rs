@@ -7,23 +7,30 @@ use log::trace;
 
 use enumset::{enum_set, EnumSet, EnumSetType};
 
-use embassy_futures::select::select_slice;
+use embassy_futures::select::{select, select_slice, Either};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
 
 use gfx_xtra::draw_target::Flushable;
 
 use channel_bridge::notification::Notification;
 
+use crate::alert::{self, AlertState};
 use crate::battery::{self, BatteryState};
+use crate::button::{Button, ButtonGesture};
+use crate::flow::{self, FlowState};
 use crate::keepalive::{self, RemainingTime};
+use crate::moisture::{self, SensorStates};
+use crate::ota::{self, OtaState};
 use crate::screen::shapes::util::clear;
-use crate::valve::{self, ValveState};
-use crate::wm::{self, WaterMeterState};
+use crate::temperature::{self, TemperatureState};
+use crate::valve::{self, ValveState, MAX_VALVES};
+use crate::wm::{self, WaterMeterState, MAX_METERS};
 
 pub use shapes::Color;
 
-use self::pages::{Battery, Summary};
+use self::pages::{Battery, Meter, Summary, Valve};
 use self::shapes::Action;
 
 mod pages;
@@ -31,8 +38,12 @@ mod shapes;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum Page {
-    Summary = 0,
-    Battery = 1,
+    Summary,
+    /// The page of a valve other than the main one, which is on the summary page
+    Valve(usize),
+    /// Likewise for the water meters
+    Meter(usize),
+    Battery,
 }
 
 impl Page {
@@ -41,26 +52,48 @@ impl Page {
     }
 
     pub fn prev(&self) -> Self {
+        self.switch(false)
+    }
+
+    pub fn next(&self) -> Self {
+        self.switch(true)
+    }
+
+    /// The valve the valve actions of the page are for
+    pub fn valve(&self) -> usize {
         match self {
-            Self::Summary => Self::Battery,
-            Self::Battery => Self::Summary,
+            Self::Valve(valve) => *valve,
+            _ => 0,
         }
     }
 
-    pub fn next(&self) -> Self {
+    /// The water meter the water meter actions of the page are for
+    pub fn meter(&self) -> usize {
         match self {
-            Self::Summary => Self::Battery,
-            Self::Battery => Self::Summary,
+            Self::Meter(meter) => *meter,
+            _ => 0,
         }
     }
 
     pub fn actions(&self) -> EnumSet<Action> {
         let actions = match self {
-            Self::Summary => Action::OpenValve | Action::CloseValve | Action::Arm | Action::Disarm,
+            Self::Summary => {
+                Action::OpenValve
+                    | Action::CloseValve
+                    | Action::OpenValvePartially
+                    | Action::Arm
+                    | Action::Disarm
+                    | Action::AcknowledgeAlerts
+                    | Action::ClearAlerts
+                    | Action::CheckForUpdate
+                    | Action::Update
+            }
+            Self::Valve(_) => Action::OpenValve | Action::CloseValve | Action::OpenValvePartially,
+            Self::Meter(_) => Action::Arm | Action::Disarm,
             Self::Battery => EnumSet::empty(),
         };
 
-        let mut actions = actions.intersection(Action::active());
+        let mut actions = actions.intersection(Action::active(self.valve(), self.meter()));
 
         if !actions.is_empty() {
             actions |= Action::Dismiss;
@@ -68,6 +101,33 @@ impl Page {
 
         actions
     }
+
+    // The pages of the disabled valves and meters are skipped
+    fn switch(&self, next: bool) -> Self {
+        let mut pages = heapless::Vec::<Self, { MAX_VALVES + MAX_METERS }>::new();
+
+        pages.push(Self::Summary).unwrap();
+
+        for (valve, _) in valve::enabled().filter(|(valve, _)| *valve > 0) {
+            pages.push(Self::Valve(valve)).unwrap();
+        }
+
+        for (meter, _) in wm::enabled().filter(|(meter, _)| *meter > 0) {
+            pages.push(Self::Meter(meter)).unwrap();
+        }
+
+        pages.push(Self::Battery).unwrap();
+
+        let index = pages.iter().position(|page| page == self).unwrap_or(0);
+
+        let index = if next {
+            (index + 1) % pages.len()
+        } else {
+            (index + pages.len() - 1) % pages.len()
+        };
+
+        pages[index]
+    }
 }
 
 impl Default for Page {
@@ -82,8 +142,13 @@ pub enum DataSource {
     Valve,
     WM,
     WMStats,
+    Flow,
+    Alert,
+    Moisture,
+    Temperature,
     Battery,
     RemainingTime,
+    Ota,
 }
 
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
@@ -101,22 +166,120 @@ impl ScreenState {
                     | DataSource::Valve
                     | DataSource::WM
                     | DataSource::WMStats
+                    | DataSource::Flow
+                    | DataSource::Alert
+                    | DataSource::Moisture
+                    | DataSource::Temperature
                     | DataSource::Battery
                     | DataSource::RemainingTime
+                    | DataSource::Ota
             ),
             active_page: Page::new(),
             page_actions: None,
         }
     }
 
-    pub fn valve(&self) -> Option<Option<ValveState>> {
+    /// Previous, next and action on a press of the first, second and third button; a double press
+    /// of the first two moves twice, while one of the third leaves the actions of the page. Both
+    /// first buttons together go back to the summary page.
+    fn gesture(&mut self, gesture: ButtonGesture) {
+        match gesture {
+            ButtonGesture::Press(Button::Button1) => self.prev(),
+            ButtonGesture::Press(Button::Button2) => self.next(),
+            ButtonGesture::Press(Button::Button3) => self.act(),
+            ButtonGesture::DoublePress(Button::Button1) => {
+                self.prev();
+                self.prev();
+            }
+            ButtonGesture::DoublePress(Button::Button2) => {
+                self.next();
+                self.next();
+            }
+            ButtonGesture::DoublePress(Button::Button3) => self.page_actions = None,
+            ButtonGesture::Chord(Button::Button1, Button::Button2) => {
+                self.active_page = Page::Summary;
+                self.page_actions = None;
+            }
+            _ => return,
+        }
+
+        self.changeset.insert(DataSource::Page);
+    }
+
+    fn prev(&mut self) {
+        if let Some((actions, action)) = self.page_actions {
+            self.page_actions = action.prev(&actions).map(|action| (actions, action));
+        } else {
+            self.active_page = self.active_page.prev();
+        }
+    }
+
+    fn next(&mut self) {
+        if let Some((actions, action)) = self.page_actions {
+            self.page_actions = action.next(&actions).map(|action| (actions, action));
+        } else {
+            self.active_page = self.active_page.next();
+        }
+    }
+
+    fn act(&mut self) {
+        if let Some((_, action)) = self.page_actions {
+            self.page_actions = None;
+            action.trigger(self.active_page.valve(), self.active_page.meter());
+        } else {
+            let actions = self.active_page.actions();
+            self.page_actions = Action::first(&actions).map(|action| (actions, action));
+        }
+    }
+
+    pub fn valve(&self, valve: usize) -> Option<Option<ValveState>> {
         self.changed([DataSource::Valve, DataSource::Page])
-            .then(|| valve::STATE.get())
+            .then(|| valve::STATE.get()[valve])
     }
 
-    pub fn wm(&self) -> Option<WaterMeterState> {
+    pub fn wm(&self, meter: usize) -> Option<WaterMeterState> {
         self.changed([DataSource::WM, DataSource::Page])
-            .then(|| wm::STATE.get())
+            .then(|| wm::STATE.get()[meter])
+    }
+
+    // Wet sensors, raised alerts and leaks take the place of the flow line,
+    // so a change in any of them redraws all
+    pub fn flow(&self, meter: usize) -> Option<FlowState> {
+        self.changed([
+            DataSource::Flow,
+            DataSource::Alert,
+            DataSource::Moisture,
+            DataSource::WM,
+            DataSource::Page,
+        ])
+        .then(|| flow::STATE.get()[meter])
+    }
+
+    pub fn alert(&self) -> Option<AlertState> {
+        self.changed([
+            DataSource::Flow,
+            DataSource::Alert,
+            DataSource::Moisture,
+            DataSource::WM,
+            DataSource::Page,
+        ])
+        .then(|| alert::STATE.get())
+    }
+
+    pub fn moisture(&self) -> Option<SensorStates> {
+        self.changed([
+            DataSource::Flow,
+            DataSource::Alert,
+            DataSource::Moisture,
+            DataSource::WM,
+            DataSource::Page,
+        ])
+        .then(|| moisture::STATE.get())
+    }
+
+    pub fn temperature(&self) -> Option<TemperatureState> {
+        self.changed([DataSource::Temperature, DataSource::Page])
+            .then(|| temperature::STATE.get())
     }
 
     pub fn battery(&self) -> Option<BatteryState> {
@@ -124,11 +287,18 @@ impl ScreenState {
             .then(|| battery::STATE.get())
     }
 
+    // Remaining time and OTA progress share the bottom status line,
+    // so a change in either of them redraws both
     pub fn remaining_time(&self) -> Option<RemainingTime> {
-        self.changed([DataSource::RemainingTime, DataSource::Page])
+        self.changed([DataSource::RemainingTime, DataSource::Ota, DataSource::Page])
             .then(|| keepalive::STATE.get())
     }
 
+    pub fn ota(&self) -> Option<OtaState> {
+        self.changed([DataSource::RemainingTime, DataSource::Ota, DataSource::Page])
+            .then(|| ota::STATE.get())
+    }
+
     fn changed<const N: usize>(&self, changes: [DataSource; N]) -> bool {
         changes
             .iter()
@@ -136,16 +306,21 @@ impl ScreenState {
     }
 }
 
-pub(crate) static BUTTON1_PRESSED_NOTIF: Notification = Notification::new();
-pub(crate) static BUTTON2_PRESSED_NOTIF: Notification = Notification::new();
-pub(crate) static BUTTON3_PRESSED_NOTIF: Notification = Notification::new();
+pub(crate) static BUTTON_GESTURE: Signal<CriticalSectionRawMutex, ButtonGesture> = Signal::new();
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static VALVE_CONFIG_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WM_CONFIG_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static FLOW_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERT_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static MOISTURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TEMPERATURE_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static MQTT_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static REMAINING_TIME_NOTIF: Notification = Notification::new();
+pub(crate) static OTA_STATE_NOTIF: Notification = Notification::new();
 
 static DRAW_REQUEST_NOTIF: Notification = Notification::new();
 
@@ -154,17 +329,30 @@ static STATE: Mutex<CriticalSectionRawMutex, RefCell<ScreenState>> =
 
 pub async fn process() {
     let mut notifs = [
-        BUTTON1_PRESSED_NOTIF.wait(),
-        BUTTON2_PRESSED_NOTIF.wait(),
-        BUTTON3_PRESSED_NOTIF.wait(),
         VALVE_STATE_NOTIF.wait(),
         WM_STATE_NOTIF.wait(),
         BATTERY_STATE_NOTIF.wait(),
         REMAINING_TIME_NOTIF.wait(),
+        OTA_STATE_NOTIF.wait(),
+        FLOW_STATE_NOTIF.wait(),
+        ALERT_STATE_NOTIF.wait(),
+        VALVE_CONFIG_STATE_NOTIF.wait(),
+        WM_CONFIG_STATE_NOTIF.wait(),
+        MOISTURE_STATE_NOTIF.wait(),
+        TEMPERATURE_STATE_NOTIF.wait(),
     ];
 
     loop {
-        let (_, index) = select_slice(&mut notifs).await;
+        let index = match select(BUTTON_GESTURE.wait(), select_slice(&mut notifs)).await {
+            Either::First(gesture) => {
+                STATE.lock(|screen_state| screen_state.borrow_mut().gesture(gesture));
+
+                DRAW_REQUEST_NOTIF.notify();
+
+                continue;
+            }
+            Either::Second((_, index)) => index,
+        };
 
         {
             STATE.lock(|screen_state| {
@@ -172,48 +360,53 @@ pub async fn process() {
 
                 match index {
                     0 => {
-                        if let Some((actions, action)) = screen_state.page_actions {
-                            screen_state.page_actions =
-                                action.prev(&actions).map(|action| (actions, action));
-                        } else {
-                            screen_state.active_page = screen_state.active_page.prev();
-                        }
-
-                        screen_state.changeset.insert(DataSource::Page);
+                        screen_state.changeset.insert(DataSource::Valve);
                     }
                     1 => {
-                        if let Some((actions, action)) = screen_state.page_actions {
-                            screen_state.page_actions =
-                                action.next(&actions).map(|action| (actions, action));
-                        } else {
-                            screen_state.active_page = screen_state.active_page.next();
-                        }
-
-                        screen_state.changeset.insert(DataSource::Page);
+                        screen_state.changeset.insert(DataSource::WM);
                     }
                     2 => {
-                        if let Some((_, action)) = screen_state.page_actions {
-                            screen_state.page_actions = None;
-                            action.trigger();
-                        } else {
-                            let actions = screen_state.active_page.actions();
-                            screen_state.page_actions =
-                                Action::first(&actions).map(|action| (actions, action));
-                        }
-
-                        screen_state.changeset.insert(DataSource::Page);
+                        screen_state.changeset.insert(DataSource::Battery);
                     }
                     3 => {
-                        screen_state.changeset.insert(DataSource::Valve);
+                        screen_state.changeset.insert(DataSource::RemainingTime);
                     }
                     4 => {
-                        screen_state.changeset.insert(DataSource::WM);
+                        screen_state.changeset.insert(DataSource::Ota);
                     }
                     5 => {
-                        screen_state.changeset.insert(DataSource::Battery);
+                        screen_state.changeset.insert(DataSource::Flow);
                     }
                     6 => {
-                        screen_state.changeset.insert(DataSource::RemainingTime);
+                        screen_state.changeset.insert(DataSource::Alert);
+                    }
+                    7 => {
+                        // The page of a valve which got disabled is gone
+                        if let Page::Valve(valve) = screen_state.active_page {
+                            if !valve::CONFIG.get()[valve].enabled {
+                                screen_state.active_page = Page::Summary;
+                                screen_state.page_actions = None;
+                            }
+                        }
+
+                        screen_state.changeset.insert(DataSource::Page);
+                    }
+                    8 => {
+                        // Likewise for the page of a meter
+                        if let Page::Meter(meter) = screen_state.active_page {
+                            if !wm::CONFIG.get()[meter].enabled {
+                                screen_state.active_page = Page::Summary;
+                                screen_state.page_actions = None;
+                            }
+                        }
+
+                        screen_state.changeset.insert(DataSource::Page);
+                    }
+                    9 => {
+                        screen_state.changeset.insert(DataSource::Moisture);
+                    }
+                    10 => {
+                        screen_state.changeset.insert(DataSource::Temperature);
                     }
                     _ => unreachable!(),
                 }
@@ -293,10 +486,28 @@ where
         Page::Summary => Summary::draw(
             display,
             page_changed,
-            screen_state.valve().as_ref(),
-            screen_state.wm().as_ref(),
+            screen_state.valve(0).as_ref(),
+            screen_state.wm(0).as_ref(),
+            screen_state.flow(0).as_ref(),
+            screen_state.alert().as_ref(),
+            screen_state.moisture().as_ref(),
+            screen_state.temperature().as_ref(),
             screen_state.battery().as_ref(),
             screen_state.remaining_time().as_ref(),
+            screen_state.ota().as_ref(),
+        )?,
+        Page::Valve(valve) => Valve::draw(
+            display,
+            page_changed,
+            &valve::CONFIG.get()[valve].display_name(valve),
+            screen_state.valve(valve).as_ref(),
+        )?,
+        Page::Meter(meter) => Meter::draw(
+            display,
+            page_changed,
+            &wm::CONFIG.get()[meter].display_name(meter),
+            screen_state.wm(meter).as_ref(),
+            screen_state.flow(meter).as_ref(),
         )?,
         Page::Battery => Battery::draw(display, page_changed, screen_state.battery().as_ref())?,
     }
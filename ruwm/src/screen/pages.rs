@@ -4,13 +4,17 @@ use embedded_graphics::{
     prelude::{DrawTarget, DrawTargetExt, Size},
     primitives::Rectangle,
 };
+pub use meter::*;
 pub use summary::*;
+pub use valve::*;
 
 use super::{shapes::Textbox, Color};
 
 pub mod actions;
 mod battery;
+mod meter;
 mod summary;
+mod valve;
 
 pub fn with_title<'a, T>(
     target: &'a mut T,
@@ -1,6 +1,12 @@
-use embedded_graphics::draw_target::DrawTarget;
+use core::fmt::Write;
 
-use crate::battery::BatteryState;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    prelude::{Dimensions, DrawTargetExt, Point, Size},
+    primitives::Rectangle,
+};
+
+use crate::battery::{BatteryState, Days};
 use crate::screen::shapes::{self, Color};
 
 use super::with_title;
@@ -19,11 +25,68 @@ impl Battery {
         let mut target = with_title(target, page_changed, "Battery")?;
 
         if let Some(state) = state {
+            let bbox = target.bounding_box();
+
+            let Size { width, height } = bbox.size;
+
+            let estimate_font = if width <= 128 {
+                profont::PROFONT_9_POINT
+            } else {
+                profont::PROFONT_14_POINT
+            };
+
+            let mut text_buf = heapless::String::<20>::new();
+
+            if let Some(remaining_days) = state.remaining_days {
+                write!(&mut text_buf, "{} left", Days(remaining_days)).unwrap();
+            }
+
+            let mut estimate_shape = shapes::Textbox {
+                text: "                    ",
+                color: Color::White,
+                font: estimate_font,
+                padding: 1,
+                outline: 0,
+                strikethrough: false,
+                ..Default::default()
+            };
+
+            // Clears the whole line, and only then the estimate is drawn centered
+            let line_size = estimate_shape.preferred_size();
+            let line_size = Size::new(width.min(line_size.width), line_size.height);
+
             shapes::Battery {
-                charged_percentage: state.percentage(),
+                charged_percentage: state.percentage,
                 ..Default::default()
             }
-            .draw(&mut target)?;
+            .draw(&mut target.cropped(&Rectangle::new(
+                bbox.top_left,
+                Size::new(width, height.saturating_sub(line_size.height)),
+            )))?;
+
+            let y_offs = bbox.top_left.y + height.saturating_sub(line_size.height) as i32;
+
+            estimate_shape.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(
+                    bbox.top_left.x + ((width - line_size.width) / 2) as i32,
+                    y_offs,
+                ),
+                line_size,
+            )))?;
+
+            if !text_buf.is_empty() {
+                estimate_shape.text = &text_buf;
+
+                let estimate_size = estimate_shape.preferred_size();
+
+                estimate_shape.draw(&mut target.cropped(&Rectangle::new(
+                    Point::new(
+                        bbox.top_left.x + (width.saturating_sub(estimate_size.width) / 2) as i32,
+                        y_offs,
+                    ),
+                    estimate_size,
+                )))?;
+            }
         }
 
         Ok(())
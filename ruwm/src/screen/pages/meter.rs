@@ -0,0 +1,97 @@
+use core::{cmp::min, fmt::Write};
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    prelude::{Dimensions, DrawTargetExt, Point, Size},
+    primitives::Rectangle,
+};
+
+use crate::flow::FlowState;
+use crate::screen::shapes::{self, Color};
+use crate::wm::WaterMeterState;
+
+use super::with_title;
+
+pub struct Meter;
+
+impl Meter {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        name: &str,
+        wm_state: Option<&WaterMeterState>,
+        flow_state: Option<&FlowState>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, name)?;
+
+        let bbox = target.bounding_box();
+
+        let Size { width, .. } = bbox.size;
+
+        let (main_font, flow_font) = if width <= 128 {
+            (profont::PROFONT_18_POINT, profont::PROFONT_9_POINT)
+        } else {
+            (profont::PROFONT_24_POINT, profont::PROFONT_14_POINT)
+        };
+
+        let mut y_offs = bbox.top_left.y;
+
+        let wm_shape = shapes::WaterMeterClassic::<8> {
+            edges_count: wm_state.map(|wm| wm.edges_count),
+            font: main_font,
+            ..Default::default()
+        };
+
+        if wm_state.is_some() {
+            wm_shape.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(
+                    ((width - wm_shape.preferred_size().width) / 2) as i32,
+                    y_offs,
+                ),
+                wm_shape.preferred_size(),
+            )))?;
+        }
+
+        y_offs += (wm_shape.preferred_size().height + 5) as i32;
+
+        let mut flow_shape = shapes::Textbox {
+            text: "              ",
+            color: Color::LightBlue,
+            font: flow_font,
+            padding: 1,
+            outline: 0,
+            strikethrough: false,
+            ..Default::default()
+        };
+
+        let flow_shape_size = flow_shape.preferred_size();
+
+        if let Some(flow_state) = flow_state {
+            let mut text_buf = heapless::String::<14>::new();
+
+            if wm_state.map(|wm| wm.leaking).unwrap_or(false) {
+                flow_shape.text = "Leak!";
+                flow_shape.color = Color::Red;
+            } else if flow_state.flowing {
+                write!(
+                    &mut text_buf,
+                    "Flow {}/h",
+                    min(flow_state.edges_per_hour, 9_999_999)
+                )
+                .unwrap();
+
+                flow_shape.text = &text_buf;
+            }
+
+            flow_shape.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(((width - flow_shape_size.width) / 2) as i32, y_offs),
+                flow_shape_size,
+            )))?;
+        }
+
+        Ok(())
+    }
+}
@@ -8,43 +8,62 @@ use embedded_graphics::{
 
 use gfx_xtra::draw_target::{DrawTargetExt2, RotateAngle};
 
+use crate::alert::{AlertKind, AlertState, AlertStatus};
 use crate::battery::BatteryState;
+use crate::flow::FlowState;
 use crate::keepalive::RemainingTime;
+use crate::moisture::SensorStates;
+use crate::ota::OtaState;
 use crate::screen::shapes::{self, BatteryChargedText, Color};
+use crate::temperature::{Celsius, FreezeStatus, TemperatureState};
 use crate::valve::ValveState;
 use crate::wm::WaterMeterState;
 
 pub struct Summary;
 
 impl Summary {
+    #[allow(clippy::too_many_arguments)]
     pub fn draw<D>(
         target: &mut D,
         _page_changed: bool,
         valve_state: Option<&Option<ValveState>>,
         wm_state: Option<&WaterMeterState>,
+        flow_state: Option<&FlowState>,
+        alert_state: Option<&AlertState>,
+        moisture_state: Option<&SensorStates>,
+        temperature_state: Option<&TemperatureState>,
         battery_state: Option<&BatteryState>,
         remaining_time_state: Option<&RemainingTime>,
+        ota_state: Option<&OtaState>,
     ) -> Result<(), D::Error>
     where
         D: DrawTarget<Color = Color>,
     {
         let bbox = target.bounding_box();
 
-        let top_height = Self::draw_top_status_line(target, battery_state)?;
-        let bottom_height = Self::draw_bottom_status_line(target, remaining_time_state)?;
+        let top_height = Self::draw_top_status_line(target, temperature_state, battery_state)?;
+        let bottom_height = Self::draw_bottom_status_line(target, remaining_time_state, ota_state)?;
 
         let content_rect = Rectangle::new(
             bbox.top_left + Size::new(0, top_height + 5),
             bbox.size - Size::new(0, top_height + bottom_height + 5),
         );
 
-        Self::draw_content(&mut target.cropped(&content_rect), valve_state, wm_state)?;
+        Self::draw_content(
+            &mut target.cropped(&content_rect),
+            valve_state,
+            wm_state,
+            flow_state,
+            alert_state,
+            moisture_state,
+        )?;
 
         Ok(())
     }
 
     fn draw_top_status_line<D>(
         target: &mut D,
+        temperature_state: Option<&TemperatureState>,
         battery_state: Option<&BatteryState>,
     ) -> Result<u32, D::Error>
     where
@@ -92,11 +111,42 @@ impl Summary {
             status_mqtt.preferred_size(),
         )))?;
 
-        //x_offs += (status_mqtt.preferred_size().width + status_padding) as i32;
+        x_offs += (status_mqtt.preferred_size().width + status_padding) as i32;
+
+        if let Some(temperature_state) = temperature_state {
+            let mut text_buf = heapless::String::<8>::new();
+
+            if let Some(temperature) = temperature_state.temperature {
+                write!(&mut text_buf, "{}C", Celsius(temperature)).unwrap();
+            }
+
+            let mut status_temperature = shapes::Textbox {
+                text: "      ",
+                color: match temperature_state.status {
+                    FreezeStatus::Normal => Color::LightBlue,
+                    FreezeStatus::Warning => Color::Yellow,
+                    FreezeStatus::Freezing => Color::Red,
+                },
+                font: status_font,
+                padding: 1,
+                outline: 0,
+                strikethrough: false,
+                ..Default::default()
+            };
+
+            let status_temperature_size = status_temperature.preferred_size();
+
+            status_temperature.text = &text_buf;
+
+            status_temperature.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(x_offs, y_offs),
+                status_temperature_size,
+            )))?;
+        }
 
         let status_battery_size = Size::new(status_height * 2, status_height);
         let status_battery = shapes::Battery {
-            charged_percentage: battery_state.and_then(|battery_state| battery_state.percentage()),
+            charged_percentage: battery_state.and_then(|battery_state| battery_state.percentage),
             text: BatteryChargedText::No,
             cathode: Size::new(status_height / 2, status_height / 4),
             padding: 1,
@@ -153,6 +203,9 @@ impl Summary {
         target: &mut D,
         valve_state: Option<&Option<ValveState>>,
         wm_state: Option<&WaterMeterState>,
+        flow_state: Option<&FlowState>,
+        alert_state: Option<&AlertState>,
+        moisture_state: Option<&SensorStates>,
     ) -> Result<(), D::Error>
     where
         D: DrawTarget<Color = Color>,
@@ -161,10 +214,10 @@ impl Summary {
 
         let Size { width, .. } = bbox.size;
 
-        let main_font = if width <= 128 {
-            profont::PROFONT_18_POINT
+        let (main_font, flow_font) = if width <= 128 {
+            (profont::PROFONT_18_POINT, profont::PROFONT_9_POINT)
         } else {
-            profont::PROFONT_24_POINT
+            (profont::PROFONT_24_POINT, profont::PROFONT_14_POINT)
         };
 
         let mut y_offs = bbox.top_left.y;
@@ -187,6 +240,68 @@ impl Summary {
 
         y_offs += (wm_shape.preferred_size().height + 5) as i32;
 
+        let mut flow_shape = shapes::Textbox {
+            text: "              ",
+            color: Color::LightBlue,
+            font: flow_font,
+            padding: 1,
+            outline: 0,
+            strikethrough: false,
+            ..Default::default()
+        };
+
+        let flow_shape_size = flow_shape.preferred_size();
+
+        if let Some(flow_state) = flow_state {
+            let mut text_buf = heapless::String::<14>::new();
+
+            let alert = alert_state.and_then(|alert_state| {
+                alert_state
+                    .with_status(AlertStatus::Raised)
+                    .map(|kind| (kind, Color::Red))
+                    .chain(
+                        alert_state
+                            .with_status(AlertStatus::Acknowledged)
+                            .map(|kind| (kind, Color::Yellow)),
+                    )
+                    .next()
+            });
+
+            let wet = moisture_state
+                .map(|moisture_state| moisture_state.iter().flatten().any(|state| state.is_wet()))
+                .unwrap_or(false);
+
+            if wet {
+                flow_shape.text = "Floor wet!";
+                flow_shape.color = Color::Red;
+            } else if let Some((kind, color)) = alert {
+                flow_shape.text = match kind {
+                    AlertKind::DailyBudget => "Day budget!",
+                    AlertKind::WeeklyBudget => "Week budget!",
+                    AlertKind::MonthlyBudget => "Month budget!",
+                    AlertKind::Draw => "Long draw!",
+                    AlertKind::PressureDrop => "Pressure drop!",
+                };
+                flow_shape.color = color;
+            } else if flow_state.flowing {
+                write!(
+                    &mut text_buf,
+                    "Flow {}/h",
+                    min(flow_state.edges_per_hour, 9_999_999)
+                )
+                .unwrap();
+
+                flow_shape.text = &text_buf;
+            }
+
+            flow_shape.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(((width - flow_shape_size.width) / 2) as i32, y_offs),
+                flow_shape_size,
+            )))?;
+        }
+
+        y_offs += (flow_shape_size.height + 5) as i32;
+
         if valve_state.is_some() {
             let main_height = bbox.bottom_right().unwrap().x - y_offs;
 
@@ -211,6 +326,7 @@ impl Summary {
     fn draw_bottom_status_line<D>(
         target: &mut D,
         remaining_time: Option<&RemainingTime>,
+        ota_state: Option<&OtaState>,
     ) -> Result<u32, D::Error>
     where
         D: DrawTarget<Color = Color>,
@@ -239,9 +355,17 @@ impl Summary {
             let status_rt_size = status_rt.preferred_size();
 
             let mut text_buf = heapless::String::<12>::new();
-            status_rt.text = match remaining_time {
-                RemainingTime::Indefinite => status_rt.text,
-                RemainingTime::Duration(duration) => {
+            status_rt.text = match (
+                remaining_time,
+                ota_state.and_then(|ota_state| ota_state.status.progress()),
+            ) {
+                (_, Some(progress)) => {
+                    write!(&mut text_buf, "Update {}%", progress).unwrap();
+
+                    &text_buf
+                }
+                (RemainingTime::Indefinite, _) => status_rt.text,
+                (RemainingTime::Duration(duration), _) => {
                     write!(&mut text_buf, "Sleep in {}s", min(duration.as_secs(), 99)).unwrap();
 
                     &text_buf
@@ -0,0 +1,32 @@
+use embedded_graphics::draw_target::DrawTarget;
+
+use crate::screen::shapes::{self, Color};
+use crate::valve::ValveState;
+
+use super::with_title;
+
+pub struct Valve;
+
+impl Valve {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        name: &str,
+        state: Option<&Option<ValveState>>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, name)?;
+
+        if let Some(state) = state {
+            shapes::Valve {
+                open_percentage: state.map(|state| state.open_percentage()),
+                ..Default::default()
+            }
+            .draw(&mut target)?;
+        }
+
+        Ok(())
+    }
+}
@@ -7,8 +7,10 @@ use embedded_graphics::primitives::Rectangle;
 use enumset::{EnumSet, EnumSetType};
 use valve::{ValveCommand, ValveState};
 
+use crate::alert::{self, AlertCommand, AlertStatus};
 use crate::dto::water_meter::WaterMeterCommand;
-use crate::{valve, wm};
+use crate::ota::{OtaCommand, OtaStatus};
+use crate::{ota, valve, wm};
 
 use super::util::{clear_cropped, fill, text};
 use super::Color;
@@ -18,8 +20,11 @@ pub enum Action {
     Dismiss,
     OpenValve,
     CloseValve,
+    OpenValvePartially,
     Arm,
     Disarm,
+    AcknowledgeAlerts,
+    ClearAlerts,
     CheckForUpdate,
     Update,
     Pair,
@@ -33,8 +38,11 @@ impl Action {
             Self::Dismiss => "Dismiss",
             Self::OpenValve => "Open Valve",
             Self::CloseValve => "Close Valve",
+            Self::OpenValvePartially => "Partial Open",
             Self::Arm => "Arm",
             Self::Disarm => "Disarm",
+            Self::AcknowledgeAlerts => "Ack Alerts",
+            Self::ClearAlerts => "Clear Alerts",
             Self::CheckForUpdate => "Check for Update",
             Self::Update => "Update",
             Self::Pair => "Pair",
@@ -82,10 +90,12 @@ impl Action {
             .find_map(|(index, action)| (index as i32 == cindex).then_some(action))
     }
 
-    pub fn active() -> EnumSet<Self> {
+    /// The actions which make sense in the current state, with the valve ones for that valve
+    /// and the water meter ones for that meter
+    pub fn active(valve: usize, meter: usize) -> EnumSet<Self> {
         let mut actions = EnumSet::empty();
 
-        let valve_state = valve::STATE.get();
+        let valve_state = valve::STATE.get()[valve];
 
         if !matches!(
             valve_state,
@@ -101,7 +111,13 @@ impl Action {
             actions |= Action::CloseValve;
         }
 
-        let wm_state = wm::STATE.get();
+        if let Some(partial) = valve::PROFILE.get()[valve].partial {
+            if valve_state != Some(ValveState::Partial(partial)) {
+                actions |= Action::OpenValvePartially;
+            }
+        }
+
+        let wm_state = wm::STATE.get()[meter];
 
         if !wm_state.armed {
             actions |= Action::Arm;
@@ -109,17 +125,45 @@ impl Action {
             actions |= Action::Disarm;
         }
 
+        let alert_state = alert::STATE.get();
+
+        if alert_state.is_raised() {
+            actions |= Action::AcknowledgeAlerts;
+        }
+
+        if alert_state.is_raised()
+            || alert_state
+                .with_status(AlertStatus::Acknowledged)
+                .next()
+                .is_some()
+        {
+            actions |= Action::ClearAlerts;
+        }
+
+        let ota_status = ota::STATE.get().status;
+
+        if !ota_status.is_busy() {
+            actions |= Action::CheckForUpdate;
+
+            if matches!(ota_status, OtaStatus::UpdateAvailable(_)) {
+                actions |= Action::Update;
+            }
+        }
+
         actions
     }
 
-    pub fn trigger(&self) {
+    pub fn trigger(&self, valve: usize, meter: usize) {
         match self {
-            Self::OpenValve => valve::COMMAND.signal(ValveCommand::Open),
-            Self::CloseValve => valve::COMMAND.signal(ValveCommand::Close),
-            Self::Arm => wm::COMMAND.signal(WaterMeterCommand::Arm),
-            Self::Disarm => wm::COMMAND.signal(WaterMeterCommand::Disarm),
-            // Self::CheckForUpdate => "Check for Update",
-            // Self::Update => "Update",
+            Self::OpenValve => valve::COMMAND[valve].signal(ValveCommand::Open),
+            Self::CloseValve => valve::COMMAND[valve].signal(ValveCommand::Close),
+            Self::OpenValvePartially => valve::COMMAND[valve].signal(ValveCommand::OpenPartially),
+            Self::Arm => wm::COMMAND[meter].signal(WaterMeterCommand::Arm),
+            Self::Disarm => wm::COMMAND[meter].signal(WaterMeterCommand::Disarm),
+            Self::AcknowledgeAlerts => alert::COMMAND.signal(AlertCommand::AcknowledgeAll),
+            Self::ClearAlerts => alert::COMMAND.signal(AlertCommand::ClearAll),
+            Self::CheckForUpdate => ota::COMMAND.signal(OtaCommand::CheckForUpdate),
+            Self::Update => ota::COMMAND.signal(OtaCommand::Update),
             // Self::Pair => "Pair",
             // Self::Provision => "Provision",
             // Self::Reprovision => "Reprovision",
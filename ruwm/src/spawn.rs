@@ -1,8 +1,9 @@
 use core::fmt::Debug;
 
-use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
+use embedded_svc::http::client::asynch::Connection as HttpConnection;
 use embedded_svc::mqtt::client::asynch::{Client, Connection, Publish};
 use embedded_svc::wifi::asynch::Wifi;
 use embedded_svc::ws::asynch::server::Acceptor;
@@ -13,29 +14,37 @@ use edge_executor::*;
 
 use channel_bridge::asynch::*;
 
-use valve::ValveState;
-use wm_stats::WaterMeterStatsState;
+use valve::{ValveConfigs, ValveDriver, ValveProfiles, ValveStates};
+use wm_stats::{CalendarStats, WaterMeterStatsStates};
 
-use crate::battery::Adc;
-use crate::button::{self, PressedLevel};
+use crate::alert::{self, AlertConfig, AlertState};
+use crate::away::{self, AwayConfig, AwayState};
+use crate::battery::{Adc, BatteryConfig, BatteryHistory};
+use crate::button::{self, ButtonTimings, PressedLevel};
+use crate::moisture::{self, SensorConfigs};
+use crate::ota::{self, Ota, SharedOta};
+use crate::power::{self, PowerPolicy, WakeReason};
+use crate::pressure::{self, PressureConfig};
 use crate::pulse_counter::{PulseCounter, PulseWakeup};
 use crate::screen::Color;
+use crate::temperature::{self, TemperatureConfig, TemperatureSensor};
+use crate::time::{self, Rtc, Sntp, TimeState, TimeZone};
 use crate::web::{self, WebEvent, WebRequest};
-use crate::wm::{self, WaterMeterState};
-use crate::{battery, emergency, keepalive, mqtt, screen, wm_stats, ws};
+use crate::wifi::WifiPolicy;
+use crate::wm::{self, MeterConfigs, WaterMeterState, WaterMeterStates};
+use crate::{battery, emergency, flow, keepalive, metrics, mqtt, screen, wm_stats, ws};
 use crate::{valve, wifi};
 
 #[allow(clippy::too_many_arguments)]
 pub fn high_prio<'a, const C: usize>(
     executor: &LocalExecutor<'a, C>,
-    valve_power_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_open_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_close_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_persister: impl FnMut(Option<ValveState>) + 'a,
+    wake_reason: WakeReason,
+    valve_driver: impl ValveDriver + 'a,
+    valve_persister: impl FnMut(ValveStates) + 'a,
     pulse_counter: impl PulseCounter + 'a,
     pulse_wakeup: impl PulseWakeup + 'a,
-    wm_persister: impl FnMut(WaterMeterState) + 'a,
-    wm_stats_persister: impl FnMut(WaterMeterStatsState) + 'a,
+    wm_persister: impl FnMut(WaterMeterStates) + 'a,
+    wm_stats_persister: impl FnMut(WaterMeterStatsStates) + 'a,
     battery_voltage: impl Adc + 'a,
     power_pin: impl InputPin + 'a,
     _roller: bool,
@@ -43,24 +52,16 @@ pub fn high_prio<'a, const C: usize>(
     button2_pin: impl InputPin<Error = impl Debug + 'a> + Wait + 'a,
     button3_pin: impl InputPin<Error = impl Debug + 'a> + Wait + 'a,
 ) {
-    executor.spawn(valve::process()).detach();
-
-    executor
-        .spawn(valve::spin(
-            valve_power_pin,
-            valve_open_pin,
-            valve_close_pin,
-        ))
-        .detach();
+    valve(executor, 0, valve_driver);
 
     executor.spawn(valve::persist(valve_persister)).detach();
 
-    executor
-        .spawn(wm::process(pulse_counter, pulse_wakeup))
-        .detach();
+    wm(executor, 0, pulse_counter, pulse_wakeup);
 
     executor.spawn(wm::persist(wm_persister)).detach();
 
+    executor.spawn(flow::process()).detach();
+
     executor
         .spawn(wm_stats::persist(wm_stats_persister))
         .detach();
@@ -69,13 +70,11 @@ pub fn high_prio<'a, const C: usize>(
         .spawn(battery::process(battery_voltage, power_pin))
         .detach();
 
-    executor
-        .spawn(button::button3_process(button3_pin, PressedLevel::Low))
-        .detach();
-
     executor.spawn(emergency::process()).detach();
 
-    executor.spawn(keepalive::process()).detach();
+    executor.spawn(keepalive::process(wake_reason)).detach();
+
+    executor.spawn(metrics::process()).detach();
 
     // if roller {
     //     executor
@@ -83,11 +82,12 @@ pub fn high_prio<'a, const C: usize>(
     //         .detach();
     // } else {
     executor
-        .spawn(button::button1_process(button1_pin, PressedLevel::Low))
-        .detach();
-
-    executor
-        .spawn(button::button2_process(button2_pin, PressedLevel::Low))
+        .spawn(button::process(
+            button1_pin,
+            button2_pin,
+            button3_pin,
+            PressedLevel::Low,
+        ))
         .detach();
     // }
 }
@@ -95,12 +95,13 @@ pub fn high_prio<'a, const C: usize>(
 pub fn low_prio<'a, const C: usize, D>(
     executor: &LocalExecutor<'a, C>,
     display: &'a mut D,
-    wm_flash: impl FnMut(WaterMeterState) + 'a,
+    wm_flash: impl FnMut(usize, WaterMeterState) + 'a,
+    wm_stats_flash: impl FnMut(usize, CalendarStats) + 'a,
 ) where
     D: Flushable<Color = Color> + 'a,
     D::Error: Debug,
 {
-    low_prio_common(executor, wm_flash);
+    low_prio_common(executor, wm_flash, wm_stats_flash);
 
     executor.spawn(screen::run_draw(display)).detach();
 }
@@ -108,31 +109,241 @@ pub fn low_prio<'a, const C: usize, D>(
 pub fn low_prio_owned<'a, const C: usize, D>(
     executor: &LocalExecutor<'a, C>,
     display: D,
-    wm_flash: impl FnMut(WaterMeterState) + 'a,
+    wm_flash: impl FnMut(usize, WaterMeterState) + 'a,
+    wm_stats_flash: impl FnMut(usize, CalendarStats) + 'a,
 ) where
     D: Flushable<Color = Color> + 'a,
     D::Error: Debug,
 {
-    low_prio_common(executor, wm_flash);
+    low_prio_common(executor, wm_flash, wm_stats_flash);
 
     executor.spawn(screen::run_draw_owned(display)).detach();
 }
 
 fn low_prio_common<'a, const C: usize>(
     executor: &LocalExecutor<'a, C>,
-    wm_flash: impl FnMut(WaterMeterState) + 'a,
+    wm_flash: impl FnMut(usize, WaterMeterState) + 'a,
+    wm_stats_flash: impl FnMut(usize, CalendarStats) + 'a,
 ) {
     executor.spawn(wm_stats::process()).detach();
 
     executor.spawn(screen::process()).detach();
 
     executor.spawn(wm::flash(wm_flash)).detach();
+
+    executor.spawn(wm_stats::flash(wm_stats_flash)).detach();
 }
 
 pub fn wifi<'a, const C: usize>(executor: &LocalExecutor<'a, C>, wifi: impl Wifi + 'a) {
     executor.spawn(wifi::process(wifi)).detach();
 }
 
+pub fn time<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    rtc: impl Rtc + 'a,
+    sntp: impl Sntp + 'a,
+    persister: impl FnMut(TimeState, TimeZone) + 'a,
+    flasher: impl FnMut(TimeZone) + 'a,
+) {
+    executor.spawn(time::process(rtc, sntp)).detach();
+
+    executor.spawn(time::persist(persister)).detach();
+
+    executor.spawn(time::flash(flasher)).detach();
+}
+
+pub fn alert<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    persister: impl FnMut(AlertState) + 'a,
+    flasher: impl FnMut(AlertConfig) + 'a,
+) {
+    executor.spawn(alert::process()).detach();
+
+    executor.spawn(alert::persist(persister)).detach();
+
+    executor.spawn(alert::flash(flasher)).detach();
+}
+
+pub fn away<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    persister: impl FnMut(AwayState) + 'a,
+    flasher: impl FnMut(AwayConfig) + 'a,
+) {
+    executor.spawn(away::process()).detach();
+
+    executor.spawn(away::persist(persister)).detach();
+
+    executor.spawn(away::flash(flasher)).detach();
+}
+
+/// The valve with that index; the main valve is spawned with the other high priority tasks
+pub fn valve<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    valve: usize,
+    driver: impl ValveDriver + 'a,
+) {
+    valve::set_driven(valve);
+
+    executor.spawn(valve::process(valve)).detach();
+
+    executor.spawn(valve::spin(valve, driver)).detach();
+}
+
+/// Optional; without a current sense the valve turns for the travel times of its profile only
+pub fn valve_current_sense<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    valve: usize,
+    current_sense: impl Adc + 'a,
+) {
+    executor
+        .spawn(valve::sense_current(valve, current_sense))
+        .detach();
+}
+
+pub fn valve_profile<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    persister: impl FnMut(ValveProfiles) + 'a,
+    flasher: impl FnMut(ValveProfiles) + 'a,
+) {
+    executor.spawn(valve::persist_profile(persister)).detach();
+
+    executor.spawn(valve::flash_profile(flasher)).detach();
+}
+
+pub fn valve_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(ValveConfigs) + 'a,
+) {
+    executor.spawn(valve::flash_config(flasher)).detach();
+}
+
+/// The water meter with that index; the main meter is spawned with the other high priority tasks
+pub fn wm<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    meter: usize,
+    pulse_counter: impl PulseCounter + 'a,
+    pulse_wakeup: impl PulseWakeup + 'a,
+) {
+    executor
+        .spawn(wm::process(meter, pulse_counter, pulse_wakeup))
+        .detach();
+}
+
+pub fn wm_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(MeterConfigs) + 'a,
+) {
+    executor.spawn(wm::flash_config(flasher)).detach();
+}
+
+/// A moisture probe which drives its pin to `wet_level` when wet
+pub fn moisture_digital<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    sensor: usize,
+    pin: impl InputPin + Wait + 'a,
+    wet_level: PressedLevel,
+) {
+    executor
+        .spawn(moisture::process_digital(sensor, pin, wet_level))
+        .detach();
+}
+
+/// A moisture sensor whose reading rises with the moisture
+pub fn moisture_analog<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    sensor: usize,
+    adc: impl Adc + 'a,
+) {
+    executor
+        .spawn(moisture::process_analog(sensor, adc))
+        .detach();
+}
+
+pub fn moisture_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(SensorConfigs) + 'a,
+) {
+    executor.spawn(moisture::flash_config(flasher)).detach();
+}
+
+/// Optional; without a sensor there is no freeze protection
+pub fn temperature<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    sensor: impl TemperatureSensor + 'a,
+) {
+    executor.spawn(temperature::process(sensor)).detach();
+}
+
+pub fn battery_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(BatteryConfig) + 'a,
+) {
+    executor.spawn(battery::flash(flasher)).detach();
+}
+
+pub fn power_policy<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(PowerPolicy) + 'a,
+) {
+    executor.spawn(power::flash(flasher)).detach();
+}
+
+pub fn wifi_policy<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(WifiPolicy) + 'a,
+) {
+    executor.spawn(wifi::flash(flasher)).detach();
+}
+
+pub fn button_timings<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(ButtonTimings) + 'a,
+) {
+    executor.spawn(button::flash(flasher)).detach();
+}
+
+pub fn battery_history<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    persister: impl FnMut(BatteryHistory) + 'a,
+    flasher: impl FnMut(BatteryHistory) + 'a,
+) {
+    executor.spawn(battery::persist_history(persister)).detach();
+
+    executor.spawn(battery::flash_history(flasher)).detach();
+}
+
+pub fn temperature_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(TemperatureConfig) + 'a,
+) {
+    executor.spawn(temperature::flash(flasher)).detach();
+}
+
+/// Optional; without a pressure transducer there is no pressure drop test
+pub fn pressure<'a, const C: usize>(executor: &LocalExecutor<'a, C>, adc: impl Adc + 'a) {
+    executor.spawn(pressure::process_sensor(adc)).detach();
+    executor.spawn(pressure::process()).detach();
+}
+
+pub fn pressure_config<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    flasher: impl FnMut(PressureConfig) + 'a,
+) {
+    executor.spawn(pressure::flash(flasher)).detach();
+}
+
+pub fn ota<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+    ota: &'a SharedOta<impl Ota + 'a>,
+    connection: impl HttpConnection + 'a,
+    manifest_url: Option<&'a str>,
+    public_key: Option<&'a [u8; 32]>,
+) {
+    executor
+        .spawn(ota::process(ota, connection, manifest_url, public_key))
+        .detach();
+}
+
 pub fn mqtt_send<'a, const L: usize, const C: usize>(
     executor: &LocalExecutor<'a, C>,
     mqtt_topic_prefix: &'a str,
@@ -0,0 +1,168 @@
+//! Freeze protection
+//!
+//! Below the warning threshold the temperature is only reported, while below the freezing
+//! threshold the valves are closed and the drain valve, if any, is opened.
+
+use core::cell::Cell;
+use core::fmt::Debug;
+
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+
+pub use crate::dto::temperature::*;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A temperature sensor, i.e. a 1-Wire or I2C one, or a thermistor on an ADC
+pub trait TemperatureSensor {
+    type Error: Debug;
+
+    /// The temperature in tenths of °C
+    async fn read(&mut self) -> Result<i16, Self::Error>;
+}
+
+impl<T> TemperatureSensor for &mut T
+where
+    T: TemperatureSensor,
+{
+    type Error = T::Error;
+
+    async fn read(&mut self) -> Result<i16, Self::Error> {
+        (*self).read().await
+    }
+}
+
+/// A sensor reading whatever temperature it is set to, for the simulator and for tests
+pub struct MockTemperatureSensor(Mutex<CriticalSectionRawMutex, Cell<Option<i16>>>);
+
+impl MockTemperatureSensor {
+    pub const fn new(temperature: i16) -> Self {
+        Self(Mutex::new(Cell::new(Some(temperature))))
+    }
+
+    /// Sets the temperature in tenths of °C; `None` makes the sensor fail
+    pub fn set(&self, temperature: Option<i16>) {
+        self.0.lock(|cell| cell.set(temperature));
+    }
+}
+
+impl TemperatureSensor for &MockTemperatureSensor {
+    type Error = ();
+
+    async fn read(&mut self) -> Result<i16, Self::Error> {
+        self.0.lock(Cell::get).ok_or(())
+    }
+}
+
+pub static STATE: State<TemperatureState> = State::new(
+    "TEMPERATURE",
+    TemperatureState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::TEMPERATURE_STATE_NOTIF,
+        &crate::screen::TEMPERATURE_STATE_NOTIF,
+        &crate::mqtt::TEMPERATURE_STATE_NOTIF,
+        &crate::web::TEMPERATURE_STATE_NOTIF,
+    ],
+);
+
+pub static CONFIG: State<TemperatureConfig> = State::new(
+    "TEMPERATURE CONFIG",
+    TemperatureConfig::new(),
+    &[
+        &crate::web::TEMPERATURE_CONFIG_STATE_NOTIF,
+        &CONFIG_NOTIFY,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
+static CONFIG_NOTIFY: Notification = Notification::new();
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+pub async fn process(mut sensor: impl TemperatureSensor) {
+    loop {
+        let temperature = sensor.read().await.ok();
+
+        STATE.update_with(|state| TemperatureState {
+            temperature,
+            // A failing sensor keeps the last status rather than lifting the policies
+            status: temperature
+                .map(|temperature| CONFIG.get().status(temperature, state.status))
+                .unwrap_or(state.status),
+        });
+
+        select(Timer::after(SAMPLE_INTERVAL), CONFIG_NOTIFY.wait()).await;
+    }
+}
+
+pub async fn flash(mut flasher: impl FnMut(TemperatureConfig)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select3, Either3};
+
+    use crate::emergency;
+    use crate::valve::{self, ValveCommand};
+
+    use super::*;
+
+    #[test]
+    fn status_follows_the_thresholds_with_hysteresis() {
+        use FreezeStatus::*;
+
+        let config = TemperatureConfig {
+            warn_below: Some(30),
+            freeze_below: Some(10),
+            drain: None,
+        };
+
+        assert_eq!(config.status(50, Normal), Normal);
+        assert_eq!(config.status(20, Normal), Warning);
+        assert_eq!(config.status(5, Normal), Freezing);
+        assert_eq!(config.status(-50, Warning), Freezing);
+
+        // Lifted only once the temperature rose above the threshold and the hysteresis
+        assert_eq!(config.status(15, Freezing), Freezing);
+        assert_eq!(config.status(20, Freezing), Warning);
+        assert_eq!(config.status(35, Warning), Warning);
+        assert_eq!(config.status(40, Warning), Normal);
+
+        let disabled = TemperatureConfig {
+            warn_below: None,
+            freeze_below: None,
+            drain: None,
+        };
+
+        assert_eq!(disabled.status(-200, Normal), Normal);
+    }
+
+    #[test]
+    fn freezing_closes_the_valves() {
+        static SENSOR: MockTemperatureSensor = MockTemperatureSensor::new(-50);
+
+        valve::set_driven(0);
+
+        let command = block_on(select3(
+            process(&SENSOR),
+            emergency::process(),
+            valve::COMMAND[0].wait(),
+        ));
+
+        assert!(matches!(command, Either3::Third(ValveCommand::Close)));
+        assert_eq!(STATE.get().temperature, Some(-50));
+        assert_eq!(STATE.get().status, FreezeStatus::Freezing);
+    }
+}
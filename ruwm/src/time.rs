@@ -0,0 +1,304 @@
+//! UTC wall-clock time which survives deep sleep
+//!
+//! `embassy_time::Instant` restarts from zero on every wakeup. The wall-clock time is instead read
+//! from the RTC once on startup, corrected for the RTC drift measured between SNTP syncs,
+//! and then advanced with `Instant` while awake.
+
+use core::cmp::min;
+use core::fmt::Debug;
+
+use log::{info, warn};
+
+use embassy_futures::select::select;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::wifi;
+
+pub use crate::dto::time::*;
+
+const SYNC_TIMEOUT: Duration = Duration::from_secs(15);
+const RESYNC_SECS: u64 = 6 * 60 * 60;
+
+// A failed sync is retried sooner, doubling the delay up to the maximum
+const RETRY_SECS: u64 = 30;
+const RETRY_MAX_SECS: u64 = 30 * 60;
+
+// Shorter intervals measure the sync latency rather than the drift
+const MIN_DRIFT_INTERVAL_SECS: u64 = 60 * 60;
+// Even the internal RC oscillator of the ESP32 is within 5%
+const MAX_DRIFT_PPM: i64 = 50_000;
+
+/// The platform real-time clock, which keeps running during deep sleep
+pub trait Rtc {
+    type Error: Debug;
+
+    /// Microseconds since the epoch; meaningless until set after a power loss
+    fn get(&self) -> Result<u64, Self::Error>;
+
+    fn set(&mut self, utc_micros: u64) -> Result<(), Self::Error>;
+}
+
+impl<T> Rtc for &mut T
+where
+    T: Rtc,
+{
+    type Error = T::Error;
+
+    fn get(&self) -> Result<u64, Self::Error> {
+        (**self).get()
+    }
+
+    fn set(&mut self, utc_micros: u64) -> Result<(), Self::Error> {
+        (*self).set(utc_micros)
+    }
+}
+
+pub trait Sntp {
+    type Error: Debug;
+
+    /// Queries the time servers and returns the microseconds since the epoch
+    async fn sync(&mut self) -> Result<u64, Self::Error>;
+}
+
+impl<T> Sntp for &mut T
+where
+    T: Sntp,
+{
+    type Error = T::Error;
+
+    async fn sync(&mut self) -> Result<u64, Self::Error> {
+        (*self).sync().await
+    }
+}
+
+pub static STATE: State<TimeState> = State::new(
+    "TIME",
+    TimeState::new(),
+    &[
+        &crate::away::TIME_STATE_NOTIF,
+        &crate::alert::TIME_STATE_NOTIF,
+        &crate::mqtt::TIME_STATE_NOTIF,
+        &crate::web::TIME_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+/// The time zone of the local time, i.e. of the consumption history periods
+pub static TIME_ZONE: State<TimeZone> = State::new(
+    "TIME ZONE",
+    TimeZone::UTC,
+    &[
+        &crate::away::TIME_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+        &TIME_ZONE_FLASH_NOTIFY,
+    ],
+);
+
+pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+static TIME_ZONE_FLASH_NOTIFY: Notification = Notification::new();
+
+/// The UTC time in microseconds at an `Instant` in microseconds
+static BASE: State<Option<(u64, u64)>> = State::new("TIME BASE", None, &[]);
+
+/// Seconds since the epoch, or `None` while the wall-clock time is not known
+pub fn now() -> Option<u64> {
+    now_micros().map(|micros| micros / 1_000_000)
+}
+
+/// Seconds since the epoch in the local time zone
+pub fn local_now() -> Option<i64> {
+    now().map(|secs| TIME_ZONE.get().local_secs(secs))
+}
+
+fn now_micros() -> Option<u64> {
+    if STATE.get().valid {
+        BASE.get()
+            .map(|(utc, instant)| utc + (Instant::now().as_micros() - instant))
+    } else {
+        None
+    }
+}
+
+pub async fn process(mut rtc: impl Rtc, mut sntp: impl Sntp) {
+    match rtc.get() {
+        Ok(rtc_micros) => start(rtc_micros),
+        Err(err) => warn!("Reading the RTC failed: {:?}", err),
+    }
+
+    let mut failures = 0;
+
+    loop {
+        let mut wait_secs = RESYNC_SECS;
+
+        if wifi::STATE.get().is_connected() && needs_sync() {
+            if sync(&mut rtc, &mut sntp).await {
+                failures = 0;
+            } else {
+                wait_secs = min(RETRY_SECS << min(failures, 8), RETRY_MAX_SECS);
+                failures += 1;
+            }
+        }
+
+        select(
+            WIFI_STATE_NOTIF.wait(),
+            Timer::after(Duration::from_secs(wait_secs)),
+        )
+        .await;
+    }
+}
+
+pub async fn persist(mut persister: impl FnMut(TimeState, TimeZone)) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        persister(STATE.get(), TIME_ZONE.get());
+    }
+}
+
+/// Flashes the time zone, which, unlike the rest of the time state, has to survive a power loss
+pub async fn flash(mut flasher: impl FnMut(TimeZone)) {
+    loop {
+        TIME_ZONE_FLASH_NOTIFY.wait().await;
+
+        flasher(TIME_ZONE.get());
+    }
+}
+
+fn start(rtc_micros: u64) {
+    let state = STATE.get();
+
+    let synced_micros = state.synced_secs.unwrap_or(0) * 1_000_000;
+
+    if state.valid && rtc_micros < synced_micros {
+        // The RTC was reset, i.e. by a brownout
+        STATE.update(TimeState {
+            valid: false,
+            ..state
+        });
+    } else if state.valid {
+        let correction =
+            state.drift_ppm.unwrap_or(0) as i64 * (rtc_micros - synced_micros) as i64 / 1_000_000;
+
+        BASE.set(Some((
+            (rtc_micros as i64 - correction) as u64,
+            Instant::now().as_micros(),
+        )));
+    }
+}
+
+fn needs_sync() -> bool {
+    let state = STATE.get();
+
+    match (now(), state.synced_secs) {
+        (Some(now), Some(synced_secs)) => now.saturating_sub(synced_secs) >= RESYNC_SECS,
+        _ => true,
+    }
+}
+
+async fn sync(rtc: &mut impl Rtc, sntp: &mut impl Sntp) -> bool {
+    let rtc_micros = rtc.get().ok();
+    let started = Instant::now();
+
+    let utc_micros = match with_timeout(SYNC_TIMEOUT, sntp.sync()).await {
+        Ok(Ok(utc_micros)) => utc_micros,
+        Ok(Err(err)) => {
+            warn!("SNTP sync failed: {:?}", err);
+            return false;
+        }
+        Err(_) => {
+            warn!("SNTP sync timed out");
+            return false;
+        }
+    };
+
+    let now = Instant::now();
+
+    let state = STATE.get();
+
+    let drift_ppm = rtc_micros
+        .filter(|_| state.valid)
+        .zip(state.synced_secs)
+        .and_then(|(rtc_micros, synced_secs)| {
+            let interval = utc_micros.checked_sub(synced_secs * 1_000_000)?;
+
+            (interval >= MIN_DRIFT_INTERVAL_SECS * 1_000_000).then(|| {
+                let rtc_micros = rtc_micros + (now - started).as_micros();
+                let error = rtc_micros as i64 - utc_micros as i64;
+
+                error as i128 * 1_000_000 / interval as i128
+            })
+        })
+        .filter(|drift_ppm| drift_ppm.abs() <= MAX_DRIFT_PPM as i128)
+        .map(|drift_ppm| match state.drift_ppm {
+            // Smooth out the jitter of the SNTP responses
+            Some(prev) => (prev as i128 * 3 + drift_ppm) / 4,
+            None => drift_ppm,
+        })
+        .map(|drift_ppm| drift_ppm as i32)
+        .or(state.drift_ppm);
+
+    if let Err(err) = rtc.set(utc_micros) {
+        warn!("Setting the RTC failed: {:?}", err);
+    }
+
+    BASE.set(Some((utc_micros, now.as_micros())));
+
+    STATE.update(TimeState {
+        valid: true,
+        synced_secs: Some(utc_micros / 1_000_000),
+        drift_ppm,
+    });
+
+    info!("Time synced, RTC drift: {:?} ppm", drift_ppm);
+
+    true
+}
+
+#[cfg(feature = "std")]
+pub use host::SystemClock;
+
+#[cfg(feature = "std")]
+mod host {
+    use std::io;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::{Rtc, Sntp};
+
+    /// The host system clock, which is already synced by the operating system
+    pub struct SystemClock;
+
+    impl SystemClock {
+        fn now() -> io::Result<u64> {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_micros() as u64)
+                .map_err(|_| io::ErrorKind::InvalidData.into())
+        }
+    }
+
+    impl Rtc for SystemClock {
+        type Error = io::Error;
+
+        fn get(&self) -> Result<u64, Self::Error> {
+            Self::now()
+        }
+
+        // Setting the host clock needs privileges, and it is right already anyway
+        fn set(&mut self, _utc_micros: u64) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl Sntp for SystemClock {
+        type Error = io::Error;
+
+        async fn sync(&mut self) -> Result<u64, Self::Error> {
+            Self::now()
+        }
+    }
+}
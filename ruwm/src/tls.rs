@@ -0,0 +1,234 @@
+//! Certificates for serving the web UI over TLS
+//!
+//! Devices without a user-provided certificate generate a self-signed one with a NIST P-256 key
+//! on first boot. Both the certificate and the key are DER encoded, which is what `mbedtls` and
+//! most other TLS stacks accept next to PEM.
+
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xa0;
+const TAG_CONTEXT_1: u8 = 0xa1;
+
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+// The device clock is not necessarily set when the certificate is generated,
+// so the validity is not derived from it
+const NOT_BEFORE: &[u8] = b"240101000000Z";
+const NOT_AFTER: &[u8] = b"99991231235959Z";
+
+pub const CERTIFICATE_MAX_LEN: usize = 512;
+pub const KEY_MAX_LEN: usize = 128;
+
+const PEM_CERTIFICATE_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+const PEM_KEY_BEGIN: &str = "-----BEGIN ";
+const PEM_KEY_END: &str = "PRIVATE KEY-----";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TlsError {
+    /// The secret is not a valid P-256 scalar; retry with another random one
+    Key,
+    BufferTooSmall,
+}
+
+/// Generates a self-signed certificate for `common_name`
+///
+/// `secret` and `serial` have to come from a cryptographically secure random number generator.
+/// Returns the lengths of the DER encoded certificate and private key written into the buffers.
+pub fn generate_self_signed(
+    secret: &[u8; 32],
+    serial: &[u8; 16],
+    common_name: &str,
+    certificate: &mut [u8],
+    key: &mut [u8],
+) -> Result<(usize, usize), TlsError> {
+    let signing_key = SigningKey::from_slice(secret).map_err(|_| TlsError::Key)?;
+
+    let public_key = signing_key.verifying_key().to_encoded_point(false);
+    let public_key = public_key.as_bytes();
+
+    // Positive and without a leading zero
+    let mut serial = *serial;
+    serial[0] = (serial[0] & 0x7f) | 0x40;
+
+    let mut der = Der::new(certificate);
+
+    der.tlv(TAG_SEQUENCE, |der| {
+        let tbs_start = der.len;
+
+        der.tlv(TAG_SEQUENCE, |der| {
+            der.tlv(TAG_CONTEXT_0, |der| der.put(TAG_INTEGER, &[2]))?;
+            der.put(TAG_INTEGER, &serial)?;
+            der.tlv(TAG_SEQUENCE, |der| der.put(TAG_OID, OID_ECDSA_WITH_SHA256))?;
+            der.name(common_name)?;
+            der.tlv(TAG_SEQUENCE, |der| {
+                der.put(TAG_UTC_TIME, NOT_BEFORE)?;
+                der.put(TAG_GENERALIZED_TIME, NOT_AFTER)
+            })?;
+            der.name(common_name)?;
+            der.tlv(TAG_SEQUENCE, |der| {
+                der.tlv(TAG_SEQUENCE, |der| {
+                    der.put(TAG_OID, OID_EC_PUBLIC_KEY)?;
+                    der.put(TAG_OID, OID_PRIME256V1)
+                })?;
+                der.bit_string(public_key)
+            })
+        })?;
+
+        let signature: Signature = signing_key.sign(&der.buf[tbs_start..der.len]);
+        let (r, s) = signature.split_bytes();
+
+        der.tlv(TAG_SEQUENCE, |der| der.put(TAG_OID, OID_ECDSA_WITH_SHA256))?;
+        der.tlv(TAG_BIT_STRING, |der| {
+            der.raw(&[0])?;
+            der.tlv(TAG_SEQUENCE, |der| {
+                der.unsigned(&r)?;
+                der.unsigned(&s)
+            })
+        })
+    })?;
+
+    let certificate_len = der.len;
+
+    // SEC1 `ECPrivateKey`
+    let mut der = Der::new(key);
+
+    der.tlv(TAG_SEQUENCE, |der| {
+        der.put(TAG_INTEGER, &[1])?;
+        der.put(TAG_OCTET_STRING, secret)?;
+        der.tlv(TAG_CONTEXT_0, |der| der.put(TAG_OID, OID_PRIME256V1))?;
+        der.tlv(TAG_CONTEXT_1, |der| der.bit_string(public_key))
+    })?;
+
+    Ok((certificate_len, der.len))
+}
+
+/// Splits an uploaded PEM bundle into its certificate (chain) and its private key
+///
+/// The key can be in any of the PEM private key formats (i.e. PKCS#8 or SEC1)
+pub fn split_pem_bundle(bundle: &str) -> Option<(&str, &str)> {
+    let certificate_start = bundle.find(PEM_CERTIFICATE_BEGIN)?;
+    let certificate_end = bundle.rfind(PEM_CERTIFICATE_END)? + PEM_CERTIFICATE_END.len();
+
+    let key_end = bundle.find(PEM_KEY_END)?;
+    let key_start = bundle[..key_end].rfind(PEM_KEY_BEGIN)?;
+    let key_end = key_start + bundle[key_start..].rfind(PEM_KEY_END)? + PEM_KEY_END.len();
+
+    (certificate_start < certificate_end).then_some((
+        &bundle[certificate_start..certificate_end],
+        &bundle[key_start..key_end],
+    ))
+}
+
+struct Der<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Der<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn tlv(
+        &mut self,
+        tag: u8,
+        content: impl FnOnce(&mut Self) -> Result<(), TlsError>,
+    ) -> Result<(), TlsError> {
+        // Reserve room for the longest length encoding and move the content back afterwards
+        const HEADER_MAX_LEN: usize = 4;
+
+        let start = self.len;
+
+        self.raw(&[0; HEADER_MAX_LEN])?;
+        content(self)?;
+
+        let content_len = self.len - start - HEADER_MAX_LEN;
+
+        let mut header = [tag, 0, 0, 0];
+        let header_len = if content_len < 0x80 {
+            header[1] = content_len as u8;
+            2
+        } else if content_len < 0x100 {
+            header[1] = 0x81;
+            header[2] = content_len as u8;
+            3
+        } else {
+            header[1] = 0x82;
+            header[2..4].copy_from_slice(&(content_len as u16).to_be_bytes());
+            4
+        };
+
+        self.buf[start..start + header_len].copy_from_slice(&header[..header_len]);
+        self.buf
+            .copy_within(start + HEADER_MAX_LEN..self.len, start + header_len);
+
+        self.len -= HEADER_MAX_LEN - header_len;
+
+        Ok(())
+    }
+
+    fn put(&mut self, tag: u8, content: &[u8]) -> Result<(), TlsError> {
+        self.tlv(tag, |der| der.raw(content))
+    }
+
+    fn bit_string(&mut self, content: &[u8]) -> Result<(), TlsError> {
+        self.tlv(TAG_BIT_STRING, |der| {
+            der.raw(&[0])?;
+            der.raw(content)
+        })
+    }
+
+    fn unsigned(&mut self, value: &[u8]) -> Result<(), TlsError> {
+        let start = value
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(value.len() - 1);
+        let value = &value[start..];
+
+        self.tlv(TAG_INTEGER, |der| {
+            if value[0] & 0x80 != 0 {
+                der.raw(&[0])?;
+            }
+
+            der.raw(value)
+        })
+    }
+
+    fn name(&mut self, common_name: &str) -> Result<(), TlsError> {
+        self.tlv(TAG_SEQUENCE, |der| {
+            der.tlv(TAG_SET, |der| {
+                der.tlv(TAG_SEQUENCE, |der| {
+                    der.put(TAG_OID, OID_COMMON_NAME)?;
+                    der.put(TAG_UTF8_STRING, common_name.as_bytes())
+                })
+            })
+        })
+    }
+
+    fn raw(&mut self, data: &[u8]) -> Result<(), TlsError> {
+        let end = self.len + data.len();
+
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(TlsError::BufferTooSmall)?
+            .copy_from_slice(data);
+
+        self.len = end;
+
+        Ok(())
+    }
+}
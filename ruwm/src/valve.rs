@@ -1,189 +1,740 @@
+use core::cell::Cell;
+use core::cmp::min;
 use core::fmt::Debug;
-use core::future::pending;
 
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::signal::Signal;
 
 use embedded_hal::delay::DelayNs;
-use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::{Error, ErrorKind, OutputPin};
 
 use channel_bridge::notification::Notification;
 
+use crate::battery::Adc;
 use crate::state::State;
 
 pub use crate::dto::valve::*;
 
-pub const TURN_TICKS: usize = 20;
-pub const TICK_DELAY: Duration = Duration::from_secs(1);
+/// The actuator of the valve
+///
+/// A turn starts with `Some` command and ends with `None` once the travel time of the profile
+/// elapsed, or the turn was interrupted. The driver keeps whatever the valve needs to stay where
+/// it is when the turn ends.
+pub trait ValveDriver {
+    type Error: Debug;
 
-pub static STATE: State<Option<ValveState>> = State::new(
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error>;
+
+    /// How long to drive a valve which is switched by a pulse, after which it is driven with
+    /// `None` even though the turn goes on for the travel time of the profile
+    fn pulse(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Restores what the valve needs to stay in its persisted state, as the pins start low
+    /// after a restart; a driver which keeps nothing at rest has nothing to restore
+    fn restore(&mut self, _state: Option<ValveState>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<T> ValveDriver for &mut T
+where
+    T: ValveDriver,
+{
+    type Error = T::Error;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        (*self).drive(command)
+    }
+
+    fn pulse(&self) -> Option<Duration> {
+        (**self).pulse()
+    }
+
+    fn restore(&mut self, state: Option<ValveState>) -> Result<(), Self::Error> {
+        (*self).restore(state)
+    }
+}
+
+/// A motor behind an H-bridge, with a power line and a line for each direction
+pub struct HBridgeDriver<P, O, C> {
+    power_pin: P,
+    open_pin: O,
+    close_pin: C,
+}
+
+impl<P, O, C> HBridgeDriver<P, O, C> {
+    pub const fn new(power_pin: P, open_pin: O, close_pin: C) -> Self {
+        Self {
+            power_pin,
+            open_pin,
+            close_pin,
+        }
+    }
+}
+
+impl<P, O, C> ValveDriver for HBridgeDriver<P, O, C>
+where
+    P: OutputPin,
+    O: OutputPin,
+    C: OutputPin,
+{
+    type Error = ErrorKind;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        match command {
+            Some(ValveCommand::Open) | Some(ValveCommand::OpenPartially) => {
+                self.close_pin.set_low().map_err(|e| e.kind())?;
+                self.open_pin.set_high().map_err(|e| e.kind())?;
+                self.power_pin.set_high().map_err(|e| e.kind())?;
+            }
+            Some(ValveCommand::Close) => {
+                self.open_pin.set_low().map_err(|e| e.kind())?;
+                self.close_pin.set_high().map_err(|e| e.kind())?;
+                self.power_pin.set_high().map_err(|e| e.kind())?;
+            }
+            None => {
+                self.power_pin.set_low().map_err(|e| e.kind())?;
+                self.open_pin.set_low().map_err(|e| e.kind())?;
+                self.close_pin.set_low().map_err(|e| e.kind())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A normally-open solenoid, which closes while its coil is energized
+///
+/// The coil stays energized, and draws its hold current, for as long as the valve is closed.
+pub struct SolenoidDriver<P> {
+    coil_pin: P,
+}
+
+impl<P> SolenoidDriver<P> {
+    pub const fn new(coil_pin: P) -> Self {
+        Self { coil_pin }
+    }
+}
+
+impl<P> ValveDriver for SolenoidDriver<P>
+where
+    P: OutputPin,
+{
+    type Error = P::Error;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        match command {
+            Some(ValveCommand::Close) => self.coil_pin.set_high(),
+            Some(_) => self.coil_pin.set_low(),
+            None => Ok(()),
+        }
+    }
+
+    fn restore(&mut self, state: Option<ValveState>) -> Result<(), Self::Error> {
+        match state {
+            Some(ValveState::Closed) | Some(ValveState::Closing(_)) => self.coil_pin.set_high(),
+            _ => self.coil_pin.set_low(),
+        }
+    }
+}
+
+/// A latching solenoid, which is switched by a pulse on the coil of each direction
+///
+/// The coil only stands a short pulse, typically tens of milliseconds, so it is not driven
+/// for the travel time of the profile.
+pub struct LatchingSolenoidDriver<O, C> {
+    open_pin: O,
+    close_pin: C,
+    pulse: Duration,
+}
+
+impl<O, C> LatchingSolenoidDriver<O, C> {
+    pub const fn new(open_pin: O, close_pin: C, pulse: Duration) -> Self {
+        Self {
+            open_pin,
+            close_pin,
+            pulse,
+        }
+    }
+}
+
+impl<O, C> ValveDriver for LatchingSolenoidDriver<O, C>
+where
+    O: OutputPin,
+    C: OutputPin,
+{
+    type Error = ErrorKind;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        match command {
+            Some(ValveCommand::Close) => {
+                self.open_pin.set_low().map_err(|e| e.kind())?;
+                self.close_pin.set_high().map_err(|e| e.kind())?;
+            }
+            Some(_) => {
+                self.close_pin.set_low().map_err(|e| e.kind())?;
+                self.open_pin.set_high().map_err(|e| e.kind())?;
+            }
+            None => {
+                self.open_pin.set_low().map_err(|e| e.kind())?;
+                self.close_pin.set_low().map_err(|e| e.kind())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pulse(&self) -> Option<Duration> {
+        Some(self.pulse)
+    }
+}
+
+/// A valve with its own controller and a single control line, high for open
+pub struct SingleLineDriver<P> {
+    pin: P,
+}
+
+impl<P> SingleLineDriver<P> {
+    pub const fn new(pin: P) -> Self {
+        Self { pin }
+    }
+}
+
+impl<P> ValveDriver for SingleLineDriver<P>
+where
+    P: OutputPin,
+{
+    type Error = P::Error;
+
+    fn drive(&mut self, command: Option<ValveCommand>) -> Result<(), Self::Error> {
+        match command {
+            Some(ValveCommand::Close) => self.pin.set_low(),
+            Some(_) => self.pin.set_high(),
+            None => Ok(()),
+        }
+    }
+
+    fn restore(&mut self, state: Option<ValveState>) -> Result<(), Self::Error> {
+        match state {
+            Some(ValveState::Closed) | Some(ValveState::Closing(_)) | None => self.pin.set_low(),
+            Some(_) => self.pin.set_high(),
+        }
+    }
+}
+
+/// The granularity of the progress updates while the valve turns
+const STEP_PERCENTAGE: u8 = 5;
+
+const CURRENT_SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+pub type ValveStates = [Option<ValveState>; MAX_VALVES];
+pub type ValveProfiles = [ValveProfile; MAX_VALVES];
+pub type ValveFaults = [Option<ValveFault>; MAX_VALVES];
+pub type ValveConfigs = [ValveConfig; MAX_VALVES];
+
+pub static STATE: State<ValveStates> = State::new(
     "VALVE",
-    None,
+    [None; MAX_VALVES],
     &[
         &crate::keepalive::NOTIF,
         &crate::emergency::VALVE_STATE_NOTIF,
         &crate::screen::VALVE_STATE_NOTIF,
         &crate::mqtt::VALVE_STATE_NOTIF,
         &crate::web::VALVE_STATE_NOTIF,
+        &crate::metrics::VALVE_STATE_NOTIF,
+        &crate::pressure::VALVE_STATE_NOTIF,
         &STATE_PERSIST_NOTIFY,
     ],
 );
 
+pub static PROFILE: State<ValveProfiles> = State::new(
+    "VALVE PROFILE",
+    [ValveProfile::DEFAULT; MAX_VALVES],
+    &[
+        &crate::web::VALVE_PROFILE_STATE_NOTIF,
+        &PROFILE_PERSIST_NOTIFY,
+        &PROFILE_FLASH_NOTIFY,
+    ],
+);
+
+/// The fault of the last turn of each valve, if it failed even after a retry
+pub static FAULT: State<ValveFaults> = State::new(
+    "VALVE FAULT",
+    [None; MAX_VALVES],
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::mqtt::VALVE_FAULT_STATE_NOTIF,
+        &crate::web::VALVE_FAULT_STATE_NOTIF,
+    ],
+);
+
+pub static CONFIG: State<ValveConfigs> = State::new(
+    "VALVE CONFIG",
+    [
+        ValveConfig::new(true),
+        ValveConfig::new(false),
+        ValveConfig::new(false),
+    ],
+    &[
+        &crate::screen::VALVE_CONFIG_STATE_NOTIF,
+        &crate::web::VALVE_CONFIG_STATE_NOTIF,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+static PROFILE_PERSIST_NOTIFY: Notification = Notification::new();
+static PROFILE_FLASH_NOTIFY: Notification = Notification::new();
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+#[allow(clippy::declare_interior_mutable_const)]
+const COMMAND_SIGNAL: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const TARGET_SIGNAL: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const STATE_SIGNAL: Signal<CriticalSectionRawMutex, Option<ValveState>> = Signal::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const RUNNING_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const FAULT_SIGNAL: Signal<CriticalSectionRawMutex, ValveFault> = Signal::new();
+
+pub(crate) static COMMAND: [Signal<CriticalSectionRawMutex, ValveCommand>; MAX_VALVES] =
+    [COMMAND_SIGNAL; MAX_VALVES];
 
-pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
+/// The target open percentage
+static SPIN_COMMAND: [Signal<CriticalSectionRawMutex, u8>; MAX_VALVES] =
+    [TARGET_SIGNAL; MAX_VALVES];
+static SPIN_WORKING: [Signal<CriticalSectionRawMutex, Option<ValveState>>; MAX_VALVES] =
+    [STATE_SIGNAL; MAX_VALVES];
 
-static SPIN_COMMAND: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
-static SPIN_WORKING: Signal<CriticalSectionRawMutex, Option<u8>> = Signal::new();
+static MOTOR_RUNNING: [Signal<CriticalSectionRawMutex, bool>; MAX_VALVES] =
+    [RUNNING_SIGNAL; MAX_VALVES];
+static MOTOR_FAULT: [Signal<CriticalSectionRawMutex, ValveFault>; MAX_VALVES] =
+    [FAULT_SIGNAL; MAX_VALVES];
 
+/// The valves which have a driver, i.e. which were spawned
+static DRIVEN: Mutex<CriticalSectionRawMutex, Cell<[bool; MAX_VALVES]>> =
+    Mutex::new(Cell::new([false; MAX_VALVES]));
+
+pub(crate) fn set_driven(valve: usize) {
+    DRIVEN.lock(|driven| {
+        let mut valves = driven.get();
+        valves[valve] = true;
+        driven.set(valves);
+    });
+}
+
+fn driven(valve: usize) -> bool {
+    DRIVEN.lock(|driven| driven.get()[valve])
+}
+
+/// Applies a new configuration to a valve; the main valve stays enabled, while a valve without
+/// a driver cannot be enabled
+pub fn configure(valve: usize, mut config: ValveConfig) {
+    if config.enabled && !driven(valve) {
+        log::warn!("Valve {} has no driver, keeping it disabled", valve);
+        config.enabled = false;
+    }
+
+    config.enabled |= valve == 0;
+
+    CONFIG.update_with(|mut configs| {
+        configs[valve] = config;
+        configs
+    });
+}
+
+/// The enabled valves, with their index
+///
+/// A valve enabled by a configuration from another board, but without a driver on this one,
+/// is left out
+pub fn enabled() -> impl Iterator<Item = (usize, ValveConfig)> {
+    CONFIG
+        .get()
+        .into_iter()
+        .enumerate()
+        .filter(|(valve, config)| config.enabled && driven(*valve))
+}
+
+/// Closes the valve without an executor, i.e. before anything else on a wakeup by a leak
+///
+/// The profile has to come from memory which survives the deep sleep, as the persisted
+/// one is not loaded yet at that point
 pub fn emergency_close(
-    power_pin: &mut impl OutputPin<Error = impl Debug>,
-    open_pin: &mut impl OutputPin<Error = impl Debug>,
-    close_pin: &mut impl OutputPin<Error = impl Debug>,
+    driver: &mut impl ValveDriver,
+    profile: &ValveProfile,
     delay: &mut impl DelayNs,
 ) {
     log::error!("Start: emergency closing valve due to ULP wakeup...");
 
-    start_spin(Some(ValveCommand::Close), power_pin, open_pin, close_pin);
+    driver.drive(Some(ValveCommand::Close)).unwrap();
+
+    let duration = driver
+        .pulse()
+        .unwrap_or(Duration::from_millis(profile.close_ms as _));
 
-    delay.delay_ms((TICK_DELAY.as_secs() * 1000 * TURN_TICKS as u64) as u32);
+    delay.delay_ms(duration.as_millis() as _);
 
-    start_spin(None, power_pin, open_pin, close_pin);
+    driver.drive(None).unwrap();
 
     log::error!("End: emergency closing valve due to ULP wakeup");
 }
 
-pub async fn process() {
+/// Processes the commands of the valve with that index
+pub async fn process(valve: usize) {
     loop {
         let current_state = {
-            match select(COMMAND.wait(), SPIN_WORKING.wait()).await {
-                Either::First(command) => match command {
-                    ValveCommand::Open => {
-                        let state = STATE.get();
-
-                        if !matches!(state, Some(ValveState::Open) | Some(ValveState::Opening(_))) {
-                            SPIN_COMMAND.signal(ValveCommand::Open);
-                            Some(ValveState::Opening(0))
-                        } else {
-                            state
-                        }
-                    }
-                    ValveCommand::Close => {
-                        let state = STATE.get();
-
-                        if !matches!(
-                            state,
-                            Some(ValveState::Closed) | Some(ValveState::Closing(_))
-                        ) {
-                            SPIN_COMMAND.signal(ValveCommand::Close);
-                            Some(ValveState::Closing(0))
-                        } else {
-                            state
-                        }
-                    }
-                },
-                Either::Second(progress) => {
-                    let state = STATE.get();
-
-                    if let Some(progress) = progress {
-                        match state {
-                            Some(ValveState::Opening(_)) => Some(ValveState::Opening(progress)),
-                            Some(ValveState::Closing(_)) => Some(ValveState::Closing(progress)),
-                            _ => None,
-                        }
-                    } else {
-                        match state {
-                            Some(ValveState::Opening(_)) => Some(ValveState::Open),
-                            Some(ValveState::Closing(_)) => Some(ValveState::Closed),
-                            state => state,
+            match select(COMMAND[valve].wait(), SPIN_WORKING[valve].wait()).await {
+                Either::First(command) => {
+                    let state = STATE.get()[valve];
+
+                    let target = match command {
+                        ValveCommand::Open => Some(100),
+                        ValveCommand::Close => Some(0),
+                        ValveCommand::OpenPartially => PROFILE.get()[valve].partial,
+                    };
+
+                    match target {
+                        Some(target) if !heading_to(state, target) => {
+                            SPIN_COMMAND[valve].signal(target);
+
+                            let position = state.and_then(|state| state.position());
+
+                            Some(match position {
+                                Some(position) if target > position => {
+                                    moving(ValveCommand::Open, position)
+                                }
+                                Some(position) => moving(ValveCommand::Close, position),
+                                None if target == 100 => moving(ValveCommand::Open, 0),
+                                None => moving(ValveCommand::Close, 100),
+                            })
                         }
+                        _ => state,
                     }
                 }
+                Either::Second(state) => state,
             }
         };
 
-        STATE.update(current_state);
+        STATE.update_with(|mut states| {
+            states[valve] = current_state;
+            states
+        });
     }
 }
 
-pub async fn spin(
-    mut power_pin: impl OutputPin<Error = impl Debug>,
-    mut open_pin: impl OutputPin<Error = impl Debug>,
-    mut close_pin: impl OutputPin<Error = impl Debug>,
-) {
-    let mut current_command: Option<ValveCommand> = None;
-    let mut remaining_ticks: usize = 0;
+/// Turns the valve with that index
+pub async fn spin(valve: usize, mut driver: impl ValveDriver) {
+    let mut position = STATE.get()[valve].and_then(|state| state.position());
+    // The direction of the last turn and when it stopped, for the dead time before reversing
+    let mut stopped: Option<(ValveCommand, Instant)> = None;
+    let mut next_target = None;
+    // Whether the turn to the current target was already retried after a jam
+    let mut retried = false;
+
+    driver.restore(STATE.get()[valve]).unwrap();
 
     loop {
-        start_spin(
-            current_command,
-            &mut power_pin,
-            &mut open_pin,
-            &mut close_pin,
-        );
+        driver.drive(None).unwrap();
+
+        let target = match next_target.take() {
+            Some(target) => target,
+            None => {
+                retried = false;
+
+                SPIN_COMMAND[valve].wait().await
+            }
+        };
 
-        let command = SPIN_COMMAND.wait();
+        let profile = PROFILE.get()[valve];
+        let dead_time = Duration::from_millis(profile.dead_time_ms as _);
+
+        // Without a known position only the end stops can be reached reliably,
+        // so a partial position is approached from the closed one
+        let (from, to) = match position {
+            Some(position) => (position, target),
+            None if target == 100 => (0, 100),
+            None => (100, 0),
+        };
 
-        let timer = if current_command.is_some() {
-            futures::future::Either::Left(Timer::after(TICK_DELAY))
+        if from == to {
+            SPIN_WORKING[valve].signal(Some(at_rest(to)));
+            continue;
+        }
+
+        let command = if to > from {
+            ValveCommand::Open
         } else {
-            futures::future::Either::Right(pending())
+            ValveCommand::Close
+        };
+
+        if let Some((stopped_command, stopped_at)) = stopped {
+            let elapsed = Instant::now() - stopped_at;
+
+            if stopped_command != command && elapsed < dead_time {
+                if let Either::First(target) = select(
+                    SPIN_COMMAND[valve].wait(),
+                    Timer::after(dead_time - elapsed),
+                )
+                .await
+                {
+                    next_target = Some(target);
+                    retried = false;
+                    continue;
+                }
+            }
+        }
+
+        start(&mut driver, command).await;
+
+        MOTOR_FAULT[valve].reset();
+        MOTOR_RUNNING[valve].signal(true);
+
+        let percentage_ms = profile.travel_ms(command) as u64 / 100;
+        let mut current = from;
+
+        let turn = loop {
+            let step = min(STEP_PERCENTAGE, current.abs_diff(to));
+
+            match select3(
+                SPIN_COMMAND[valve].wait(),
+                Timer::after(Duration::from_millis(percentage_ms * step as u64)),
+                MOTOR_FAULT[valve].wait(),
+            )
+            .await
+            {
+                Either3::First(target) => break Turn::Interrupted(target),
+                Either3::Second(_) => {
+                    current = step_towards(command, current, step);
+
+                    if current == to {
+                        break Turn::Done;
+                    }
+
+                    SPIN_WORKING[valve].signal(Some(moving(command, current)));
+                }
+                Either3::Third(ValveFault::Stalled) if to == 0 || to == 100 => {
+                    // The end stop came early, which also makes the position known again
+                    current = to;
+                    break Turn::Done;
+                }
+                Either3::Third(fault) => break Turn::Jammed(fault),
+            }
         };
 
-        match select(command, timer).await {
-            Either::First(command) => {
-                current_command = Some(command);
-                remaining_ticks = TURN_TICKS;
+        driver.drive(None).unwrap();
+
+        MOTOR_RUNNING[valve].signal(false);
+
+        stopped = Some((command, Instant::now()));
+
+        match turn {
+            Turn::Interrupted(interrupted_target) => {
+                // Only an estimate, as the step in progress is lost
+                position = position.map(|_| current);
+                next_target = Some(interrupted_target);
+                retried = false;
             }
-            Either::Second(_) => {
-                if remaining_ticks > 0 {
-                    remaining_ticks -= 1;
+            Turn::Done => {
+                position = Some(current);
+
+                if to == target {
+                    set_fault(valve, None);
+                    SPIN_WORKING[valve].signal(Some(at_rest(to)));
                 } else {
-                    current_command = None;
+                    next_target = Some(target);
                 }
+            }
+            Turn::Jammed(fault) if !retried => {
+                log::warn!("Valve {} jammed ({}), retrying", valve, fault.name());
 
-                SPIN_WORKING.signal(if remaining_ticks > 0 {
-                    Some((100 - remaining_ticks * 100 / TURN_TICKS) as u8)
+                retried = true;
+
+                // Back off for a step in the opposite direction, then try again
+                let back_off = if command == ValveCommand::Close {
+                    ValveCommand::Open
                 } else {
-                    None
-                });
+                    ValveCommand::Close
+                };
+
+                Timer::after(dead_time).await;
+
+                start(&mut driver, back_off).await;
+
+                Timer::after(Duration::from_millis(
+                    profile.travel_ms(back_off) as u64 / 100 * STEP_PERCENTAGE as u64,
+                ))
+                .await;
+
+                driver.drive(None).unwrap();
+
+                stopped = Some((back_off, Instant::now()));
+
+                position = position.map(|_| step_towards(back_off, current, STEP_PERCENTAGE));
+                next_target = Some(target);
+            }
+            Turn::Jammed(fault) => {
+                log::error!("Valve {} jammed ({}), giving up", valve, fault.name());
+
+                position = None;
+
+                set_fault(valve, Some(fault));
+                SPIN_WORKING[valve].signal(None);
             }
         }
     }
 }
 
-fn start_spin(
-    command: Option<ValveCommand>,
-    power_pin: &mut impl OutputPin<Error = impl Debug>,
-    open_pin: &mut impl OutputPin<Error = impl Debug>,
-    close_pin: &mut impl OutputPin<Error = impl Debug>,
-) {
-    match command {
-        Some(ValveCommand::Open) => {
-            close_pin.set_low().unwrap();
-            open_pin.set_high().unwrap();
-            power_pin.set_high().unwrap();
+/// Starts a turn, which for a valve switched by a pulse already ends driving it
+async fn start(driver: &mut impl ValveDriver, command: ValveCommand) {
+    driver.drive(Some(command)).unwrap();
+
+    if let Some(pulse) = driver.pulse() {
+        Timer::after(pulse).await;
+
+        driver.drive(None).unwrap();
+    }
+}
+
+/// Samples the motor current while the valve with that index turns, and reports a stall
+/// or an overcurrent according to the current limits of its profile
+pub async fn sense_current(valve: usize, mut adc: impl Adc) {
+    let mut running = false;
+
+    loop {
+        if !running {
+            running = MOTOR_RUNNING[valve].wait().await;
+            continue;
         }
-        Some(ValveCommand::Close) => {
-            open_pin.set_low().unwrap();
-            close_pin.set_high().unwrap();
-            power_pin.set_high().unwrap();
+
+        let Some(limits) = PROFILE.get()[valve].current_limits else {
+            running = MOTOR_RUNNING[valve].wait().await;
+            continue;
+        };
+
+        // The inrush current of a starting motor is way above the limits
+        if let Either::First(new_running) = select(
+            MOTOR_RUNNING[valve].wait(),
+            Timer::after(Duration::from_millis(limits.inrush_ms as _)),
+        )
+        .await
+        {
+            running = new_running;
+            continue;
         }
-        None => {
-            power_pin.set_low().unwrap();
-            open_pin.set_low().unwrap();
-            close_pin.set_low().unwrap();
+
+        loop {
+            match select(
+                MOTOR_RUNNING[valve].wait(),
+                Timer::after(CURRENT_SAMPLE_INTERVAL),
+            )
+            .await
+            {
+                Either::First(new_running) => {
+                    running = new_running;
+                    break;
+                }
+                Either::Second(_) => {
+                    let fault = match adc.read().await {
+                        Ok(current) if current >= limits.overcurrent => {
+                            Some(ValveFault::Overcurrent)
+                        }
+                        Ok(current) if current >= limits.stall => Some(ValveFault::Stalled),
+                        _ => None,
+                    };
+
+                    if let Some(fault) = fault {
+                        MOTOR_FAULT[valve].signal(fault);
+
+                        // One report per run of the motor
+                        running = MOTOR_RUNNING[valve].wait().await;
+                        break;
+                    }
+                }
+            }
         }
-    };
+    }
+}
+
+fn set_fault(valve: usize, fault: Option<ValveFault>) {
+    FAULT.update_with(|mut faults| {
+        faults[valve] = fault;
+        faults
+    });
+}
+
+enum Turn {
+    Done,
+    Interrupted(u8),
+    Jammed(ValveFault),
+}
+
+fn step_towards(command: ValveCommand, position: u8, step: u8) -> u8 {
+    if command == ValveCommand::Close {
+        position.saturating_sub(step)
+    } else {
+        min(position + step, 100)
+    }
+}
+
+fn heading_to(state: Option<ValveState>, target: u8) -> bool {
+    match state {
+        Some(ValveState::Open) | Some(ValveState::Opening(_)) => target == 100,
+        Some(ValveState::Closed) | Some(ValveState::Closing(_)) => target == 0,
+        Some(ValveState::Partial(position)) => target == position,
+        None => false,
+    }
 }
 
-pub async fn persist(mut persister: impl FnMut(Option<ValveState>)) {
+fn moving(command: ValveCommand, position: u8) -> ValveState {
+    match command {
+        ValveCommand::Close => ValveState::Closing(100 - position),
+        _ => ValveState::Opening(position),
+    }
+}
+
+fn at_rest(position: u8) -> ValveState {
+    match position {
+        100 => ValveState::Open,
+        0 => ValveState::Closed,
+        position => ValveState::Partial(position),
+    }
+}
+
+pub async fn persist(mut persister: impl FnMut(ValveStates)) {
     loop {
         STATE_PERSIST_NOTIFY.wait().await;
 
         persister(STATE.get());
     }
 }
+
+pub async fn persist_profile(mut persister: impl FnMut(ValveProfiles)) {
+    loop {
+        PROFILE_PERSIST_NOTIFY.wait().await;
+
+        persister(PROFILE.get());
+    }
+}
+
+pub async fn flash_profile(mut flasher: impl FnMut(ValveProfiles)) {
+    loop {
+        PROFILE_FLASH_NOTIFY.wait().await;
+
+        flasher(PROFILE.get());
+    }
+}
+
+pub async fn flash_config(mut flasher: impl FnMut(ValveConfigs)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
+    }
+}
@@ -1,11 +1,15 @@
 use core::cell::Cell;
+use core::str;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 
 use channel_bridge::asynch::*;
 use channel_bridge::notification::Notification;
 
 use edge_frame::dto::Role;
 
-use embassy_futures::select::{select, select4};
+use embassy_futures::select::{select, select3, select4};
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex, RawMutex};
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::mutex::Mutex as AsyncMutex;
@@ -14,21 +18,58 @@ use embassy_sync::signal::Signal;
 use futures::FutureExt;
 use log::info;
 
+use crate::alert;
+use crate::away;
 use crate::battery;
+use crate::button;
+use crate::flow;
+use crate::moisture;
+use crate::ota;
+use crate::power::{self, SleepBlocker};
+use crate::pressure;
+use crate::quit::{self, Participant};
 use crate::state::State;
+use crate::temperature;
+use crate::time;
 use crate::utils::select::EitherUnwrap;
 use crate::valve;
+use crate::wifi;
 use crate::wm;
 
 pub use crate::dto::web::*;
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static VALVE_PROFILE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static VALVE_FAULT_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static VALVE_CONFIG_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WM_CONFIG_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static FLOW_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BATTERY_CONFIG_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static REMAINING_TIME_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static MQTT_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static OTA_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERT_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERT_CONFIG_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static AWAY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static AWAY_CONFIG_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static MOISTURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static MOISTURE_CONFIG_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TEMPERATURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TEMPERATURE_CONFIG_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static PRESSURE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static PRESSURE_CONFIG_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static POWER_POLICY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BUTTON_TIMINGS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WIFI_POLICY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static TIME_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static SHUTDOWN_NOTIF: Notification = Notification::new();
+
+/// The number of connected web clients
+pub static CLIENTS: State<usize> = State::new("WEB CLIENTS", 0, &[&crate::keepalive::NOTIF]);
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum AuthEvent {
@@ -48,6 +89,35 @@ impl AuthEvent {
     }
 }
 
+/// The notifications of a single web client about the state changes it should be sent
+pub struct StateNotifs<'a> {
+    pub valve: &'a Notification,
+    pub valve_profile: &'a Notification,
+    pub valve_fault: &'a Notification,
+    pub valve_config: &'a Notification,
+    pub wm: &'a Notification,
+    pub wm_config: &'a Notification,
+    pub flow: &'a Notification,
+    pub battery: &'a Notification,
+    pub battery_config: &'a Notification,
+    pub ota: &'a Notification,
+    pub alert: &'a Notification,
+    pub alert_config: &'a Notification,
+    pub away: &'a Notification,
+    pub away_config: &'a Notification,
+    pub moisture: &'a Notification,
+    pub moisture_config: &'a Notification,
+    pub temperature: &'a Notification,
+    pub temperature_config: &'a Notification,
+    pub pressure: &'a Notification,
+    pub pressure_config: &'a Notification,
+    pub power_policy: &'a Notification,
+    pub button_timings: &'a Notification,
+    pub wifi_policy: &'a Notification,
+    pub time: &'a Notification,
+    pub shutdown: &'a Notification,
+}
+
 pub async fn process<S, R>(sender: S, receiver: R)
 where
     S: Sender<Data = WebEvent>,
@@ -56,49 +126,267 @@ where
     handle(
         sender,
         receiver,
-        &VALVE_STATE_NOTIF,
-        &WM_STATE_NOTIF,
-        &BATTERY_STATE_NOTIF,
+        Role::None,
+        StateNotifs {
+            valve: &VALVE_STATE_NOTIF,
+            valve_profile: &VALVE_PROFILE_STATE_NOTIF,
+            valve_fault: &VALVE_FAULT_STATE_NOTIF,
+            valve_config: &VALVE_CONFIG_STATE_NOTIF,
+            wm: &WM_STATE_NOTIF,
+            wm_config: &WM_CONFIG_STATE_NOTIF,
+            flow: &FLOW_STATE_NOTIF,
+            battery: &BATTERY_STATE_NOTIF,
+            battery_config: &BATTERY_CONFIG_STATE_NOTIF,
+            ota: &OTA_STATE_NOTIF,
+            alert: &ALERT_STATE_NOTIF,
+            alert_config: &ALERT_CONFIG_STATE_NOTIF,
+            away: &AWAY_STATE_NOTIF,
+            away_config: &AWAY_CONFIG_STATE_NOTIF,
+            moisture: &MOISTURE_STATE_NOTIF,
+            moisture_config: &MOISTURE_CONFIG_STATE_NOTIF,
+            temperature: &TEMPERATURE_STATE_NOTIF,
+            temperature_config: &TEMPERATURE_CONFIG_STATE_NOTIF,
+            pressure: &PRESSURE_STATE_NOTIF,
+            pressure_config: &PRESSURE_CONFIG_STATE_NOTIF,
+            power_policy: &POWER_POLICY_STATE_NOTIF,
+            button_timings: &BUTTON_TIMINGS_STATE_NOTIF,
+            wifi_policy: &WIFI_POLICY_STATE_NOTIF,
+            time: &TIME_STATE_NOTIF,
+            shutdown: &SHUTDOWN_NOTIF,
+        },
     )
     .await
     .unwrap();
 }
 
+/// Serves a web client until it disconnects
+///
+/// Clients which are already authenticated by other means (i.e. HTTP auth) start with `role`,
+/// others start unauthenticated and have to send `WebRequest::Authenticate`
 pub async fn handle<S, R>(
     sender: S,
     receiver: R,
-    valve_state_notif: &Notification,
-    wm_state_notif: &Notification,
-    battery_state_notif: &Notification,
+    role: Role,
+    notifs: StateNotifs<'_>,
 ) -> Result<(), R::Error>
 where
     S: Sender<Data = WebEvent>,
     R: Receiver<Data = Option<WebRequest>, Error = S::Error>,
 {
-    let role = Mutex::<NoopRawMutex, _>::new(Cell::new(Role::None));
     let auth_signal = Signal::<CriticalSectionRawMutex, _>::new();
 
+    auth_signal.signal(if role == Role::None {
+        AuthEvent::Connected
+    } else {
+        AuthEvent::Authenticated(role)
+    });
+
+    let role = Mutex::<NoopRawMutex, _>::new(Cell::new(role));
+
     let sender = AsyncMutex::<NoopRawMutex, _>::new(sender);
 
-    auth_signal.signal(AuthEvent::Connected);
+    power::register_blocker(SleepBlocker::WebClient, || {
+        power::POLICY.get().web_keeps_awake && CLIENTS.get() > 0
+    });
+
+    let _client = Client::connect();
 
-    select(
+    select3(
         receive(receiver, &role, &auth_signal),
-        select4(
+        process_shutdown(&sender, &role, notifs.shutdown),
+        select(
             process_auth_event(&sender, &auth_signal),
-            process_state_update(&sender, &role, &valve::STATE, valve_state_notif, |state| {
-                WebEvent::ValveState(state)
-            }),
-            process_state_update(&sender, &role, &wm::STATE, wm_state_notif, |state| {
-                WebEvent::WaterMeterState(state)
-            }),
-            process_state_update(
-                &sender,
-                &role,
-                &battery::STATE,
-                battery_state_notif,
-                WebEvent::BatteryState,
-            ),
+            select3(
+                select4(
+                    select4(
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &valve::STATE,
+                            notifs.valve,
+                            WebEvent::ValveState,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &valve::PROFILE,
+                            notifs.valve_profile,
+                            WebEvent::ValveProfile,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &valve::FAULT,
+                            notifs.valve_fault,
+                            WebEvent::ValveFault,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &valve::CONFIG,
+                            notifs.valve_config,
+                            WebEvent::ValveConfig,
+                        ),
+                    )
+                    .map(EitherUnwrap::unwrap),
+                    select3(
+                        process_state_update(&sender, &role, &wm::STATE, notifs.wm, |state| {
+                            WebEvent::WaterMeterState(state)
+                        }),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &wm::CONFIG,
+                            notifs.wm_config,
+                            WebEvent::WaterMeterConfig,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &flow::STATE,
+                            notifs.flow,
+                            WebEvent::FlowState,
+                        ),
+                    )
+                    .map(EitherUnwrap::unwrap),
+                    select3(
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &battery::STATE,
+                            notifs.battery,
+                            WebEvent::BatteryState,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &alert::STATE,
+                            notifs.alert,
+                            WebEvent::AlertState,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &alert::CONFIG,
+                            notifs.alert_config,
+                            WebEvent::AlertConfig,
+                        ),
+                    )
+                    .map(EitherUnwrap::unwrap),
+                    select4(
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &ota::STATE,
+                            notifs.ota,
+                            WebEvent::OtaState,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &away::STATE,
+                            notifs.away,
+                            WebEvent::AwayState,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &away::CONFIG,
+                            notifs.away_config,
+                            WebEvent::AwayConfig,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &time::STATE,
+                            notifs.time,
+                            WebEvent::TimeState,
+                        ),
+                    )
+                    .map(EitherUnwrap::unwrap),
+                )
+                .map(EitherUnwrap::unwrap),
+                select4(
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &moisture::STATE,
+                        notifs.moisture,
+                        WebEvent::MoistureState,
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &moisture::CONFIG,
+                        notifs.moisture_config,
+                        WebEvent::MoistureConfig,
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &temperature::STATE,
+                        notifs.temperature,
+                        WebEvent::TemperatureState,
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &temperature::CONFIG,
+                        notifs.temperature_config,
+                        WebEvent::TemperatureConfig,
+                    ),
+                )
+                .map(EitherUnwrap::unwrap),
+                select4(
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &pressure::STATE,
+                        notifs.pressure,
+                        WebEvent::PressureState,
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &pressure::CONFIG,
+                        notifs.pressure_config,
+                        WebEvent::PressureConfig,
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &battery::CONFIG,
+                        notifs.battery_config,
+                        WebEvent::BatteryConfig,
+                    ),
+                    select3(
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &power::POLICY,
+                            notifs.power_policy,
+                            WebEvent::PowerPolicy,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &button::TIMINGS,
+                            notifs.button_timings,
+                            WebEvent::ButtonTimings,
+                        ),
+                        process_state_update(
+                            &sender,
+                            &role,
+                            &wifi::POLICY,
+                            notifs.wifi_policy,
+                            WebEvent::WifiPolicy,
+                        ),
+                    )
+                    .map(EitherUnwrap::unwrap),
+                )
+                .map(EitherUnwrap::unwrap),
+            )
+            .map(EitherUnwrap::unwrap),
         )
         .map(EitherUnwrap::unwrap),
     )
@@ -106,6 +394,26 @@ where
     .unwrap()
 }
 
+/// A connected client, which is counted and takes part in the shutdown until it is dropped,
+/// even when its connection is dropped midway or fails
+struct Client;
+
+impl Client {
+    fn connect() -> Self {
+        CLIENTS.update_with(|clients| clients + 1);
+        quit::register(Participant::Web);
+
+        Self
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        quit::unregister(Participant::Web);
+        CLIENTS.update_with(|clients| clients - 1);
+    }
+}
+
 async fn receive<R>(
     mut receiver: R,
     role: &Mutex<impl RawMutex, Cell<Role>>,
@@ -121,12 +429,72 @@ where
         if let Some(request) = request {
             let new_auth_event = if request.role() <= role.lock(Cell::get) {
                 match request {
-                    WebRequest::ValveCommand(command) => {
-                        valve::COMMAND.signal(command);
+                    WebRequest::ValveCommand(index, command) if index < valve::MAX_VALVES => {
+                        valve::COMMAND[index].signal(command);
                         None
                     }
-                    WebRequest::WaterMeterCommand(command) => {
-                        wm::COMMAND.signal(command);
+                    WebRequest::ValveProfile(index, profile) if index < valve::MAX_VALVES => {
+                        valve::PROFILE.update_with(|mut profiles| {
+                            profiles[index] = profile;
+                            profiles
+                        });
+                        None
+                    }
+                    WebRequest::ValveConfig(index, config) if index < valve::MAX_VALVES => {
+                        valve::configure(index, config);
+                        None
+                    }
+                    WebRequest::ValveCommand(..)
+                    | WebRequest::ValveProfile(..)
+                    | WebRequest::ValveConfig(..) => None,
+                    WebRequest::WaterMeterCommand(index, command) if index < wm::MAX_METERS => {
+                        wm::COMMAND[index].signal(command);
+                        None
+                    }
+                    WebRequest::WaterMeterConfig(index, config) if index < wm::MAX_METERS => {
+                        wm::configure(index, config);
+                        None
+                    }
+                    WebRequest::WaterMeterCommand(..) | WebRequest::WaterMeterConfig(..) => None,
+                    WebRequest::MoistureConfig(index, config) if index < moisture::MAX_SENSORS => {
+                        moisture::configure(index, config);
+                        None
+                    }
+                    WebRequest::MoistureConfig(..) => None,
+                    WebRequest::TemperatureConfig(config) => {
+                        temperature::CONFIG.update(config);
+                        None
+                    }
+                    WebRequest::PressureCommand(command) => {
+                        pressure::COMMAND.signal(command);
+                        None
+                    }
+                    WebRequest::BatteryConfig(config) => {
+                        battery::CONFIG.update(config);
+                        None
+                    }
+                    WebRequest::PowerPolicy(policy) => {
+                        power::POLICY.update(policy);
+                        None
+                    }
+                    WebRequest::ButtonTimings(timings) => {
+                        button::TIMINGS.update(timings);
+                        None
+                    }
+                    WebRequest::WifiPolicy(policy) => {
+                        wifi::POLICY.update(policy);
+                        None
+                    }
+                    WebRequest::OtaCommand(command) => {
+                        ota::COMMAND.signal(command);
+                        None
+                    }
+                    WebRequest::AlertCommand(command) => {
+                        alert::COMMAND.signal(command);
+                        None
+                    }
+                    WebRequest::AwayCommand(command) => {
+                        away::COMMAND.signal(command);
                         None
                     }
                     WebRequest::Authenticate(username, password) => {
@@ -158,6 +526,24 @@ where
     Ok(())
 }
 
+async fn process_shutdown<S>(
+    sender: &AsyncMutex<impl RawMutex, S>,
+    role: &Mutex<impl RawMutex, Cell<Role>>,
+    shutdown_notif: &Notification,
+) -> Result<(), S::Error>
+where
+    S: Sender<Data = WebEvent>,
+{
+    loop {
+        shutdown_notif.wait().await;
+
+        // Whatever changed last might still be waiting to be sent
+        send_states(sender, role.lock(Cell::get)).await?;
+
+        quit::flushed(Participant::Web);
+    }
+}
+
 async fn process_auth_event<'a, S>(
     sender: &AsyncMutex<impl RawMutex, S>,
     auth_signal: &Signal<CriticalSectionRawMutex, AuthEvent>,
@@ -176,27 +562,84 @@ where
 
         send_event(sender, web_event, event.role()).await?;
 
-        send_event(
-            sender,
-            WebEvent::ValveState(valve::STATE.get()),
-            event.role(),
-        )
-        .await?;
+        send_states(sender, event.role()).await?;
+    }
+}
 
-        send_event(
-            sender,
-            WebEvent::WaterMeterState(wm::STATE.get()),
-            event.role(),
-        )
-        .await?;
+/// Sends the current state of everything the role can see
+async fn send_states<S>(sender: &AsyncMutex<impl RawMutex, S>, role: Role) -> Result<(), S::Error>
+where
+    S: Sender<Data = WebEvent>,
+{
+    send_event(sender, WebEvent::ValveState(valve::STATE.get()), role).await?;
 
-        send_event(
-            sender,
-            WebEvent::BatteryState(battery::STATE.get()),
-            event.role(),
-        )
-        .await?;
-    }
+    send_event(sender, WebEvent::ValveProfile(valve::PROFILE.get()), role).await?;
+
+    send_event(sender, WebEvent::ValveFault(valve::FAULT.get()), role).await?;
+
+    send_event(sender, WebEvent::ValveConfig(valve::CONFIG.get()), role).await?;
+
+    send_event(sender, WebEvent::WaterMeterState(wm::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::WaterMeterConfig(wm::CONFIG.get()), role).await?;
+
+    send_event(sender, WebEvent::FlowState(flow::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::MoistureState(moisture::STATE.get()), role).await?;
+
+    send_event(
+        sender,
+        WebEvent::MoistureConfig(moisture::CONFIG.get()),
+        role,
+    )
+    .await?;
+
+    send_event(
+        sender,
+        WebEvent::TemperatureState(temperature::STATE.get()),
+        role,
+    )
+    .await?;
+
+    send_event(
+        sender,
+        WebEvent::TemperatureConfig(temperature::CONFIG.get()),
+        role,
+    )
+    .await?;
+
+    send_event(sender, WebEvent::PressureState(pressure::STATE.get()), role).await?;
+
+    send_event(
+        sender,
+        WebEvent::PressureConfig(pressure::CONFIG.get()),
+        role,
+    )
+    .await?;
+
+    send_event(sender, WebEvent::BatteryState(battery::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::BatteryConfig(battery::CONFIG.get()), role).await?;
+
+    send_event(sender, WebEvent::PowerPolicy(power::POLICY.get()), role).await?;
+
+    send_event(sender, WebEvent::ButtonTimings(button::TIMINGS.get()), role).await?;
+
+    send_event(sender, WebEvent::WifiPolicy(wifi::POLICY.get()), role).await?;
+
+    send_event(sender, WebEvent::OtaState(ota::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::AlertState(alert::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::AlertConfig(alert::CONFIG.get()), role).await?;
+
+    send_event(sender, WebEvent::AwayState(away::STATE.get()), role).await?;
+
+    send_event(sender, WebEvent::AwayConfig(away::CONFIG.get()), role).await?;
+
+    send_event(sender, WebEvent::TimeState(time::STATE.get()), role).await?;
+
+    Ok(())
 }
 
 async fn process_state_update<'a, S, T>(
@@ -236,6 +679,113 @@ where
     }
 }
 
-fn authenticate(_username: &str, _password: &str) -> Option<Role> {
-    Some(Role::Admin) // TODO
+/// An account which can log in over the websocket and over HTTP basic auth
+#[derive(Copy, Clone, Debug)]
+pub struct Credentials {
+    pub username: &'static str,
+    /// The account is disabled without a password
+    pub password: Option<&'static str>,
+    pub role: Role,
+}
+
+impl Credentials {
+    pub const fn new(username: &'static str, password: Option<&'static str>, role: Role) -> Self {
+        Self {
+            username,
+            password,
+            role,
+        }
+    }
+}
+
+static CREDENTIALS: Mutex<CriticalSectionRawMutex, Cell<&'static [Credentials]>> =
+    Mutex::new(Cell::new(&[]));
+
+/// Sets the accounts which can log in; until then, nobody can
+pub fn set_credentials(credentials: &'static [Credentials]) {
+    CREDENTIALS.lock(|cell| cell.set(credentials));
+}
+
+/// Resolves the role of a plain HTTP request from its `Authorization` header
+///
+/// HTTP basic auth is checked against the same credentials as the websocket authentication,
+/// while a bearer token matching `api_token` grants the admin role
+pub fn http_role(authorization: Option<&str>, api_token: Option<&str>) -> Role {
+    let Some(authorization) = authorization else {
+        return Role::None;
+    };
+
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return match api_token {
+            Some(api_token) if constant_time_eq(token.trim().as_bytes(), api_token.as_bytes()) => {
+                Role::Admin
+            }
+            _ => Role::None,
+        };
+    }
+
+    let Some(credentials) = authorization.strip_prefix("Basic ") else {
+        return Role::None;
+    };
+
+    let mut buf = [0; USERNAME_MAX_LEN + PASSWORD_MAX_LEN + 1];
+
+    STANDARD
+        .decode_slice(credentials.trim(), &mut buf)
+        .ok()
+        .and_then(|len| str::from_utf8(&buf[..len]).ok())
+        .and_then(|credentials| credentials.split_once(':'))
+        .and_then(|(username, password)| authenticate(username, password))
+        .unwrap_or(Role::None)
+}
+
+// Do not leak through timing how much of a token matches
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn authenticate(username: &str, password: &str) -> Option<Role> {
+    CREDENTIALS
+        .lock(Cell::get)
+        .iter()
+        .find(|credentials| {
+            // Both are compared, so that the timing does not tell whether the username exists
+            let username_eq =
+                constant_time_eq(username.as_bytes(), credentials.username.as_bytes());
+            let password_eq = credentials
+                .password
+                .is_some_and(|expected| constant_time_eq(password.as_bytes(), expected.as_bytes()));
+
+            username_eq & password_eq
+        })
+        .map(|credentials| credentials.role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CREDENTIALS: [Credentials; 3] = [
+        Credentials::new("admin", Some("secret"), Role::Admin),
+        Credentials::new("user", Some("password"), Role::User),
+        Credentials::new("guest", None, Role::User),
+    ];
+
+    #[test]
+    fn http_role_checks_the_credentials() {
+        set_credentials(&CREDENTIALS);
+
+        let role = |authorization| http_role(Some(authorization), Some("token"));
+
+        // admin:secret, user:password, user:secret and guest:
+        assert_eq!(role("Basic YWRtaW46c2VjcmV0"), Role::Admin);
+        assert_eq!(role("Basic dXNlcjpwYXNzd29yZA=="), Role::User);
+        assert_eq!(role("Basic dXNlcjpzZWNyZXQ="), Role::None);
+        assert_eq!(role("Basic Z3Vlc3Q6"), Role::None);
+
+        assert_eq!(role("Bearer token"), Role::Admin);
+        assert_eq!(role("Bearer secret"), Role::None);
+        assert_eq!(http_role(Some("Bearer token"), None), Role::None);
+        assert_eq!(http_role(None, Some("token")), Role::None);
+    }
 }
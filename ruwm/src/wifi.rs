@@ -2,96 +2,308 @@ use core::fmt::Debug;
 
 use embassy_futures::select::{select, Either};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
-use embassy_time::{Duration, Timer};
-use log::info;
+use embassy_time::{with_timeout, Duration, Timer};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-use embedded_svc::wifi::{asynch::Wifi, AuthMethod, Configuration};
+use heapless::Vec;
 
+use embedded_svc::wifi::{
+    asynch::Wifi, AccessPointConfiguration, ClientConfiguration, Configuration,
+};
+
+use channel_bridge::notification::Notification;
+
+use crate::battery;
 use crate::state::State;
 
+pub use crate::dto::wifi::*;
+
+pub const MAX_KNOWN_NETWORKS: usize = 4;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+const CONNECTED_POLL: Duration = Duration::from_secs(1);
+
+#[allow(clippy::large_enum_variant)]
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub enum WifiCommand {
     SetConfiguration(Configuration),
+    /// Client networks to try, in priority order
+    SetKnownNetworks(Vec<ClientConfiguration, MAX_KNOWN_NETWORKS>),
 }
 
-pub static STATE: State<Option<bool>> = State::new(
+pub static STATE: State<WifiState> = State::new(
     "WIFI",
-    None,
+    WifiState::Idle,
     &[
-        &crate::keepalive::NOTIF,
         &crate::screen::WIFI_STATE_NOTIF,
         &crate::mqtt::WIFI_STATE_NOTIF,
         &crate::web::WIFI_STATE_NOTIF,
+        &crate::time::WIFI_STATE_NOTIF,
     ],
 );
 
+pub static POLICY: State<WifiPolicy> = State::new(
+    "WIFI POLICY",
+    WifiPolicy::new(),
+    &[&crate::web::WIFI_POLICY_STATE_NOTIF, &POLICY_FLASH_NOTIFY],
+);
+
 pub static COMMAND: Signal<CriticalSectionRawMutex, WifiCommand> = Signal::new();
 
-pub async fn process<W: Wifi>(mut wifi: W) -> Result<(), W::Error> {
-    let mut stay_connected = false;
+static POLICY_FLASH_NOTIFY: Notification = Notification::new();
+
+pub async fn process(mut wifi: impl Wifi) {
+    let mut networks = Vec::<ClientConfiguration, MAX_KNOWN_NETWORKS>::new();
+    let mut access_point = None;
 
     loop {
-        let result = select(COMMAND.wait(), Timer::after(Duration::from_secs(1))).await;
+        let command = run(&mut wifi, &networks, access_point.as_ref()).await;
+
+        info!("Got command: {:?}", command);
+
+        match command {
+            WifiCommand::SetConfiguration(conf) => {
+                networks.clear();
 
-        match result {
-            Either::First(command) => {
-                if wifi.is_started().await? {
-                    let _ = wifi.stop().await?;
+                let (client, ap) = match conf {
+                    Configuration::None => (None, None),
+                    Configuration::Client(client) => (Some(client), None),
+                    Configuration::AccessPoint(ap) => (None, Some(ap)),
+                    Configuration::Mixed(client, ap) => (Some(client), Some(ap)),
+                };
+
+                if let Some(client) = client {
+                    networks.push(client).unwrap();
                 }
 
-                stay_connected = false;
+                access_point = ap;
+            }
+            WifiCommand::SetKnownNetworks(known) => networks = known,
+        }
+    }
+}
+
+async fn run<W: Wifi>(
+    wifi: &mut W,
+    networks: &[ClientConfiguration],
+    access_point: Option<&AccessPointConfiguration>,
+) -> WifiCommand {
+    let networks = networks
+        .iter()
+        .filter(|network| !network.ssid.is_empty())
+        .collect::<Vec<_, MAX_KNOWN_NETWORKS>>();
+
+    if networks.is_empty() {
+        let result = if let Some(ap) = access_point {
+            start(wifi, &Configuration::AccessPoint(ap.clone())).await
+        } else {
+            stop(wifi).await
+        };
 
-                match command {
-                    WifiCommand::SetConfiguration(conf) => {
-                        info!("Got configuration: {:?}", conf);
+        if let Err(err) = result {
+            warn!("Wifi error: {:?}", err);
+        }
 
-                        wifi.set_configuration(&conf).await?;
+        set_state(WifiState::Idle);
+
+        return COMMAND.wait().await;
+    }
 
-                        if !matches!(conf, Configuration::None) {
-                            wifi.start().await?;
+    let mut attempt = 0_u16;
+    let mut round = 0_u32;
 
-                            while !wifi.is_started().await? {
-                                Timer::after(Duration::from_millis(100)).await;
-                            }
+    'rounds: loop {
+        let mut error = false;
 
-                            info!("Wifi started");
-                        }
+        for (index, network) in networks.iter().enumerate() {
+            let index = index as u8;
 
-                        match &conf {
-                            Configuration::Client(conf) | Configuration::Mixed(conf, _) => {
-                                if conf.auth_method != AuthMethod::None {
-                                    wifi.connect().await?;
+            set_state(WifiState::Connecting {
+                network: index,
+                attempt,
+            });
 
-                                    while !wifi.is_connected().await? {
-                                        Timer::after(Duration::from_millis(100)).await;
-                                    }
+            let conf = if let Some(ap) = access_point {
+                Configuration::Mixed((*network).clone(), ap.clone())
+            } else {
+                Configuration::Client((*network).clone())
+            };
 
-                                    stay_connected = true;
+            match select(COMMAND.wait(), connect(wifi, &conf)).await {
+                Either::First(command) => return command,
+                Either::Second(Ok(true)) => {
+                    info!("Wifi connected to {}", network.ssid);
 
-                                    info!("Wifi connected");
-                                }
-                            }
-                            _ => (),
-                        }
+                    set_state(WifiState::Connected { network: index });
+
+                    if let Either::First(command) =
+                        select(COMMAND.wait(), wait_disconnected(wifi)).await
+                    {
+                        return command;
                     }
+
+                    info!("Wifi disconnection detected, reconnecting...");
+
+                    attempt = 0;
+                    round = 0;
+
+                    continue 'rounds;
+                }
+                Either::Second(Ok(false)) => {
+                    info!("Wifi connection to {} timed out", network.ssid);
+                }
+                Either::Second(Err(err)) => {
+                    warn!("Wifi connection to {} failed: {:?}", network.ssid, err);
+
+                    error = true;
                 }
             }
-            Either::Second(_) => {
-                if stay_connected {
-                    if !wifi.is_connected().await? {
-                        info!("Wifi disconnection detected, reconnecting...");
 
-                        wifi.connect().await?;
+            attempt = attempt.saturating_add(1);
+        }
 
-                        while !wifi.is_connected().await? {
-                            Timer::after(Duration::from_millis(100)).await;
-                        }
+        round += 1;
 
-                        info!("Wifi connected");
-                    }
+        if let Err(err) = stop_client(wifi, access_point).await {
+            warn!("Wifi error: {:?}", err);
+        }
+
+        let policy = POLICY.get();
+        let powered = battery::STATE.get().powered.unwrap_or(false);
+
+        let retry_secs = if policy.should_give_up(attempt, powered) {
+            info!("Wifi giving up after {} attempts", attempt);
+
+            set_state(WifiState::GaveUp);
+
+            match policy.give_up.and_then(|give_up| give_up.sleep_secs) {
+                Some(sleep_secs) => {
+                    attempt = 0;
+                    round = 0;
+
+                    sleep_secs
                 }
+                None => return COMMAND.wait().await,
+            }
+        } else {
+            let retry_secs = policy.backoff_secs(round);
+
+            set_state(WifiState::Backoff {
+                attempt,
+                retry_secs,
+                error,
+            });
+
+            retry_secs
+        };
+
+        if let Either::First(command) = select(
+            COMMAND.wait(),
+            Timer::after(Duration::from_secs(retry_secs as _)),
+        )
+        .await
+        {
+            return command;
+        }
+    }
+}
+
+/// Only a settled connection state is an activity which keeps the device awake, as the retries
+/// would otherwise keep it awake on battery for the whole backoff schedule
+fn set_state(state: WifiState) {
+    let settled = matches!(
+        state,
+        WifiState::Idle | WifiState::Connected { .. } | WifiState::GaveUp
+    );
+
+    if STATE.update(state) && settled {
+        crate::keepalive::NOTIF.notify();
+    }
+}
+
+async fn connect<W: Wifi>(wifi: &mut W, conf: &Configuration) -> Result<bool, W::Error> {
+    let result = with_timeout(CONNECT_TIMEOUT, async {
+        start(wifi, conf).await?;
+
+        wifi.connect().await?;
+
+        while !wifi.is_connected().await? {
+            Timer::after(Duration::from_millis(100)).await;
+        }
+
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => Ok(true),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Ok(false),
+    }
+}
+
+async fn start<W: Wifi>(wifi: &mut W, conf: &Configuration) -> Result<(), W::Error> {
+    stop(wifi).await?;
+
+    wifi.set_configuration(conf).await?;
+    wifi.start().await?;
+
+    while !wifi.is_started().await? {
+        Timer::after(Duration::from_millis(100)).await;
+    }
+
+    info!("Wifi started");
+
+    Ok(())
+}
+
+async fn stop<W: Wifi>(wifi: &mut W) -> Result<(), W::Error> {
+    if wifi.is_started().await? {
+        wifi.stop().await?;
+    }
+
+    Ok(())
+}
+
+/// Stops the client, but keeps the access point up, as it is the only way to reach a device which
+/// cannot connect to any of the known networks
+async fn stop_client<W: Wifi>(
+    wifi: &mut W,
+    access_point: Option<&AccessPointConfiguration>,
+) -> Result<(), W::Error> {
+    if let Some(ap) = access_point {
+        let conf = Configuration::AccessPoint(ap.clone());
+
+        if wifi.is_started().await? {
+            wifi.set_configuration(&conf).await
+        } else {
+            start(wifi, &conf).await
+        }
+    } else {
+        stop(wifi).await
+    }
+}
+
+async fn wait_disconnected<W: Wifi>(wifi: &mut W) {
+    loop {
+        Timer::after(CONNECTED_POLL).await;
+
+        match wifi.is_connected().await {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => {
+                warn!("Wifi error: {:?}", err);
+                break;
             }
         }
     }
 }
+
+pub async fn flash(mut flasher: impl FnMut(WifiPolicy)) {
+    loop {
+        POLICY_FLASH_NOTIFY.wait().await;
+
+        flasher(POLICY.get());
+    }
+}
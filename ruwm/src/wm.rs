@@ -1,23 +1,28 @@
-use embassy_futures::select::select;
+use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 
 use channel_bridge::notification::Notification;
 
 use crate::pulse_counter::{PulseCounter, PulseWakeup};
+use crate::quit::{self, Participant};
 use crate::state::State;
 
 pub use crate::dto::water_meter::*;
 
 pub const FLASH_WRITE_CYCLE: usize = 20;
 
-pub static STATE: State<WaterMeterState> = State::new(
+pub type WaterMeterStates = [WaterMeterState; MAX_METERS];
+pub type MeterConfigs = [MeterConfig; MAX_METERS];
+
+pub static STATE: State<WaterMeterStates> = State::new(
     "WM",
-    WaterMeterState::new(),
+    [WaterMeterState::new(); MAX_METERS],
     &[
         &crate::keepalive::NOTIF,
         &crate::emergency::WM_STATE_NOTIF,
         &crate::wm_stats::WM_STATE_NOTIF,
+        &crate::flow::WM_STATE_NOTIF,
         &crate::screen::WM_STATE_NOTIF,
         &crate::mqtt::WM_STATE_NOTIF,
         &crate::web::WM_STATE_NOTIF,
@@ -26,25 +31,70 @@ pub static STATE: State<WaterMeterState> = State::new(
     ],
 );
 
+pub static CONFIG: State<MeterConfigs> = State::new(
+    "WM CONFIG",
+    [
+        MeterConfig::new(true),
+        MeterConfig::new(false),
+        MeterConfig::new(false),
+    ],
+    &[
+        &crate::screen::WM_CONFIG_STATE_NOTIF,
+        &crate::web::WM_CONFIG_STATE_NOTIF,
+        &CONFIG_FLASH_NOTIFY,
+    ],
+);
+
+pub(crate) static SHUTDOWN_NOTIF: Notification = Notification::new();
+
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
 static STATE_FLASH_NOTIFY: Notification = Notification::new();
+static CONFIG_FLASH_NOTIFY: Notification = Notification::new();
+
+#[allow(clippy::declare_interior_mutable_const)]
+const COMMAND_SIGNAL: Signal<CriticalSectionRawMutex, WaterMeterCommand> = Signal::new();
 
-pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, WaterMeterCommand> = Signal::new();
+pub(crate) static COMMAND: [Signal<CriticalSectionRawMutex, WaterMeterCommand>; MAX_METERS] =
+    [COMMAND_SIGNAL; MAX_METERS];
 
-pub async fn process(pulse_counter: impl PulseCounter, pulse_wakeup: impl PulseWakeup) {
+/// Applies a new configuration to a meter; the main meter stays enabled
+pub fn configure(meter: usize, mut config: MeterConfig) {
+    config.enabled |= meter == 0;
+
+    CONFIG.update_with(|mut configs| {
+        configs[meter] = config;
+        configs
+    });
+}
+
+/// The enabled meters, with their index
+pub fn enabled() -> impl Iterator<Item = (usize, MeterConfig)> {
+    CONFIG
+        .get()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, config)| config.enabled)
+}
+
+/// Counts the pulses of the meter with that index
+pub async fn process(
+    meter: usize,
+    pulse_counter: impl PulseCounter,
+    pulse_wakeup: impl PulseWakeup,
+) {
     select(
-        process_pulses(pulse_counter),
-        process_commands(pulse_wakeup),
+        process_pulses(meter, pulse_counter),
+        process_commands(meter, pulse_wakeup),
     )
     .await;
 }
 
-async fn process_pulses(mut pulse_counter: impl PulseCounter) {
+async fn process_pulses(meter: usize, mut pulse_counter: impl PulseCounter) {
     loop {
         let pulses = pulse_counter.take_pulses().await.unwrap();
 
         if pulses > 0 {
-            STATE.update_with(|state| WaterMeterState {
+            update(meter, |state| WaterMeterState {
                 edges_count: state.edges_count + pulses,
                 armed: state.armed,
                 leaking: state.armed,
@@ -53,13 +103,17 @@ async fn process_pulses(mut pulse_counter: impl PulseCounter) {
     }
 }
 
-async fn process_commands(mut pulse_wakeup: impl PulseWakeup) {
+async fn process_commands(meter: usize, mut pulse_wakeup: impl PulseWakeup) {
+    // The pulse counter might have been re-initialized since the state was persisted,
+    // i.e. after a power loss, so the wakeup has to follow the persisted state rather than a command
+    pulse_wakeup.set_enabled(STATE.get()[meter].armed).unwrap();
+
     loop {
-        let armed = COMMAND.wait().await == WaterMeterCommand::Arm;
+        let armed = COMMAND[meter].wait().await == WaterMeterCommand::Arm;
 
         pulse_wakeup.set_enabled(armed).unwrap();
 
-        STATE.update_with(|state| WaterMeterState {
+        update(meter, |state| WaterMeterState {
             edges_count: state.edges_count,
             armed,
             leaking: state.leaking,
@@ -67,7 +121,14 @@ async fn process_commands(mut pulse_wakeup: impl PulseWakeup) {
     }
 }
 
-pub async fn persist(mut persister: impl FnMut(WaterMeterState)) {
+fn update(meter: usize, updater: impl FnOnce(WaterMeterState) -> WaterMeterState) {
+    STATE.update_with(|mut states| {
+        states[meter] = updater(states[meter]);
+        states
+    });
+}
+
+pub async fn persist(mut persister: impl FnMut(WaterMeterStates)) {
     loop {
         STATE_PERSIST_NOTIFY.wait().await;
 
@@ -75,20 +136,58 @@ pub async fn persist(mut persister: impl FnMut(WaterMeterState)) {
     }
 }
 
-pub async fn flash(mut flasher: impl FnMut(WaterMeterState)) {
-    let mut cycle = 0;
+/// Flashes the state of each meter under its own key, as `flasher(meter, state)`
+///
+/// Only every `FLASH_WRITE_CYCLE`th change is written, and the skipped ones on shutdown.
+pub async fn flash(mut flasher: impl FnMut(usize, WaterMeterState)) {
+    let mut flashed = STATE.get();
+    let mut cycles = [0; MAX_METERS];
+    let mut skipped = [false; MAX_METERS];
+
+    quit::register(Participant::WaterMeter);
 
     loop {
-        STATE_FLASH_NOTIFY.wait().await;
+        let shutdown = matches!(
+            select(STATE_FLASH_NOTIFY.wait(), SHUTDOWN_NOTIF.wait()).await,
+            Either::Second(_)
+        );
 
-        if cycle == 0 {
-            flasher(STATE.get());
-        }
+        let states = STATE.get();
+
+        for meter in 0..MAX_METERS {
+            if states[meter] != flashed[meter] {
+                flashed[meter] = states[meter];
+
+                skipped[meter] = cycles[meter] != 0;
+
+                if !skipped[meter] {
+                    flasher(meter, states[meter]);
+                }
+
+                cycles[meter] += 1;
+
+                if cycles[meter] >= FLASH_WRITE_CYCLE {
+                    cycles[meter] = 0;
+                }
+            }
 
-        cycle += 1;
+            if shutdown && skipped[meter] {
+                skipped[meter] = false;
 
-        if cycle >= FLASH_WRITE_CYCLE {
-            cycle = 0;
+                flasher(meter, states[meter]);
+            }
         }
+
+        if shutdown {
+            quit::flushed(Participant::WaterMeter);
+        }
+    }
+}
+
+pub async fn flash_config(mut flasher: impl FnMut(MeterConfigs)) {
+    loop {
+        CONFIG_FLASH_NOTIFY.wait().await;
+
+        flasher(CONFIG.get());
     }
 }
@@ -4,49 +4,89 @@ use embassy_futures::select::{select, Either};
 
 use channel_bridge::notification::Notification;
 
-use crate::{state::*, wm};
+use crate::wm::MAX_METERS;
+use crate::{state::*, time, wm};
 
 pub use crate::dto::water_meter_stats::*;
 
-pub static STATE: State<WaterMeterStatsState> = State::new(
+pub type WaterMeterStatsStates = [WaterMeterStatsState; MAX_METERS];
+
+const STATS: WaterMeterStatsState = WaterMeterStatsState::new();
+
+pub static STATE: State<WaterMeterStatsStates> = State::new(
     "WM STATS",
-    WaterMeterStatsState::new(),
+    [STATS; MAX_METERS],
     &[
         &crate::keepalive::NOTIF,
         &crate::screen::WM_STATS_STATE_NOTIF,
         &crate::web::WM_STATS_STATE_NOTIF,
+        &crate::alert::WM_STATS_STATE_NOTIF,
         &STATE_PERSIST_NOTIFY,
+        &STATE_FLASH_NOTIFY,
     ],
 );
 
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
 
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+static STATE_FLASH_NOTIFY: Notification = Notification::new();
 
 pub async fn process() {
     loop {
-        let edges_count = match select(
+        let edges_counts = match select(
             WM_STATE_NOTIF.wait(),
             Timer::after(Duration::from_secs(10) /*Duration::from_millis(200)*/),
         )
         .await
         {
-            Either::First(_) => wm::STATE.get().edges_count,
-            Either::Second(_) => STATE.get().most_recent.edges_count,
+            Either::First(_) => wm::STATE.get().map(|state| state.edges_count),
+            Either::Second(_) => STATE.get().map(|state| state.most_recent.edges_count),
         };
 
-        STATE.update_with(|mut state| {
-            state.update(edges_count, Instant::now().as_secs());
+        let now_secs = Instant::now().as_secs();
+        // Not known while the time is not valid, which leaves the calendar periods alone
+        let utc_secs = time::now();
+        let time_zone = time::TIME_ZONE.get();
+
+        STATE.update_with(|mut states| {
+            for (meter, _) in wm::enabled() {
+                states[meter].update(edges_counts[meter], now_secs, utc_secs, &time_zone);
+            }
 
-            state
+            states
         });
     }
 }
 
-pub async fn persist(mut persister: impl FnMut(WaterMeterStatsState)) {
+pub async fn persist(mut persister: impl FnMut(WaterMeterStatsStates)) {
     loop {
         STATE_PERSIST_NOTIFY.wait().await;
 
         persister(STATE.get());
     }
 }
+
+/// Flashes the consumption history of each meter once per hour, as `flasher(meter, history)`,
+/// as it outlives the RTC memory only in flash
+///
+/// The restored history was flashed already for its current hour, be it restored from flash
+/// or from the RTC memory, so the first flash after a wakeup is for the next hour
+pub async fn flash(mut flasher: impl FnMut(usize, CalendarStats)) {
+    let mut flashed_hours = STATE.get().map(|state| Some(state.calendar.hours.period));
+
+    loop {
+        STATE_FLASH_NOTIFY.wait().await;
+
+        for (meter, state) in STATE.get().into_iter().enumerate() {
+            let calendar = state.calendar;
+
+            if !calendar.hours.edges.is_empty()
+                && flashed_hours[meter] != Some(calendar.hours.period)
+            {
+                flashed_hours[meter] = Some(calendar.hours.period);
+
+                flasher(meter, calendar);
+            }
+        }
+    }
+}
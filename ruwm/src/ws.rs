@@ -5,6 +5,8 @@ use embedded_svc::ws::asynch::server::Acceptor;
 use channel_bridge::asynch::{ws, *};
 use channel_bridge::notification::Notification;
 
+use edge_frame::dto::Role;
+
 use crate::web::{self, *};
 
 #[cfg(feature = "ws-max-connections-16")]
@@ -32,15 +34,53 @@ pub const WS_MAX_FRAME_LEN: usize = 512;
 const NOTIF: Notification = Notification::new();
 
 static HANDLERS_VALVE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_VALVE_PROFILE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_VALVE_FAULT_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_VALVE_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WM_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_WM_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WM_STATS_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_FLOW_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_BATTERY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_REMAINING_TIME_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_MQTT_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WIFI_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_OTA_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_ALERT_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_ALERT_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_AWAY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_AWAY_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_MOISTURE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_MOISTURE_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_TEMPERATURE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_TEMPERATURE_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_PRESSURE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_PRESSURE_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_BATTERY_CONFIG_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_POWER_POLICY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_BUTTON_TIMINGS_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_WIFI_POLICY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_TIME_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_SHUTDOWN_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 
 struct WebHandler;
 
@@ -55,14 +95,7 @@ impl ws::AcceptorHandler for WebHandler {
         R: Receiver<Error = S::Error, Data = Option<Self::ReceiveData>>,
         S::Error: core::fmt::Debug,
     {
-        web::handle(
-            sender,
-            receiver,
-            &HANDLERS_VALVE_STATE_NOTIF[index],
-            &HANDLERS_WM_STATE_NOTIF[index],
-            &HANDLERS_WM_STATS_STATE_NOTIF[index],
-        )
-        .await
+        web::handle(sender, receiver, Role::None, state_notifs(index)).await
     }
 }
 
@@ -87,13 +120,45 @@ where
     web::handle(
         ws::WsSvcSender::new(sender, send_buf),
         ws::WsSvcReceiver::new(receiver, recv_buf),
-        &HANDLERS_VALVE_STATE_NOTIF[index],
-        &HANDLERS_WM_STATE_NOTIF[index],
-        &HANDLERS_WM_STATS_STATE_NOTIF[index],
+        Role::None,
+        state_notifs(index),
     )
     .await
 }
 
+/// The state notifications of the web client served by the connection handler with that index
+///
+/// Websocket and plain HTTP clients share the handler slots, as a handler serves one connection at a time
+pub fn state_notifs(index: usize) -> StateNotifs<'static> {
+    StateNotifs {
+        valve: &HANDLERS_VALVE_STATE_NOTIF[index],
+        valve_profile: &HANDLERS_VALVE_PROFILE_STATE_NOTIF[index],
+        valve_fault: &HANDLERS_VALVE_FAULT_STATE_NOTIF[index],
+        valve_config: &HANDLERS_VALVE_CONFIG_STATE_NOTIF[index],
+        wm: &HANDLERS_WM_STATE_NOTIF[index],
+        wm_config: &HANDLERS_WM_CONFIG_STATE_NOTIF[index],
+        flow: &HANDLERS_FLOW_STATE_NOTIF[index],
+        battery: &HANDLERS_BATTERY_STATE_NOTIF[index],
+        battery_config: &HANDLERS_BATTERY_CONFIG_STATE_NOTIF[index],
+        ota: &HANDLERS_OTA_STATE_NOTIF[index],
+        alert: &HANDLERS_ALERT_STATE_NOTIF[index],
+        alert_config: &HANDLERS_ALERT_CONFIG_STATE_NOTIF[index],
+        away: &HANDLERS_AWAY_STATE_NOTIF[index],
+        away_config: &HANDLERS_AWAY_CONFIG_STATE_NOTIF[index],
+        moisture: &HANDLERS_MOISTURE_STATE_NOTIF[index],
+        moisture_config: &HANDLERS_MOISTURE_CONFIG_STATE_NOTIF[index],
+        temperature: &HANDLERS_TEMPERATURE_STATE_NOTIF[index],
+        temperature_config: &HANDLERS_TEMPERATURE_CONFIG_STATE_NOTIF[index],
+        pressure: &HANDLERS_PRESSURE_STATE_NOTIF[index],
+        pressure_config: &HANDLERS_PRESSURE_CONFIG_STATE_NOTIF[index],
+        power_policy: &HANDLERS_POWER_POLICY_STATE_NOTIF[index],
+        button_timings: &HANDLERS_BUTTON_TIMINGS_STATE_NOTIF[index],
+        wifi_policy: &HANDLERS_WIFI_POLICY_STATE_NOTIF[index],
+        time: &HANDLERS_TIME_STATE_NOTIF[index],
+        shutdown: &HANDLERS_SHUTDOWN_NOTIF[index],
+    }
+}
+
 pub async fn broadcast() {
     let mut notifs = [
         VALVE_STATE_NOTIF.wait(),
@@ -103,6 +168,28 @@ pub async fn broadcast() {
         REMAINING_TIME_STATE_NOTIF.wait(),
         MQTT_STATE_NOTIF.wait(),
         WIFI_STATE_NOTIF.wait(),
+        OTA_STATE_NOTIF.wait(),
+        FLOW_STATE_NOTIF.wait(),
+        ALERT_STATE_NOTIF.wait(),
+        ALERT_CONFIG_STATE_NOTIF.wait(),
+        AWAY_STATE_NOTIF.wait(),
+        AWAY_CONFIG_STATE_NOTIF.wait(),
+        VALVE_PROFILE_STATE_NOTIF.wait(),
+        VALVE_FAULT_STATE_NOTIF.wait(),
+        VALVE_CONFIG_STATE_NOTIF.wait(),
+        WM_CONFIG_STATE_NOTIF.wait(),
+        MOISTURE_STATE_NOTIF.wait(),
+        MOISTURE_CONFIG_STATE_NOTIF.wait(),
+        TEMPERATURE_STATE_NOTIF.wait(),
+        TEMPERATURE_CONFIG_STATE_NOTIF.wait(),
+        PRESSURE_STATE_NOTIF.wait(),
+        PRESSURE_CONFIG_STATE_NOTIF.wait(),
+        BATTERY_CONFIG_STATE_NOTIF.wait(),
+        POWER_POLICY_STATE_NOTIF.wait(),
+        BUTTON_TIMINGS_STATE_NOTIF.wait(),
+        WIFI_POLICY_STATE_NOTIF.wait(),
+        TIME_STATE_NOTIF.wait(),
+        SHUTDOWN_NOTIF.wait(),
     ];
 
     loop {
@@ -114,6 +201,28 @@ pub async fn broadcast() {
             4 => &HANDLERS_REMAINING_TIME_STATE_NOTIF,
             5 => &HANDLERS_MQTT_STATE_NOTIF,
             6 => &HANDLERS_WIFI_STATE_NOTIF,
+            7 => &HANDLERS_OTA_STATE_NOTIF,
+            8 => &HANDLERS_FLOW_STATE_NOTIF,
+            9 => &HANDLERS_ALERT_STATE_NOTIF,
+            10 => &HANDLERS_ALERT_CONFIG_STATE_NOTIF,
+            11 => &HANDLERS_AWAY_STATE_NOTIF,
+            12 => &HANDLERS_AWAY_CONFIG_STATE_NOTIF,
+            13 => &HANDLERS_VALVE_PROFILE_STATE_NOTIF,
+            14 => &HANDLERS_VALVE_FAULT_STATE_NOTIF,
+            15 => &HANDLERS_VALVE_CONFIG_STATE_NOTIF,
+            16 => &HANDLERS_WM_CONFIG_STATE_NOTIF,
+            17 => &HANDLERS_MOISTURE_STATE_NOTIF,
+            18 => &HANDLERS_MOISTURE_CONFIG_STATE_NOTIF,
+            19 => &HANDLERS_TEMPERATURE_STATE_NOTIF,
+            20 => &HANDLERS_TEMPERATURE_CONFIG_STATE_NOTIF,
+            21 => &HANDLERS_PRESSURE_STATE_NOTIF,
+            22 => &HANDLERS_PRESSURE_CONFIG_STATE_NOTIF,
+            23 => &HANDLERS_BATTERY_CONFIG_STATE_NOTIF,
+            24 => &HANDLERS_POWER_POLICY_STATE_NOTIF,
+            25 => &HANDLERS_BUTTON_TIMINGS_STATE_NOTIF,
+            26 => &HANDLERS_WIFI_POLICY_STATE_NOTIF,
+            27 => &HANDLERS_TIME_STATE_NOTIF,
+            28 => &HANDLERS_SHUTDOWN_NOTIF,
             _ => unreachable!(),
         };
 